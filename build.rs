@@ -1,4 +1,5 @@
 use std::env;
+use std::process::Command;
 
 fn main() {
     // Read the variable from the environment the build script is running in.
@@ -15,4 +16,26 @@ fn main() {
 
     // Print it as a cargo instruction, which will show up in build logs.
     println!("cargo:warning=Building package version: {}", version);
+
+    // Docker builds have no .git directory, so the commit can also be passed in.
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let commit = env::var("GIT_COMMIT_SHA")
+        .ok()
+        .filter(|sha| !sha.trim().is_empty())
+        .or_else(git_head)
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT_SHA={}", commit.trim());
+}
+
+fn git_head() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
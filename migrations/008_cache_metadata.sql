@@ -0,0 +1,8 @@
+-- Record who posted the cached media and where it came from, so the owner can
+-- query popular creators and look up cached entries by text (/findcached).
+-- All columns are nullable: rows cached before this migration keep NULLs and
+-- are still matched on caption and source_url.
+ALTER TABLE media_cache ADD COLUMN uploader TEXT;
+ALTER TABLE media_cache ADD COLUMN title TEXT;
+ALTER TABLE media_cache ADD COLUMN extractor TEXT;
+ALTER TABLE media_cache ADD COLUMN duration DOUBLE PRECISION;
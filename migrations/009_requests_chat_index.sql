@@ -0,0 +1,2 @@
+-- Speeds up the per-chat daily download count used by the download quota.
+CREATE INDEX idx_requests_chat_id_created_at ON requests(chat_id, created_at);
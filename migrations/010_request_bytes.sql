@@ -0,0 +1,2 @@
+-- Size of the files downloaded and uploaded for a request, for traffic reporting.
+ALTER TABLE requests ADD COLUMN bytes_transferred BIGINT;
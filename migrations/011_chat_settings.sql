@@ -0,0 +1,6 @@
+-- Per-chat preferences changed with bot commands; chats without a row use the defaults.
+CREATE TABLE chat_settings (
+    chat_id BIGINT PRIMARY KEY,
+    source_as_button BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- The messages each download was delivered in, for tracing a message back to its source
+-- with `/trace`. `cache_id` is the media_cache row that served a cache hit; it is kept
+-- without a foreign key so the trace survives the cache entry expiring.
+CREATE TABLE deliveries (
+    chat_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    source_url TEXT NOT NULL,
+    cache_id INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (chat_id, message_id)
+);
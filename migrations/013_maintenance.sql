@@ -0,0 +1,7 @@
+-- Maintenance mode set with /maintenance, kept across restarts. At most one row.
+CREATE TABLE maintenance (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    enabled BOOLEAN NOT NULL,
+    message TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,5 @@
+-- A Telegram file id only works for the bot that uploaded the file, so each bot keeps its
+-- own cache entries. Rows cached before this have bot_id 0, are never hit and age out.
+ALTER TABLE media_cache ADD COLUMN bot_id BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE media_cache DROP CONSTRAINT media_cache_source_url_key;
+ALTER TABLE media_cache ADD CONSTRAINT media_cache_bot_id_source_url_key UNIQUE (bot_id, source_url);
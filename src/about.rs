@@ -0,0 +1,73 @@
+//! The `/about` command: what is running and how it is configured.
+
+use crate::downloader::escape_html_text;
+use crate::object_store::format_file_size;
+use crate::validator::{MAX_DURATION_SECONDS, ValidationConfig};
+
+/// Commit the binary was built from, embedded by `build.rs`; "unknown" outside a checkout.
+pub const GIT_COMMIT_SHA: &str = env!("GIT_COMMIT_SHA");
+
+/// Facts about this deployment, gathered once at startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AboutInfo {
+    pub version: String,
+    pub commit: String,
+    pub execution_environment: String,
+    /// "PostgreSQL", "disabled" for `none://`, "file" for `file://`, or "in-memory" when
+    /// configured so or after falling back to it.
+    pub storage_backend: &'static str,
+    /// Whether `OWNER_CHAT_ID` is set, which enables the owner commands.
+    pub owner_configured: bool,
+    pub validation: ValidationConfig,
+    pub yt_dlp_version: Option<String>,
+}
+
+impl AboutInfo {
+    /// The `/about` reply, in HTML.
+    pub fn render(&self) -> String {
+        let commit: String = self.commit.chars().take(7).collect();
+        indoc::formatdoc! { "
+            <b>CrabberBot</b> {version} (<code>{commit}</code>)
+            Downloads videos and photos from Instagram, TikTok, YouTube Shorts and the other sites yt-dlp supports.
+
+            Environment: {environment}
+            Storage: {storage}
+            Owner commands: {owner}
+            Limits: {size} per file, {minutes} minutes per video
+            yt-dlp: {yt_dlp}",
+            version = escape_html_text(&self.version),
+            commit = escape_html_text(&commit),
+            environment = escape_html_text(&self.execution_environment),
+            storage = self.storage_backend,
+            owner = if self.owner_configured { "enabled" } else { "disabled" },
+            size = format_file_size(self.validation.max_filesize_bytes),
+            minutes = MAX_DURATION_SECONDS / 60.0,
+            yt_dlp = escape_html_text(self.yt_dlp_version.as_deref().unwrap_or("unknown")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_summarizes_the_deployment() {
+        let about = AboutInfo {
+            version: "2.1.0".to_string(),
+            commit: "0123456789abcdef".to_string(),
+            execution_environment: "<staging>".to_string(),
+            storage_backend: "PostgreSQL",
+            owner_configured: true,
+            validation: ValidationConfig::for_bot_api(true),
+            yt_dlp_version: None,
+        };
+        let text = about.render();
+
+        assert!(text.starts_with("<b>CrabberBot</b> 2.1.0 (<code>0123456</code>)\n"));
+        assert!(text.contains("Environment: &lt;staging&gt;\n"));
+        assert!(text.contains("Storage: PostgreSQL\nOwner commands: enabled\n"));
+        assert!(text.contains("Limits: 2147.5 MB per file, 30 minutes per video\n"));
+        assert!(text.ends_with("yt-dlp: unknown"));
+    }
+}
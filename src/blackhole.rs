@@ -0,0 +1,225 @@
+//! `Storage` that keeps nothing, selected with `STORAGE_URL=none://`. Every lookup misses
+//! and every write is dropped, so each link is downloaded afresh and everyone is on the
+//! free tier; purchases are refused, as they couldn't be credited. Useful for tests and
+//! for deployments that don't want any persistence.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::downloader::MediaType;
+use crate::handler::CallbackContext;
+use crate::maintenance::MaintenanceState;
+use crate::storage::{
+    ActivityReport, CacheMetadata, CacheSearchResult, CacheStats, CachedMedia, ChatSettings,
+    Delivery, PaymentRecord, RequestLogEntry, Storage,
+};
+use crate::subscription::{SubscriptionInfo, SubscriptionTier};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlackholeStorage;
+
+#[async_trait]
+impl Storage for BlackholeStorage {
+    async fn get_cached_media(&self, _bot_id: i64, _source_url: &str) -> Option<CachedMedia> {
+        None
+    }
+
+    async fn store_cached_media(
+        &self,
+        _bot_id: i64,
+        _source_url: &str,
+        _caption: &str,
+        _files: &[(String, MediaType)],
+        _audio_cache_path: Option<String>,
+        _media_duration_secs: Option<i32>,
+        _metadata: &CacheMetadata,
+    ) {
+    }
+
+    async fn search_cache(&self, _query: &str, _limit: i64) -> Vec<CacheSearchResult> {
+        Vec::new()
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+
+    async fn activity_report(&self, _window: Duration) -> ActivityReport {
+        ActivityReport::default()
+    }
+
+    async fn log_request(
+        &self,
+        _chat_id: i64,
+        _source_url: &str,
+        _status: &str,
+        _processing_time_ms: i64,
+        _bytes_transferred: Option<i64>,
+    ) {
+    }
+
+    async fn get_daily_download_count(&self, _chat_id: i64) -> i64 {
+        0
+    }
+
+    async fn get_top_urls(
+        &self,
+        _limit: i64,
+        _since: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<String> {
+        Vec::new()
+    }
+
+    async fn recent_requests(&self, _limit: i64) -> Vec<RequestLogEntry> {
+        Vec::new()
+    }
+
+    async fn record_delivery(
+        &self,
+        _chat_id: i64,
+        _message_ids: &[i32],
+        _source_url: &str,
+        _cache_id: Option<i32>,
+    ) {
+    }
+
+    async fn get_delivery(&self, _chat_id: i64, _message_id: i32) -> Option<Delivery> {
+        None
+    }
+
+    async fn get_subscription(&self, _user_id: i64) -> SubscriptionInfo {
+        SubscriptionInfo::free_default()
+    }
+
+    async fn upsert_subscription(&self, _user_id: i64, _tier: SubscriptionTier, _days: i64) {}
+
+    async fn get_chat_settings(&self, _chat_id: i64) -> ChatSettings {
+        ChatSettings::default()
+    }
+
+    async fn set_source_as_button(&self, _chat_id: i64, _enabled: bool) {}
+
+    async fn get_maintenance(&self) -> MaintenanceState {
+        MaintenanceState::default()
+    }
+
+    async fn set_maintenance(&self, _state: &MaintenanceState) {}
+
+    async fn migrate_chat(&self, _old_chat_id: i64, _new_chat_id: i64) {}
+
+    fn records_payments(&self) -> bool {
+        false
+    }
+
+    async fn record_payment(
+        &self,
+        user_id: i64,
+        telegram_charge_id: &str,
+        _provider_charge_id: &str,
+        product: &str,
+        amount: i32,
+    ) {
+        // The only write worth a trace: without it a purchase would leave no record at all.
+        log::warn!(
+            "Payment not stored (storage disabled): user {} bought {} for {} (charge {})",
+            user_id,
+            product,
+            amount,
+            telegram_charge_id
+        );
+    }
+
+    async fn consume_ai_seconds(&self, _user_id: i64, _seconds: i32) {}
+
+    async fn add_topup_seconds(&self, _user_id: i64, _seconds: i32) {}
+
+    async fn record_premium_usage(
+        &self,
+        _user_id: i64,
+        _feature: &str,
+        _source_url: &str,
+        _duration_secs: i32,
+        _units: f64,
+        _cost_usd: f64,
+    ) {
+    }
+
+    /// Hands out 0; the context can't be looked up again, so its buttons report expiry.
+    async fn store_callback_context(&self, _ctx: &CallbackContext) -> i32 {
+        0
+    }
+
+    async fn get_callback_context(&self, _context_id: i32) -> Option<CallbackContext> {
+        None
+    }
+
+    async fn cache_transcript(
+        &self,
+        _context_id: i32,
+        _transcript: &str,
+        _language: Option<String>,
+    ) {
+    }
+
+    async fn revoke_subscription(&self, _user_id: i64) {}
+
+    async fn revoke_topup(&self, _user_id: i64, _seconds: i32) {}
+
+    async fn get_latest_payment(&self, _user_id: i64) -> Option<PaymentRecord> {
+        None
+    }
+
+    async fn get_recent_payments(&self, _user_id: i64, _limit: i64) -> Vec<PaymentRecord> {
+        Vec::new()
+    }
+
+    async fn has_ai_usage_since(
+        &self,
+        _user_id: i64,
+        _since: chrono::DateTime<chrono::Utc>,
+    ) -> bool {
+        false
+    }
+
+    async fn cleanup_expired_callback_contexts(&self) {}
+
+    async fn expire_stale_topups(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forgets_everything_it_is_given() {
+        let storage = BlackholeStorage;
+        let url = "https://example.com/video";
+        storage
+            .store_cached_media(
+                1,
+                url,
+                "caption",
+                &[("file-id".to_string(), MediaType::Video)],
+                None,
+                Some(10),
+                &CacheMetadata::default(),
+            )
+            .await;
+        storage
+            .log_request(1, url, "success", 100, Some(1024))
+            .await;
+        storage
+            .upsert_subscription(1, SubscriptionTier::Basic, 30)
+            .await;
+
+        assert!(storage.get_cached_media(1, url).await.is_none());
+        assert!(storage.search_cache("caption", 10).await.is_empty());
+        assert_eq!(storage.cache_stats().await, CacheStats::default());
+        assert_eq!(storage.get_daily_download_count(1).await, 0);
+        assert_eq!(
+            storage.get_subscription(1).await.tier,
+            SubscriptionTier::Free
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Keeps the bot's name, description and command menus in sync with what we want,
+//! without re-sending values Telegram already has. `setMyName` in particular is
+//! rate-limited hard enough that setting it on every boot breaks crash-looping deploys.
+
+use async_trait::async_trait;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand, BotCommandScope};
+use url::Url;
+
+/// The subset of the Bot API used to read and update the bot profile.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait BotProfileApi: Send + Sync {
+    async fn get_my_name(&self) -> Result<String, teloxide::RequestError>;
+    async fn set_my_name(&self, name: &str) -> Result<(), teloxide::RequestError>;
+    async fn get_my_description(&self) -> Result<String, teloxide::RequestError>;
+    async fn set_my_description(&self, description: &str) -> Result<(), teloxide::RequestError>;
+    async fn get_my_commands(
+        &self,
+        scope: BotCommandScope,
+    ) -> Result<Vec<BotCommand>, teloxide::RequestError>;
+    async fn set_my_commands(
+        &self,
+        commands: Vec<BotCommand>,
+        scope: BotCommandScope,
+    ) -> Result<(), teloxide::RequestError>;
+}
+
+#[async_trait]
+impl BotProfileApi for Bot {
+    async fn get_my_name(&self) -> Result<String, teloxide::RequestError> {
+        Ok(Requester::get_my_name(self).await?.name)
+    }
+
+    async fn set_my_name(&self, name: &str) -> Result<(), teloxide::RequestError> {
+        Requester::set_my_name(self).name(name).await?;
+        Ok(())
+    }
+
+    async fn get_my_description(&self) -> Result<String, teloxide::RequestError> {
+        Ok(Requester::get_my_description(self).await?.description)
+    }
+
+    async fn set_my_description(&self, description: &str) -> Result<(), teloxide::RequestError> {
+        Requester::set_my_description(self)
+            .description(description)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_my_commands(
+        &self,
+        scope: BotCommandScope,
+    ) -> Result<Vec<BotCommand>, teloxide::RequestError> {
+        Requester::get_my_commands(self).scope(scope).await
+    }
+
+    async fn set_my_commands(
+        &self,
+        commands: Vec<BotCommand>,
+        scope: BotCommandScope,
+    ) -> Result<(), teloxide::RequestError> {
+        Requester::set_my_commands(self, commands)
+            .scope(scope)
+            .await?;
+        Ok(())
+    }
+}
+
+pub const BOT_DESCRIPTION: &str = "Your friendly media downloader from various platforms like Instagram, TikTok, YouTube, and more!";
+
+/// Display name for the bot; deployments whose webhook URL mentions "test" are marked as such.
+pub fn bot_name_for(webhook_url: &Url) -> &'static str {
+    if webhook_url.as_str().contains("test") {
+        "CrabberBot TEST"
+    } else {
+        "CrabberBot | Video Downloader"
+    }
+}
+
+/// The profile the bot should have after startup.
+#[derive(Debug, Clone)]
+pub struct DesiredProfile {
+    /// `None` leaves the name as configured in BotFather.
+    pub name: Option<String>,
+    pub description: String,
+    pub command_menus: Vec<(BotCommandScope, Vec<BotCommand>)>,
+}
+
+/// Telegram reports command names without the leading slash our definitions carry.
+fn same_commands(current: &[BotCommand], desired: &[BotCommand]) -> bool {
+    current.len() == desired.len()
+        && current.iter().zip(desired).all(|(a, b)| {
+            a.command.trim_start_matches('/') == b.command.trim_start_matches('/')
+                && a.description == b.description
+        })
+}
+
+/// Update whatever differs from `desired`. Errors are logged and never abort startup.
+pub async fn sync(bot: &dyn BotProfileApi, desired: &DesiredProfile) {
+    if let Some(name) = &desired.name {
+        match bot.get_my_name().await {
+            Ok(current) if current == *name => log::info!("Bot name unchanged, skipping"),
+            current => {
+                if let Err(e) = current {
+                    log::warn!("Failed to fetch bot name: {}", e);
+                }
+                match bot.set_my_name(name).await {
+                    Ok(()) => log::info!("Successfully set bot name. {}", name),
+                    Err(e) => log::warn!("Failed to set bot name: {}", e),
+                }
+            }
+        }
+    }
+
+    match bot.get_my_description().await {
+        Ok(current) if current == desired.description => {
+            log::info!("Bot description unchanged, skipping");
+        }
+        current => {
+            if let Err(e) = current {
+                log::warn!("Failed to fetch bot description: {}", e);
+            }
+            match bot.set_my_description(&desired.description).await {
+                Ok(()) => log::info!("Successfully set bot description."),
+                Err(e) => log::warn!("Failed to set bot description: {}", e),
+            }
+        }
+    }
+
+    for (scope, commands) in &desired.command_menus {
+        match bot.get_my_commands(scope.clone()).await {
+            Ok(current) if same_commands(&current, commands) => {
+                log::info!("Bot commands for {:?} unchanged, skipping", scope);
+            }
+            current => {
+                if let Err(e) = current {
+                    log::warn!("Failed to fetch bot commands for {:?}: {}", scope, e);
+                }
+                match bot.set_my_commands(commands.clone(), scope.clone()).await {
+                    Ok(()) => log::info!("Successfully set bot commands for {:?}.", scope),
+                    Err(e) => log::warn!("Failed to set bot commands for {:?}: {}", scope, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error() -> teloxide::RequestError {
+        teloxide::RequestError::Api(teloxide::ApiError::Unknown("Too Many Requests".into()))
+    }
+
+    fn desired() -> DesiredProfile {
+        DesiredProfile {
+            name: Some("CrabberBot".to_string()),
+            description: "Downloads media".to_string(),
+            command_menus: vec![(
+                BotCommandScope::AllPrivateChats,
+                vec![BotCommand::new("/start", "start interaction.")],
+            )],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_skips_unchanged_values() {
+        let mut mock_bot = MockBotProfileApi::new();
+        mock_bot
+            .expect_get_my_name()
+            .returning(|| Ok("CrabberBot".to_string()));
+        mock_bot
+            .expect_get_my_description()
+            .returning(|| Ok("Downloads media".to_string()));
+        mock_bot
+            .expect_get_my_commands()
+            .returning(|_| Ok(vec![BotCommand::new("start", "start interaction.")]));
+        mock_bot.expect_set_my_name().never();
+        mock_bot.expect_set_my_description().never();
+        mock_bot.expect_set_my_commands().never();
+
+        sync(&mock_bot, &desired()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_sets_changed_values() {
+        let mut mock_bot = MockBotProfileApi::new();
+        mock_bot
+            .expect_get_my_name()
+            .returning(|| Ok("Old name".to_string()));
+        mock_bot
+            .expect_set_my_name()
+            .withf(|name| name == "CrabberBot")
+            .times(1)
+            .returning(|_| Ok(()));
+        mock_bot
+            .expect_get_my_description()
+            .returning(|| Ok(String::new()));
+        mock_bot
+            .expect_set_my_description()
+            .withf(|description| description == "Downloads media")
+            .times(1)
+            .returning(|_| Ok(()));
+        mock_bot.expect_get_my_commands().returning(|_| Ok(vec![]));
+        mock_bot
+            .expect_set_my_commands()
+            .withf(|commands, scope| {
+                commands.len() == 1 && *scope == BotCommandScope::AllPrivateChats
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        sync(&mock_bot, &desired()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_survives_api_failures() {
+        let mut mock_bot = MockBotProfileApi::new();
+        mock_bot.expect_get_my_name().returning(|| Err(api_error()));
+        mock_bot
+            .expect_set_my_name()
+            .times(1)
+            .returning(|_| Err(api_error()));
+        mock_bot
+            .expect_get_my_description()
+            .returning(|| Err(api_error()));
+        mock_bot
+            .expect_set_my_description()
+            .times(1)
+            .returning(|_| Err(api_error()));
+        mock_bot
+            .expect_get_my_commands()
+            .returning(|_| Err(api_error()));
+        mock_bot
+            .expect_set_my_commands()
+            .times(1)
+            .returning(|_, _| Err(api_error()));
+
+        sync(&mock_bot, &desired()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_leaves_name_alone_when_not_desired() {
+        let mut mock_bot = MockBotProfileApi::new();
+        mock_bot.expect_get_my_name().never();
+        mock_bot.expect_set_my_name().never();
+        mock_bot
+            .expect_get_my_description()
+            .returning(|| Ok("Downloads media".to_string()));
+        mock_bot
+            .expect_get_my_commands()
+            .returning(|_| Ok(vec![BotCommand::new("start", "start interaction.")]));
+
+        sync(
+            &mock_bot,
+            &DesiredProfile {
+                name: None,
+                ..desired()
+            },
+        )
+        .await;
+    }
+
+    #[test]
+    fn test_bot_name_for_test_deployments() {
+        let test = Url::parse("https://crabberbot-test.example.com").unwrap();
+        let prod = Url::parse("https://crabberbot.example.com").unwrap();
+        assert_eq!(bot_name_for(&test), "CrabberBot TEST");
+        assert_eq!(bot_name_for(&prod), "CrabberBot | Video Downloader");
+    }
+}
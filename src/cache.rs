@@ -0,0 +1,180 @@
+//! Short-lived in-memory caches whose entries expire after a fixed time.
+//!
+//! Expired entries are never returned, but they stay in memory until `evict_expired`
+//! runs, which a background task does every `EVICTION_INTERVAL`.
+
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+
+/// How often the background task evicts expired entries.
+pub const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct MemoryCache<K: Eq + Hash, V> {
+    store: DashMap<K, (V, Instant)>,
+    ttl: Duration,
+}
+
+impl<K, V> MemoryCache<K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Entries expire `ttl` after they were inserted. With a zero `ttl` nothing is kept.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            store: DashMap::new(),
+            ttl,
+        }
+    }
+
+    fn is_fresh(&self, inserted_at: Instant, now: Instant) -> bool {
+        now.duration_since(inserted_at) < self.ttl
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Look up `key` as of `now`, for callers that track time themselves.
+    pub fn get_at(&self, key: &K, now: Instant) -> Option<V> {
+        let entry = self.store.get(key)?;
+        let (value, inserted_at) = entry.value();
+        self.is_fresh(*inserted_at, now).then(|| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_at(key, value, Instant::now());
+    }
+
+    /// Insert as of `now`, for callers that track time themselves.
+    pub fn insert_at(&self, key: K, value: V, now: Instant) {
+        self.store.insert(key, (value, now));
+    }
+
+    /// Insert as of `now` unless `key` has an entry that is still fresh. Returns whether
+    /// the value was inserted.
+    pub fn insert_if_absent_at(&self, key: K, value: V, now: Instant) -> bool {
+        match self.store.entry(key) {
+            Entry::Occupied(entry) if self.is_fresh(entry.get().1, now) => false,
+            Entry::Occupied(mut entry) => {
+                entry.insert((value, now));
+                true
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((value, now));
+                true
+            }
+        }
+    }
+
+    /// Remove `key` and return its value if it was still fresh at `now`.
+    pub fn remove_at(&self, key: &K, now: Instant) -> Option<V> {
+        let (_, (value, inserted_at)) = self.store.remove(key)?;
+        self.is_fresh(inserted_at, now).then_some(value)
+    }
+
+    /// Remove every entry whose key matches `predicate`.
+    pub fn remove_where(&self, predicate: impl Fn(&K) -> bool) {
+        self.store.retain(|key, _| !predicate(key));
+    }
+
+    pub fn evict_expired(&self) {
+        let now = Instant::now();
+        self.store
+            .retain(|_, (_, inserted_at)| self.is_fresh(*inserted_at, now));
+    }
+
+    /// Evict expired entries, then the oldest ones until at most `max_entries` remain.
+    /// Entries inserted at the same instant as the last one to go are dropped with it.
+    pub fn shrink_to(&self, max_entries: usize, now: Instant) {
+        self.store
+            .retain(|_, (_, inserted_at)| self.is_fresh(*inserted_at, now));
+        let excess = self.store.len().saturating_sub(max_entries);
+        if excess == 0 {
+            return;
+        }
+        let mut inserted: Vec<Instant> = self.store.iter().map(|entry| entry.value().1).collect();
+        inserted.sort_unstable();
+        let cutoff = inserted[excess - 1];
+        self.store
+            .retain(|_, (_, inserted_at)| *inserted_at > cutoff);
+    }
+
+    /// Number of entries, including expired ones not evicted yet.
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_get_ignores_expired_entries() {
+        let cache = MemoryCache::new(TTL);
+        cache.insert("fresh", 1);
+        cache.insert_at("stale", 2, Instant::now() - TTL);
+
+        assert_eq!(cache.get(&"fresh"), Some(1));
+        assert_eq!(cache.get(&"stale"), None);
+        assert_eq!(cache.get(&"missing"), None);
+    }
+
+    #[test]
+    fn test_evict_expired_removes_only_expired_entries() {
+        let cache = MemoryCache::new(TTL);
+        cache.insert("fresh", 1);
+        cache.insert_at("stale", 2, Instant::now() - TTL);
+        assert_eq!(cache.len(), 2);
+
+        cache.evict_expired();
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"fresh"), Some(1));
+    }
+
+    #[test]
+    fn test_insert_if_absent_replaces_expired_entries() {
+        let cache = MemoryCache::new(TTL);
+        let start = Instant::now();
+        assert!(cache.insert_if_absent_at("a", 1, start));
+        assert!(!cache.insert_if_absent_at("a", 2, start + Duration::from_secs(5)));
+        assert!(cache.insert_if_absent_at("a", 3, start + TTL));
+        assert_eq!(cache.remove_at(&"a", start + TTL), Some(3));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_shrink_to_drops_expired_then_oldest_entries() {
+        let cache = MemoryCache::new(TTL);
+        let start = Instant::now();
+        cache.insert_at("expired", 0, start - TTL);
+        for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            cache.insert_at(key, i, start + Duration::from_secs(i as u64));
+        }
+
+        cache.shrink_to(2, start + Duration::from_secs(5));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"c"), Some(2));
+        assert_eq!(cache.get(&"d"), Some(3));
+    }
+
+    #[test]
+    fn test_zero_ttl_keeps_nothing() {
+        let cache = MemoryCache::new(Duration::ZERO);
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
@@ -0,0 +1,245 @@
+//! Prefetches yt-dlp metadata for the most requested URLs at startup.
+//!
+//! URLs that are already in the media cache are answered without yt-dlp and are skipped.
+//! For the others, the first request after a restart finds its metadata waiting in
+//! `PrefetchingDownloader` instead of paying for a cold yt-dlp run.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{TimeDelta, Utc};
+use url::Url;
+
+use crate::cache::MemoryCache;
+use crate::downloader::{DownloadError, DownloadedMedia, Downloader, MediaInfo};
+use crate::storage::Storage;
+
+/// How many of the most requested URLs are warmed.
+const WARM_URL_COUNT: i64 = 10;
+/// How far back request history is considered.
+const WARM_HISTORY: TimeDelta = TimeDelta::days(7);
+/// Prefetched metadata older than this is refetched, since formats and sizes change.
+const PREFETCHED_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Downloader that answers the first metadata lookup of a URL from prefetched metadata.
+pub struct PrefetchingDownloader {
+    inner: Arc<dyn Downloader>,
+    prefetched: MemoryCache<String, MediaInfo>,
+}
+
+impl PrefetchingDownloader {
+    pub fn new(inner: Arc<dyn Downloader>) -> Self {
+        Self {
+            inner,
+            prefetched: MemoryCache::new(PREFETCHED_TTL),
+        }
+    }
+
+    fn take_prefetched(&self, url: &Url, now: Instant) -> Option<MediaInfo> {
+        self.prefetched.remove_at(&url.to_string(), now)
+    }
+
+    pub fn evict_expired(&self) {
+        self.prefetched.evict_expired();
+    }
+}
+
+#[async_trait]
+impl Downloader for PrefetchingDownloader {
+    async fn get_media_metadata(&self, url: &Url) -> Result<MediaInfo, DownloadError> {
+        if let Some(info) = self.take_prefetched(url, Instant::now()) {
+            log::info!("Using prefetched metadata for {}", url);
+            return Ok(info);
+        }
+        self.inner.get_media_metadata(url).await
+    }
+
+    async fn download_media(
+        &self,
+        info: &mut MediaInfo,
+        url: &Url,
+    ) -> Result<DownloadedMedia, DownloadError> {
+        self.inner.download_media(info, url).await
+    }
+
+    fn estimate_download_time(&self, info: &MediaInfo) -> Option<Duration> {
+        self.inner.estimate_download_time(info)
+    }
+
+    async fn list_formats(&self, url: &Url) -> Result<String, DownloadError> {
+        self.inner.list_formats(url).await
+    }
+
+    async fn download_thumbnail(
+        &self,
+        info: &MediaInfo,
+        url: &Url,
+    ) -> Result<PathBuf, DownloadError> {
+        self.inner.download_thumbnail(info, url).await
+    }
+
+    async fn download_single_entry(
+        &self,
+        entry: &MediaInfo,
+        parent_url: &Url,
+    ) -> Result<PathBuf, DownloadError> {
+        self.inner.download_single_entry(entry, parent_url).await
+    }
+}
+
+pub struct CacheWarmer {
+    storage: Arc<dyn Storage>,
+    downloader: Arc<PrefetchingDownloader>,
+    /// The bots whose media caches are checked; the prefetch is shared by all of them.
+    bot_ids: Vec<i64>,
+}
+
+impl CacheWarmer {
+    pub fn new(
+        storage: Arc<dyn Storage>,
+        downloader: Arc<PrefetchingDownloader>,
+        bot_ids: Vec<i64>,
+    ) -> Self {
+        Self {
+            storage,
+            downloader,
+            bot_ids,
+        }
+    }
+
+    async fn is_cached_by_every_bot(&self, source_url: &str) -> bool {
+        for &bot_id in &self.bot_ids {
+            if self
+                .storage
+                .get_cached_media(bot_id, source_url)
+                .await
+                .is_none()
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Prefetch metadata for popular URLs missing from a bot's media cache, one at a time
+    /// so startup doesn't compete with user requests. Returns how many were prefetched.
+    pub async fn run(&self) -> usize {
+        let urls = self
+            .storage
+            .get_top_urls(WARM_URL_COUNT, Utc::now() - WARM_HISTORY)
+            .await;
+        let mut warmed = 0;
+        for source_url in urls {
+            if self.is_cached_by_every_bot(&source_url).await {
+                continue;
+            }
+            let Ok(url) = Url::parse(&source_url) else {
+                log::warn!("Skipping unparseable popular URL: {}", source_url);
+                continue;
+            };
+            match self.downloader.inner.get_media_metadata(&url).await {
+                Ok(info) => {
+                    self.downloader.prefetched.insert(url.to_string(), info);
+                    warmed += 1;
+                }
+                Err(e) => log::warn!(
+                    "Failed to prefetch metadata for {}: {}",
+                    url,
+                    e.display_to_log()
+                ),
+            }
+        }
+        log::info!("Prefetched metadata for {} popular URL(s)", warmed);
+        warmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::MockDownloader;
+    use crate::storage::{CachedMedia, MockStorage};
+    use crate::test_utils::create_test_info;
+
+    fn cached_media() -> CachedMedia {
+        CachedMedia {
+            id: 1,
+            files: Vec::new(),
+            caption: String::new(),
+            audio_cache_path: None,
+            media_duration_secs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_prefetches_uncached_urls_once() {
+        let mut mock_storage = MockStorage::new();
+        mock_storage.expect_get_top_urls().returning(|_, _| {
+            vec![
+                "https://a.com/cached".to_string(),
+                "https://a.com/fails".to_string(),
+                "https://a.com/warm".to_string(),
+            ]
+        });
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, url| (url == "https://a.com/cached").then(cached_media));
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_get_media_metadata()
+            .withf(|url| url.as_str() == "https://a.com/fails")
+            .returning(|_| Err(DownloadError::CommandFailed("boom".to_string())));
+        mock_downloader
+            .expect_get_media_metadata()
+            .withf(|url| url.as_str() == "https://a.com/warm")
+            .times(2)
+            .returning(|_| Ok(create_test_info()));
+        let downloader = Arc::new(PrefetchingDownloader::new(Arc::new(mock_downloader)));
+
+        let warmer = CacheWarmer::new(Arc::new(mock_storage), downloader.clone(), vec![1]);
+        assert_eq!(warmer.run().await, 1);
+
+        // The first lookup is served from the prefetch, the second goes to yt-dlp again.
+        let url = Url::parse("https://a.com/warm").unwrap();
+        assert!(downloader.get_media_metadata(&url).await.is_ok());
+        assert!(downloader.prefetched.is_empty());
+        assert!(downloader.get_media_metadata(&url).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_run_prefetches_urls_missing_from_any_bot_cache() {
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_get_top_urls()
+            .returning(|_, _| vec!["https://a.com/warm".to_string()]);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|bot_id, _| (bot_id == 1).then(cached_media));
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(|_| Ok(create_test_info()));
+        let downloader = Arc::new(PrefetchingDownloader::new(Arc::new(mock_downloader)));
+
+        let warmer = CacheWarmer::new(Arc::new(mock_storage), downloader, vec![1, 2]);
+        assert_eq!(warmer.run().await, 1);
+    }
+
+    #[test]
+    fn test_stale_prefetch_is_ignored() {
+        let downloader = PrefetchingDownloader::new(Arc::new(MockDownloader::new()));
+        let url = Url::parse("https://a.com/warm").unwrap();
+        let fetched_at = Instant::now();
+        downloader
+            .prefetched
+            .insert_at(url.to_string(), create_test_info(), fetched_at);
+
+        assert_eq!(
+            downloader.take_prefetched(&url, fetched_at + PREFETCHED_TTL),
+            None
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Handling of URLs inside source descriptions, configured with `CAPTION_LINKS`.
+//!
+//! Descriptions often end with bare links ("full video: youtube.com/..."), which Telegram
+//! shows as dead text inside the caption blockquote. They can be kept as they are, turned
+//! into anchors, or removed.
+
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use url::Url;
+
+use crate::downloader::escape_html_text;
+use crate::handler::parse_link;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptionLinks {
+    #[default]
+    Keep,
+    Linkify,
+    Strip,
+}
+
+impl FromStr for CaptionLinks {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "linkify" => Ok(Self::Linkify),
+            "strip" => Ok(Self::Strip),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A piece of caption content. Plain text is escaped when rendered and may be cut when
+/// the caption is truncated; markup is kept whole or dropped, so a tag is never split.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CaptionSegment {
+    Text(String),
+    Html(String),
+}
+
+impl CaptionSegment {
+    pub(crate) fn render(&self) -> String {
+        match self {
+            Self::Text(text) => escape_html_text(text),
+            Self::Html(html) => html.clone(),
+        }
+    }
+}
+
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\S+").expect("valid regex"));
+
+/// Characters around a link that belong to the sentence, as in "(see example.com/x)."
+const LEADING_PUNCTUATION: &[char] = &['(', '[', '<', '"', '\''];
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\''];
+
+/// The web link a description token stands for, using the same parser as user messages.
+fn token_link(token: &str) -> Option<Url> {
+    parse_link(token).filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Split "(example.com/x)." into "(", "example.com/x" and ").".
+fn split_punctuation(token: &str) -> (&str, &str, &str) {
+    let core = token.trim_start_matches(LEADING_PUNCTUATION);
+    let lead = &token[..token.len() - core.len()];
+    let trimmed = core.trim_end_matches(TRAILING_PUNCTUATION);
+    (lead, trimmed, &core[trimmed.len()..])
+}
+
+/// Caption segments for a description with its links handled according to `mode`.
+pub(crate) fn description_segments(description: &str, mode: CaptionLinks) -> Vec<CaptionSegment> {
+    match mode {
+        CaptionLinks::Keep => vec![CaptionSegment::Text(description.to_string())],
+        CaptionLinks::Linkify => linkify(description),
+        CaptionLinks::Strip => vec![CaptionSegment::Text(strip_links(description))],
+    }
+}
+
+fn linkify(description: &str) -> Vec<CaptionSegment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut last = 0;
+    for token in TOKEN.find_iter(description) {
+        text.push_str(&description[last..token.start()]);
+        last = token.end();
+        let (lead, core, trail) = split_punctuation(token.as_str());
+        let Some(url) = token_link(core) else {
+            text.push_str(token.as_str());
+            continue;
+        };
+        text.push_str(lead);
+        segments.push(CaptionSegment::Text(std::mem::take(&mut text)));
+        segments.push(CaptionSegment::Html(format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html_text(url.as_str()).replace('"', "&quot;"),
+            escape_html_text(core)
+        )));
+        text.push_str(trail);
+    }
+    text.push_str(&description[last..]);
+    segments.push(CaptionSegment::Text(text));
+    segments.retain(|segment| *segment != CaptionSegment::Text(String::new()));
+    segments
+}
+
+fn strip_links(description: &str) -> String {
+    let mut text = String::new();
+    let mut last = 0;
+    let mut after_link = false;
+    for token in TOKEN.find_iter(description) {
+        let gap = &description[last..token.start()];
+        last = token.end();
+        let (lead, core, trail) = split_punctuation(token.as_str());
+        if token_link(core).is_some() {
+            // Drop the space before the link, so "full video: x.com/v" becomes "full video:".
+            text.push_str(gap.trim_end_matches([' ', '\t']));
+            text.push_str(lead);
+            text.push_str(trail);
+            after_link = lead.is_empty() && trail.is_empty();
+            continue;
+        }
+        if after_link && (text.is_empty() || text.ends_with('\n')) {
+            text.push_str(gap.trim_start_matches([' ', '\t']));
+        } else {
+            text.push_str(gap);
+        }
+        text.push_str(token.as_str());
+        after_link = false;
+    }
+    text.push_str(&description[last..]);
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(description: &str, mode: CaptionLinks) -> String {
+        description_segments(description, mode)
+            .iter()
+            .map(CaptionSegment::render)
+            .collect()
+    }
+
+    const DESCRIPTION: &str = "Full video: youtube.com/watch?v=abc&t=1 (mirror https://example.com/v).\nThanks, e.g. node.js/18";
+
+    #[test]
+    fn test_keep_leaves_links_as_text() {
+        assert_eq!(
+            rendered(DESCRIPTION, CaptionLinks::Keep),
+            escape_html_text(DESCRIPTION)
+        );
+    }
+
+    #[test]
+    fn test_linkify_wraps_links_in_anchors() {
+        assert_eq!(
+            rendered(DESCRIPTION, CaptionLinks::Linkify),
+            "Full video: <a href=\"https://youtube.com/watch?v=abc&amp;t=1\">youtube.com/watch?v=abc&amp;t=1</a> \
+             (mirror <a href=\"https://example.com/v\">https://example.com/v</a>).\nThanks, e.g. node.js/18"
+        );
+    }
+
+    #[test]
+    fn test_strip_removes_links() {
+        assert_eq!(
+            rendered(DESCRIPTION, CaptionLinks::Strip),
+            "Full video: (mirror).\nThanks, e.g. node.js/18"
+        );
+        assert_eq!(
+            rendered("https://a.com/x more text", CaptionLinks::Strip),
+            "more text"
+        );
+    }
+
+    #[test]
+    fn test_non_web_schemes_are_not_links() {
+        assert_eq!(
+            rendered("note: javascript:alert(1)", CaptionLinks::Linkify),
+            "note: javascript:alert(1)"
+        );
+    }
+
+    #[test]
+    fn test_parse_caption_links() {
+        assert_eq!("Linkify".parse(), Ok(CaptionLinks::Linkify));
+        assert_eq!("strip".parse(), Ok(CaptionLinks::Strip));
+        assert_eq!("keep".parse(), Ok(CaptionLinks::Keep));
+        assert_eq!("remove".parse::<CaptionLinks>(), Err(()));
+    }
+}
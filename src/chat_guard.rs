@@ -0,0 +1,455 @@
+//! Stops talking to a chat once Telegram says the bot can't reach it.
+//!
+//! When a user blocks the bot or a group removes it halfway through a request, every
+//! later message of that request fails the same way. `UnreachableChatGuard` wraps the API
+//! for one request and answers those messages with the original error without sending
+//! them.
+
+use std::future::Future;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+use teloxide::ApiError;
+use teloxide::types::{
+    ChatAction, ChatId, ChatMemberStatus, InlineKeyboardMarkup, InputMedia, MessageId,
+    ReactionType, ThreadId, UserId,
+};
+
+use crate::storage::CachedFile;
+use crate::telegram_api::{
+    MediaSource, SentMedia, StatusMessage, TelegramApi, is_chat_unreachable,
+};
+
+pub struct UnreachableChatGuard<'a> {
+    inner: GuardedApi<'a>,
+    /// Shared with the guards returned by `in_thread`.
+    unreachable: Arc<OnceLock<(ChatId, ApiError)>>,
+}
+
+/// The API a guard sends through: the one it was made for, or the topic's API it owns
+/// when it came from `in_thread`.
+enum GuardedApi<'a> {
+    Borrowed(&'a dyn TelegramApi),
+    Owned(Arc<dyn TelegramApi>),
+}
+
+impl<'a> Deref for GuardedApi<'a> {
+    type Target = dyn TelegramApi + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Borrowed(api) => *api,
+            Self::Owned(api) => api.as_ref(),
+        }
+    }
+}
+
+impl<'a> UnreachableChatGuard<'a> {
+    pub fn new(inner: &'a dyn TelegramApi) -> Self {
+        Self {
+            inner: GuardedApi::Borrowed(inner),
+            unreachable: Arc::default(),
+        }
+    }
+
+    /// Whether a request to `chat_id` failed because the bot can't reach it.
+    pub fn is_unreachable(&self, chat_id: ChatId) -> bool {
+        self.unreachable
+            .get()
+            .is_some_and(|(chat, _)| *chat == chat_id)
+    }
+
+    async fn guard<T>(
+        &self,
+        chat_id: ChatId,
+        request: impl Future<Output = Result<T, teloxide::RequestError>>,
+    ) -> Result<T, teloxide::RequestError> {
+        if let Some((chat, error)) = self.unreachable.get()
+            && *chat == chat_id
+        {
+            return Err(teloxide::RequestError::Api(error.clone()));
+        }
+        let result = request.await;
+        if let Err(error @ teloxide::RequestError::Api(api_error)) = &result
+            && is_chat_unreachable(error)
+            && self.unreachable.set((chat_id, api_error.clone())).is_ok()
+        {
+            log::warn!(
+                "Chat {} is unreachable ({}), not sending it anything else for this request",
+                chat_id,
+                api_error
+            );
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TelegramApi for UnreachableChatGuard<'_> {
+    async fn send_video(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+        thumbnail_filepath: Option<PathBuf>,
+        duration: Option<u32>,
+        width: Option<u32>,
+        height: Option<u32>,
+        reply_markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(String, MessageId), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.send_video(
+                chat_id,
+                message_id,
+                media,
+                caption,
+                thumbnail_filepath,
+                duration,
+                width,
+                height,
+                reply_markup,
+            ),
+        )
+        .await
+    }
+
+    async fn send_photo(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+        reply_markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(String, MessageId), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .send_photo(chat_id, message_id, media, caption, reply_markup),
+        )
+        .await
+    }
+
+    async fn send_document(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+    ) -> Result<MessageId, teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .send_document(chat_id, message_id, media, caption),
+        )
+        .await
+    }
+
+    async fn edit_message_reply_markup(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .edit_message_reply_markup(chat_id, message_id, keyboard),
+        )
+        .await
+    }
+
+    async fn send_text_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        message: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.send_text_message(chat_id, message_id, message),
+        )
+        .await
+    }
+
+    async fn send_status_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &str,
+    ) -> Result<StatusMessage, teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.send_status_message(chat_id, message_id, text),
+        )
+        .await
+    }
+
+    async fn edit_message_text(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.edit_message_text(chat_id, message_id, text),
+        )
+        .await
+    }
+
+    async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(chat_id, self.inner.delete_message(chat_id, message_id))
+            .await
+    }
+
+    async fn send_media_group(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        media: Vec<InputMedia>,
+    ) -> Result<Vec<SentMedia>, teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.send_media_group(chat_id, message_id, media),
+        )
+        .await
+    }
+
+    async fn send_chat_action(
+        &self,
+        chat_id: ChatId,
+        action: ChatAction,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(chat_id, self.inner.send_chat_action(chat_id, action))
+            .await
+    }
+
+    async fn set_message_reaction(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        reaction: Option<ReactionType>,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .set_message_reaction(chat_id, message_id, reaction),
+        )
+        .await
+    }
+
+    async fn send_cached_media_group(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        files: &[CachedFile],
+        caption: &str,
+    ) -> Result<Vec<MessageId>, teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .send_cached_media_group(chat_id, message_id, files, caption),
+        )
+        .await
+    }
+
+    async fn send_audio(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        file_path: &Path,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.send_audio(chat_id, message_id, file_path),
+        )
+        .await
+    }
+
+    async fn send_video_note(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        file_path: &Path,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner.send_video_note(chat_id, message_id, file_path),
+        )
+        .await
+    }
+
+    async fn send_invoice(
+        &self,
+        chat_id: ChatId,
+        title: &str,
+        description: &str,
+        payload: &str,
+        price_amount: u32,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .send_invoice(chat_id, title, description, payload, price_amount),
+        )
+        .await
+    }
+
+    async fn get_chat_member(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> Result<ChatMemberStatus, teloxide::RequestError> {
+        self.guard(chat_id, self.inner.get_chat_member(chat_id, user_id))
+            .await
+    }
+
+    async fn answer_callback_query(
+        &self,
+        callback_query_id: &str,
+        text: Option<String>,
+    ) -> Result<(), teloxide::RequestError> {
+        self.inner
+            .answer_callback_query(callback_query_id, text)
+            .await
+    }
+
+    async fn answer_pre_checkout_query(
+        &self,
+        pre_checkout_query_id: &str,
+        ok: bool,
+        error_message: Option<String>,
+    ) -> Result<(), teloxide::RequestError> {
+        self.inner
+            .answer_pre_checkout_query(pre_checkout_query_id, ok, error_message)
+            .await
+    }
+
+    async fn send_text_with_keyboard(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &str,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .send_text_with_keyboard(chat_id, message_id, text, keyboard),
+        )
+        .await
+    }
+
+    async fn send_text_no_reply(
+        &self,
+        chat_id: ChatId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(chat_id, self.inner.send_text_no_reply(chat_id, text))
+            .await
+    }
+
+    async fn reply_to_sent_message(
+        &self,
+        chat_id: ChatId,
+        sent_message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.guard(
+            chat_id,
+            self.inner
+                .reply_to_sent_message(chat_id, sent_message_id, text),
+        )
+        .await
+    }
+
+    async fn refund_star_payment(
+        &self,
+        user_id: i64,
+        telegram_payment_charge_id: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.inner
+            .refund_star_payment(user_id, telegram_payment_charge_id)
+            .await
+    }
+
+    /// The wrapped API in the topic, guarded together with this one: a chat found
+    /// unreachable through either is skipped by both.
+    fn in_thread(&self, chat_id: ChatId, thread_id: Option<ThreadId>) -> Arc<dyn TelegramApi> {
+        Arc::new(UnreachableChatGuard {
+            inner: GuardedApi::Owned(self.inner.in_thread(chat_id, thread_id)),
+            unreachable: self.unreachable.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telegram_api::MockTelegramApi;
+    use mockall::predicate::*;
+
+    #[tokio::test]
+    async fn test_stops_sending_to_unreachable_chat_only() {
+        let mut api = MockTelegramApi::new();
+        api.expect_send_text_message()
+            .with(eq(ChatId(1)), always(), always())
+            .times(1)
+            .returning(|_, _, _| Err(teloxide::RequestError::Api(ApiError::BotBlocked)));
+        api.expect_send_text_no_reply()
+            .with(eq(ChatId(2)), always())
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let guard = UnreachableChatGuard::new(&api);
+
+        assert!(!guard.is_unreachable(ChatId(1)));
+        for _ in 0..2 {
+            assert!(matches!(
+                guard.send_text_message(ChatId(1), MessageId(1), "hi").await,
+                Err(teloxide::RequestError::Api(ApiError::BotBlocked))
+            ));
+        }
+        assert!(guard.is_unreachable(ChatId(1)));
+        assert!(!guard.is_unreachable(ChatId(2)));
+        guard.send_text_no_reply(ChatId(2), "owner").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_topic_api_shares_the_guard() {
+        let mut topic_api = MockTelegramApi::new();
+        topic_api
+            .expect_send_text_message()
+            .times(1)
+            .returning(|_, _, _| Err(teloxide::RequestError::Api(ApiError::BotKicked)));
+        let topic_api: Arc<dyn TelegramApi> = Arc::new(topic_api);
+        let mut api = MockTelegramApi::new();
+        api.expect_in_thread()
+            .times(1)
+            .return_once(move |_, _| topic_api);
+        api.expect_send_text_message().never();
+        let guard = UnreachableChatGuard::new(&api);
+
+        let in_topic = guard.in_thread(ChatId(1), Some(ThreadId(MessageId(5))));
+        for _ in 0..2 {
+            assert!(
+                in_topic
+                    .send_text_message(ChatId(1), MessageId(1), "hi")
+                    .await
+                    .is_err()
+            );
+        }
+        assert!(guard.is_unreachable(ChatId(1)));
+        assert!(matches!(
+            guard.send_text_message(ChatId(1), MessageId(1), "hi").await,
+            Err(teloxide::RequestError::Api(ApiError::BotKicked))
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+//! Registry of running yt-dlp processes, so shutdown can terminate them instead of leaving
+//! them downloading as orphans.
+//!
+//! A request that is cancelled (e.g. by the request timeout) drops its `Child`, which
+//! `kill_on_drop` turns into a SIGKILL; the registry covers the case where the bot exits
+//! while requests are still in flight.
+
+use std::collections::HashSet;
+use std::process::{Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::process::Command;
+
+/// Time between SIGTERM and SIGKILL when terminating children on shutdown.
+pub const CHILD_TERMINATION_GRACE: Duration = Duration::from_secs(5);
+
+const TERMINATION_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Default)]
+pub struct ChildProcesses {
+    pids: Arc<Mutex<HashSet<u32>>>,
+}
+
+/// Removes a PID from the registry once its process has been reaped or dropped.
+struct RegisteredChild {
+    pids: Arc<Mutex<HashSet<u32>>>,
+    pid: Option<u32>,
+}
+
+impl Drop for RegisteredChild {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            self.pids
+                .lock()
+                .expect("child process registry lock poisoned")
+                .remove(&pid);
+        }
+    }
+}
+
+impl ChildProcesses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `Command::output`, but the child is registered while it runs.
+    pub async fn output(&self, command: &mut Command) -> std::io::Result<Output> {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let child = command.spawn()?;
+        let _registered = self.register(child.id());
+        child.wait_with_output().await
+    }
+
+    fn register(&self, pid: Option<u32>) -> RegisteredChild {
+        if let Some(pid) = pid {
+            self.pids
+                .lock()
+                .expect("child process registry lock poisoned")
+                .insert(pid);
+        }
+        RegisteredChild {
+            pids: self.pids.clone(),
+            pid,
+        }
+    }
+
+    pub fn active_children(&self) -> usize {
+        self.pids
+            .lock()
+            .expect("child process registry lock poisoned")
+            .len()
+    }
+
+    /// Send `signal` to every registered child and return how many there were.
+    fn signal_all(&self, signal: libc::c_int) -> usize {
+        let pids = self
+            .pids
+            .lock()
+            .expect("child process registry lock poisoned");
+        for &pid in pids.iter() {
+            // SAFETY: kill(2) has no memory-safety preconditions.
+            if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+                log::warn!(
+                    "Failed to signal child process {}: {}",
+                    pid,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        pids.len()
+    }
+
+    /// SIGTERM every running child, then SIGKILL whatever is still running after `grace`.
+    pub async fn terminate_all(&self, grace: Duration) {
+        let terminated = self.signal_all(libc::SIGTERM);
+        if terminated == 0 {
+            return;
+        }
+        log::info!("Sent SIGTERM to {} yt-dlp process(es)", terminated);
+
+        let deadline = Instant::now() + grace;
+        while self.active_children() > 0 && Instant::now() < deadline {
+            tokio::time::sleep(TERMINATION_POLL_INTERVAL).await;
+        }
+        let killed = self.signal_all(libc::SIGKILL);
+        if killed > 0 {
+            log::warn!(
+                "Sent SIGKILL to {} yt-dlp process(es) still running after {:?}",
+                killed,
+                grace
+            );
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Bot commands and the Telegram command menus they are registered in.
+//!
+//! Private chats see every user command, groups only the few that make sense there,
+//! and the owner's chat additionally sees the owner commands.
+
+use teloxide::types::{BotCommand, BotCommandScope, ChatId, Recipient};
+use teloxide::utils::command::BotCommands;
+
+#[derive(BotCommands, Clone, Debug, PartialEq)]
+#[command(
+    rename_rule = "lowercase",
+    description = "These commands are supported:"
+)]
+pub enum Command {
+    #[command(description = "start interaction and display a guide.")]
+    Start,
+    #[command(description = "display the guide.")]
+    Help,
+    #[command(description = "download media from a link, e.g. in groups.")]
+    Dl(String),
+    #[command(description = "turn a video of up to a minute into a round video.")]
+    Roundify(String),
+    #[command(description = "send only the thumbnail or cover image of a link.")]
+    Thumb(String),
+    #[command(description = "show bot version.")]
+    Version,
+    #[command(description = "show the version, limits and configuration.")]
+    About,
+    #[command(description = "show bot environment.")]
+    Environment,
+    #[command(description = "subscribe or buy AI Video Minutes top-up.")]
+    Subscribe,
+    #[command(description = "view Terms of Service.")]
+    Terms,
+    #[command(description = "contact customer support or get help with a payment issue.")]
+    Support(String),
+    #[command(description = "send feedback or report a broken link.")]
+    Feedback(String),
+    #[command(description = "request a refund for your most recent purchase.")]
+    Refundme,
+    #[command(description = "show today's downloads and remaining quota.")]
+    Quota,
+    #[command(description = "show the source link as a button instead of in the caption (on/off).")]
+    Sourcebutton(String),
+}
+
+/// Owner-only commands, handled in a separate dptree branch that pre-filters on
+/// owner chat_id. They only appear in the owner's own command menu.
+#[derive(BotCommands, Clone, Debug, PartialEq)]
+#[command(rename_rule = "lowercase")]
+pub enum OwnerCommand {
+    #[command(description = "grant a subscription or top-up minutes.")]
+    Grant(String),
+    #[command(description = "reply to a support request.")]
+    Reply(String),
+    #[command(description = "refund a payment and revoke access.")]
+    Refund(String),
+    #[command(description = "search the media cache.")]
+    Findcached(String),
+    #[command(description = "show cache and request statistics.")]
+    Stats,
+    #[command(description = "show the requests in progress.")]
+    Queue,
+    #[command(description = "show what yt-dlp reports for a link.")]
+    Info(String),
+    #[command(description = "show which link and cache entry a sent message came from.")]
+    Trace(String),
+    #[command(description = "refuse new downloads: on [message] or off.")]
+    Maintenance(String),
+}
+
+/// Commands listed in group chats, where the rest of the menu is clutter.
+const GROUP_COMMANDS: [&str; 2] = ["dl", "help"];
+
+/// Menu for private chats: every user command.
+pub fn private_chat_commands() -> Vec<BotCommand> {
+    Command::bot_commands()
+}
+
+/// Menu for group chats.
+pub fn group_chat_commands() -> Vec<BotCommand> {
+    Command::bot_commands()
+        .into_iter()
+        .filter(|command| {
+            GROUP_COMMANDS
+                .iter()
+                .any(|name| command.command.trim_start_matches('/') == *name)
+        })
+        .collect()
+}
+
+/// Menu for the owner's chat. A chat scope replaces the private-chat menu there, so it
+/// repeats the user commands before the owner commands.
+pub fn owner_chat_commands() -> Vec<BotCommand> {
+    let mut commands = private_chat_commands();
+    commands.extend(OwnerCommand::bot_commands());
+    commands
+}
+
+/// Menus per scope: private chats, group chats and, if configured, the owner's chat.
+pub fn command_menus(owner_chat_id: i64) -> Vec<(BotCommandScope, Vec<BotCommand>)> {
+    let mut menus = vec![
+        (BotCommandScope::AllPrivateChats, private_chat_commands()),
+        (BotCommandScope::AllGroupChats, group_chat_commands()),
+    ];
+    if owner_chat_id != 0 {
+        menus.push((
+            BotCommandScope::Chat {
+                chat_id: Recipient::Id(ChatId(owner_chat_id)),
+            },
+            owner_chat_commands(),
+        ));
+    }
+    menus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(commands: &[BotCommand]) -> Vec<&str> {
+        commands
+            .iter()
+            .map(|c| c.command.trim_start_matches('/'))
+            .collect()
+    }
+
+    #[test]
+    fn test_private_menu_lists_every_user_command() {
+        assert_eq!(
+            names(&private_chat_commands()),
+            [
+                "start",
+                "help",
+                "dl",
+                "roundify",
+                "thumb",
+                "version",
+                "about",
+                "environment",
+                "subscribe",
+                "terms",
+                "support",
+                "feedback",
+                "refundme",
+                "quota",
+                "sourcebutton"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_menu_is_minimal() {
+        assert_eq!(names(&group_chat_commands()), ["help", "dl"]);
+    }
+
+    #[test]
+    fn test_owner_menu_adds_owner_commands() {
+        let owner = owner_chat_commands();
+        let owner_names = names(&owner);
+        assert!(owner_names.starts_with(&names(&private_chat_commands())));
+        assert!(owner_names.ends_with(&[
+            "grant",
+            "reply",
+            "refund",
+            "findcached",
+            "stats",
+            "queue",
+            "info",
+            "trace",
+            "maintenance"
+        ]));
+        assert!(owner.iter().all(|c| !c.description.is_empty()));
+    }
+
+    #[test]
+    fn test_command_menus_scopes() {
+        let menus = command_menus(999);
+        assert_eq!(
+            menus,
+            vec![
+                (BotCommandScope::AllPrivateChats, private_chat_commands()),
+                (BotCommandScope::AllGroupChats, group_chat_commands()),
+                (
+                    BotCommandScope::Chat {
+                        chat_id: Recipient::Id(ChatId(999)),
+                    },
+                    owner_chat_commands(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_menus_without_owner() {
+        let scopes: Vec<BotCommandScope> = command_menus(0)
+            .into_iter()
+            .map(|(scope, _)| scope)
+            .collect();
+        assert_eq!(
+            scopes,
+            [
+                BotCommandScope::AllPrivateChats,
+                BotCommandScope::AllGroupChats
+            ]
+        );
+    }
+}
@@ -1,24 +1,35 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use teloxide::prelude::*;
-use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, MessageKind};
+use teloxide::types::{
+    ChatAction, ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, MessageKind,
+};
 
-use crate::concurrency::ConcurrencyLimiter;
-use crate::handler::{CallbackContext, send_long_text};
+use crate::child_processes::ChildProcesses;
+use crate::concurrency::{ActiveRequest, BotChat, ConcurrencyLimiter};
+use crate::downloader::{CaptionOptions, Downloader, MediaInfo, escape_html_text};
+use crate::handler::{CallbackContext, parse_link, send_long_text};
+use crate::maintenance::{Maintenance, MaintenanceState};
+use crate::object_store::format_file_size;
 use crate::premium::summarizer::{GeminiResult, Summarizer};
 use crate::premium::transcriber::{DeepgramUsage, Transcriber};
 use crate::premium::{
     GEMINI_INPUT_COST_PER_MILLION_TOKENS, GEMINI_OUTPUT_COST_PER_MILLION_TOKENS,
     MAX_PREMIUM_FILE_DURATION_SECS,
 };
-use crate::storage::Storage;
+use crate::rate_limiter::RateLimiter;
+use crate::storage::{ActivityReport, CacheStats, Delivery, Storage};
+use crate::storage_metrics::{StorageCounters, StorageMetricsSnapshot};
 use crate::subscription::{
     PRODUCT_SUB_BASIC, PRODUCT_SUB_PRO, PRODUCT_TOPUP_60, SubscriptionTier, TOPUP_PRICE_STARS,
     TOPUP_SECONDS,
 };
 use crate::telegram_api::TelegramApi;
 use crate::terms;
+use crate::uploads::{PendingUploads, UploadSnapshot};
+use crate::url_cleanup::display_host;
 
 async fn log_telegram_failure<T>(
     result: Result<T, teloxide::RequestError>,
@@ -39,11 +50,20 @@ async fn log_telegram_failure<T>(
     }
 }
 
+/// Answer to purchases while storage is disabled (`STORAGE_URL=none://`) or only kept in
+/// memory.
+pub const PAYMENTS_DISABLED_TEXT: &str = "Purchases are unavailable on this bot: it keeps no lasting records, so they couldn't be credited.";
+
 pub async fn handle_subscribe(
     api: Arc<dyn TelegramApi>,
     message: Message,
     storage: Arc<dyn Storage>,
 ) -> ResponseResult<()> {
+    if !storage.records_payments() {
+        api.send_text_message(message.chat.id, message.id, PAYMENTS_DISABLED_TEXT)
+            .await?;
+        return Ok(());
+    }
     let user_id = message
         .from
         .as_ref()
@@ -117,7 +137,7 @@ pub async fn handle_grant(
     }
 
     const USAGE: &str = "Usage:\n/grant [user_id] &lt;tier&gt; [days]  (tier: basic, pro, ultra, free)\n/grant [user_id] topup &lt;minutes&gt;";
-    let parts: Vec<&str> = args.trim().split_whitespace().collect();
+    let parts: Vec<&str> = args.split_whitespace().collect();
     let self_uid = || {
         message
             .from
@@ -336,6 +356,72 @@ Note: <b>Telegram support and BotFather cannot help with purchases made through
     Ok(())
 }
 
+pub const FEEDBACK_SENT_MESSAGE: &str = "✅ Feedback sent! Thank you.";
+pub const FEEDBACK_RATE_LIMITED_MESSAGE: &str = "❌ You can only send feedback once per hour.";
+pub const FEEDBACK_FAILED_MESSAGE: &str =
+    "❌ Sorry, your feedback could not be delivered. Please try again later.";
+
+/// `/feedback <text>`: relay the text to the owner, identifying the sender only by chat id.
+/// The hourly limit is per user and only counts feedback that reached the owner.
+pub async fn handle_feedback(
+    api: Arc<dyn TelegramApi>,
+    rate_limiter: Arc<RateLimiter>,
+    message: Message,
+    text: String,
+    owner_chat_id: i64,
+) -> ResponseResult<()> {
+    let chat_id = message.chat.id;
+    let text = text.trim();
+    if text.is_empty() {
+        api.send_text_message(
+            chat_id,
+            message.id,
+            "Please write your feedback after the command, for example:\n\
+             <code>/feedback Links from example.com stopped working</code>",
+        )
+        .await?;
+        return Ok(());
+    }
+    let user_id = match message.from.as_ref() {
+        Some(user) if owner_chat_id != 0 => user.id,
+        _ => {
+            api.send_text_message(
+                chat_id,
+                message.id,
+                "Sorry, feedback is not available right now.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if !rate_limiter.feedback_allowed(user_id) {
+        log::info!("Rate-limited feedback from user_id: {}", user_id);
+        api.send_text_message(chat_id, message.id, FEEDBACK_RATE_LIMITED_MESSAGE)
+            .await?;
+        return Ok(());
+    }
+
+    let relay = format!(
+        "[Feedback] from chat_id: <code>{chat_id}</code>\n\n{}",
+        escape_html_text(text)
+    );
+    let relayed = log_telegram_failure(
+        api.send_text_no_reply(ChatId(owner_chat_id), &relay).await,
+        ChatId(owner_chat_id),
+        "feedback_relay",
+    )
+    .await
+    .is_some();
+    let reply = if relayed {
+        rate_limiter.record_feedback(user_id);
+        FEEDBACK_SENT_MESSAGE
+    } else {
+        FEEDBACK_FAILED_MESSAGE
+    };
+    api.send_text_message(chat_id, message.id, reply).await?;
+    Ok(())
+}
+
 pub async fn handle_reply(
     api: Arc<dyn TelegramApi>,
     message: Message,
@@ -577,6 +663,475 @@ pub async fn handle_refund(
     Ok(())
 }
 
+/// Owner-only: `/findcached <text>` lists cached entries whose uploader, title,
+/// extractor, caption or URL contains the given text.
+pub async fn handle_findcached(
+    api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
+    message: Message,
+    args: String,
+    owner_chat_id: i64,
+) -> ResponseResult<()> {
+    if message.chat.id.0 != owner_chat_id {
+        return Ok(());
+    }
+    let query = args.trim();
+    if query.is_empty() {
+        api.send_text_message(
+            message.chat.id,
+            message.id,
+            "Usage: /findcached &lt;text&gt;",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    api.send_chat_action(message.chat.id, ChatAction::Typing)
+        .await?;
+    let results = storage.search_cache(query, 10).await;
+    if results.is_empty() {
+        api.send_text_message(
+            message.chat.id,
+            message.id,
+            &format!("No cached media matches \"{}\".", escape_html_text(query)),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut lines = format!("Cached media matching \"{}\":\n", escape_html_text(query));
+    for r in &results {
+        let label = [
+            r.metadata.extractor.as_deref(),
+            r.metadata.uploader.as_deref(),
+            r.metadata.title.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(escape_html_text)
+        .collect::<Vec<_>>()
+        .join(" · ");
+        lines.push_str(&format!("\n{} {}", escape_html_text(&r.source_url), label));
+    }
+    api.send_text_message(message.chat.id, message.id, &lines)
+        .await?;
+    Ok(())
+}
+
+/// `<chat_id> <message_id>`, as taken by `/trace`.
+fn parse_trace_args(args: &str) -> Option<(i64, i32)> {
+    let mut parts = args.split_whitespace();
+    let chat_id = parts.next()?.parse().ok()?;
+    let message_id = parts.next()?.parse().ok()?;
+    parts.next().is_none().then_some((chat_id, message_id))
+}
+
+fn format_delivery(delivery: &Delivery) -> String {
+    let timestamp = |at: &chrono::DateTime<chrono::Utc>| at.format("%Y-%m-%d %H:%M:%S UTC");
+    let cache = match (delivery.cache_id, delivery.cached_at) {
+        (None, _) => "none (fresh download)".to_string(),
+        (Some(id), Some(cached_at)) => format!("{id}, stored {}", timestamp(&cached_at)),
+        (Some(id), None) => format!("{id} (expired)"),
+    };
+    format!(
+        "<b>Source:</b> {}\n<b>Cache entry:</b> {}\n<b>Delivered:</b> {}",
+        escape_html_text(&delivery.source_url),
+        cache,
+        timestamp(&delivery.delivered_at)
+    )
+}
+
+/// Owner-only: `/trace <chat_id> <message_id>` shows which link and cache entry a
+/// message the bot sent was delivered from.
+pub async fn handle_trace(
+    api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
+    message: Message,
+    args: String,
+    owner_chat_id: i64,
+) -> ResponseResult<()> {
+    if message.chat.id.0 != owner_chat_id {
+        return Ok(());
+    }
+    let text = match parse_trace_args(&args) {
+        None => "Usage: /trace &lt;chat_id&gt; &lt;message_id&gt;".to_string(),
+        Some((chat_id, message_id)) => match storage.get_delivery(chat_id, message_id).await {
+            Some(delivery) => format_delivery(&delivery),
+            None => format!("No delivery recorded for message {message_id} in chat {chat_id}."),
+        },
+    };
+    api.send_text_message(message.chat.id, message.id, &text)
+        .await?;
+    Ok(())
+}
+
+fn format_maintenance(state: &MaintenanceState) -> String {
+    match state.refusal() {
+        Some(refusal) => format!(
+            "Maintenance mode is <b>on</b>. New requests get:\n{}",
+            escape_html_text(refusal)
+        ),
+        None => "Maintenance mode is <b>off</b>.".to_string(),
+    }
+}
+
+/// Owner-only: `/maintenance on [message]` refuses new downloads with `message`, or a
+/// default notice, until `/maintenance off`. Without arguments it shows the current mode.
+pub async fn handle_maintenance(
+    api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
+    maintenance: Arc<Maintenance>,
+    message: Message,
+    args: String,
+    owner_chat_id: i64,
+) -> ResponseResult<()> {
+    if message.chat.id.0 != owner_chat_id {
+        return Ok(());
+    }
+    let text = if args.trim().is_empty() {
+        format_maintenance(&maintenance.state())
+    } else {
+        match MaintenanceState::parse(&args) {
+            Some(state) => {
+                log::info!("Owner set maintenance mode: {:?}", state);
+                let text = format_maintenance(&state);
+                maintenance.set(state, storage.as_ref()).await;
+                text
+            }
+            None => "Usage: /maintenance on [message] | off".to_string(),
+        }
+    };
+    api.send_text_message(message.chat.id, message.id, &text)
+        .await?;
+    Ok(())
+}
+
+/// Trailing windows summarised by `/stats`, with their labels.
+const STATS_WINDOWS: [(&str, Duration); 2] = [
+    ("Last 24h", Duration::from_secs(24 * 60 * 60)),
+    ("Last 7d", Duration::from_secs(7 * 24 * 60 * 60)),
+];
+
+/// Longest domain name shown in `/stats`, so the report stays well under Telegram's
+/// 4096-character message limit.
+const STATS_DOMAIN_MAX_CHARS: usize = 48;
+
+fn format_processing_time(ms: Option<f64>) -> String {
+    match ms {
+        Some(ms) if ms < 1000.0 => format!("{ms:.0} ms"),
+        Some(ms) => format!("{:.1} s", ms / 1000.0),
+        None => "n/a".to_string(),
+    }
+}
+
+/// Render one activity window as a Telegram HTML section.
+fn format_activity_report(label: &str, report: &ActivityReport) -> String {
+    let mut text = format!(
+        "<b>{label}</b>\nRequests: {} (cache hits: {}, fresh downloads: {})\n\
+         Processing time: median {}, p95 {}\nData transferred: {}\n",
+        report.total_requests,
+        report.cache_hits,
+        report.fresh_downloads,
+        format_processing_time(report.median_processing_ms),
+        format_processing_time(report.p95_processing_ms),
+        format_file_size(report.bytes_transferred.max(0) as u64),
+    );
+    if report.top_failing_domains.is_empty() {
+        text.push_str("Failing domains: none");
+    } else {
+        text.push_str("Top failing domains:");
+        for entry in &report.top_failing_domains {
+            text.push_str(&format!(
+                "\n• {}: {}",
+                format_stats_domain(&entry.domain),
+                entry.failures
+            ));
+        }
+    }
+    if !report.top_traffic_domains.is_empty() {
+        text.push_str("\nMost traffic:");
+        for entry in &report.top_traffic_domains {
+            text.push_str(&format!(
+                "\n• {}: {}",
+                format_stats_domain(&entry.domain),
+                format_file_size(entry.bytes.max(0) as u64)
+            ));
+        }
+    }
+    if !report.top_geo_blocked_domains.is_empty() {
+        text.push_str("\nGeo-blocked:");
+        for entry in &report.top_geo_blocked_domains {
+            text.push_str(&format!(
+                "\n• {}: {}",
+                format_stats_domain(&entry.domain),
+                entry.failures
+            ));
+        }
+    }
+    text
+}
+
+/// A host for the `/stats` domain lists: Unicode and escaped, shortened to
+/// `STATS_DOMAIN_MAX_CHARS` without splitting an escape.
+fn format_stats_domain(domain: &str) -> String {
+    let mut text = String::new();
+    let mut len = 0;
+    for c in display_host(domain).chars() {
+        let escaped = escape_html_text(c.encode_utf8(&mut [0; 4]));
+        len += escaped.chars().count();
+        if len > STATS_DOMAIN_MAX_CHARS {
+            break;
+        }
+        text.push_str(&escaped);
+    }
+    text
+}
+
+/// Render the Telegram upload section of `/stats`.
+fn format_uploads(uploads: &UploadSnapshot) -> String {
+    format!(
+        "<b>Uploads</b>\nIn progress: {} (oldest: {})\nRecent average: {}",
+        uploads.active,
+        format_processing_time(uploads.oldest_age.map(|age| age.as_secs_f64() * 1000.0)),
+        format_processing_time(uploads.completed_avg_ms),
+    )
+}
+
+/// Render the storage section of `/stats`.
+fn format_storage_metrics(metrics: &StorageMetricsSnapshot) -> String {
+    let mut text = format!(
+        "<b>Storage</b>\nCache lookups: {} hits, {} misses\nCache writes: {}",
+        metrics.cache_hits, metrics.cache_misses, metrics.cache_stores
+    );
+    if !metrics.slowest_operations.is_empty() {
+        text.push_str("\nSlowest operations:");
+        for (operation, timings) in &metrics.slowest_operations {
+            text.push_str(&format!(
+                "\n• {}: mean {}, max {} ({} calls)",
+                operation,
+                format_processing_time(Some(timings.mean().as_secs_f64() * 1000.0)),
+                format_processing_time(Some(timings.max.as_secs_f64() * 1000.0)),
+                timings.calls
+            ));
+        }
+    }
+    text
+}
+
+/// Render the full `/stats` reply.
+fn format_stats(
+    cache: &CacheStats,
+    reports: &[(&str, ActivityReport)],
+    uploads: &UploadSnapshot,
+    storage_metrics: &StorageMetricsSnapshot,
+    yt_dlp_processes: usize,
+) -> String {
+    let mut sections = vec![format!(
+        "<b>Media cache</b>\nEntries: {}\nCached files: {}",
+        cache.entries, cache.files
+    )];
+    sections.extend(
+        reports
+            .iter()
+            .map(|(label, report)| format_activity_report(label, report)),
+    );
+    sections.push(format_uploads(uploads));
+    sections.push(format_storage_metrics(storage_metrics));
+    sections.push(format!(
+        "<b>yt-dlp</b>\nRunning processes: {}",
+        yt_dlp_processes
+    ));
+    sections.join("\n\n")
+}
+
+/// Owner-only: `/stats` reports cache size, request activity for the last day and week,
+/// Telegram upload timings, storage timings and the number of running yt-dlp processes.
+pub async fn handle_stats(
+    api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
+    pending_uploads: Arc<PendingUploads>,
+    storage_metrics: Arc<StorageCounters>,
+    child_processes: ChildProcesses,
+    message: Message,
+    owner_chat_id: i64,
+) -> ResponseResult<()> {
+    if message.chat.id.0 != owner_chat_id {
+        return Ok(());
+    }
+    api.send_chat_action(message.chat.id, ChatAction::Typing)
+        .await?;
+    let cache = storage.cache_stats().await;
+    let mut reports = Vec::with_capacity(STATS_WINDOWS.len());
+    for (label, window) in STATS_WINDOWS {
+        reports.push((label, storage.activity_report(window).await));
+    }
+    let text = format_stats(
+        &cache,
+        &reports,
+        &pending_uploads.snapshot(),
+        &storage_metrics.snapshot(),
+        child_processes.active_children(),
+    );
+    api.send_text_message(message.chat.id, message.id, &text)
+        .await?;
+    Ok(())
+}
+
+/// Requests listed by `/queue`, longest-running first, so it fits in one message.
+const QUEUE_MAX_REQUESTS: usize = 20;
+
+/// Render the `/queue` reply.
+fn format_queue(
+    requests: &[(BotChat, ActiveRequest)],
+    now: Instant,
+    yt_dlp_processes: usize,
+) -> String {
+    let mut text = format!(
+        "<b>Requests in progress:</b> {}\n<b>yt-dlp processes:</b> {}",
+        requests.len(),
+        yt_dlp_processes
+    );
+    let mut requests: Vec<&(BotChat, ActiveRequest)> = requests.iter().collect();
+    requests.sort_by_key(|(_, request)| request.started);
+    for (key, request) in requests.iter().take(QUEUE_MAX_REQUESTS) {
+        text.push_str(&format!(
+            "\n• chat {}: {}, running {}s ({})",
+            key.chat_id,
+            request
+                .domain
+                .as_deref()
+                .map_or_else(|| "unknown site".to_string(), format_stats_domain),
+            now.saturating_duration_since(request.started).as_secs(),
+            request.phase
+        ));
+    }
+    if requests.len() > QUEUE_MAX_REQUESTS {
+        text.push_str(&format!(
+            "\n…and {} more",
+            requests.len() - QUEUE_MAX_REQUESTS
+        ));
+    }
+    text
+}
+
+pub async fn handle_queue(
+    api: Arc<dyn TelegramApi>,
+    download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
+    child_processes: ChildProcesses,
+    message: Message,
+    owner_chat_id: i64,
+) -> ResponseResult<()> {
+    if message.chat.id.0 != owner_chat_id {
+        return Ok(());
+    }
+    let text = format_queue(
+        &download_limiter.active_requests(),
+        Instant::now(),
+        child_processes.active_children(),
+    );
+    api.send_text_message(message.chat.id, message.id, &text)
+        .await?;
+    Ok(())
+}
+
+/// Longest `yt-dlp --list-formats` output appended to `/info`, to fit in one message.
+const INFO_FORMATS_MAX_CHARS: usize = 3000;
+
+/// Render the metadata summary of an `/info` reply.
+fn format_media_info(info: &MediaInfo) -> String {
+    let mut lines = vec![format!("<b>ID:</b> {}", escape_html_text(&info.id))];
+    let fields = [
+        ("Title", info.title.clone()),
+        ("Uploader", info.uploader.clone()),
+        ("Extractor", info.extractor.clone()),
+        ("Type", info.media_type.clone()),
+        ("Duration", info.duration.map(|d| format!("{d:.0} s"))),
+        (
+            "Approx. size",
+            info.filesize
+                .map(|bytes| format!("{:.1} MB", bytes as f64 / 1_000_000.0)),
+        ),
+        ("Resolution", info.resolution.clone()),
+        (
+            "Entries",
+            info.entries
+                .as_ref()
+                .map(|entries| entries.len().to_string()),
+        ),
+        ("Live", info.is_live_stream().then(|| "yes".to_string())),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            lines.push(format!("<b>{label}:</b> {}", escape_html_text(&value)));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Wrap `yt-dlp --list-formats` output in a `<pre>` block, truncated to fit a message.
+fn format_formats_block(formats: &str) -> String {
+    let formats = formats.trim();
+    let mut text: String = formats.chars().take(INFO_FORMATS_MAX_CHARS).collect();
+    if text.len() < formats.len() {
+        text.push('…');
+    }
+    format!("<pre>{}</pre>", escape_html_text(&text))
+}
+
+/// Owner-only: `/info <url>` shows the metadata yt-dlp reports for a link. With debug
+/// logging enabled, the available formats are appended to help debug quality selection.
+pub async fn handle_info(
+    api: Arc<dyn TelegramApi>,
+    downloader: Arc<dyn Downloader>,
+    message: Message,
+    args: String,
+    owner_chat_id: i64,
+    include_formats: bool,
+    caption_options: CaptionOptions,
+) -> ResponseResult<()> {
+    if message.chat.id.0 != owner_chat_id {
+        return Ok(());
+    }
+    let Some(url) = parse_link(args.trim()) else {
+        api.send_text_message(message.chat.id, message.id, "Usage: /info &lt;url&gt;")
+            .await?;
+        return Ok(());
+    };
+
+    api.send_chat_action(message.chat.id, ChatAction::Typing)
+        .await?;
+    let mut text = match downloader.get_media_metadata(&url).await {
+        Ok(info) => {
+            let (_, caption_len) = info.build_caption_preview(&url, &caption_options);
+            format!(
+                "{}\n<b>Caption:</b> {} / {} bytes",
+                format_media_info(&info),
+                caption_len,
+                MediaInfo::TELEGRAM_CAPTION_LIMIT
+            )
+        }
+        Err(e) => format!(
+            "Failed to fetch metadata: {}",
+            escape_html_text(&e.to_string())
+        ),
+    };
+    if include_formats {
+        match downloader.list_formats(&url).await {
+            Ok(formats) => {
+                text.push_str("\n\n");
+                text.push_str(&format_formats_block(&formats));
+            }
+            Err(e) => text.push_str(&format!(
+                "\n\nFailed to list formats: {}",
+                escape_html_text(&e.to_string())
+            )),
+        }
+    }
+    api.send_text_message(message.chat.id, message.id, &text)
+        .await?;
+    Ok(())
+}
+
 pub async fn handle_successful_payment(
     api: Arc<dyn TelegramApi>,
     storage: Arc<dyn Storage>,
@@ -708,6 +1263,7 @@ pub async fn handle_refunded_payment(
 pub async fn handle_pre_checkout_query(
     _bot: Bot,
     api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
     query: PreCheckoutQuery,
 ) -> ResponseResult<()> {
     log::info!(
@@ -717,12 +1273,14 @@ pub async fn handle_pre_checkout_query(
         query.invoice_payload
     );
     let payload = &query.invoice_payload;
-    let ok = payload.starts_with("sub_") || payload.starts_with("topup_");
-    let error_msg: Option<String> = if ok {
+    let error_msg: Option<String> = if !storage.records_payments() {
+        Some(PAYMENTS_DISABLED_TEXT.to_string())
+    } else if payload.starts_with("sub_") || payload.starts_with("topup_") {
         None
     } else {
         Some("Unknown product".to_string())
     };
+    let ok = error_msg.is_none();
     api.answer_pre_checkout_query(&query.id.0, ok, error_msg)
         .await?;
     Ok(())
@@ -776,7 +1334,7 @@ pub async fn handle_callback_query(
 
     // User confirmed T&C and wants to proceed with the invoice
     if let Some(payload) = data.strip_prefix("agree:") {
-        return handle_agree_button(payload, chat_id, &*api).await;
+        return handle_agree_button(payload, chat_id, message_id, &*api, &*storage).await;
     }
 
     if data == "cancel:purchase" {
@@ -950,8 +1508,20 @@ async fn handle_subscription_button(
 async fn handle_agree_button(
     payload: &str,
     chat_id: ChatId,
+    message_id: MessageId,
     api: &dyn TelegramApi,
+    storage: &dyn Storage,
 ) -> ResponseResult<()> {
+    if !storage.records_payments() {
+        log_telegram_failure(
+            api.send_text_message(chat_id, message_id, PAYMENTS_DISABLED_TEXT)
+                .await,
+            chat_id,
+            "payments_disabled",
+        )
+        .await;
+        return Ok(());
+    }
     let (title, description, amount) = match payload {
         PRODUCT_SUB_BASIC => (
             "Basic Subscription",
@@ -1038,6 +1608,7 @@ async fn handle_audio_extraction(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_transcription(
     context_id: i32,
     ctx: &CallbackContext,
@@ -1096,6 +1667,7 @@ async fn handle_transcription(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_summarization(
     context_id: i32,
     ctx: &CallbackContext,
@@ -1161,6 +1733,7 @@ async fn handle_summarization(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn prepare_ai_action(
     context_id: i32,
     ctx: &CallbackContext,
@@ -1211,8 +1784,7 @@ async fn prepare_ai_action(
         return Ok(None);
     }
 
-    api.send_chat_action(chat_id, teloxide::types::ChatAction::Typing)
-        .await?;
+    api.send_chat_action(chat_id, ChatAction::Typing).await?;
 
     if let Some(cached) = &ctx.transcript {
         return Ok(Some((
@@ -1315,11 +1887,15 @@ async fn record_gemini_usage(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::concurrency::RequestPhase;
+    use crate::downloader::MockDownloader;
     use crate::premium::summarizer::MockSummarizer;
     use crate::premium::transcriber::{MockTranscriber, TranscriptionResult};
     use crate::storage::MockStorage;
+    use crate::storage_metrics::OperationTimings;
     use crate::subscription::{SubscriptionInfo, SubscriptionTier};
     use crate::telegram_api::MockTelegramApi;
+    use mockall::predicate::*;
     use teloxide::types::{ChatId, MessageId};
 
     // ---------------------------------------------------------------------------
@@ -1476,9 +2052,17 @@ mod tests {
         }))
         .unwrap();
 
-        handle_pre_checkout_query(teloxide::Bot::new("fake_token"), Arc::new(mock_api), query)
-            .await
-            .unwrap();
+        let mut storage = MockStorage::new();
+        storage.expect_records_payments().return_const(true);
+
+        handle_pre_checkout_query(
+            teloxide::Bot::new("fake_token"),
+            Arc::new(mock_api),
+            Arc::new(storage),
+            query,
+        )
+        .await
+        .unwrap();
     }
 
     #[tokio::test]
@@ -1486,7 +2070,7 @@ mod tests {
         let mut mock_api = MockTelegramApi::new();
         mock_api
             .expect_answer_pre_checkout_query()
-            .withf(|_, ok, err| !ok && err.is_some())
+            .withf(|_, ok, err| !ok && err.as_deref() != Some(PAYMENTS_DISABLED_TEXT))
             .times(1)
             .returning(|_, _, _| Ok(()));
 
@@ -1499,9 +2083,63 @@ mod tests {
         }))
         .unwrap();
 
-        handle_pre_checkout_query(teloxide::Bot::new("fake_token"), Arc::new(mock_api), query)
+        let mut storage = MockStorage::new();
+        storage.expect_records_payments().return_const(true);
+
+        handle_pre_checkout_query(
+            teloxide::Bot::new("fake_token"),
+            Arc::new(mock_api),
+            Arc::new(storage),
+            query,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_payments_are_refused_without_lasting_storage() {
+        let storages: [Arc<dyn Storage>; 2] = [
+            Arc::new(crate::blackhole::BlackholeStorage),
+            Arc::new(crate::memory_storage::MemoryStorage::new()),
+        ];
+        let mut mock_api = MockTelegramApi::new();
+        mock_api
+            .expect_answer_pre_checkout_query()
+            .withf(|_, ok, err| !ok && err.as_deref() == Some(PAYMENTS_DISABLED_TEXT))
+            .times(2)
+            .returning(|_, _, _| Ok(()));
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == PAYMENTS_DISABLED_TEXT)
+            .times(2)
+            .returning(|_, _, _| Ok(()));
+        let api: Arc<dyn TelegramApi> = Arc::new(mock_api);
+
+        for storage in storages {
+            let query: PreCheckoutQuery = serde_json::from_value(serde_json::json!({
+                "id": "pq_123",
+                "from": {"id": 200, "is_bot": false, "first_name": "Test"},
+                "currency": "XTR",
+                "total_amount": 50,
+                "invoice_payload": "sub_basic"
+            }))
+            .unwrap();
+            handle_pre_checkout_query(
+                teloxide::Bot::new("fake_token"),
+                api.clone(),
+                storage.clone(),
+                query,
+            )
+            .await
+            .unwrap();
+            handle_subscribe(
+                api.clone(),
+                make_message(base_message_json(200, 200)),
+                storage,
+            )
             .await
             .unwrap();
+        }
     }
 
     // ---------------------------------------------------------------------------
@@ -1569,6 +2207,90 @@ mod tests {
         .unwrap();
     }
 
+    #[tokio::test]
+    async fn test_handle_feedback_relays_once_per_hour() {
+        let mut mock_api = MockTelegramApi::new();
+        mock_api
+            .expect_send_text_no_reply()
+            .with(
+                eq(ChatId(999)),
+                eq("[Feedback] from chat_id: <code>100</code>\n\nexample.com &lt;broken&gt;"),
+            )
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let mut seq = mockall::Sequence::new();
+        for reply in [FEEDBACK_SENT_MESSAGE, FEEDBACK_RATE_LIMITED_MESSAGE] {
+            mock_api
+                .expect_send_text_message()
+                .with(eq(ChatId(100)), always(), eq(reply))
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_, _, _| Ok(()));
+        }
+        let api: Arc<dyn TelegramApi> = Arc::new(mock_api);
+        let rate_limiter = Arc::new(RateLimiter::new(0));
+
+        for _ in 0..2 {
+            handle_feedback(
+                api.clone(),
+                rate_limiter.clone(),
+                make_message(base_message_json(100, 200)),
+                "example.com <broken>".to_string(),
+                999,
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_feedback_limit_is_per_user_and_only_counts_relayed_feedback() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut relay_seq = mockall::Sequence::new();
+        mock_api
+            .expect_send_text_no_reply()
+            .times(1)
+            .in_sequence(&mut relay_seq)
+            .returning(|_, _| {
+                Err(teloxide::RequestError::Io(
+                    std::io::Error::other("down").into(),
+                ))
+            });
+        mock_api
+            .expect_send_text_no_reply()
+            .times(1)
+            .in_sequence(&mut relay_seq)
+            .returning(|_, _| Ok(()));
+        let mut seq = mockall::Sequence::new();
+        for (chat_id, reply) in [
+            (100, FEEDBACK_FAILED_MESSAGE),
+            (100, FEEDBACK_SENT_MESSAGE),
+            (-300, FEEDBACK_RATE_LIMITED_MESSAGE),
+        ] {
+            mock_api
+                .expect_send_text_message()
+                .with(eq(ChatId(chat_id)), always(), eq(reply))
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_, _, _| Ok(()));
+        }
+        let api: Arc<dyn TelegramApi> = Arc::new(mock_api);
+        let rate_limiter = Arc::new(RateLimiter::new(0));
+
+        // The same user retries after a failed relay, then tries again from a group.
+        for chat_id in [100, 100, -300] {
+            handle_feedback(
+                api.clone(),
+                rate_limiter.clone(),
+                make_message(base_message_json(chat_id, 200)),
+                "broken link".to_string(),
+                999,
+            )
+            .await
+            .unwrap();
+        }
+    }
+
     // ---------------------------------------------------------------------------
     // handle_refund
     // ---------------------------------------------------------------------------
@@ -1592,6 +2314,555 @@ mod tests {
         .unwrap();
     }
 
+    // ---------------------------------------------------------------------------
+    // handle_findcached
+    // ---------------------------------------------------------------------------
+
+    #[tokio::test]
+    async fn test_handle_findcached_lists_matches() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+
+        mock_storage
+            .expect_search_cache()
+            .withf(|query, limit| query == "crab" && *limit == 10)
+            .times(1)
+            .returning(|_, _| {
+                vec![crate::storage::CacheSearchResult {
+                    source_url: "https://youtube.com/watch?v=abc".to_string(),
+                    metadata: crate::storage::CacheMetadata {
+                        uploader: Some("Crab & Co".to_string()),
+                        title: None,
+                        extractor: Some("youtube".to_string()),
+                        duration: None,
+                    },
+                    last_used_at: chrono::Utc::now(),
+                }]
+            });
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| {
+                text.contains("https://youtube.com/watch?v=abc")
+                    && text.contains("youtube · Crab &amp; Co")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        mock_api
+            .expect_send_chat_action()
+            .with(eq(ChatId(999)), eq(ChatAction::Typing))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let message = make_message(base_message_json(999, 999));
+        handle_findcached(
+            Arc::new(mock_api),
+            Arc::new(mock_storage),
+            message,
+            " crab ".to_string(),
+            999,
+        )
+        .await
+        .unwrap();
+    }
+
+    // ---------------------------------------------------------------------------
+    // handle_trace
+    // ---------------------------------------------------------------------------
+
+    #[test]
+    fn test_parse_trace_args() {
+        assert_eq!(parse_trace_args(" -1001234 56 "), Some((-1001234, 56)));
+        assert_eq!(parse_trace_args("-1001234"), None);
+        assert_eq!(parse_trace_args("-1001234 56 7"), None);
+        assert_eq!(parse_trace_args("chat 56"), None);
+    }
+
+    #[tokio::test]
+    async fn test_handle_trace_shows_the_cache_entry() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        let delivered_at = chrono::DateTime::parse_from_rfc3339("2026-03-04T05:06:07Z")
+            .unwrap()
+            .to_utc();
+        mock_storage
+            .expect_get_delivery()
+            .with(eq(-1001234), eq(56))
+            .times(1)
+            .returning(move |_, _| {
+                Some(Delivery {
+                    source_url: "https://example.com/a?b=1&c=2".to_string(),
+                    cache_id: Some(17),
+                    delivered_at,
+                    cached_at: Some(delivered_at - chrono::TimeDelta::days(1)),
+                })
+            });
+        mock_api
+            .expect_send_text_message()
+            .withf(|chat, _, text| {
+                *chat == ChatId(999)
+                    && text
+                        == "<b>Source:</b> https://example.com/a?b=1&amp;c=2\n\
+                            <b>Cache entry:</b> 17, stored 2026-03-03 05:06:07 UTC\n\
+                            <b>Delivered:</b> 2026-03-04 05:06:07 UTC"
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        handle_trace(
+            Arc::new(mock_api),
+            Arc::new(mock_storage),
+            make_message(base_message_json(999, 999)),
+            "-1001234 56".to_string(),
+            999,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_trace_unknown_message() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        mock_storage.expect_get_delivery().returning(|_, _| None);
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == "No delivery recorded for message 56 in chat 5.")
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        handle_trace(
+            Arc::new(mock_api),
+            Arc::new(mock_storage),
+            make_message(base_message_json(999, 999)),
+            "5 56".to_string(),
+            999,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_maintenance_toggles_and_persists() {
+        let expected = MaintenanceState::parse("on yt-dlp <3 broke").unwrap();
+        let mut mock_storage = MockStorage::new();
+        let stored = expected.clone();
+        mock_storage
+            .expect_set_maintenance()
+            .withf(move |state| *state == stored)
+            .times(1)
+            .returning(|_| ());
+        let storage: Arc<dyn Storage> = Arc::new(mock_storage);
+        let maintenance = Arc::new(Maintenance::default());
+        let mut mock_api = MockTelegramApi::new();
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| {
+                text == "Maintenance mode is <b>on</b>. New requests get:\nyt-dlp &lt;3 broke"
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == "Usage: /maintenance on [message] | off")
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let api: Arc<dyn TelegramApi> = Arc::new(mock_api);
+
+        for args in ["on yt-dlp <3 broke", "sideways"] {
+            handle_maintenance(
+                api.clone(),
+                storage.clone(),
+                maintenance.clone(),
+                make_message(base_message_json(999, 999)),
+                args.to_string(),
+                999,
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(maintenance.state(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_handle_trace_ignores_other_chats() {
+        let mut mock_storage = MockStorage::new();
+        mock_storage.expect_get_delivery().never();
+
+        handle_trace(
+            Arc::new(MockTelegramApi::new()),
+            Arc::new(mock_storage),
+            make_message(base_message_json(100, 100)),
+            "5 56".to_string(),
+            999,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_stats_reports_cache_size() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+
+        mock_storage
+            .expect_cache_stats()
+            .times(1)
+            .returning(|| crate::storage::CacheStats {
+                entries: 12,
+                files: 30,
+            });
+        mock_storage
+            .expect_activity_report()
+            .times(2)
+            .returning(|_| ActivityReport::default());
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| {
+                text.contains("Entries: 12")
+                    && text.contains("Cached files: 30")
+                    && text.contains("Last 24h")
+                    && text.contains("Last 7d")
+                    && text.contains("In progress: 0 (oldest: n/a)")
+                    && text.contains("Cache lookups: 0 hits, 0 misses")
+                    && text.contains("Running processes: 0")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        mock_api
+            .expect_send_chat_action()
+            .with(eq(ChatId(999)), eq(ChatAction::Typing))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let message = make_message(base_message_json(999, 999));
+        handle_stats(
+            Arc::new(mock_api),
+            Arc::new(mock_storage),
+            Arc::new(PendingUploads::new()),
+            Arc::new(StorageCounters::default()),
+            ChildProcesses::new(),
+            message,
+            999,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_format_activity_report_empty_window() {
+        let text = format_activity_report("Last 24h", &ActivityReport::default());
+        assert_eq!(
+            text,
+            "<b>Last 24h</b>\nRequests: 0 (cache hits: 0, fresh downloads: 0)\n\
+             Processing time: median n/a, p95 n/a\nData transferred: 0.0 MB\nFailing domains: none"
+        );
+    }
+
+    #[test]
+    fn test_format_activity_report_with_failures() {
+        let report = ActivityReport {
+            total_requests: 120,
+            cache_hits: 45,
+            fresh_downloads: 60,
+            median_processing_ms: Some(850.0),
+            p95_processing_ms: Some(12_345.0),
+            top_failing_domains: vec![
+                crate::storage::DomainFailures {
+                    domain: "tiktok.com".to_string(),
+                    failures: 9,
+                },
+                crate::storage::DomainFailures {
+                    domain: "a<b>.com".to_string(),
+                    failures: 2,
+                },
+            ],
+            bytes_transferred: 1_234_500_000,
+            top_traffic_domains: vec![crate::storage::DomainTraffic {
+                domain: "youtube.com".to_string(),
+                bytes: 1_000_000_000,
+            }],
+            top_geo_blocked_domains: vec![crate::storage::DomainFailures {
+                domain: "bbc.co.uk".to_string(),
+                failures: 4,
+            }],
+        };
+        let text = format_activity_report("Last 7d", &report);
+        assert!(text.contains("Requests: 120 (cache hits: 45, fresh downloads: 60)"));
+        assert!(text.contains("median 850 ms, p95 12.3 s"));
+        assert!(text.contains("Data transferred: 1234.5 MB"));
+        assert!(text.contains(
+            "• tiktok.com: 9\n• a&lt;b&gt;.com: 2\nMost traffic:\n• youtube.com: 1000.0 MB\n\
+             Geo-blocked:\n• bbc.co.uk: 4"
+        ));
+    }
+
+    #[test]
+    fn test_format_activity_report_shows_unicode_domains() {
+        let report = ActivityReport {
+            top_failing_domains: vec![crate::storage::DomainFailures {
+                domain: "xn--e1afmkfd.xn--p1ai".to_string(),
+                failures: 3,
+            }],
+            ..Default::default()
+        };
+        assert!(format_activity_report("Last 24h", &report).contains("• пример.рф: 3"));
+    }
+
+    #[test]
+    fn test_format_uploads() {
+        let uploads = UploadSnapshot {
+            active: 2,
+            oldest_age: Some(Duration::from_millis(4500)),
+            completed_avg_ms: Some(850.0),
+        };
+        assert_eq!(
+            format_uploads(&uploads),
+            "<b>Uploads</b>\nIn progress: 2 (oldest: 4.5 s)\nRecent average: 850 ms"
+        );
+    }
+
+    #[test]
+    fn test_format_storage_metrics() {
+        let metrics = StorageMetricsSnapshot {
+            cache_hits: 30,
+            cache_misses: 10,
+            cache_stores: 9,
+            slowest_operations: vec![(
+                "activity_report",
+                OperationTimings {
+                    calls: 4,
+                    total: Duration::from_millis(2000),
+                    max: Duration::from_millis(1200),
+                },
+            )],
+        };
+        assert_eq!(
+            format_storage_metrics(&metrics),
+            "<b>Storage</b>\nCache lookups: 30 hits, 10 misses\nCache writes: 9\n\
+             Slowest operations:\n• activity_report: mean 500 ms, max 1.2 s (4 calls)"
+        );
+    }
+
+    #[test]
+    fn test_format_stats_stays_within_message_limit() {
+        let report = ActivityReport {
+            total_requests: i64::MAX,
+            cache_hits: i64::MAX,
+            fresh_downloads: i64::MAX,
+            median_processing_ms: Some(1e12),
+            p95_processing_ms: Some(1e12),
+            top_failing_domains: (0..crate::storage::TOP_FAILING_DOMAINS)
+                .map(|i| crate::storage::DomainFailures {
+                    domain: format!("{i}{}", "&".repeat(1000)),
+                    failures: i64::MAX,
+                })
+                .collect(),
+            bytes_transferred: i64::MAX,
+            top_traffic_domains: (0..crate::storage::TOP_TRAFFIC_DOMAINS)
+                .map(|i| crate::storage::DomainTraffic {
+                    domain: format!("{i}{}", "&".repeat(1000)),
+                    bytes: i64::MAX,
+                })
+                .collect(),
+            top_geo_blocked_domains: (0..crate::storage::TOP_GEO_BLOCKED_DOMAINS)
+                .map(|i| crate::storage::DomainFailures {
+                    domain: format!("{i}{}", "&".repeat(1000)),
+                    failures: i64::MAX,
+                })
+                .collect(),
+        };
+        let cache = CacheStats {
+            entries: i64::MAX,
+            files: i64::MAX,
+        };
+        let uploads = UploadSnapshot {
+            active: usize::MAX,
+            oldest_age: Some(Duration::from_secs(u64::from(u32::MAX))),
+            completed_avg_ms: Some(1e12),
+        };
+        let storage_metrics = StorageMetricsSnapshot {
+            cache_hits: u64::MAX,
+            cache_misses: u64::MAX,
+            cache_stores: u64::MAX,
+            slowest_operations: vec![
+                (
+                    "cleanup_expired_callback_contexts",
+                    OperationTimings {
+                        calls: u64::MAX,
+                        total: Duration::MAX,
+                        max: Duration::MAX,
+                    }
+                );
+                crate::storage_metrics::SLOWEST_STORAGE_OPERATIONS
+            ],
+        };
+        let text = format_stats(
+            &cache,
+            &[("Last 24h", report.clone()), ("Last 7d", report)],
+            &uploads,
+            &storage_metrics,
+            usize::MAX,
+        );
+        assert!(
+            text.chars().count() < 4096,
+            "length {}",
+            text.chars().count()
+        );
+    }
+
+    fn queued(
+        chat_id: i64,
+        running_secs: u64,
+        domain: Option<&str>,
+        now: Instant,
+    ) -> (BotChat, ActiveRequest) {
+        (
+            BotChat {
+                bot_id: UserId(1),
+                chat_id: ChatId(chat_id),
+            },
+            ActiveRequest {
+                started: now - Duration::from_secs(running_secs),
+                domain: domain.map(str::to_string),
+                phase: RequestPhase::Downloading,
+            },
+        )
+    }
+
+    #[test]
+    fn test_format_queue_lists_longest_running_first() {
+        let now = Instant::now();
+        let requests = [
+            queued(-100123, 5, None, now),
+            queued(42, 84, Some("tiktok.com"), now),
+        ];
+        assert_eq!(
+            format_queue(&requests, now, 1),
+            "<b>Requests in progress:</b> 2\n<b>yt-dlp processes:</b> 1\n\
+             • chat 42: tiktok.com, running 84s (downloading)\n\
+             • chat -100123: unknown site, running 5s (downloading)"
+        );
+    }
+
+    #[test]
+    fn test_format_queue_truncates_to_fit_one_message() {
+        let now = Instant::now();
+        let long_domain = format!("{}.com", "&".repeat(1000));
+        let requests: Vec<_> = (0..100)
+            .map(|i| queued(i64::MIN + i, 1000 - i as u64, Some(&long_domain), now))
+            .collect();
+        let text = format_queue(&requests, now, usize::MAX);
+        assert!(
+            text.chars().count() < 4096,
+            "length {}",
+            text.chars().count()
+        );
+        assert!(text.contains("running 1000s"));
+        assert!(!text.contains("running 980s"));
+        assert!(text.ends_with("…and 80 more"));
+    }
+
+    #[test]
+    fn test_format_formats_block_truncates_and_escapes() {
+        let short = format_formats_block("ID  EXT\n18  mp4 <audio>\n");
+        assert_eq!(short, "<pre>ID  EXT\n18  mp4 &lt;audio&gt;</pre>");
+
+        let long = format_formats_block(&"x".repeat(INFO_FORMATS_MAX_CHARS + 100));
+        assert_eq!(
+            long,
+            format!("<pre>{}…</pre>", "x".repeat(INFO_FORMATS_MAX_CHARS))
+        );
+    }
+
+    fn info_test_media() -> MediaInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": "abc",
+            "title": "A <b> title",
+            "extractor": "youtube",
+            "duration": 61.4
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_handle_info_appends_formats_in_debug_mode() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_get_media_metadata()
+            .returning(|_| Ok(info_test_media()));
+        mock_downloader
+            .expect_list_formats()
+            .withf(|url| url.as_str() == "https://youtu.be/abc")
+            .times(1)
+            .returning(|_| Ok("ID EXT\n22 mp4".to_string()));
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| {
+                text.contains("<b>Title:</b> A &lt;b&gt; title")
+                    && text.contains("<b>Duration:</b> 61 s")
+                    && text.contains("<b>Caption:</b> ")
+                    && text.contains(" / 1024 bytes")
+                    && text.ends_with("<pre>ID EXT\n22 mp4</pre>")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        mock_api
+            .expect_send_chat_action()
+            .with(eq(ChatId(999)), eq(ChatAction::Typing))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        handle_info(
+            Arc::new(mock_api),
+            Arc::new(mock_downloader),
+            make_message(base_message_json(999, 1)),
+            "youtu.be/abc".to_string(),
+            999,
+            true,
+            CaptionOptions::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_info_skips_formats_outside_debug_mode() {
+        let mut mock_api = MockTelegramApi::new();
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_get_media_metadata()
+            .returning(|_| Ok(info_test_media()));
+        mock_downloader.expect_list_formats().never();
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.starts_with("<b>ID:</b> abc") && !text.contains("<pre>"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        mock_api
+            .expect_send_chat_action()
+            .with(eq(ChatId(999)), eq(ChatAction::Typing))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        handle_info(
+            Arc::new(mock_api),
+            Arc::new(mock_downloader),
+            make_message(base_message_json(999, 1)),
+            "https://youtu.be/abc".to_string(),
+            999,
+            false,
+            CaptionOptions::default(),
+        )
+        .await
+        .unwrap();
+    }
+
     // ---------------------------------------------------------------------------
     // handle_audio_extraction
     // ---------------------------------------------------------------------------
@@ -1,40 +1,397 @@
+use std::fmt;
+use std::hash::Hash;
 use std::sync::Arc;
+use std::time::Instant;
 
-use dashmap::DashSet;
-use teloxide::types::ChatId;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use teloxide::types::{ChatId, UserId};
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
-pub struct LockGuard {
-    set: Arc<DashSet<ChatId>>,
-    id: ChatId,
+/// Lock key for requests that are scoped to one bot when several bots share a limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BotChat {
+    pub bot_id: UserId,
+    pub chat_id: ChatId,
 }
 
-impl Drop for LockGuard {
+impl fmt::Display for BotChat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (bot {})", self.chat_id, self.bot_id)
+    }
+}
+
+/// The stage a locked request has reached, as shown by `/queue`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RequestPhase {
+    #[default]
+    Starting,
+    FetchingMetadata,
+    Downloading,
+    Uploading,
+}
+
+impl fmt::Display for RequestPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Starting => "starting",
+            Self::FetchingMetadata => "fetching metadata",
+            Self::Downloading => "downloading",
+            Self::Uploading => "uploading",
+        })
+    }
+}
+
+/// What the holder of a `ConcurrencyLimiter` lock is working on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveRequest {
+    pub started: Instant,
+    /// Host of the link being processed, once known.
+    pub domain: Option<String>,
+    pub phase: RequestPhase,
+}
+
+impl ActiveRequest {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            domain: None,
+            phase: RequestPhase::default(),
+        }
+    }
+}
+
+/// Lets a request report its progress to the limiter it holds a lock on. The default
+/// handle is not attached to any limiter and ignores updates.
+#[derive(Debug, Clone, Default)]
+pub struct RequestProgress(Option<Arc<std::sync::Mutex<ActiveRequest>>>);
+
+impl RequestProgress {
+    fn update(&self, change: impl FnOnce(&mut ActiveRequest)) {
+        if let Some(request) = &self.0 {
+            change(&mut request.lock().expect("active request lock poisoned"));
+        }
+    }
+
+    pub fn set_domain(&self, domain: &str) {
+        self.update(|request| request.domain = Some(domain.to_string()));
+    }
+
+    pub fn set_phase(&self, phase: RequestPhase) {
+        self.update(|request| request.phase = phase);
+    }
+}
+
+type ActiveRequests<K> = DashMap<K, Arc<std::sync::Mutex<ActiveRequest>>>;
+
+pub struct LockGuard<K: Eq + Hash + fmt::Display = ChatId> {
+    active: Arc<ActiveRequests<K>>,
+    id: K,
+    request: Arc<std::sync::Mutex<ActiveRequest>>,
+}
+
+impl<K: Eq + Hash + fmt::Display> LockGuard<K> {
+    /// A handle for reporting what the locked request is doing.
+    pub fn progress(&self) -> RequestProgress {
+        RequestProgress(Some(Arc::clone(&self.request)))
+    }
+}
+
+impl<K: Eq + Hash + fmt::Display> Drop for LockGuard<K> {
     fn drop(&mut self) {
         log::info!("Releasing lock for chat_id: {}", self.id);
-        self.set.remove(&self.id);
+        // The entry may have been forgotten, and the key locked again, since.
+        self.active
+            .remove_if(&self.id, |_, request| Arc::ptr_eq(request, &self.request));
     }
 }
 
-#[derive(Clone, Default)]
-pub struct ConcurrencyLimiter {
-    processing_users: Arc<DashSet<ChatId>>,
+/// Allows one in-flight request per key, by default per chat.
+#[derive(Clone)]
+pub struct ConcurrencyLimiter<K: Eq + Hash = ChatId> {
+    processing_users: Arc<ActiveRequests<K>>,
 }
 
-impl ConcurrencyLimiter {
+impl<K: Eq + Hash> Default for ConcurrencyLimiter<K> {
+    fn default() -> Self {
+        Self {
+            processing_users: Arc::default(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + fmt::Display> ConcurrencyLimiter<K> {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn try_lock(&self, chat_id: ChatId) -> Option<LockGuard> {
-        if self.processing_users.insert(chat_id) {
-            log::info!("Acquired lock for chat_id: {}", chat_id);
-            Some(LockGuard {
-                set: Arc::clone(&self.processing_users),
-                id: chat_id,
+    pub fn try_lock(&self, key: K) -> Option<LockGuard<K>> {
+        match self.processing_users.entry(key.clone()) {
+            Entry::Vacant(entry) => {
+                log::info!("Acquired lock for chat_id: {}", key);
+                let request = Arc::new(std::sync::Mutex::new(ActiveRequest::new()));
+                entry.insert(Arc::clone(&request));
+                Some(LockGuard {
+                    active: Arc::clone(&self.processing_users),
+                    id: key,
+                    request,
+                })
+            }
+            Entry::Occupied(_) => {
+                log::info!("User {} is already being processed.", key);
+                None
+            }
+        }
+    }
+
+    /// Release `key`'s lock without waiting for its guard, e.g. for a group that became a
+    /// supergroup and will never send from its old id again.
+    pub fn forget(&self, key: &K) {
+        if self.processing_users.remove(key).is_some() {
+            log::info!("Dropped lock for chat_id: {}", key);
+        }
+    }
+
+    /// Number of keys currently holding a lock.
+    pub fn active_count(&self) -> usize {
+        self.processing_users.len()
+    }
+
+    /// Every locked key and what its request is doing, in no particular order.
+    pub fn active_requests(&self) -> Vec<(K, ActiveRequest)> {
+        self.processing_users
+            .iter()
+            .map(|entry| {
+                let request = entry
+                    .value()
+                    .lock()
+                    .expect("active request lock poisoned")
+                    .clone();
+                (entry.key().clone(), request)
             })
-        } else {
-            log::info!("User {} is already being processed.", chat_id);
-            None
+            .collect()
+    }
+}
+
+/// Serializes work per key: unlike `ConcurrencyLimiter`, later callers wait their turn
+/// instead of being turned away. Idle keys are removed.
+#[derive(Debug)]
+pub struct KeyedMutex<K: Eq + Hash> {
+    locks: DashMap<K, Arc<Mutex<()>>>,
+}
+
+impl<K: Eq + Hash> Default for KeyedMutex<K> {
+    fn default() -> Self {
+        Self {
+            locks: DashMap::new(),
         }
     }
 }
+
+pub struct KeyedMutexGuard<'a, K: Eq + Hash> {
+    owner: &'a KeyedMutex<K>,
+    key: K,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl<K: Eq + Hash> Drop for KeyedMutexGuard<'_, K> {
+    fn drop(&mut self) {
+        self.guard.take();
+        // Only the map still holds the mutex once nobody else is waiting for it.
+        self.owner
+            .locks
+            .remove_if(&self.key, |_, lock| Arc::strong_count(lock) == 1);
+    }
+}
+
+impl<K: Eq + Hash + Clone + fmt::Display> KeyedMutex<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn lock(&self, key: K) -> KeyedMutexGuard<'_, K> {
+        let lock = Arc::clone(&self.locks.entry(key.clone()).or_default());
+        let guard = match Arc::clone(&lock).try_lock_owned() {
+            Ok(guard) => guard,
+            Err(_) => {
+                log::info!("Waiting for in-flight work on {}", key);
+                lock.lock_owned().await
+            }
+        };
+        KeyedMutexGuard {
+            owner: self,
+            key,
+            guard: Some(guard),
+        }
+    }
+
+    /// Number of keys currently locked or waited on.
+    pub fn active_count(&self) -> usize {
+        self.locks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Barrier;
+    use tokio::task::JoinSet;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_only_one_concurrent_lock_per_chat() {
+        let limiter = ConcurrencyLimiter::new();
+        let start = Arc::new(Barrier::new(10));
+        // Every task holds its result until all have tried, so the winner's
+        // guard is still alive while the others attempt the lock.
+        let attempted = Arc::new(Barrier::new(10));
+        let mut tasks = JoinSet::new();
+
+        for _ in 0..10 {
+            let limiter = limiter.clone();
+            let start = Arc::clone(&start);
+            let attempted = Arc::clone(&attempted);
+            tasks.spawn(async move {
+                start.wait().await;
+                let guard = limiter.try_lock(ChatId(42));
+                attempted.wait().await;
+                guard.is_some()
+            });
+        }
+
+        let mut successes = 0;
+        let mut failures = 0;
+        while let Some(result) = tasks.join_next().await {
+            if result.unwrap() {
+                successes += 1;
+            } else {
+                failures += 1;
+            }
+        }
+        assert_eq!(successes, 1);
+        assert_eq!(failures, 9);
+        assert!(limiter.try_lock(ChatId(42)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_different_chats_lock_independently() {
+        let limiter = ConcurrencyLimiter::new();
+        let first = limiter.try_lock(ChatId(1));
+        let second = limiter.try_lock(ChatId(2));
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert_eq!(limiter.active_count(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_active_count_under_concurrent_access() {
+        let limiter = ConcurrencyLimiter::new();
+        let barrier = Arc::new(Barrier::new(11));
+        let release = Arc::new(Barrier::new(11));
+        let mut tasks = JoinSet::new();
+
+        for chat in 0..10 {
+            let limiter = limiter.clone();
+            let barrier = Arc::clone(&barrier);
+            let release = Arc::clone(&release);
+            tasks.spawn(async move {
+                let guard = limiter.try_lock(ChatId(chat));
+                assert!(guard.is_some());
+                barrier.wait().await;
+                release.wait().await;
+            });
+        }
+
+        barrier.wait().await;
+        assert_eq!(limiter.active_count(), 10);
+        release.wait().await;
+        while let Some(result) = tasks.join_next().await {
+            result.unwrap();
+        }
+        assert_eq!(limiter.active_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_progress_updates_the_active_request() {
+        let limiter = ConcurrencyLimiter::new();
+        let guard = limiter.try_lock(ChatId(5)).unwrap();
+        let progress = guard.progress();
+
+        let [(chat_id, request)] = limiter.active_requests().try_into().unwrap();
+        assert_eq!(chat_id, ChatId(5));
+        assert_eq!(
+            (request.domain, request.phase),
+            (None, RequestPhase::Starting)
+        );
+
+        progress.set_domain("tiktok.com");
+        progress.set_phase(RequestPhase::Downloading);
+        let [(_, request)] = limiter.active_requests().try_into().unwrap();
+        assert_eq!(request.domain.as_deref(), Some("tiktok.com"));
+        assert_eq!(request.phase, RequestPhase::Downloading);
+
+        drop(guard);
+        assert!(limiter.active_requests().is_empty());
+        // Updates after the lock is released, or without a lock, go nowhere.
+        progress.set_phase(RequestPhase::Uploading);
+        RequestProgress::default().set_phase(RequestPhase::Uploading);
+    }
+
+    #[tokio::test]
+    async fn test_forget_releases_the_key() {
+        let limiter = ConcurrencyLimiter::new();
+        let old = limiter.try_lock(ChatId(-1)).unwrap();
+        limiter.forget(&ChatId(-1));
+        assert_eq!(limiter.active_count(), 0);
+
+        let new = limiter.try_lock(ChatId(-1)).unwrap();
+        // The forgotten guard must not release the new lock.
+        drop(old);
+        assert_eq!(limiter.active_count(), 1);
+        drop(new);
+        assert_eq!(limiter.active_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_mutex_serializes_same_key() {
+        let locks = Arc::new(KeyedMutex::new());
+        let first = locks.lock("a".to_string()).await;
+        let _other_key = locks.lock("b".to_string()).await;
+
+        let waiter = tokio::spawn({
+            let locks = Arc::clone(&locks);
+            async move {
+                let _guard = locks.lock("a".to_string()).await;
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        drop(first);
+        waiter.await.unwrap();
+        assert_eq!(locks.active_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_same_chat_on_different_bots_locks_independently() {
+        let limiter = ConcurrencyLimiter::new();
+        let chat_id = ChatId(7);
+        let public = limiter.try_lock(BotChat {
+            bot_id: UserId(1),
+            chat_id,
+        });
+        let friends = limiter.try_lock(BotChat {
+            bot_id: UserId(2),
+            chat_id,
+        });
+        assert!(public.is_some());
+        assert!(friends.is_some());
+        assert!(
+            limiter
+                .try_lock(BotChat {
+                    bot_id: UserId(1),
+                    chat_id,
+                })
+                .is_none()
+        );
+    }
+}
@@ -4,22 +4,105 @@ use std::time::Duration;
 use thiserror::Error;
 use url::Url;
 
+use crate::caption_links::CaptionLinks;
+use crate::cookies::CookieProfiles;
+use crate::dashboard::DashboardCredentials;
+use crate::downloader::{
+    Attribution, DEFAULT_ATTRIBUTION_NAME, DEFAULT_ATTRIBUTION_URL, GeoBypass, YtDlpNetwork,
+};
+use crate::dry_run::TelegramMode;
+use crate::handler::{DEFAULT_REQUEST_TIMEOUT, DEFAULT_SLOW_DOWNLOAD_WARNING};
+use crate::object_store::{DEFAULT_LINK_EXPIRY, MAX_LINK_EXPIRY, ObjectStoreConfig};
+use crate::quota::DailyDownloadQuota;
+use crate::rate_limiter::DEFAULT_COMMAND_RATE_LIMIT;
+use crate::recent_requests::DEFAULT_DEDUP_WINDOW;
+use crate::storage::{PoolSettings, StorageSettings, StorageUrl, StorageUrlError};
+use crate::url_cleanup::{self, UrlCleanupRule};
+use crate::validator::DEFAULT_WARN_MARGIN_PERCENT;
+
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub execution_environment: String,
-    pub database_url: String,
+    /// One dispatcher is started per token; they share the downloader, storage and limiters.
+    pub bot_tokens: Vec<String>,
+    /// Bot API server to use instead of api.telegram.org, e.g. a local `telegram-bot-api`.
+    pub telegram_api_url: Option<Url>,
+    /// Set by `TELOXIDE_API_URL` or `LOCAL_BOT_API_URL`: the API is a local Bot API server,
+    /// which accepts uploads up to 2 GB.
+    pub use_local_bot_api: bool,
+    /// `TELEGRAM_MODE=dryrun` runs without contacting Telegram; see `dry_run`.
+    pub telegram_mode: TelegramMode,
+    /// Artificial delay of every dry-run API call, from `TELEGRAM_DRYRUN_LATENCY_MS`.
+    pub dry_run_latency: Duration,
+    /// From `STORAGE_URL`, or `DATABASE_URL` (Postgres) when that is unset.
+    pub storage_url: StorageUrl,
     pub postgres_max_connections: u32,
     pub postgres_min_connections: u32,
     pub postgres_acquire_timeout: Duration,
+    pub postgres_statement_timeout: Option<Duration>,
+    /// Fail startup when Postgres is unreachable; otherwise fall back to in-memory storage.
+    pub storage_required: bool,
     pub deepgram_api_key: String,
     pub gemini_api_key: String,
     pub gemini_model: String,
     pub owner_chat_id: i64,
+    /// Minimum time between dispatcher error reports sent to the owner chat.
+    pub error_report_interval: Duration,
     pub port: u16,
     pub webhook_url: Url,
+    /// Secret Telegram sends with every webhook update. `None` lets teloxide generate one.
+    pub webhook_secret: Option<String>,
+    /// Login for the `/dashboard` page, from `DASHBOARD_USERNAME` and `DASHBOARD_PASSWORD`;
+    /// `None` leaves the page off.
+    pub dashboard_credentials: Option<DashboardCredentials>,
+    /// Where errors are reported in builds with the `sentry` feature.
+    pub sentry_dsn: Option<String>,
     pub yt_dlp_path: String,
+    /// `YTDLP_GEO_BYPASS=true` adds `--geo-bypass`; `YTDLP_GEO_BYPASS_COUNTRY=US` adds
+    /// `--geo-bypass-country US` instead and takes precedence.
+    pub geo_bypass: GeoBypass,
+    /// yt-dlp's own HTTP timeout and retries, from `YTDLP_SOCKET_TIMEOUT_SECS` and
+    /// `YTDLP_HTTP_RETRIES`.
+    pub yt_dlp_network: YtDlpNetwork,
+    /// Per-site cookie files from `YT_DLP_COOKIES`, e.g. `instagram.com=/secrets/ig.txt`.
+    pub cookies: CookieProfiles,
+    /// Keep yt-dlp's `.info.json` next to each download, from `YTDLP_WRITE_INFO_JSON`.
+    pub yt_dlp_write_info_json: bool,
+    /// Have yt-dlp name files so they are valid on NTFS and FAT, for downloads on a
+    /// Windows share, from `YTDLP_WINDOWS_SAFE_FILENAMES`.
+    pub yt_dlp_windows_filenames: bool,
     pub downloads_dir: PathBuf,
     pub audio_cache_dir: PathBuf,
+    pub url_cleanup_rules: Vec<UrlCleanupRule>,
+    /// How links in source descriptions appear in captions, from `CAPTION_LINKS`.
+    pub caption_links: CaptionLinks,
+    /// Add the upload date and duration under the caption header, from `CAPTION_SHOW_META`.
+    pub caption_show_meta: bool,
+    /// The "via" link opening captions, from `ATTRIBUTION`, `ATTRIBUTION_NAME` and
+    /// `ATTRIBUTION_URL`.
+    pub attribution: Attribution,
+    /// Ceiling for a whole download request, from `REQUEST_TIMEOUT_SECONDS`.
+    pub request_timeout: Duration,
+    /// Downloads running longer than this get a "still downloading" status, from
+    /// `SLOW_DOWNLOAD_WARN_SECS`; 0 disables it.
+    pub slow_download_warning: Option<Duration>,
+    /// Repeats of the same URL from the same chat within this window are dropped, from
+    /// `DEDUP_WINDOW_SECS`; 0 disables it.
+    pub dedup_window: Duration,
+    /// Downloads allowed per chat per UTC day, from `DAILY_QUOTA_PER_USER`; 0 disables it.
+    pub daily_quota: DailyDownloadQuota,
+    /// Commands allowed per chat per minute, from `COMMAND_RATE_LIMIT`; 0 disables it.
+    pub command_rate_limit: u32,
+    /// Upper bound on media_cache rows; least-recently-used entries beyond it are evicted.
+    pub cache_max_entries: Option<i64>,
+    /// Bucket for files too large for Telegram, enabled by `OBJECT_STORE_BUCKET`.
+    pub object_store: Option<ObjectStoreConfig>,
+    /// Media this many percent over the duration or size limit is downloaded with a
+    /// warning instead of rejected, from `VALIDATION_WARN_MARGIN_PERCENT`.
+    pub validation_warn_margin_percent: u32,
+    /// Videos are re-encoded to at most this bitrate before sending, from
+    /// `COMPRESSION_MAX_BITRATE_KBPS`; 0 disables it.
+    pub compression_max_bitrate_kbps: u32,
 }
 
 #[derive(Debug, Error)]
@@ -28,6 +111,14 @@ pub enum ConfigError {
     Missing(&'static str),
     #[error("Invalid value for {name}: {value}")]
     Invalid { name: &'static str, value: String },
+    #[error("Failed to read {name} from {path}: {source}")]
+    SecretFile {
+        name: &'static str,
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("{name} file {path} is empty")]
+    EmptySecretFile { name: &'static str, path: String },
     #[error("Failed to create directory {path}: {source}")]
     Directory {
         path: String,
@@ -36,10 +127,60 @@ pub enum ConfigError {
 }
 
 impl AppConfig {
+    /// The backend selected by the storage URL, with the Postgres pool settings if any.
+    pub fn storage_settings(&self) -> StorageSettings {
+        match &self.storage_url {
+            StorageUrl::Postgres(database_url) => StorageSettings::Postgres(PoolSettings {
+                database_url: database_url.clone(),
+                max_connections: self.postgres_max_connections,
+                min_connections: self.postgres_min_connections,
+                acquire_timeout: self.postgres_acquire_timeout,
+                statement_timeout: self.postgres_statement_timeout,
+            }),
+            StorageUrl::Memory => StorageSettings::Memory,
+            StorageUrl::File(path) => StorageSettings::File(path.clone()),
+            StorageUrl::Disabled => StorageSettings::Disabled,
+        }
+    }
+
     pub fn from_env() -> Result<Self, ConfigError> {
         let execution_environment =
             std::env::var("EXECUTION_ENVIRONMENT").unwrap_or_else(|_| "local".to_string());
-        let database_url = required("DATABASE_URL")?;
+        let bot_tokens = match std::env::var("TELOXIDE_TOKENS") {
+            Ok(value) => parse_bot_tokens(&value).ok_or(ConfigError::Invalid {
+                name: "TELOXIDE_TOKENS",
+                value: "<redacted>".to_string(),
+            })?,
+            Err(_) => vec![required_secret("TELOXIDE_TOKEN", "TELOXIDE_TOKEN_FILE")?],
+        };
+        let telegram_api_url = match (
+            std::env::var("TELOXIDE_API_URL"),
+            std::env::var("LOCAL_BOT_API_URL"),
+        ) {
+            (Ok(_), Ok(value)) => {
+                return Err(ConfigError::Invalid {
+                    name: "LOCAL_BOT_API_URL",
+                    value: format!("{value} (TELOXIDE_API_URL is also set)"),
+                });
+            }
+            (Ok(value), Err(_)) => Some(parse_url("TELOXIDE_API_URL", value)?),
+            (Err(_), Ok(value)) => Some(parse_url("LOCAL_BOT_API_URL", value)?),
+            (Err(_), Err(_)) => None,
+        };
+        // Only a self-hosted server is worth pointing the bot at.
+        let use_local_bot_api = telegram_api_url.is_some();
+        let telegram_mode = parse_env("TELEGRAM_MODE", TelegramMode::default())?;
+        let dry_run_latency_ms = parse_env("TELEGRAM_DRYRUN_LATENCY_MS", 0u64)?;
+        let storage_url = match std::env::var("STORAGE_URL") {
+            // The error names only the scheme, so a password in the URL is never logged.
+            Ok(value) => value
+                .parse()
+                .map_err(|e: StorageUrlError| ConfigError::Invalid {
+                    name: "STORAGE_URL",
+                    value: e.to_string(),
+                })?,
+            Err(_) => StorageUrl::Postgres(required_secret("DATABASE_URL", "DATABASE_URL_FILE")?),
+        };
         let postgres_max_connections = parse_env("POSTGRES_MAX_CONNECTIONS", 10u32)?;
         let postgres_min_connections = parse_env("POSTGRES_MIN_CONNECTIONS", 0u32)?;
         if postgres_min_connections > postgres_max_connections {
@@ -49,11 +190,15 @@ impl AppConfig {
             });
         }
         let postgres_acquire_timeout_secs = parse_env("POSTGRES_ACQUIRE_TIMEOUT_SECS", 5u64)?;
+        // 0 disables the server-side statement timeout.
+        let postgres_statement_timeout_secs = parse_env("POSTGRES_STATEMENT_TIMEOUT_SECS", 30u64)?;
+        let storage_required = parse_env("STORAGE_REQUIRED", true)?;
         let deepgram_api_key = std::env::var("DEEPGRAM_API_KEY").unwrap_or_default();
         let gemini_api_key = std::env::var("GEMINI_API_KEY").unwrap_or_default();
         let gemini_model =
             std::env::var("GEMINI_MODEL").unwrap_or_else(|_| "gemini-3.1-flash-lite".to_string());
         let owner_chat_id = parse_env("OWNER_CHAT_ID", 0i64)?;
+        let error_report_interval_mins = parse_env("ERROR_REPORT_INTERVAL_MINS", 10u64)?;
         let port = parse_env("PORT", 8080u16)?;
         let webhook_url = required("WEBHOOK_URL")?
             .parse()
@@ -61,7 +206,47 @@ impl AppConfig {
                 name: "WEBHOOK_URL",
                 value: std::env::var("WEBHOOK_URL").unwrap_or_default(),
             })?;
+        let webhook_secret = secret("WEBHOOK_SECRET", "WEBHOOK_SECRET_FILE")?;
+        if let Some(secret) = &webhook_secret
+            && !is_valid_webhook_secret(secret)
+        {
+            return Err(ConfigError::Invalid {
+                name: "WEBHOOK_SECRET",
+                value: "<redacted>".to_string(),
+            });
+        }
+        let dashboard_credentials = match (
+            std::env::var("DASHBOARD_USERNAME").ok(),
+            secret("DASHBOARD_PASSWORD", "DASHBOARD_PASSWORD_FILE")?,
+        ) {
+            (Some(username), Some(password)) => Some(DashboardCredentials { username, password }),
+            (None, None) => None,
+            (Some(_), None) => return Err(ConfigError::Missing("DASHBOARD_PASSWORD")),
+            (None, Some(_)) => return Err(ConfigError::Missing("DASHBOARD_USERNAME")),
+        };
+        let sentry_dsn = secret("SENTRY_DSN", "SENTRY_DSN_FILE")?;
         let yt_dlp_path = std::env::var("YT_DLP_PATH").unwrap_or_else(|_| "yt-dlp".to_string());
+        let geo_bypass = parse_geo_bypass(
+            parse_env("YTDLP_GEO_BYPASS", false)?,
+            std::env::var("YTDLP_GEO_BYPASS_COUNTRY").ok(),
+        )?;
+        let network_defaults = YtDlpNetwork::default();
+        let yt_dlp_network = YtDlpNetwork {
+            socket_timeout_secs: parse_env(
+                "YTDLP_SOCKET_TIMEOUT_SECS",
+                network_defaults.socket_timeout_secs,
+            )?,
+            http_retries: parse_env("YTDLP_HTTP_RETRIES", network_defaults.http_retries)?,
+        };
+        let yt_dlp_write_info_json = parse_env("YTDLP_WRITE_INFO_JSON", false)?;
+        let yt_dlp_windows_filenames = parse_env("YTDLP_WINDOWS_SAFE_FILENAMES", false)?;
+        let cookies = match std::env::var("YT_DLP_COOKIES") {
+            Ok(value) => CookieProfiles::parse(&value).ok_or(ConfigError::Invalid {
+                name: "YT_DLP_COOKIES",
+                value,
+            })?,
+            Err(_) => CookieProfiles::default(),
+        };
         let downloads_dir = PathBuf::from(
             std::env::var("DOWNLOADS_DIR").unwrap_or_else(|_| "/downloads".to_string()),
         );
@@ -70,32 +255,272 @@ impl AppConfig {
                 .unwrap_or_else(|_| downloads_dir.join("audio_cache").to_string_lossy().into()),
         );
 
+        let url_cleanup_rules = match std::env::var("URL_CLEANUP_RULES_JSON") {
+            Ok(json) => url_cleanup::parse_rules(&json).map_err(|_| ConfigError::Invalid {
+                name: "URL_CLEANUP_RULES_JSON",
+                value: json,
+            })?,
+            Err(_) => url_cleanup::default_rules(),
+        };
+
+        let caption_links = parse_env("CAPTION_LINKS", CaptionLinks::default())?;
+        let caption_show_meta = parse_env("CAPTION_SHOW_META", false)?;
+        let attribution = parse_attribution(
+            std::env::var("ATTRIBUTION").ok(),
+            std::env::var("ATTRIBUTION_NAME").ok(),
+            std::env::var("ATTRIBUTION_URL").ok(),
+        )?;
+
+        let request_timeout_secs =
+            parse_env("REQUEST_TIMEOUT_SECONDS", DEFAULT_REQUEST_TIMEOUT.as_secs())?;
+        if request_timeout_secs == 0 {
+            return Err(ConfigError::Invalid {
+                name: "REQUEST_TIMEOUT_SECONDS",
+                value: request_timeout_secs.to_string(),
+            });
+        }
+        let slow_download_warn_secs = parse_env(
+            "SLOW_DOWNLOAD_WARN_SECS",
+            DEFAULT_SLOW_DOWNLOAD_WARNING.as_secs(),
+        )?;
+        let dedup_window_secs = parse_env("DEDUP_WINDOW_SECS", DEFAULT_DEDUP_WINDOW.as_secs())?;
+        let daily_quota = DailyDownloadQuota(parse_env("DAILY_QUOTA_PER_USER", 0u32)?);
+        let command_rate_limit = parse_env("COMMAND_RATE_LIMIT", DEFAULT_COMMAND_RATE_LIMIT)?;
+        let validation_warn_margin_percent = parse_env(
+            "VALIDATION_WARN_MARGIN_PERCENT",
+            DEFAULT_WARN_MARGIN_PERCENT,
+        )?;
+        let compression_max_bitrate_kbps = parse_env("COMPRESSION_MAX_BITRATE_KBPS", 0u32)?;
+
+        let cache_max_entries = match std::env::var("CACHE_MAX_ENTRIES") {
+            Ok(value) => Some(value.parse::<i64>().ok().filter(|&n| n > 0).ok_or(
+                ConfigError::Invalid {
+                    name: "CACHE_MAX_ENTRIES",
+                    value,
+                },
+            )?),
+            Err(_) => None,
+        };
+
+        let object_store = match std::env::var("OBJECT_STORE_BUCKET") {
+            Ok(bucket) => Some(object_store_config(bucket)?),
+            Err(_) => None,
+        };
+
         ensure_dir(&downloads_dir)?;
         ensure_dir(&audio_cache_dir)?;
 
         Ok(Self {
             execution_environment,
-            database_url,
+            bot_tokens,
+            telegram_api_url,
+            use_local_bot_api,
+            telegram_mode,
+            dry_run_latency: Duration::from_millis(dry_run_latency_ms),
+            storage_url,
             postgres_max_connections,
             postgres_min_connections,
             postgres_acquire_timeout: Duration::from_secs(postgres_acquire_timeout_secs),
+            postgres_statement_timeout: (postgres_statement_timeout_secs > 0)
+                .then(|| Duration::from_secs(postgres_statement_timeout_secs)),
+            storage_required,
             deepgram_api_key,
             gemini_api_key,
             gemini_model,
             owner_chat_id,
+            error_report_interval: Duration::from_secs(error_report_interval_mins * 60),
             port,
             webhook_url,
+            webhook_secret,
+            dashboard_credentials,
+            sentry_dsn,
             yt_dlp_path,
+            geo_bypass,
+            yt_dlp_network,
+            cookies,
+            yt_dlp_write_info_json,
+            yt_dlp_windows_filenames,
             downloads_dir,
             audio_cache_dir,
+            url_cleanup_rules,
+            caption_links,
+            caption_show_meta,
+            attribution,
+            request_timeout: Duration::from_secs(request_timeout_secs),
+            slow_download_warning: (slow_download_warn_secs > 0)
+                .then(|| Duration::from_secs(slow_download_warn_secs)),
+            dedup_window: Duration::from_secs(dedup_window_secs),
+            daily_quota,
+            command_rate_limit,
+            cache_max_entries,
+            object_store,
+            validation_warn_margin_percent,
+            compression_max_bitrate_kbps,
         })
     }
 }
 
+fn object_store_config(bucket: String) -> Result<ObjectStoreConfig, ConfigError> {
+    if bucket.trim().is_empty() {
+        return Err(ConfigError::Invalid {
+            name: "OBJECT_STORE_BUCKET",
+            value: bucket,
+        });
+    }
+    let endpoint = parse_url(
+        "OBJECT_STORE_ENDPOINT",
+        std::env::var("OBJECT_STORE_ENDPOINT")
+            .unwrap_or_else(|_| "https://storage.googleapis.com".to_string()),
+    )?;
+    let region = std::env::var("OBJECT_STORE_REGION").unwrap_or_else(|_| "auto".to_string());
+    let link_expiry_hours = parse_env(
+        "OBJECT_STORE_LINK_EXPIRY_HOURS",
+        DEFAULT_LINK_EXPIRY.as_secs() / 3600,
+    )?;
+    let link_expiry = Duration::from_secs(link_expiry_hours * 3600);
+    if link_expiry.is_zero() || link_expiry > MAX_LINK_EXPIRY {
+        return Err(ConfigError::Invalid {
+            name: "OBJECT_STORE_LINK_EXPIRY_HOURS",
+            value: link_expiry_hours.to_string(),
+        });
+    }
+    Ok(ObjectStoreConfig {
+        endpoint,
+        bucket: bucket.trim().to_string(),
+        region,
+        access_key_id: required_secret(
+            "OBJECT_STORE_ACCESS_KEY_ID",
+            "OBJECT_STORE_ACCESS_KEY_ID_FILE",
+        )?,
+        secret_access_key: required_secret(
+            "OBJECT_STORE_SECRET_ACCESS_KEY",
+            "OBJECT_STORE_SECRET_ACCESS_KEY_FILE",
+        )?,
+        link_expiry,
+    })
+}
+
+/// Split a comma-separated `TELOXIDE_TOKENS` value, ignoring blanks and repeated tokens.
+/// Returns `None` when no token is left.
+fn parse_bot_tokens(value: &str) -> Option<Vec<String>> {
+    let mut tokens: Vec<String> = Vec::new();
+    for token in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !tokens.iter().any(|t| t == token) {
+            tokens.push(token.to_string());
+        }
+    }
+    (!tokens.is_empty()).then_some(tokens)
+}
+
+/// The country code, when set, wins over the plain on/off switch.
+fn parse_geo_bypass(enabled: bool, country: Option<String>) -> Result<GeoBypass, ConfigError> {
+    match country
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+    {
+        Some(code) if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) => {
+            Ok(GeoBypass::Country(code.to_ascii_uppercase()))
+        }
+        Some(code) => Err(ConfigError::Invalid {
+            name: "YTDLP_GEO_BYPASS_COUNTRY",
+            value: code,
+        }),
+        None if enabled => Ok(GeoBypass::Enabled),
+        None => Ok(GeoBypass::Disabled),
+    }
+}
+
+/// `ATTRIBUTION=off` drops the link; otherwise the name and URL fall back to the public bot.
+fn parse_attribution(
+    mode: Option<String>,
+    name: Option<String>,
+    url: Option<String>,
+) -> Result<Attribution, ConfigError> {
+    match mode.as_deref().map(str::trim) {
+        None | Some("on") => {}
+        Some("off") => return Ok(Attribution::Off),
+        Some(_) => {
+            return Err(ConfigError::Invalid {
+                name: "ATTRIBUTION",
+                value: mode.unwrap_or_default(),
+            });
+        }
+    }
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_ATTRIBUTION_NAME.to_string());
+    let url = parse_url(
+        "ATTRIBUTION_URL",
+        url.unwrap_or_else(|| DEFAULT_ATTRIBUTION_URL.to_string()),
+    )?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ConfigError::Invalid {
+            name: "ATTRIBUTION_URL",
+            value: url.to_string(),
+        });
+    }
+    Ok(Attribution::Link { name, url })
+}
+
+fn parse_url(name: &'static str, value: String) -> Result<Url, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Invalid { name, value })
+}
+
 fn required(name: &'static str) -> Result<String, ConfigError> {
     std::env::var(name).map_err(|_| ConfigError::Missing(name))
 }
 
+/// A secret from the `name` variable or, for Docker/Kubernetes secret mounts, from the file
+/// named by `file_var`. The variable wins when both are set.
+fn secret(name: &'static str, file_var: &'static str) -> Result<Option<String>, ConfigError> {
+    resolve_secret(
+        name,
+        std::env::var(name).ok(),
+        std::env::var_os(file_var).map(PathBuf::from),
+    )
+}
+
+fn required_secret(name: &'static str, file_var: &'static str) -> Result<String, ConfigError> {
+    secret(name, file_var)?.ok_or(ConfigError::Missing(name))
+}
+
+fn resolve_secret(
+    name: &'static str,
+    value: Option<String>,
+    file: Option<PathBuf>,
+) -> Result<Option<String>, ConfigError> {
+    if value.is_some() {
+        return Ok(value);
+    }
+    let Some(file) = file else {
+        return Ok(None);
+    };
+    let contents = std::fs::read_to_string(&file).map_err(|source| ConfigError::SecretFile {
+        name,
+        path: file.display().to_string(),
+        source,
+    })?;
+    let contents = contents.trim();
+    if contents.is_empty() {
+        return Err(ConfigError::EmptySecretFile {
+            name,
+            path: file.display().to_string(),
+        });
+    }
+    Ok(Some(contents.to_string()))
+}
+
+/// Telegram accepts 1-256 characters from `A-Z`, `a-z`, `0-9`, `_` and `-`.
+fn is_valid_webhook_secret(secret: &str) -> bool {
+    (1..=256).contains(&secret.len())
+        && secret
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
 fn parse_env<T>(name: &'static str, default: T) -> Result<T, ConfigError>
 where
     T: std::str::FromStr,
@@ -114,3 +539,146 @@ fn ensure_dir(path: &std::path::Path) -> Result<(), ConfigError> {
         source,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_safe_filenames_env_enables_the_flag() {
+        let downloads_dir = tempfile::tempdir().unwrap();
+        let env = [
+            ("TELOXIDE_TOKEN", "123:token"),
+            ("STORAGE_URL", "memory://"),
+            ("WEBHOOK_URL", "https://bot.example.com/webhook"),
+            ("DOWNLOADS_DIR", downloads_dir.path().to_str().unwrap()),
+        ];
+        // SAFETY: no other test sets these variables or reads them while this one runs.
+        unsafe {
+            for (name, value) in env {
+                std::env::set_var(name, value);
+            }
+        }
+        let default = AppConfig::from_env().unwrap();
+        unsafe { std::env::set_var("YTDLP_WINDOWS_SAFE_FILENAMES", "true") };
+        let enabled = AppConfig::from_env().unwrap();
+        unsafe {
+            for (name, _) in env {
+                std::env::remove_var(name);
+            }
+            std::env::remove_var("YTDLP_WINDOWS_SAFE_FILENAMES");
+        }
+
+        assert!(!default.yt_dlp_windows_filenames);
+        assert!(enabled.yt_dlp_windows_filenames);
+    }
+
+    #[test]
+    fn test_parse_geo_bypass() {
+        assert_eq!(parse_geo_bypass(false, None).unwrap(), GeoBypass::Disabled);
+        assert_eq!(parse_geo_bypass(true, None).unwrap(), GeoBypass::Enabled);
+        assert_eq!(
+            parse_geo_bypass(true, Some("us".to_string())).unwrap(),
+            GeoBypass::Country("US".to_string())
+        );
+        assert_eq!(
+            parse_geo_bypass(false, Some(" DE ".to_string())).unwrap(),
+            GeoBypass::Country("DE".to_string())
+        );
+        assert_eq!(
+            parse_geo_bypass(true, Some(String::new())).unwrap(),
+            GeoBypass::Enabled
+        );
+        assert!(parse_geo_bypass(false, Some("USA".to_string())).is_err());
+        assert!(parse_geo_bypass(false, Some("U1".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_parse_attribution() {
+        assert_eq!(
+            parse_attribution(None, None, None).unwrap(),
+            Attribution::default()
+        );
+        assert_eq!(
+            parse_attribution(
+                Some("on".to_string()),
+                Some("MyBot".to_string()),
+                Some("https://example.com/bot".to_string())
+            )
+            .unwrap(),
+            Attribution::Link {
+                name: "MyBot".to_string(),
+                url: Url::parse("https://example.com/bot").unwrap()
+            }
+        );
+        assert_eq!(
+            parse_attribution(Some("off".to_string()), Some("MyBot".to_string()), None).unwrap(),
+            Attribution::Off
+        );
+        assert!(parse_attribution(Some("no".to_string()), None, None).is_err());
+        assert!(parse_attribution(None, None, Some("javascript:alert(1)".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_resolve_secret_prefers_variable_over_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"from-file\n").unwrap();
+        let path = Some(file.path().to_path_buf());
+
+        assert_eq!(
+            resolve_secret("TOKEN", Some("from-env".to_string()), path.clone()).unwrap(),
+            Some("from-env".to_string())
+        );
+        assert_eq!(
+            resolve_secret("TOKEN", None, path).unwrap(),
+            Some("from-file".to_string())
+        );
+        assert_eq!(resolve_secret("TOKEN", None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve_secret_rejects_empty_or_missing_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), " \n").unwrap();
+        assert!(matches!(
+            resolve_secret("TOKEN", None, Some(file.path().to_path_buf())),
+            Err(ConfigError::EmptySecretFile { name: "TOKEN", .. })
+        ));
+
+        let missing = file.path().with_extension("missing");
+        assert!(matches!(
+            resolve_secret("TOKEN", None, Some(missing)),
+            Err(ConfigError::SecretFile { name: "TOKEN", .. })
+        ));
+    }
+
+    #[test]
+    fn test_is_valid_webhook_secret() {
+        assert!(is_valid_webhook_secret("abc_DEF-123"));
+        assert!(!is_valid_webhook_secret(""));
+        assert!(!is_valid_webhook_secret("has space"));
+        assert!(!is_valid_webhook_secret(&"a".repeat(257)));
+    }
+
+    #[test]
+    fn test_parse_bot_tokens_splits_and_trims() {
+        assert_eq!(
+            parse_bot_tokens("123:abc, 456:def ,"),
+            Some(vec!["123:abc".to_string(), "456:def".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_bot_tokens_drops_duplicates() {
+        assert_eq!(
+            parse_bot_tokens("123:abc,123:abc"),
+            Some(vec!["123:abc".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_bot_tokens_rejects_empty_list() {
+        assert_eq!(parse_bot_tokens(""), None);
+        assert_eq!(parse_bot_tokens(" , ,"), None);
+    }
+}
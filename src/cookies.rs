@@ -0,0 +1,152 @@
+//! Per-site yt-dlp cookie files, so a session for one site is never sent to another.
+//!
+//! `YT_DLP_COOKIES` maps hosts to Netscape cookie files, e.g.
+//! `instagram.com=/secrets/ig.txt;youtube.com=/secrets/yt.txt`. A host also matches its
+//! subdomains, the most specific host wins, and `*=/path` sets a default for every other site.
+
+use std::path::{Path, PathBuf};
+
+use crate::url_cleanup::ascii_host;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookieProfiles {
+    /// (ASCII host, cookie file), most specific host first.
+    profiles: Vec<(String, PathBuf)>,
+    default: Option<PathBuf>,
+}
+
+impl CookieProfiles {
+    /// Parse `host=path` entries separated by `;`. Returns `None` for malformed entries or
+    /// a host listed twice.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut profiles = Self::default();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (host, path) = entry.split_once('=')?;
+            let (host, path) = (host.trim(), path.trim());
+            if host.is_empty() || path.is_empty() {
+                return None;
+            }
+            if host == "*" {
+                if profiles.default.is_some() {
+                    return None;
+                }
+                profiles.default = Some(PathBuf::from(path));
+                continue;
+            }
+            let host = ascii_host(host);
+            if profiles.profiles.iter().any(|(h, _)| *h == host) {
+                return None;
+            }
+            profiles.profiles.push((host, PathBuf::from(path)));
+        }
+        profiles
+            .profiles
+            .sort_by_key(|(host, _)| std::cmp::Reverse(host.len()));
+        Some(profiles)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty() && self.default.is_none()
+    }
+
+    /// Cookie file for `host`: the most specific matching profile, else the default.
+    pub fn for_host(&self, host: &str) -> Option<&Path> {
+        let host = ascii_host(host);
+        self.profiles
+            .iter()
+            .find(|(profile, _)| {
+                host == *profile
+                    || host
+                        .strip_suffix(profile.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .map(|(_, path)| path.as_path())
+            .or(self.default.as_deref())
+    }
+
+    /// Log the configured profiles, warning about cookie files that don't exist.
+    pub fn log_startup_summary(&self) {
+        let entries = self
+            .profiles
+            .iter()
+            .map(|(host, path)| (host.as_str(), path))
+            .chain(self.default.iter().map(|path| ("*", path)));
+        for (host, path) in entries {
+            if path.is_file() {
+                log::info!("yt-dlp cookies for {}: {}", host, path.display());
+            } else {
+                log::warn!(
+                    "yt-dlp cookies file for {} not found: {}",
+                    host,
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles() -> CookieProfiles {
+        CookieProfiles::parse(
+            "instagram.com=/secrets/ig.txt; youtube.com=/secrets/yt.txt;\
+             music.youtube.com=/secrets/ytm.txt",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_for_host_matches_exact_host_and_subdomains() {
+        let profiles = profiles();
+        assert_eq!(
+            profiles.for_host("instagram.com"),
+            Some(Path::new("/secrets/ig.txt"))
+        );
+        assert_eq!(
+            profiles.for_host("www.instagram.com"),
+            Some(Path::new("/secrets/ig.txt"))
+        );
+        assert_eq!(
+            profiles.for_host("M.YouTube.com."),
+            Some(Path::new("/secrets/yt.txt"))
+        );
+        assert_eq!(profiles.for_host("notinstagram.com"), None);
+        assert_eq!(profiles.for_host("example.com"), None);
+    }
+
+    #[test]
+    fn test_for_host_prefers_most_specific_profile() {
+        assert_eq!(
+            profiles().for_host("music.youtube.com"),
+            Some(Path::new("/secrets/ytm.txt"))
+        );
+    }
+
+    #[test]
+    fn test_default_profile_is_fallback() {
+        let profiles =
+            CookieProfiles::parse("x.com=/secrets/x.txt;*=/secrets/default.txt").unwrap();
+        assert_eq!(
+            profiles.for_host("x.com"),
+            Some(Path::new("/secrets/x.txt"))
+        );
+        assert_eq!(
+            profiles.for_host("vimeo.com"),
+            Some(Path::new("/secrets/default.txt"))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_entries() {
+        assert_eq!(CookieProfiles::parse("instagram.com"), None);
+        assert_eq!(CookieProfiles::parse("=/secrets/ig.txt"), None);
+        assert_eq!(CookieProfiles::parse("instagram.com="), None);
+        assert_eq!(
+            CookieProfiles::parse("instagram.com=/a.txt;Instagram.com=/b.txt"),
+            None
+        );
+        assert!(CookieProfiles::parse("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! `/dashboard`: a server-rendered status page for operators, behind HTTP basic auth.
+//!
+//! It shows what `/about`, `/queue` and `/stats` report in Telegram, gathered through the
+//! same `Storage` and `ConcurrencyLimiter` the bot uses.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::about::AboutInfo;
+use crate::concurrency::{ActiveRequest, BotChat, ConcurrencyLimiter};
+use crate::downloader::escape_html_text;
+use crate::storage::{CacheStats, RequestLogEntry, Storage};
+
+/// Requests listed on the page, newest first.
+const RECENT_REQUESTS: i64 = 20;
+
+/// Username and password for the page, from `DASHBOARD_USERNAME` and `DASHBOARD_PASSWORD`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashboardCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl DashboardCredentials {
+    /// Whether an `Authorization` header value carries these credentials. Digests of both
+    /// are compared in constant time, so neither the contents nor the length leak.
+    fn accepts(&self, authorization: &str) -> bool {
+        let Some(encoded) = authorization.strip_prefix("Basic ") else {
+            return false;
+        };
+        let Ok(decoded) = BASE64.decode(encoded.trim()) else {
+            return false;
+        };
+        let expected = format!("{}:{}", self.username, self.password);
+        Sha256::digest(decoded)
+            .ct_eq(&Sha256::digest(expected))
+            .into()
+    }
+}
+
+/// Everything the page is rendered from.
+#[derive(Clone)]
+pub struct DashboardState {
+    pub credentials: DashboardCredentials,
+    pub about: Arc<AboutInfo>,
+    /// When the process started, for the uptime.
+    pub started: Instant,
+    pub download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
+    pub storage: Arc<dyn Storage>,
+}
+
+/// The `/dashboard` route.
+pub fn router(state: DashboardState) -> Router {
+    Router::new()
+        .route("/dashboard", get(dashboard))
+        .with_state(state)
+}
+
+async fn dashboard(State(state): State<DashboardState>, headers: HeaderMap) -> Response {
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| state.credentials.accepts(value));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="CrabberBot""#)],
+        )
+            .into_response();
+    }
+    let now = Instant::now();
+    let page = render(
+        &state.about,
+        now.saturating_duration_since(state.started),
+        &state.download_limiter.active_requests(),
+        now,
+        &state.storage.cache_stats().await,
+        &state.storage.recent_requests(RECENT_REQUESTS).await,
+    );
+    Html(page).into_response()
+}
+
+/// "3d 4h 5m", leaving out leading zero units.
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+fn render(
+    about: &AboutInfo,
+    uptime: Duration,
+    active: &[(BotChat, ActiveRequest)],
+    now: Instant,
+    cache: &CacheStats,
+    requests: &[RequestLogEntry],
+) -> String {
+    let mut active: Vec<&(BotChat, ActiveRequest)> = active.iter().collect();
+    active.sort_by_key(|(_, request)| request.started);
+    let active_rows: String = active
+        .iter()
+        .map(|(key, request)| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}s</td></tr>\n",
+                key.chat_id,
+                escape_html_text(request.domain.as_deref().unwrap_or("unknown site")),
+                request.phase,
+                now.saturating_duration_since(request.started).as_secs()
+            )
+        })
+        .collect();
+    let request_rows: String = requests
+        .iter()
+        .map(|request| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                request.created_at.format("%Y-%m-%d %H:%M:%S"),
+                request.chat_id,
+                escape_html_text(&request.source_url),
+                escape_html_text(&request.status),
+                request
+                    .processing_time_ms
+                    .map_or_else(|| "–".to_string(), |ms| format!("{ms} ms"))
+            )
+        })
+        .collect();
+    indoc::formatdoc! { r#"
+        <!DOCTYPE html>
+        <html>
+        <head><meta charset="utf-8"><title>CrabberBot dashboard</title></head>
+        <body>
+        <h1>CrabberBot {version}</h1>
+        <p>Commit {commit} · up {uptime} · storage {storage} · yt-dlp {yt_dlp}</p>
+        <p>Media cache: {entries} entries, {files} files</p>
+        <h2>Active downloads ({active_count})</h2>
+        <table>
+        <tr><th>Chat</th><th>Site</th><th>Phase</th><th>Running</th></tr>
+        {active_rows}</table>
+        <h2>Recent requests</h2>
+        <table>
+        <tr><th>Time (UTC)</th><th>Chat</th><th>URL</th><th>Status</th><th>Duration</th></tr>
+        {request_rows}</table>
+        </body>
+        </html>
+        "#,
+        version = escape_html_text(&about.version),
+        commit = escape_html_text(&about.commit),
+        uptime = format_uptime(uptime),
+        storage = about.storage_backend,
+        yt_dlp = escape_html_text(about.yt_dlp_version.as_deref().unwrap_or("unknown")),
+        entries = cache.entries,
+        files = cache.files,
+        active_count = active.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+    use crate::validator::ValidationConfig;
+    use axum::body::Body;
+    use axum::http::Request;
+    use teloxide::types::{ChatId, UserId};
+    use tower::ServiceExt;
+
+    fn state(
+        storage: MockStorage,
+        download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
+    ) -> DashboardState {
+        DashboardState {
+            credentials: DashboardCredentials {
+                username: "admin".to_string(),
+                password: "hunter2".to_string(),
+            },
+            about: Arc::new(AboutInfo {
+                version: "2.1.0".to_string(),
+                commit: "0123456".to_string(),
+                execution_environment: "test".to_string(),
+                storage_backend: "PostgreSQL",
+                owner_configured: true,
+                validation: ValidationConfig::default(),
+                yt_dlp_version: Some("2025.01.01".to_string()),
+            }),
+            started: Instant::now(),
+            download_limiter,
+            storage: Arc::new(storage),
+        }
+    }
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut request = Request::get("/dashboard");
+        if let Some(credentials) = authorization {
+            request = request.header(
+                header::AUTHORIZATION,
+                format!("Basic {}", BASE64.encode(credentials)),
+            );
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_renders_for_the_operator() {
+        let mut storage = MockStorage::new();
+        storage.expect_cache_stats().returning(|| CacheStats {
+            entries: 12,
+            files: 15,
+        });
+        storage
+            .expect_recent_requests()
+            .withf(|limit| *limit == RECENT_REQUESTS)
+            .returning(|_| {
+                vec![RequestLogEntry {
+                    chat_id: 42,
+                    source_url: "https://example.com/a?b=1&c=<2>".to_string(),
+                    status: "success".to_string(),
+                    processing_time_ms: Some(1500),
+                    created_at: chrono::Utc::now(),
+                }]
+            });
+        let download_limiter = Arc::new(ConcurrencyLimiter::new());
+        let _guard = download_limiter.try_lock(BotChat {
+            bot_id: UserId(1),
+            chat_id: ChatId(7),
+        });
+        let app = router(state(storage, download_limiter));
+
+        let response = app.oneshot(request(Some("admin:hunter2"))).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("<h1>CrabberBot 2.1.0</h1>"));
+        assert!(page.contains("yt-dlp 2025.01.01"));
+        assert!(page.contains("Media cache: 12 entries, 15 files"));
+        assert!(page.contains("<h2>Active downloads (1)</h2>"));
+        assert!(page.contains("<td>7</td><td>unknown site</td><td>starting</td>"));
+        assert!(page.contains(
+            "<td>https://example.com/a?b=1&amp;c=&lt;2&gt;</td><td>success</td><td>1500 ms</td>"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_requires_the_right_credentials() {
+        let mut storage = MockStorage::new();
+        storage.expect_cache_stats().never();
+        storage.expect_recent_requests().never();
+        let app = router(state(storage, Arc::new(ConcurrencyLimiter::new())));
+
+        for authorization in [None, Some("admin:wrong"), Some("admin")] {
+            let response = app.clone().oneshot(request(authorization)).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{authorization:?}"
+            );
+            assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+        }
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(59)), "0m");
+        assert_eq!(
+            format_uptime(Duration::from_secs(2 * 3600 + 5 * 60)),
+            "2h 5m"
+        );
+        assert_eq!(
+            format_uptime(Duration::from_secs(3 * 86400 + 60)),
+            "3d 0h 1m"
+        );
+    }
+}
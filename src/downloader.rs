@@ -1,29 +1,136 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use url::Url;
 use uuid::Uuid;
 
+use crate::caption_links::{CaptionLinks, CaptionSegment, description_segments};
+use crate::child_processes::ChildProcesses;
+use crate::cookies::CookieProfiles;
+use crate::probe::{CorruptFile, verify_media_file};
+use crate::validator::MAX_PLAYLIST_ITEMS;
+
 const METADATA_TIMEOUT: Duration = Duration::from_secs(30);
 const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
-
-#[derive(Error, Debug, PartialEq)]
+/// yt-dlp `-S` format sort: prefer H.264, which every Telegram client plays inline. Used for
+/// metadata too, so `MediaInfo::vcodec` describes the format that will be downloaded.
+const FORMAT_SORT: &str = "vcodec:h264,res,acodec:m4a";
+/// Container for separately downloaded video and audio streams; yt-dlp may otherwise pick
+/// `.mkv`, which Telegram doesn't play inline.
+const MERGE_OUTPUT_FORMAT: &str = "mp4";
+/// Number of recent downloads averaged for `estimate_download_time`.
+const DOWNLOAD_SPEED_SAMPLES: usize = 20;
+/// Telegram only accepts video thumbnails as JPEGs up to 320px on the longest side.
+const THUMBNAIL_MAX_SIDE: u32 = 320;
+/// Largest video thumbnail Telegram accepts.
+const THUMBNAIL_MAX_BYTES: usize = 200 * 1024;
+/// JPEG qualities tried in turn until the thumbnail fits in `THUMBNAIL_MAX_BYTES`.
+const THUMBNAIL_JPEG_QUALITIES: [u8; 4] = [85, 70, 55, 40];
+/// JPEG quality for cover images sent as photos, which Telegram recompresses anyway.
+const COVER_JPEG_QUALITY: u8 = 90;
+
+#[derive(Error, Debug)]
 pub enum DownloadError {
     #[error("yt-dlp command failed: {0}")]
     CommandFailed(String),
+    /// yt-dlp could not be spawned or its output could not be read.
+    #[error("I/O error while {context}")]
+    IoError {
+        context: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("Failed to parse yt-dlp output: {0}")]
     ParsingFailed(String),
+    #[error("Invalid JSON from {context}")]
+    InvalidJson {
+        context: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
     #[error("yt-dlp timed out after {0} seconds")]
     Timeout(u64),
+    /// The site refuses to serve the media to the server's region.
+    #[error("media is geo-restricted: {0}")]
+    GeoBlocked(String),
+    /// A shortened link could not be followed to the URL it points to.
+    #[error("could not expand short URL")]
+    ShortUrlExpansion(#[source] reqwest::Error),
+    /// The downloader doesn't implement the operation.
+    #[error("not supported by this downloader: {0}")]
+    Unsupported(&'static str),
+    /// yt-dlp succeeded but left an empty or truncated file, usually because the site
+    /// dropped the connection.
+    #[error("downloaded file {} is corrupted", path.display())]
+    Corrupted {
+        path: PathBuf,
+        #[source]
+        source: CorruptFile,
+    },
+}
+
+/// Phrases yt-dlp and its extractors use when a site blocks the server's region.
+const GEO_BLOCK_MARKERS: &[&str] = &[
+    "not available in your country",
+    "not available in your region",
+    "not available from your location",
+    "geo restriction",
+    "geo-restricted",
+    "georestricted",
+    "blocked it in your country",
+];
+
+impl DownloadError {
+    /// The error for a failed yt-dlp run, told apart by its stderr.
+    pub fn from_yt_dlp_stderr(stderr: &str) -> Self {
+        let lowercase = stderr.to_lowercase();
+        if GEO_BLOCK_MARKERS
+            .iter()
+            .any(|marker| lowercase.contains(marker))
+        {
+            Self::GeoBlocked(stderr.to_string())
+        } else {
+            Self::CommandFailed(stderr.to_string())
+        }
+    }
+
+    /// Reply for the user, without paths, stderr or other internals.
+    pub fn display_to_user(&self) -> &'static str {
+        match self {
+            Self::Timeout(_) => {
+                "Sorry, the download is taking too long. Please try a shorter video."
+            }
+            Self::GeoBlocked(_) => {
+                "🌍 This content is not available in the server's region. Try a VPN or access it directly."
+            }
+            Self::Corrupted { .. } => {
+                "Sorry, the source delivered a corrupted file. Please try again."
+            }
+            _ => "Sorry, I could not download the media. Please try again later.",
+        }
+    }
+
+    /// The error with its full chain of causes, for operator logs.
+    pub fn display_to_log(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            message.push_str(": ");
+            message.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        message
+    }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum MediaType {
     Video,
     Photo,
@@ -74,12 +181,27 @@ pub struct MediaInfo {
     pub uploader: Option<String>,
     #[serde(default)]
     pub playlist_uploader: Option<String>,
+    /// Link to the uploader's channel or profile page.
+    #[serde(default)]
+    pub channel_url: Option<String>,
+    /// yt-dlp extractor that handled the URL, e.g. "youtube" or "Instagram".
+    #[serde(default)]
+    pub extractor: Option<String>,
     #[serde(default)]
     pub thumbnail: Option<String>,
     #[serde(default)]
     pub duration: Option<f64>,
+    /// Upload day as yt-dlp reports it, e.g. "20241102".
+    #[serde(default)]
+    pub upload_date: Option<String>,
     #[serde(rename = "filesize_approx", default)]
     pub filesize: Option<u64>,
+    /// File extension of the selected format, e.g. "mp4" or "jpg".
+    #[serde(default)]
+    pub ext: Option<String>,
+    /// Position of a playlist entry in its playlist, counting from 1.
+    #[serde(default)]
+    pub playlist_index: Option<usize>,
     #[serde(default)]
     pub entries: Option<Vec<MediaInfo>>,
     #[serde(default)]
@@ -88,23 +210,378 @@ pub struct MediaInfo {
     pub width: Option<u32>,
     #[serde(default)]
     pub height: Option<u32>,
+    /// Set by yt-dlp while the stream is still broadcasting.
+    #[serde(default)]
+    pub is_live: Option<bool>,
+    /// Set by yt-dlp for finished broadcasts, which download like regular videos.
+    #[serde(default)]
+    pub was_live: Option<bool>,
+    /// Video codec of the selected format, e.g. "avc1.64001F" or "hvc1.1.6.L93.B0".
+    #[serde(default)]
+    pub vcodec: Option<String>,
+    /// Audio codec of the selected format, e.g. "mp4a.40.2" or "opus".
+    #[serde(default)]
+    pub acodec: Option<String>,
+    /// Every format yt-dlp found, not only the selected one.
+    #[serde(default)]
+    pub formats: Vec<MediaFormat>,
+    /// yt-dlp `-f` selector to download instead of the default format, set by `fit_format_to`.
+    #[serde(skip)]
+    pub format_selector: Option<String>,
+    /// How long each phase of fetching this media took, filled in as they finish.
+    #[serde(skip)]
+    pub timings: DownloadTimings,
+}
+
+/// Durations of the phases of a download; `None` for phases that did not run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DownloadTimings {
+    /// yt-dlp `--dump-single-json`.
+    pub metadata: Option<Duration>,
+    /// The yt-dlp download run, including its stream merge and the raw thumbnail.
+    pub download: Option<Duration>,
+    /// Converting the thumbnail into one Telegram accepts.
+    pub thumbnail: Option<Duration>,
+    /// Post-download hooks such as re-encoding.
+    pub post_processing: Option<Duration>,
+}
+
+impl fmt::Display for DownloadTimings {
+    /// "metadata 1.2s · download 3.4s", listing only the phases that ran.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phases: Vec<String> = [
+            ("metadata", self.metadata),
+            ("download", self.download),
+            ("thumbnail", self.thumbnail),
+            ("post-processing", self.post_processing),
+        ]
+        .into_iter()
+        .filter_map(|(phase, duration)| {
+            duration.map(|duration| format!("{phase} {:.1}s", duration.as_secs_f64()))
+        })
+        .collect();
+        if phases.is_empty() {
+            f.write_str("no timings")
+        } else {
+            f.write_str(&phases.join(" · "))
+        }
+    }
+}
+
+/// `caption` with the download timings appended in italics, for debugging in the owner's
+/// chat. Left unchanged when the footer would push it over Telegram's caption limit.
+pub fn append_timings_footer(caption: String, timings: &DownloadTimings) -> String {
+    let footer = format!("\n\n<i>⏱ {timings}</i>");
+    let visible_len = |s: &str| s.chars().count();
+    if visible_len(&caption) + visible_len(&footer) > MediaInfo::TELEGRAM_CAPTION_LIMIT {
+        return caption;
+    }
+    caption + &footer
+}
+
+/// One row of yt-dlp's format table. yt-dlp reports a missing stream as the codec "none"
+/// and an unknown codec as null.
+#[derive(Debug, Deserialize, PartialEq, Clone, Default)]
+pub struct MediaFormat {
+    pub format_id: String,
+    #[serde(default)]
+    pub ext: Option<String>,
+    #[serde(default)]
+    pub vcodec: Option<String>,
+    #[serde(default)]
+    pub acodec: Option<String>,
+    #[serde(default)]
+    pub filesize: Option<u64>,
+    #[serde(default)]
+    pub filesize_approx: Option<u64>,
+    #[serde(default)]
+    pub height: Option<u32>,
+}
+
+impl MediaFormat {
+    /// Exact size when known, otherwise yt-dlp's estimate.
+    pub fn size(&self) -> Option<u64> {
+        self.filesize.or(self.filesize_approx)
+    }
+
+    fn is_h264_video(&self) -> bool {
+        self.vcodec.as_deref().is_some_and(|codec| {
+            ["avc1", "avc3", "h264"]
+                .iter()
+                .any(|p| codec.starts_with(p))
+        })
+    }
+
+    fn is_aac_audio(&self) -> bool {
+        self.acodec
+            .as_deref()
+            .is_some_and(|codec| ["mp4a", "aac"].iter().any(|p| codec.starts_with(p)))
+    }
+
+    fn is_video_only(&self) -> bool {
+        self.is_h264_video() && self.acodec.as_deref() == Some("none")
+    }
+
+    fn is_audio_only(&self) -> bool {
+        self.is_aac_audio() && self.vcodec.as_deref() == Some("none")
+    }
+
+    /// A single file with H.264 video and AAC audio. Progressive mp4s with unknown codecs,
+    /// such as Twitter's, are taken to be H.264 and AAC too.
+    fn is_progressive(&self) -> bool {
+        let unknown_mp4 = self.vcodec.is_none()
+            && self.acodec.is_none()
+            && self.height.is_some()
+            && self.ext.as_deref() == Some("mp4");
+        (self.is_h264_video() && self.is_aac_audio()) || unknown_mp4
+    }
+}
+
+/// What `MediaInfo::sanitize_for_logging` keeps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SanitizedMetadata {
+    pub id: String,
+    pub ext: Option<String>,
+    pub duration: Option<f64>,
+    pub filesize: Option<u64>,
+    pub has_entries: bool,
+    pub entry_count: usize,
+}
+
+impl MediaInfo {
+    /// Maximum caption length Telegram accepts for media messages.
+    pub const TELEGRAM_CAPTION_LIMIT: usize = 1024;
+
+    /// The caption `build_caption` produces for this media, with its length in bytes.
+    #[must_use]
+    pub fn build_caption_preview(
+        &self,
+        source_url: &Url,
+        options: &CaptionOptions,
+    ) -> (String, usize) {
+        let caption = build_caption(self, source_url, options);
+        let len = caption.len();
+        (caption, len)
+    }
+
+    /// yt-dlp `-o` template for a download, e.g. `<uuid>.%(id)s.%(ext)s`. An empty
+    /// `temp_dir` yields a path relative to yt-dlp's working directory.
+    pub fn output_filename_template(uuid: &str, temp_dir: &str) -> String {
+        join_temp_dir(temp_dir, &format!("{uuid}.%(id)s.%(ext)s"))
+    }
+
+    /// Path yt-dlp writes to for `output_filename_template` once `id` and `ext` are known.
+    pub fn expected_filepath(uuid: &str, id: &str, ext: &str, temp_dir: &str) -> String {
+        join_temp_dir(temp_dir, &format!("{uuid}.{id}.{ext}"))
+    }
+
+    /// Combined approximate size of a playlist's entries, counting unknown sizes as 0.
+    /// `None` for single media and when no entry size is known.
+    pub fn playlist_total_filesize(&self) -> Option<u64> {
+        let entries = self.entries.as_ref()?;
+        entries
+            .iter()
+            .any(|entry| entry.filesize.is_some())
+            .then(|| {
+                entries
+                    .iter()
+                    .map(|entry| entry.filesize.unwrap_or(0))
+                    .sum()
+            })
+    }
+
+    /// Whether this is a playlist made only of images, such as an Instagram carousel, judged
+    /// by the entries' extensions: yt-dlp sets their `_type` inconsistently across sites.
+    /// A gallery mixing photos and videos is not one.
+    pub fn is_image_gallery(&self) -> bool {
+        const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+        self.entries.as_ref().is_some_and(|entries| {
+            !entries.is_empty()
+                && entries.iter().all(|entry| {
+                    entry.ext.as_deref().is_some_and(|ext| {
+                        IMAGE_EXTENSIONS
+                            .iter()
+                            .any(|image| ext.eq_ignore_ascii_case(image))
+                    })
+                })
+        })
+    }
+
+    /// The fields worth logging: nothing the uploader wrote, such as the title or
+    /// description, and nothing that identifies them.
+    pub fn sanitize_for_logging(&self) -> SanitizedMetadata {
+        let entry_count = self.entries.as_ref().map_or(0, Vec::len);
+        SanitizedMetadata {
+            id: self.id.clone(),
+            ext: self.ext.clone(),
+            duration: self.duration,
+            filesize: self.filesize,
+            has_entries: self.entries.is_some(),
+            entry_count,
+        }
+    }
+
+    /// Whether the URL points at a stream that is currently live and would never finish downloading.
+    pub fn is_live_stream(&self) -> bool {
+        self.is_live.unwrap_or(false)
+    }
+
+    /// Video codec of the selected format; `None` when unknown or audio-only (yt-dlp's "none").
+    pub fn video_codec(&self) -> Option<&str> {
+        self.vcodec.as_deref().filter(|codec| *codec != "none")
+    }
+
+    /// Audio codec of the selected format; `None` when unknown or silent (yt-dlp's "none").
+    pub fn audio_codec(&self) -> Option<&str> {
+        self.acodec.as_deref().filter(|codec| *codec != "none")
+    }
+
+    /// The `-f` selector for the highest-resolution H.264 and AAC download of at most
+    /// `limit_bytes`: a progressive format, or a video-only format merged with the largest
+    /// audio-only format that still fits. Formats of unknown size are never chosen.
+    pub fn select_format(&self, limit_bytes: u64) -> Option<String> {
+        self.best_format_within(limit_bytes)
+            .map(|(selector, _, _)| selector)
+    }
+
+    /// The selector, size and height of the best format within `limit_bytes`.
+    fn best_format_within(&self, limit_bytes: u64) -> Option<(String, u64, Option<u32>)> {
+        let sized = |format: &&MediaFormat| format.size().is_some_and(|size| size <= limit_bytes);
+        let audio: Vec<&MediaFormat> = self
+            .formats
+            .iter()
+            .filter(|f| f.is_audio_only())
+            .filter(sized)
+            .collect();
+
+        let progressive = self
+            .formats
+            .iter()
+            .filter(|f| f.is_progressive())
+            .filter(sized)
+            .map(|f| (f.height, f.format_id.clone(), f.size().unwrap_or_default()));
+        let merged = self
+            .formats
+            .iter()
+            .filter(|f| f.is_video_only() && f.height.is_some())
+            .filter(sized)
+            .filter_map(|video| {
+                let video_size = video.size().unwrap_or_default();
+                audio
+                    .iter()
+                    .map(|a| (a, video_size + a.size().unwrap_or_default()))
+                    .filter(|(_, size)| *size <= limit_bytes)
+                    .max_by_key(|(_, size)| *size)
+                    .map(|(a, size)| {
+                        (
+                            video.height,
+                            format!("{}+{}", video.format_id, a.format_id),
+                            size,
+                        )
+                    })
+            });
+
+        progressive
+            .chain(merged)
+            .max_by_key(|(height, _, size)| (*height, *size))
+            .map(|(height, selector, size)| (selector, size, height))
+    }
+
+    /// Switch a single video to the best format that fits `limit_bytes` when the default
+    /// one is larger or of unknown size. Leaves the media unchanged when nothing fits, so
+    /// validation still rejects it.
+    pub fn fit_format_to(&mut self, limit_bytes: u64) {
+        if self.entries.is_some() || self.filesize.is_some_and(|size| size <= limit_bytes) {
+            return;
+        }
+        if let Some((selector, size, height)) = self.best_format_within(limit_bytes) {
+            log::info!(
+                "Selected format {} ({} bytes) for {} to fit {} bytes",
+                selector,
+                size,
+                self.id,
+                limit_bytes
+            );
+            self.format_selector = Some(selector);
+            self.filesize = Some(size);
+            // Keep the dimensions sent to Telegram in line with the new format.
+            self.width = match (self.width, self.height, height) {
+                (Some(width), Some(old_height), Some(new_height)) if old_height > 0 => {
+                    Some((u64::from(width) * u64::from(new_height) / u64::from(old_height)) as u32)
+                }
+                _ => None,
+            };
+            self.height = height.filter(|_| self.width.is_some());
+        }
+    }
 }
 
 /// A single downloaded file with its resolved media type.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DownloadedItem {
     pub filepath: PathBuf,
     pub media_type: MediaType,
     pub thumbnail_filepath: Option<PathBuf>,
+    /// Title of the playlist entry this item was downloaded from; `None` for single media.
+    pub title: Option<String>,
+    /// 1-based position of that entry in the playlist; `None` for single media.
+    pub playlist_index: Option<usize>,
+    /// yt-dlp's metadata for the item, kept next to it when `YTDLP_WRITE_INFO_JSON` is set.
+    pub info_json_filepath: Option<PathBuf>,
 }
 
 /// Result of a download operation: either a single item or a group.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DownloadedMedia {
     Single(DownloadedItem),
     Group(Vec<DownloadedItem>),
 }
 
+/// Check every downloaded file with `verify_media_file`. Corrupt album items are removed
+/// and dropped, so the rest is still sent and they are reported as missing; a corrupt
+/// single file, or an album with nothing left, is an error.
+async fn verify_downloaded_media(media: DownloadedMedia) -> Result<DownloadedMedia, DownloadError> {
+    match media {
+        DownloadedMedia::Single(item) => {
+            match verify_media_file(&item.filepath, item.media_type).await {
+                Ok(()) => Ok(DownloadedMedia::Single(item)),
+                Err(source) => Err(DownloadError::Corrupted {
+                    path: item.filepath,
+                    source,
+                }),
+            }
+        }
+        DownloadedMedia::Group(items) => {
+            let mut verified = Vec::with_capacity(items.len());
+            let mut last_error = None;
+            for item in items {
+                match verify_media_file(&item.filepath, item.media_type).await {
+                    Ok(()) => verified.push(item),
+                    Err(source) => {
+                        log::warn!(
+                            "Dropping corrupted album item {}: {}",
+                            item.filepath.display(),
+                            source
+                        );
+                        let _ = tokio::fs::remove_file(&item.filepath).await;
+                        if let Some(info_json) = &item.info_json_filepath {
+                            let _ = tokio::fs::remove_file(info_json).await;
+                        }
+                        last_error = Some(DownloadError::Corrupted {
+                            path: item.filepath,
+                            source,
+                        });
+                    }
+                }
+            }
+            match last_error {
+                Some(e) if verified.is_empty() => Err(e),
+                _ => Ok(DownloadedMedia::Group(verified)),
+            }
+        }
+    }
+}
+
 /// Lightweight struct for parsing each line of yt-dlp's `--print-json` output.
 #[derive(Debug, Deserialize)]
 struct DownloadOutputLine {
@@ -114,98 +591,424 @@ struct DownloadOutputLine {
     ext: Option<String>,
 }
 
+fn join_temp_dir(temp_dir: &str, filename: &str) -> String {
+    if temp_dir.is_empty() {
+        return filename.to_string();
+    }
+    format!("{}/{}", temp_dir.trim_end_matches('/'), filename)
+}
+
 #[must_use]
-fn escape_html_text(s: &str) -> String {
+pub(crate) fn escape_html_text(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
 }
 
-/// Builds a caption string from pre-download metadata and the source URL.
+/// Name shown in captions unless `ATTRIBUTION_NAME` says otherwise.
+pub const DEFAULT_ATTRIBUTION_NAME: &str = "CrabberBot";
+/// Where the caption attribution links unless `ATTRIBUTION_URL` says otherwise.
+pub const DEFAULT_ATTRIBUTION_URL: &str = "https://t.me/crabberbot?start=c";
+
+/// The "via" link that opens every caption, before the source link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribution {
+    Link {
+        name: String,
+        url: Url,
+    },
+    /// Captions start with the source link.
+    Off,
+}
+
+impl Default for Attribution {
+    fn default() -> Self {
+        Self::Link {
+            name: DEFAULT_ATTRIBUTION_NAME.to_string(),
+            url: Url::parse(DEFAULT_ATTRIBUTION_URL).expect("valid default attribution URL"),
+        }
+    }
+}
+
+impl Attribution {
+    /// The HTML put before the source link, e.g. `<a href="…">CrabberBot</a> 🦀 `.
+    fn header_prefix(&self) -> String {
+        match self {
+            Self::Link { name, url } => format!(
+                "<a href=\"{}\">{}</a> 🦀 ",
+                escape_html_text(url.as_str()).replace('"', "&quot;"),
+                escape_html_text(name)
+            ),
+            Self::Off => String::new(),
+        }
+    }
+}
+
+/// How captions are built, from `CAPTION_LINKS`, `CAPTION_SHOW_META` and the
+/// `ATTRIBUTION` variables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptionOptions {
+    /// How links inside source descriptions appear.
+    pub links: CaptionLinks,
+    /// Add a line with the upload date and duration under the header.
+    pub show_meta: bool,
+    pub attribution: Attribution,
+}
+
+impl From<CaptionLinks> for CaptionOptions {
+    fn from(links: CaptionLinks) -> Self {
+        Self {
+            links,
+            ..Self::default()
+        }
+    }
+}
+
+/// yt-dlp's `upload_date` ("20241102") as "2024-11-02".
+fn format_upload_date(upload_date: &str) -> Option<String> {
+    chrono::NaiveDate::parse_from_str(upload_date, "%Y%m%d")
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+/// A duration as "m:ss", or "h:mm:ss" from an hour on.
+fn format_duration(seconds: f64) -> String {
+    let total = seconds.round().max(0.0) as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// "📅 2024-11-02 · ⏱ 3:21", leaving out whatever is unknown. `None` if both are.
+fn caption_meta_line(info: &MediaInfo) -> Option<String> {
+    let parts: Vec<String> = [
+        info.upload_date
+            .as_deref()
+            .and_then(format_upload_date)
+            .map(|date| format!("📅 {date}")),
+        info.duration
+            .filter(|duration| *duration > 0.0)
+            .map(|duration| format!("⏱ {}", format_duration(duration))),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// The "Source" link at the start of every caption.
+fn source_anchor(source_url: &Url) -> String {
+    format!(
+        "<a href=\"{}\">Source</a>",
+        escape_html_text(source_url.as_str()).replace('"', "&quot;")
+    )
+}
+
+/// `caption`, built by `build_caption` for `source_url`, without its "Source" link, for
+/// messages that carry the link as a button instead. The header goes away entirely when
+/// nothing else is left in it.
+pub fn remove_source_link(caption: &str, source_url: &Url) -> String {
+    const SEPARATOR: &str = "\n\n";
+    let Some((header, body)) = caption.split_once(SEPARATOR) else {
+        return caption.to_string();
+    };
+    let header = header.replacen(&source_anchor(source_url), "", 1);
+    let header: Vec<&str> = header
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if header.is_empty() {
+        body.to_string()
+    } else {
+        format!("{}{SEPARATOR}{body}", header.join("\n"))
+    }
+}
+
+/// Builds a caption string from pre-download metadata and the source URL, handling links
+/// in the description and the metadata line according to `options`.
 #[must_use]
-pub fn build_caption(info: &MediaInfo, source_url: &Url) -> String {
-    const CAPTION_MAX_LEN: usize = 1024;
+pub fn build_caption(info: &MediaInfo, source_url: &Url, options: &CaptionOptions) -> String {
     const BLOCKQUOTE_OPEN: &str = "<blockquote>";
     const BLOCKQUOTE_CLOSE: &str = "</blockquote>";
     const TRUNCATION_MARKER: &str = "[...]";
     const SEPARATOR: &str = "\n\n";
 
-    let via_link = "https://t.me/crabberbot?start=c";
-    let header = format!(
-        "<a href=\"{}\">CrabberBot</a> 🦀 <a href=\"{}\">Source</a>",
-        via_link, source_url
+    let mut header = format!(
+        "{}{}",
+        options.attribution.header_prefix(),
+        source_anchor(source_url)
     );
+    if options.show_meta
+        && let Some(meta) = caption_meta_line(info)
+    {
+        header.push('\n');
+        header.push_str(&meta);
+    }
 
-    let mut quote_parts = Vec::new();
+    let mut quote = Vec::new();
     let uploader = info
         .uploader
         .as_deref()
         .or(info.playlist_uploader.as_deref());
-    if let Some(uploader) = uploader {
-        if !uploader.is_empty() {
-            quote_parts.push(format!("<i>{}</i>", escape_html_text(uploader)));
-        }
+    if let Some(uploader) = uploader
+        && !uploader.is_empty()
+    {
+        let channel_url = info
+            .channel_url
+            .as_deref()
+            .and_then(|url| Url::parse(url).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"));
+        quote.push(CaptionSegment::Html(match channel_url {
+            Some(url) => format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html_text(url.as_str()).replace('"', "&quot;"),
+                escape_html_text(uploader)
+            ),
+            None => format!("<i>{}</i>", escape_html_text(uploader)),
+        }));
     }
 
     let description = info.description.as_deref().or(info.title.as_deref());
     if let Some(desc) = description {
-        let desc = desc.trim();
-        if !desc.is_empty() {
-            quote_parts.push(escape_html_text(desc));
-        }
-    }
-
-    let full_quote_content = quote_parts.join("\n");
-    let overhead = header.chars().count()
-        + SEPARATOR.len()
-        + BLOCKQUOTE_OPEN.len()
-        + BLOCKQUOTE_CLOSE.len()
-        + TRUNCATION_MARKER.len();
-    let available_space_for_quote = CAPTION_MAX_LEN.saturating_sub(overhead);
-    let final_quote = if full_quote_content.chars().count() > available_space_for_quote {
-        let mut truncated: String = full_quote_content
-            .chars()
-            .take(available_space_for_quote)
-            .collect();
-        truncated.push_str(TRUNCATION_MARKER);
-        truncated
+        let segments = description_segments(desc.trim(), options.links);
+        if segments.iter().any(|segment| !segment.render().is_empty()) {
+            if !quote.is_empty() {
+                quote.push(CaptionSegment::Text("\n".to_string()));
+            }
+            quote.extend(segments);
+        }
+    }
+
+    let scaffold =
+        format!("{header}{SEPARATOR}{BLOCKQUOTE_OPEN}{TRUNCATION_MARKER}{BLOCKQUOTE_CLOSE}");
+    let available_space_for_quote =
+        MediaInfo::TELEGRAM_CAPTION_LIMIT.saturating_sub(scaffold.chars().count());
+    let final_quote = truncate_segments(&quote, available_space_for_quote, TRUNCATION_MARKER);
+
+    format!("{header}{SEPARATOR}{BLOCKQUOTE_OPEN}{final_quote}{BLOCKQUOTE_CLOSE}")
+}
+
+/// Render `segments`, cutting them to `budget` characters plus `marker` when they don't fit.
+/// Text is cut between characters, never inside an escape; markup is kept whole or dropped.
+fn truncate_segments(segments: &[CaptionSegment], budget: usize, marker: &str) -> String {
+    let rendered: Vec<String> = segments.iter().map(CaptionSegment::render).collect();
+    if rendered.iter().map(|r| r.chars().count()).sum::<usize>() <= budget {
+        return rendered.concat();
+    }
+    let mut output = String::new();
+    let mut remaining = budget;
+    for (segment, rendered) in segments.iter().zip(&rendered) {
+        let len = rendered.chars().count();
+        if len <= remaining {
+            output.push_str(rendered);
+            remaining -= len;
+            continue;
+        }
+        if let CaptionSegment::Text(text) = segment {
+            for c in text.chars() {
+                let escaped = escape_html_text(c.encode_utf8(&mut [0; 4]));
+                let len = escaped.chars().count();
+                if len > remaining {
+                    break;
+                }
+                output.push_str(&escaped);
+                remaining -= len;
+            }
+        }
+        break;
+    }
+    output.push_str(marker);
+    output
+}
+
+/// Convert a downloaded thumbnail (usually .webp) into a JPEG Telegram accepts as a video
+/// thumbnail, written next to it as `<name>.thumb.jpg`. The source file is left in place.
+pub(crate) fn prepare_thumbnail(path: &Path) -> Result<PathBuf, String> {
+    let img = image::ImageReader::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|reader| reader.with_guessed_format().map_err(|e| e.to_string()))
+        .and_then(|mut reader| {
+            reader.limits(crate::telegram_api::image_limits());
+            reader.decode().map_err(|e| e.to_string())
+        })?;
+    let img = if img.width().max(img.height()) > THUMBNAIL_MAX_SIDE {
+        img.thumbnail(THUMBNAIL_MAX_SIDE, THUMBNAIL_MAX_SIDE)
     } else {
-        full_quote_content
+        img
     };
+    let rgb = img.to_rgb8();
+
+    for quality in THUMBNAIL_JPEG_QUALITIES {
+        let mut bytes = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality)
+            .encode_image(&rgb)
+            .map_err(|e| e.to_string())?;
+        if bytes.len() <= THUMBNAIL_MAX_BYTES {
+            let output = path.with_extension("thumb.jpg");
+            std::fs::write(&output, bytes).map_err(|e| e.to_string())?;
+            return Ok(output);
+        }
+    }
+    Err(format!(
+        "thumbnail exceeds {} bytes even at the lowest quality",
+        THUMBNAIL_MAX_BYTES
+    ))
+}
 
-    format!("{header}{SEPARATOR}{BLOCKQUOTE_OPEN}{final_quote}{BLOCKQUOTE_CLOSE}")
+/// Make a downloaded cover image sendable as a photo: JPEGs are kept as they are, other
+/// formats (usually .webp) are converted at full size to `<name>.cover.jpg` and the source
+/// file is removed.
+pub(crate) fn prepare_cover(path: &Path) -> Result<PathBuf, String> {
+    let mut reader = image::ImageReader::open(path)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    if reader.format() == Some(image::ImageFormat::Jpeg) {
+        return Ok(path.to_path_buf());
+    }
+    reader.limits(crate::telegram_api::image_limits());
+    let rgb = reader.decode().map_err(|e| e.to_string())?.to_rgb8();
+    let mut bytes = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, COVER_JPEG_QUALITY)
+        .encode_image(&rgb)
+        .map_err(|e| e.to_string())?;
+    let output = path.with_extension("cover.jpg");
+    std::fs::write(&output, bytes).map_err(|e| e.to_string())?;
+    if let Err(e) = std::fs::remove_file(path) {
+        log::warn!("Failed to remove raw cover image {:?}: {}", path, e);
+    }
+    Ok(output)
 }
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait Downloader: Send + Sync {
     async fn get_media_metadata(&self, url: &Url) -> Result<MediaInfo, DownloadError>;
+    /// Download what `info` describes, recording the phase durations in `info.timings`.
     async fn download_media(
         &self,
-        info: &MediaInfo,
+        info: &mut MediaInfo,
         url: &Url,
     ) -> Result<DownloadedMedia, DownloadError>;
+    /// Rough download duration based on the approximate file size and observed throughput.
+    /// Returns `None` when either is unknown.
+    fn estimate_download_time(&self, info: &MediaInfo) -> Option<Duration>;
+    /// yt-dlp's `--list-formats` table for the URL, for debugging quality selection.
+    async fn list_formats(&self, url: &Url) -> Result<String, DownloadError>;
+    /// Download only the thumbnail of the media at `url`, as a JPEG. The caller removes
+    /// the file.
+    async fn download_thumbnail(
+        &self,
+        info: &MediaInfo,
+        url: &Url,
+    ) -> Result<PathBuf, DownloadError>;
+    /// Download only `entry` of the playlist at `parent_url`, for callers that handle the
+    /// entries one by one, and return the file. The caller removes it. Downloaders that
+    /// can't pick a single entry return `DownloadError::Unsupported`, rather than download
+    /// the whole playlist for it.
+    async fn download_single_entry(
+        &self,
+        _entry: &MediaInfo,
+        _parent_url: &Url,
+    ) -> Result<PathBuf, DownloadError> {
+        Err(DownloadError::Unsupported(
+            "downloading a single playlist entry",
+        ))
+    }
+}
+
+/// Mean of the most recent `capacity` samples.
+#[derive(Debug)]
+pub struct RollingAverage {
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl RollingAverage {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Returns `None` until at least one sample has been recorded.
+    #[must_use]
+    pub fn average(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+    }
 }
 
 pub struct YtDlpDownloader {
     yt_dlp_path: String,
     download_dir: PathBuf,
+    geo_bypass: GeoBypass,
+    network: YtDlpNetwork,
+    /// Cookie files passed to yt-dlp, chosen by the URL's host.
+    cookies: CookieProfiles,
+    /// Observed download throughput in bytes per second.
+    rolling_download_speed: Arc<Mutex<RollingAverage>>,
+    children: ChildProcesses,
+    /// What `yt-dlp --version` printed at startup.
+    version: Option<String>,
+    /// Save each download's `.info.json` next to it, for debugging and reprocessing.
+    write_info_json: bool,
+    /// Pass `--windows-filenames`, for download directories on Windows filesystems.
+    windows_filenames: bool,
+    /// Check downloaded files with `verify_media_file` before returning them. Only tests,
+    /// whose fake yt-dlp leaves placeholder files, turn it off.
+    verify_files: bool,
+}
+
+/// The version `yt_dlp_path` reports, e.g. "2025.01.15", or `None` if it can't be run.
+pub async fn check_version(yt_dlp_path: &str) -> Option<String> {
+    let output = tokio::process::Command::new(yt_dlp_path)
+        .arg("--version")
+        .output()
+        .await
+        .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !version.is_empty()).then_some(version)
 }
 
 impl YtDlpDownloader {
-    pub async fn new(yt_dlp_path: String, download_dir: PathBuf) -> Self {
+    pub async fn new(
+        yt_dlp_path: String,
+        download_dir: PathBuf,
+        geo_bypass: GeoBypass,
+        network: YtDlpNetwork,
+        cookies: CookieProfiles,
+    ) -> Self {
         log::info!("Using yt-dlp executable at: {}", yt_dlp_path);
         log::info!("Using download directory: {}", download_dir.display());
+        log::info!("yt-dlp geo-bypass: {}", geo_bypass);
+        log::info!(
+            "yt-dlp socket timeout: {}s, HTTP retries: {}",
+            network.socket_timeout_secs,
+            network.http_retries
+        );
+        cookies.log_startup_summary();
 
-        // Log yt-dlp version
-        if let Ok(output) = tokio::process::Command::new(&yt_dlp_path)
-            .arg("--version")
-            .output()
-            .await
-        {
-            let version = String::from_utf8_lossy(&output.stdout);
-            log::info!("yt-dlp version: {}", version.trim());
-        }
+        let version = check_version(&yt_dlp_path).await;
+        log::info!(
+            "yt-dlp version: {}",
+            version.as_deref().unwrap_or("unknown")
+        );
 
         // Log available impersonate targets to verify curl_cffi is working
         match tokio::process::Command::new(&yt_dlp_path)
@@ -236,16 +1039,69 @@ impl YtDlpDownloader {
         Self {
             yt_dlp_path,
             download_dir,
+            geo_bypass,
+            network,
+            cookies,
+            rolling_download_speed: Arc::new(Mutex::new(RollingAverage::new(
+                DOWNLOAD_SPEED_SAMPLES,
+            ))),
+            children: ChildProcesses::new(),
+            version,
+            write_info_json: false,
+            windows_filenames: false,
+            verify_files: true,
+        }
+    }
+
+    /// Also write yt-dlp's `.info.json` for every download. The files share the media's
+    /// name and are removed along with it.
+    pub fn with_info_json(mut self, write_info_json: bool) -> Self {
+        if write_info_json {
+            log::info!("yt-dlp will write .info.json files next to downloads");
+        }
+        self.write_info_json = write_info_json;
+        self
+    }
+
+    /// Keep file names valid on NTFS and FAT. yt-dlp then replaces characters such as `:`
+    /// in the media's id, so the files that go with a download are found by the name it
+    /// actually reported rather than by the id.
+    pub fn with_windows_filenames(mut self, windows_filenames: bool) -> Self {
+        if windows_filenames {
+            log::info!("yt-dlp will write Windows-safe file names");
         }
+        self.windows_filenames = windows_filenames;
+        self
     }
 
-    fn build_base_command(&self) -> tokio::process::Command {
+    /// The yt-dlp version found at startup, `None` if it could not be run.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// The yt-dlp processes this downloader has running, shared with whoever shuts it down.
+    pub fn child_processes(&self) -> ChildProcesses {
+        self.children.clone()
+    }
+
+    fn build_base_command(&self, url: &Url) -> tokio::process::Command {
         let mut command = tokio::process::Command::new(&self.yt_dlp_path);
         command
             .arg("--no-warnings")
             .arg("--ignore-config")
             .arg("--impersonate")
             .arg("chrome");
+        if let Some(flag) = playlist_flag(url) {
+            command.arg(flag);
+        }
+        command.args(self.geo_bypass.args());
+        command.args(self.network.args());
+        if let Some(cookies) = url.host_str().and_then(|host| self.cookies.for_host(host)) {
+            command.arg("--cookies").arg(cookies);
+        }
+        if self.windows_filenames {
+            command.arg("--windows-filenames");
+        }
         command.kill_on_drop(true);
         command
     }
@@ -269,15 +1125,12 @@ impl YtDlpDownloader {
         }
     }
 
-    /// Finds a thumbnail file written by `--write-thumbnail`, excluding the video file itself.
-    fn find_thumbnail(
-        download_dir: &Path,
-        uuid: &str,
-        id: &str,
-        video_filepath: &Path,
-    ) -> Option<PathBuf> {
-        let prefix = format!("{uuid}.{id}.");
-        std::fs::read_dir(download_dir)
+    /// Finds a thumbnail file written by `--write-thumbnail`, excluding the video file itself
+    /// and its `.info.json`. It shares the video's name up to the extension, which, unlike
+    /// the id, is the name yt-dlp actually wrote.
+    fn find_thumbnail(video_filepath: &Path) -> Option<PathBuf> {
+        let prefix = format!("{}.", video_filepath.file_stem()?.to_str()?);
+        std::fs::read_dir(video_filepath.parent()?)
             .ok()?
             .filter_map(Result::ok)
             .map(|entry| entry.path())
@@ -286,196 +1139,96 @@ impl YtDlpDownloader {
                     && path
                         .file_name()
                         .and_then(|name| name.to_str())
-                        .is_some_and(|name| name.starts_with(&prefix))
+                        .is_some_and(|name| {
+                            name.starts_with(&prefix) && !name.ends_with(".info.json")
+                        })
             })
     }
 
-    async fn cleanup_download_artifacts(download_dir: &Path, uuid: &str) {
-        let mut entries = match tokio::fs::read_dir(download_dir).await {
-            Ok(entries) => entries,
+    /// The `.info.json` that `--write-info-json` wrote next to `media_filepath`, if any.
+    fn find_info_json(media_filepath: &Path) -> Option<PathBuf> {
+        let path = media_filepath.with_extension("info.json");
+        path.exists().then_some(path)
+    }
+
+    /// Replace the raw thumbnail with a Telegram-compliant JPEG. A thumbnail that cannot be
+    /// converted is dropped, so the video is sent without one instead of failing.
+    fn convert_thumbnail(raw: &Path) -> Option<PathBuf> {
+        let prepared = match prepare_thumbnail(raw) {
+            Ok(prepared) => Some(prepared),
             Err(e) => {
-                log::warn!(
-                    "Failed to read downloads dir for cleanup {}: {}",
-                    download_dir.display(),
-                    e
-                );
-                return;
+                log::warn!("Dropping thumbnail {:?}: {}", raw, e);
+                None
             }
         };
+        if let Err(e) = std::fs::remove_file(raw) {
+            log::warn!("Failed to remove raw thumbnail {:?}: {}", raw, e);
+        }
+        prepared
+    }
 
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let path = entry.path();
-            let should_remove =
-                path.file_name()
-                    .and_then(|name| name.to_str())
-                    .is_some_and(|name| {
-                        name.starts_with(uuid) && name.as_bytes().get(36) == Some(&b'.')
-                    });
-            if !should_remove {
-                continue;
-            }
-            match tokio::fs::remove_file(&path).await {
-                Ok(()) => log::info!("Removed incomplete download artifact: {}", path.display()),
-                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
-                Err(e) => log::warn!(
-                    "Failed to remove incomplete download artifact {}: {}",
-                    path.display(),
-                    e
-                ),
-            }
-        }
-    }
-}
-
-/// Remove media files left in the downloads directory by older crashed or timed-out runs.
-///
-/// Normal in-flight downloads are UUID-prefixed and live at the top level of
-/// `download_dir`; durable caches live in subdirectories and are intentionally skipped.
-pub async fn cleanup_orphaned_downloads(download_dir: &Path) -> usize {
-    let mut removed = 0usize;
-    let mut entries = match tokio::fs::read_dir(download_dir).await {
-        Ok(entries) => entries,
-        Err(e) => {
-            log::warn!(
-                "Failed to read downloads dir for startup cleanup {}: {}",
-                download_dir.display(),
-                e
-            );
-            return 0;
-        }
-    };
-
-    loop {
-        match entries.next_entry().await {
-            Ok(Some(entry)) => {
-                let path = entry.path();
-                let is_file = entry
-                    .file_type()
-                    .await
-                    .is_ok_and(|file_type| file_type.is_file());
-                let should_remove = is_file
-                    && path
-                        .file_name()
-                        .and_then(|name| name.to_str())
-                        .is_some_and(is_download_artifact_name);
-                if !should_remove {
-                    continue;
-                }
-
-                match tokio::fs::remove_file(&path).await {
-                    Ok(()) => {
-                        removed += 1;
-                        log::info!("Removed orphaned download artifact: {}", path.display());
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
-                    Err(e) => log::warn!(
-                        "Failed to remove orphaned download artifact {}: {}",
-                        path.display(),
-                        e
-                    ),
-                }
-            }
-            Ok(None) => break,
-            Err(e) => {
-                log::warn!("Error reading downloads dir during startup cleanup: {}", e);
-                break;
-            }
-        }
-    }
-
-    removed
-}
-
-fn is_download_artifact_name(filename: &str) -> bool {
-    let Some((prefix, rest)) = filename.split_once('.') else {
-        return false;
-    };
-    if Uuid::parse_str(prefix).is_err() {
-        return false;
-    }
-
-    if rest.ends_with(".part") {
-        return true;
-    }
-
-    let Some(extension) = rest.rsplit('.').next().map(str::to_ascii_lowercase) else {
-        return false;
-    };
-    MediaType::from_extension(&extension).is_some() || extension == "image"
-}
-
-#[async_trait]
-impl Downloader for YtDlpDownloader {
-    async fn get_media_metadata(&self, url: &Url) -> Result<MediaInfo, DownloadError> {
-        log::info!("Fetching metadata for {}", url);
-
-        let mut command = self.build_base_command();
-        command.arg("--dump-single-json").arg(url.as_str());
-
-        let output = tokio::time::timeout(METADATA_TIMEOUT, command.output())
-            .await
-            .map_err(|_| DownloadError::Timeout(METADATA_TIMEOUT.as_secs()))?
-            .map_err(|e| DownloadError::CommandFailed(e.to_string()))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            log::error!(
-                "yt-dlp --dump-single-json failed for url {}: {}",
-                url,
-                stderr
-            );
-            return Err(DownloadError::CommandFailed(stderr.to_string()));
-        }
-
-        let stdout_str = String::from_utf8_lossy(&output.stdout);
-        log::debug!(
-            "yt-dlp metadata stdout length for {}: {} bytes",
-            url,
-            stdout_str.len()
-        );
-
-        serde_json::from_str::<MediaInfo>(&stdout_str).map_err(|e| {
-            log::error!("Failed to parse metadata JSON for {}: {}", url, e);
-            DownloadError::ParsingFailed(e.to_string())
-        })
-    }
-
-    async fn download_media(
+    /// Download what `info` describes; with `playlist_item`, only that entry of the playlist
+    /// (counting from 1).
+    async fn run_download(
         &self,
-        info: &MediaInfo,
+        info: &mut MediaInfo,
         url: &Url,
+        playlist_item: Option<usize>,
     ) -> Result<DownloadedMedia, DownloadError> {
         let uuid = uuid::Uuid::new_v4().to_string();
         let download_dir = self.download_dir.clone();
-        let filename_template = format!("{}.%(id)s.%(ext)s", uuid);
-        let thumbnail_template = format!("thumbnail:{}.%(id)s.%(ext)s", uuid);
+        let filename_template = MediaInfo::output_filename_template(&uuid, "");
+        let thumbnail_template = format!("thumbnail:{filename_template}");
         let is_single_with_thumbnail = info.entries.is_none() && info.thumbnail.is_some();
 
         log::info!("Downloading {}", url);
 
-        let mut command = self.build_base_command();
+        let mut command = self.build_base_command(url);
         command
             .current_dir(&download_dir)
             .arg("--print-json")
             .arg("-S")
-            .arg("vcodec:h264,res,acodec:m4a")
+            .arg(FORMAT_SORT)
+            .arg("--merge-output-format")
+            .arg(MERGE_OUTPUT_FORMAT)
             .arg("-o")
             .arg(&filename_template);
 
+        if let Some(selector) = &info.format_selector {
+            command.arg("-f").arg(selector);
+        }
+        if let Some(item) = playlist_item {
+            command.arg("--playlist-items").arg(item.to_string());
+        }
+
         if is_single_with_thumbnail {
             command
                 .arg("--write-thumbnail")
                 .arg("-o")
                 .arg(&thumbnail_template);
         }
+        // Named after the output template like the media. Metadata is still parsed from
+        // `--print-json`; the files are only kept for operators.
+        if self.write_info_json {
+            command
+                .arg("--write-info-json")
+                .arg("--no-write-playlist-metafiles");
+        }
 
         command.arg(url.as_str());
 
-        let output = match tokio::time::timeout(DOWNLOAD_TIMEOUT, command.output()).await {
+        let started = Instant::now();
+        let output =
+            tokio::time::timeout(DOWNLOAD_TIMEOUT, self.children.output(&mut command)).await;
+        info.timings.download = Some(started.elapsed());
+        let output = match output {
             Ok(Ok(output)) => output,
             Ok(Err(e)) => {
                 Self::cleanup_download_artifacts(&download_dir, &uuid).await;
-                return Err(DownloadError::CommandFailed(e.to_string()));
+                return Err(DownloadError::IoError {
+                    context: "running yt-dlp to download",
+                    source: e,
+                });
             }
             Err(_) => {
                 Self::cleanup_download_artifacts(&download_dir, &uuid).await;
@@ -487,7 +1240,7 @@ impl Downloader for YtDlpDownloader {
             let stderr = String::from_utf8_lossy(&output.stderr);
             log::error!("yt-dlp failed for url {}: {}", url, stderr);
             Self::cleanup_download_artifacts(&download_dir, &uuid).await;
-            return Err(DownloadError::CommandFailed(stderr.to_string()));
+            return Err(DownloadError::from_yt_dlp_stderr(&stderr));
         }
 
         let stdout_str = String::from_utf8_lossy(&output.stdout);
@@ -516,18 +1269,26 @@ impl Downloader for YtDlpDownloader {
             ));
         }
 
-        if let Some(entries) = &info.entries {
+        let media = if let Some(entries) = &info.entries {
             let items: Vec<DownloadedItem> = entries
                 .iter()
-                .filter_map(|entry| {
+                .enumerate()
+                .filter_map(|(index, entry)| {
                     let dl = downloaded_files.get(&entry.id)?;
                     let filepath = dl.filepath.as_ref()?;
                     let ext = dl.ext.as_deref()?;
                     let media_type = MediaType::from_extension(ext)?;
+                    let filepath = Self::resolve_download_path(&download_dir, filepath);
                     Some(DownloadedItem {
-                        filepath: Self::resolve_download_path(&download_dir, filepath),
+                        info_json_filepath: self
+                            .write_info_json
+                            .then(|| Self::find_info_json(&filepath))
+                            .flatten(),
+                        filepath,
                         media_type,
                         thumbnail_filepath: None,
+                        title: entry.title.clone(),
+                        playlist_index: Some(index + 1),
                     })
                 })
                 .collect();
@@ -539,7 +1300,7 @@ impl Downloader for YtDlpDownloader {
                 ));
             }
 
-            Ok(DownloadedMedia::Group(items))
+            DownloadedMedia::Group(items)
         } else {
             let dl = match downloaded_files.get(&info.id) {
                 Some(dl) => dl,
@@ -582,108 +1343,1517 @@ impl Downloader for YtDlpDownloader {
             };
 
             let thumbnail_filepath = if is_single_with_thumbnail {
-                Self::find_thumbnail(&download_dir, &uuid, &info.id, &filepath)
+                let started = Instant::now();
+                let thumbnail =
+                    Self::find_thumbnail(&filepath).and_then(|raw| Self::convert_thumbnail(&raw));
+                info.timings.thumbnail = Some(started.elapsed());
+                thumbnail
             } else {
                 None
             };
 
-            Ok(DownloadedMedia::Single(DownloadedItem {
+            DownloadedMedia::Single(DownloadedItem {
+                info_json_filepath: self
+                    .write_info_json
+                    .then(|| Self::find_info_json(&filepath))
+                    .flatten(),
                 filepath,
                 media_type,
                 thumbnail_filepath,
-            }))
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use url::Url;
-
-    #[test]
-    fn test_build_caption_normal_text() {
-        let info = MediaInfo {
-            id: "1".to_string(),
-            uploader: Some("TestUser".to_string()),
-            description: Some("A normal description".to_string()),
-            ..Default::default()
+                title: None,
+                playlist_index: None,
+            })
         };
-        let url = Url::parse("https://example.com/video").unwrap();
-        let caption = build_caption(&info, &url);
-        assert!(caption.contains("<i>TestUser</i>"));
-        assert!(caption.contains("A normal description"));
-    }
 
-    #[test]
-    fn test_build_caption_escapes_html_tags() {
-        let info = MediaInfo {
-            id: "1".to_string(),
-            uploader: Some("<script>alert('xss')</script>".to_string()),
-            description: Some("desc with <b>tags</b>".to_string()),
-            ..Default::default()
-        };
-        let url = Url::parse("https://example.com/video").unwrap();
-        let caption = build_caption(&info, &url);
-        assert!(caption.contains("&lt;script&gt;"));
-        assert!(caption.contains("&lt;b&gt;tags&lt;/b&gt;"));
-        assert!(!caption.contains("<script>"));
-        assert!(!caption.contains("<b>tags"));
+        if !self.verify_files {
+            return Ok(media);
+        }
+        match verify_downloaded_media(media).await {
+            Ok(media) => Ok(media),
+            Err(e) => {
+                Self::cleanup_download_artifacts(&download_dir, &uuid).await;
+                Err(e)
+            }
+        }
     }
 
-    #[test]
-    fn test_build_caption_escapes_ampersands() {
-        let info = MediaInfo {
-            id: "1".to_string(),
-            uploader: Some("Tom & Jerry".to_string()),
-            description: Some("A & B < C > D".to_string()),
-            ..Default::default()
+    /// Feed the throughput of a finished download into the rolling average used for estimates.
+    async fn record_download_speed(&self, media: &DownloadedMedia, elapsed: Duration) {
+        let paths: Vec<&Path> = match media {
+            DownloadedMedia::Single(item) => vec![&item.filepath],
+            DownloadedMedia::Group(items) => items.iter().map(|i| i.filepath.as_path()).collect(),
         };
-        let url = Url::parse("https://example.com/video").unwrap();
-        let caption = build_caption(&info, &url);
-        assert!(caption.contains("Tom &amp; Jerry"));
-        assert!(caption.contains("A &amp; B &lt; C &gt; D"));
-        // Verify no double-escaping
-        assert!(!caption.contains("&amp;amp;"));
+        let mut total_bytes = 0u64;
+        for path in paths {
+            if let Ok(metadata) = tokio::fs::metadata(path).await {
+                total_bytes += metadata.len();
+            }
+        }
+        let secs = elapsed.as_secs_f64();
+        if total_bytes == 0 || secs <= 0.0 {
+            return;
+        }
+        let bytes_per_sec = total_bytes as f64 / secs;
+        log::info!(
+            "Downloaded {} bytes in {:.1}s ({:.0} B/s)",
+            total_bytes,
+            secs,
+            bytes_per_sec
+        );
+        self.rolling_download_speed
+            .lock()
+            .expect("download speed lock poisoned")
+            .push(bytes_per_sec);
     }
 
-    #[tokio::test]
-    async fn test_yt_dlp_uses_custom_path_and_fails_if_invalid() {
-        let downloader = YtDlpDownloader {
-            yt_dlp_path: "/path/to/a/nonexistent/yt-dlp-binary".to_string(),
-            download_dir: PathBuf::from("/downloads"),
+    async fn cleanup_download_artifacts(download_dir: &Path, uuid: &str) {
+        let mut entries = match tokio::fs::read_dir(download_dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!(
+                    "Failed to read downloads dir for cleanup {}: {}",
+                    download_dir.display(),
+                    e
+                );
+                return;
+            }
         };
 
-        let url = Url::parse("https://example.com").unwrap();
-
-        let result = downloader.get_media_metadata(&url).await;
-
-        assert!(result.is_err());
-
-        match result {
-            Err(DownloadError::CommandFailed(msg)) => {
-                assert!(msg.contains("No such file or directory"));
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let should_remove =
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.starts_with(uuid) && name.as_bytes().get(36) == Some(&b'.')
+                    });
+            if !should_remove {
+                continue;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => log::info!("Removed incomplete download artifact: {}", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!(
+                    "Failed to remove incomplete download artifact {}: {}",
+                    path.display(),
+                    e
+                ),
             }
-            _ => panic!("Expected CommandFailed error, but got something else."),
         }
     }
+}
 
-    #[test]
-    fn test_resolve_download_path_keeps_absolute_paths() {
-        let download_dir = Path::new("/downloads");
-        let filepath = "/downloads/video.mp4";
-
-        let resolved = YtDlpDownloader::resolve_download_path(download_dir, filepath);
-
-        assert_eq!(resolved, PathBuf::from("/downloads/video.mp4"));
-    }
-
-    #[test]
-    fn test_resolve_download_path_rebases_relative_paths_under_downloads_dir() {
-        let download_dir = Path::new("/downloads");
-        let filepath = "./video.mp4";
-
-        let resolved = YtDlpDownloader::resolve_download_path(download_dir, filepath);
+/// Remove media files left in the downloads directory by older crashed or timed-out runs.
+///
+/// Normal in-flight downloads are UUID-prefixed and live at the top level of
+/// `download_dir`; durable caches live in subdirectories and are intentionally skipped.
+pub async fn cleanup_orphaned_downloads(download_dir: &Path) -> usize {
+    let mut removed = 0usize;
+    let mut entries = match tokio::fs::read_dir(download_dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!(
+                "Failed to read downloads dir for startup cleanup {}: {}",
+                download_dir.display(),
+                e
+            );
+            return 0;
+        }
+    };
+
+    loop {
+        match entries.next_entry().await {
+            Ok(Some(entry)) => {
+                let path = entry.path();
+                let is_file = entry
+                    .file_type()
+                    .await
+                    .is_ok_and(|file_type| file_type.is_file());
+                let should_remove = is_file
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(is_download_artifact_name);
+                if !should_remove {
+                    continue;
+                }
+
+                match tokio::fs::remove_file(&path).await {
+                    Ok(()) => {
+                        removed += 1;
+                        log::info!("Removed orphaned download artifact: {}", path.display());
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => log::warn!(
+                        "Failed to remove orphaned download artifact {}: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!("Error reading downloads dir during startup cleanup: {}", e);
+                break;
+            }
+        }
+    }
+
+    removed
+}
+
+/// Whether the URL explicitly asks for a playlist (`?list=`), e.g. a YouTube playlist link.
+#[must_use]
+pub fn is_playlist_url(url: &Url) -> bool {
+    url.query_pairs().any(|(key, _)| key == "list")
+}
+
+/// yt-dlp geo-restriction bypass, set via `YTDLP_GEO_BYPASS` / `YTDLP_GEO_BYPASS_COUNTRY`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GeoBypass {
+    #[default]
+    Disabled,
+    /// `--geo-bypass`: fake an X-Forwarded-For header from a country yt-dlp picks.
+    Enabled,
+    /// `--geo-bypass-country <CODE>` with a two-letter ISO 3166-1 code.
+    Country(String),
+}
+
+/// How yt-dlp handles slow or failing HTTP requests within one run, set via
+/// `YTDLP_SOCKET_TIMEOUT_SECS` / `YTDLP_HTTP_RETRIES`.
+///
+/// These are yt-dlp's own retries of its HTTP requests. They are independent of the
+/// application-level retries in `crate::retry`, which repeat Telegram API calls and never
+/// re-run yt-dlp, and of `METADATA_TIMEOUT` / `DOWNLOAD_TIMEOUT`, which bound the whole run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YtDlpNetwork {
+    /// `--socket-timeout`: seconds a connection may stall before the request fails.
+    pub socket_timeout_secs: u32,
+    /// `--retries`: how often yt-dlp repeats a failed HTTP request.
+    pub http_retries: u32,
+}
+
+impl Default for YtDlpNetwork {
+    fn default() -> Self {
+        Self {
+            socket_timeout_secs: 30,
+            http_retries: 3,
+        }
+    }
+}
+
+impl YtDlpNetwork {
+    fn args(&self) -> [String; 4] {
+        [
+            "--socket-timeout".to_string(),
+            self.socket_timeout_secs.to_string(),
+            "--retries".to_string(),
+            self.http_retries.to_string(),
+        ]
+    }
+}
+
+impl GeoBypass {
+    fn args(&self) -> Vec<&str> {
+        match self {
+            GeoBypass::Disabled => vec![],
+            GeoBypass::Enabled => vec!["--geo-bypass"],
+            GeoBypass::Country(code) => vec!["--geo-bypass-country", code],
+        }
+    }
+}
+
+impl fmt::Display for GeoBypass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoBypass::Disabled => write!(f, "disabled"),
+            GeoBypass::Enabled => write!(f, "enabled"),
+            GeoBypass::Country(code) => write!(f, "enabled (country: {code})"),
+        }
+    }
+}
+
+/// yt-dlp flag deciding whether a URL that names both a video and a playlist expands
+/// into the playlist. A `?v=` link without `list=` is a single item; the playlist size
+/// is then capped by the validator.
+fn playlist_flag(url: &Url) -> Option<&'static str> {
+    if is_playlist_url(url) {
+        Some("--yes-playlist")
+    } else if url.query_pairs().any(|(key, _)| key == "v") {
+        Some("--no-playlist")
+    } else {
+        None
+    }
+}
+
+fn is_download_artifact_name(filename: &str) -> bool {
+    let Some((prefix, rest)) = filename.split_once('.') else {
+        return false;
+    };
+    if Uuid::parse_str(prefix).is_err() {
+        return false;
+    }
+
+    if rest.ends_with(".part") {
+        return true;
+    }
+
+    let Some(extension) = rest.rsplit('.').next().map(str::to_ascii_lowercase) else {
+        return false;
+    };
+    MediaType::from_extension(&extension).is_some() || extension == "image"
+}
+
+#[async_trait]
+impl Downloader for YtDlpDownloader {
+    async fn get_media_metadata(&self, url: &Url) -> Result<MediaInfo, DownloadError> {
+        log::info!("Fetching metadata for {}", url);
+
+        let mut command = self.build_base_command(url);
+        // One item over the limit is enough to reject a longer playlist, without yt-dlp
+        // listing all of it.
+        command
+            .arg("--dump-single-json")
+            .arg("-S")
+            .arg(FORMAT_SORT)
+            .arg("--playlist-end")
+            .arg((MAX_PLAYLIST_ITEMS + 1).to_string());
+        command.arg(url.as_str());
+
+        let started = Instant::now();
+        let output = tokio::time::timeout(METADATA_TIMEOUT, self.children.output(&mut command))
+            .await
+            .map_err(|_| DownloadError::Timeout(METADATA_TIMEOUT.as_secs()))?
+            .map_err(|source| DownloadError::IoError {
+                context: "running yt-dlp --dump-single-json",
+                source,
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::error!(
+                "yt-dlp --dump-single-json failed for url {}: {}",
+                url,
+                stderr
+            );
+            return Err(DownloadError::from_yt_dlp_stderr(&stderr));
+        }
+
+        let stdout_str = String::from_utf8_lossy(&output.stdout);
+        log::debug!(
+            "yt-dlp metadata stdout length for {}: {} bytes",
+            url,
+            stdout_str.len()
+        );
+
+        let mut info = serde_json::from_str::<MediaInfo>(&stdout_str).map_err(|source| {
+            let error = DownloadError::InvalidJson {
+                context: "yt-dlp --dump-single-json",
+                source,
+            };
+            log::error!(
+                "Failed to parse metadata for {}: {}",
+                url,
+                error.display_to_log()
+            );
+            error
+        })?;
+        info.timings.metadata = Some(started.elapsed());
+        log::info!("Metadata for {}: {:?}", url, info.sanitize_for_logging());
+        Ok(info)
+    }
+
+    async fn download_media(
+        &self,
+        info: &mut MediaInfo,
+        url: &Url,
+    ) -> Result<DownloadedMedia, DownloadError> {
+        let started = Instant::now();
+        let media = self.run_download(info, url, None).await?;
+        self.record_download_speed(&media, started.elapsed()).await;
+        log::info!("Download timings for {}: {}", url, info.timings);
+        Ok(media)
+    }
+
+    async fn download_thumbnail(
+        &self,
+        info: &MediaInfo,
+        url: &Url,
+    ) -> Result<PathBuf, DownloadError> {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        let template = format!(
+            "thumbnail:{}",
+            MediaInfo::output_filename_template(&uuid, "")
+        );
+        log::info!("Downloading the thumbnail of {}", info.id);
+
+        // Only the first item of a playlist, whose cover stands for the whole post.
+        let mut command = self.build_base_command(url);
+        command
+            .current_dir(&self.download_dir)
+            .arg("--skip-download")
+            .arg("--write-thumbnail")
+            .arg("--playlist-items")
+            .arg("1")
+            .arg("-o")
+            .arg(&template)
+            .arg(url.as_str());
+
+        let output = tokio::time::timeout(METADATA_TIMEOUT, self.children.output(&mut command))
+            .await
+            .map_err(|_| DownloadError::Timeout(METADATA_TIMEOUT.as_secs()))
+            .and_then(|output| {
+                output.map_err(|source| DownloadError::IoError {
+                    context: "running yt-dlp to download a thumbnail",
+                    source,
+                })
+            });
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                Self::cleanup_download_artifacts(&self.download_dir, &uuid).await;
+                return Err(e);
+            }
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::error!(
+                "yt-dlp thumbnail download failed for url {}: {}",
+                url,
+                stderr
+            );
+            Self::cleanup_download_artifacts(&self.download_dir, &uuid).await;
+            return Err(DownloadError::from_yt_dlp_stderr(&stderr));
+        }
+
+        let prefix = format!("{uuid}.");
+        let raw = std::fs::read_dir(&self.download_dir)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&prefix))
+            })
+            .ok_or_else(|| DownloadError::ParsingFailed("yt-dlp wrote no thumbnail".to_string()))?;
+        prepare_cover(&raw).map_err(|e| {
+            let _ = std::fs::remove_file(&raw);
+            DownloadError::ParsingFailed(format!("unreadable thumbnail {raw:?}: {e}"))
+        })
+    }
+
+    async fn download_single_entry(
+        &self,
+        entry: &MediaInfo,
+        parent_url: &Url,
+    ) -> Result<PathBuf, DownloadError> {
+        let index = entry.playlist_index.ok_or_else(|| {
+            DownloadError::ParsingFailed(format!("Entry {} has no playlist index", entry.id))
+        })?;
+        let mut info = MediaInfo {
+            id: entry.id.clone(),
+            entries: Some(vec![entry.clone()]),
+            format_selector: entry.format_selector.clone(),
+            ..MediaInfo::default()
+        };
+        let item = match self
+            .run_download(&mut info, parent_url, Some(index))
+            .await?
+        {
+            DownloadedMedia::Single(item) => item,
+            // `info` lists one entry and run_download fails when none was downloaded.
+            DownloadedMedia::Group(mut items) => items.swap_remove(0),
+        };
+        Ok(item.filepath)
+    }
+
+    fn estimate_download_time(&self, info: &MediaInfo) -> Option<Duration> {
+        let filesize = info.filesize.or_else(|| {
+            info.entries
+                .as_ref()
+                .and_then(|entries| entries.iter().map(|e| e.filesize).sum::<Option<u64>>())
+        })?;
+        let bytes_per_sec = self
+            .rolling_download_speed
+            .lock()
+            .expect("download speed lock poisoned")
+            .average()?;
+        if bytes_per_sec <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(filesize as f64 / bytes_per_sec))
+    }
+
+    async fn list_formats(&self, url: &Url) -> Result<String, DownloadError> {
+        let mut command = self.build_base_command(url);
+        command.arg("--list-formats").arg(url.as_str());
+
+        let output = tokio::time::timeout(METADATA_TIMEOUT, self.children.output(&mut command))
+            .await
+            .map_err(|_| DownloadError::Timeout(METADATA_TIMEOUT.as_secs()))?
+            .map_err(|source| DownloadError::IoError {
+                context: "running yt-dlp --list-formats",
+                source,
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::error!("yt-dlp --list-formats failed for url {}: {}", url, stderr);
+            return Err(DownloadError::from_yt_dlp_stderr(&stderr));
+        }
+
+        let formats = String::from_utf8_lossy(&output.stdout).into_owned();
+        log::debug!("Available formats for {}:\n{}", url, formats);
+        Ok(formats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use url::Url;
+
+    #[test]
+    fn test_download_timings_format_only_the_phases_that_ran() {
+        let timings = DownloadTimings {
+            metadata: Some(Duration::from_millis(1234)),
+            download: Some(Duration::from_secs(3)),
+            thumbnail: None,
+            post_processing: Some(Duration::from_millis(50)),
+        };
+        assert_eq!(
+            timings.to_string(),
+            "metadata 1.2s · download 3.0s · post-processing 0.1s"
+        );
+        assert_eq!(DownloadTimings::default().to_string(), "no timings");
+    }
+
+    #[test]
+    fn test_timings_footer_is_dropped_when_the_caption_is_full() {
+        let timings = DownloadTimings {
+            download: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+        assert_eq!(
+            append_timings_footer("caption".to_string(), &timings),
+            "caption\n\n<i>⏱ download 2.0s</i>"
+        );
+        let full = "x".repeat(MediaInfo::TELEGRAM_CAPTION_LIMIT - 5);
+        assert_eq!(append_timings_footer(full.clone(), &timings), full);
+    }
+
+    #[test]
+    fn test_sanitize_for_logging_keeps_only_technical_fields() {
+        let info = MediaInfo {
+            id: "abc".to_string(),
+            title: Some("Jane's birthday".to_string()),
+            description: Some("Call me at 555-0100".to_string()),
+            uploader: Some("jane.doe".to_string()),
+            upload_date: Some("20250101".to_string()),
+            ext: Some("mp4".to_string()),
+            duration: Some(12.5),
+            filesize: Some(1024),
+            entries: Some(vec![MediaInfo::default(), MediaInfo::default()]),
+            ..Default::default()
+        };
+
+        let sanitized = info.sanitize_for_logging();
+
+        assert_eq!(
+            sanitized,
+            SanitizedMetadata {
+                id: "abc".to_string(),
+                ext: Some("mp4".to_string()),
+                duration: Some(12.5),
+                filesize: Some(1024),
+                has_entries: true,
+                entry_count: 2,
+            }
+        );
+        let logged = format!("{sanitized:?}");
+        assert!(!logged.contains("Jane") && !logged.contains("555") && !logged.contains("2025"));
+    }
+
+    #[test]
+    fn test_build_caption_normal_text() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("TestUser".to_string()),
+            description: Some("A normal description".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(caption.contains("<i>TestUser</i>"));
+        assert!(caption.contains("A normal description"));
+    }
+
+    #[test]
+    fn test_build_caption_links_uploader_to_channel() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("Tom & Jerry".to_string()),
+            channel_url: Some("https://example.com/c/tom?a=1&b=<2>".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(caption.contains(
+            "<a href=\"https://example.com/c/tom?a=1&amp;b=%3C2%3E\">Tom &amp; Jerry</a>"
+        ));
+        assert!(!caption.contains("<i>"));
+    }
+
+    #[test]
+    fn test_build_caption_ignores_non_http_channel_url() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("TestUser".to_string()),
+            channel_url: Some("javascript:alert(1)".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(caption.contains("<i>TestUser</i>"));
+        assert!(!caption.contains("javascript"));
+    }
+
+    #[test]
+    fn test_build_caption_escapes_html_tags() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("<script>alert('xss')</script>".to_string()),
+            description: Some("desc with <b>tags</b>".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(caption.contains("&lt;script&gt;"));
+        assert!(caption.contains("&lt;b&gt;tags&lt;/b&gt;"));
+        assert!(!caption.contains("<script>"));
+        assert!(!caption.contains("<b>tags"));
+    }
+
+    #[test]
+    fn test_build_caption_escapes_ampersands() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("Tom & Jerry".to_string()),
+            description: Some("A & B < C > D".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(caption.contains("Tom &amp; Jerry"));
+        assert!(caption.contains("A &amp; B &lt; C &gt; D"));
+        // Verify no double-escaping
+        assert!(!caption.contains("&amp;amp;"));
+    }
+
+    #[test]
+    fn test_build_caption_truncates_to_telegram_limit() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("TestUser".to_string()),
+            description: Some("é".repeat(5000)),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert_eq!(caption.chars().count(), MediaInfo::TELEGRAM_CAPTION_LIMIT);
+        assert!(caption.ends_with("[...]</blockquote>"));
+    }
+
+    const VIA_LINK: &str = "https://t.me/crabberbot?start=c";
+
+    /// Panics unless `caption` is HTML Telegram accepts: only the tags captions use, closed
+    /// in order, and `&`, `<` and `>` only as entities outside of tags.
+    fn assert_valid_caption_html(caption: &str) {
+        let mut open_tags = Vec::new();
+        let mut rest = caption;
+        while let Some(index) = rest.find(['<', '&', '>']) {
+            let (special, after) = rest[index..].split_at(1);
+            match special {
+                "<" => {
+                    let end = after.find('>').expect("unterminated tag");
+                    let tag = &after[..end];
+                    let name = tag.split(' ').next().unwrap();
+                    match name.strip_prefix('/') {
+                        Some(closed) => {
+                            assert_eq!(open_tags.pop(), Some(closed), "misnested: {caption}")
+                        }
+                        None => {
+                            assert!(
+                                ["a", "i", "b", "blockquote"].contains(&name),
+                                "unexpected tag <{tag}> in {caption}"
+                            );
+                            open_tags.push(name);
+                        }
+                    }
+                    assert!(!tag.contains('<'), "stray < in tag <{tag}>");
+                    for entity in tag.match_indices('&') {
+                        assert_entity(&tag[entity.0..], caption);
+                    }
+                    rest = &after[end + 1..];
+                }
+                "&" => {
+                    assert_entity(&rest[index..], caption);
+                    rest = after;
+                }
+                _ => panic!("stray > in {caption}"),
+            }
+        }
+        assert!(open_tags.is_empty(), "unclosed {open_tags:?} in {caption}");
+    }
+
+    fn assert_entity(text: &str, caption: &str) {
+        assert!(
+            ["&amp;", "&lt;", "&gt;", "&quot;"]
+                .iter()
+                .any(|entity| text.starts_with(entity)),
+            "bare & in {caption}"
+        );
+    }
+
+    fn caption_options() -> impl Strategy<Value = CaptionOptions> {
+        (
+            prop_oneof![
+                Just(CaptionLinks::Keep),
+                Just(CaptionLinks::Linkify),
+                Just(CaptionLinks::Strip)
+            ],
+            any::<bool>(),
+        )
+            .prop_map(|(links, show_meta)| CaptionOptions {
+                links,
+                show_meta,
+                ..CaptionOptions::default()
+            })
+    }
+
+    fn source_url() -> impl Strategy<Value = Url> {
+        "https://[a-z]{1,12}\\.(com|co\\.uk)/[a-zA-Z0-9_/-]{0,30}(\\?[a-z]=[a-z0-9&=<\"]{0,12})?"
+            .prop_map(|url| Url::parse(&url).unwrap())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_build_caption_fits_and_stays_valid_html(
+            title in proptest::option::of("\\PC{0,300}"),
+            description in proptest::option::of(
+                "(\\PC|\\n| https://example\\.com/a\\?b=1&c=2 |[&<>\"]){0,1500}"
+            ),
+            uploader in proptest::option::of("\\PC{0,60}"),
+            url in source_url(),
+            options in caption_options(),
+        ) {
+            let info = MediaInfo {
+                id: "1".to_string(),
+                title,
+                description,
+                uploader,
+                upload_date: Some("20241102".to_string()),
+                duration: Some(201.0),
+                ..Default::default()
+            };
+
+            let caption = build_caption(&info, &url, &options);
+
+            prop_assert!(caption.chars().count() <= MediaInfo::TELEGRAM_CAPTION_LIMIT);
+            prop_assert!(caption.contains(VIA_LINK));
+            assert_valid_caption_html(&caption);
+        }
+
+        #[test]
+        fn prop_build_caption_truncates_exactly_at_the_available_space(
+            alphabet in "[a-zA-Zé🦀ß]{1,8}",
+            offset in -1isize..=1,
+            url in source_url(),
+        ) {
+            let marker = "[...]";
+            let empty = MediaInfo {
+                id: "1".to_string(),
+                ..Default::default()
+            };
+            let scaffold = build_caption(&empty, &url, &CaptionOptions::default());
+            let available_space_for_quote =
+                MediaInfo::TELEGRAM_CAPTION_LIMIT - scaffold.chars().count() - marker.len();
+            let length = available_space_for_quote.checked_add_signed(offset).unwrap();
+            let description: String = alphabet.chars().cycle().take(length).collect();
+            let info = MediaInfo {
+                description: Some(description.clone()),
+                ..empty
+            };
+
+            let caption = build_caption(&info, &url, &CaptionOptions::default());
+
+            prop_assert!(caption.contains(VIA_LINK));
+            assert_valid_caption_html(&caption);
+            if offset <= 0 {
+                let expected_end = format!("{description}</blockquote>");
+                prop_assert!(caption.ends_with(&expected_end));
+            } else {
+                let kept: String = description.chars().take(available_space_for_quote).collect();
+                let expected_end = format!("{kept}{marker}</blockquote>");
+                prop_assert!(caption.ends_with(&expected_end));
+                prop_assert_eq!(caption.chars().count(), MediaInfo::TELEGRAM_CAPTION_LIMIT);
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_upload_date() {
+        assert_eq!(
+            format_upload_date("20241102").as_deref(),
+            Some("2024-11-02")
+        );
+        assert_eq!(format_upload_date("20241302"), None);
+        assert_eq!(format_upload_date("2024-11-02"), None);
+        assert_eq!(format_upload_date(""), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0.0), "0:00");
+        assert_eq!(format_duration(7.4), "0:07");
+        assert_eq!(format_duration(201.0), "3:21");
+        assert_eq!(format_duration(3599.6), "1:00:00");
+        assert_eq!(format_duration(3723.0), "1:02:03");
+    }
+
+    #[test]
+    fn test_caption_meta_line_omits_missing_fields() {
+        let mut info = MediaInfo {
+            upload_date: Some("20241102".to_string()),
+            duration: Some(201.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            caption_meta_line(&info).as_deref(),
+            Some("📅 2024-11-02 · ⏱ 3:21")
+        );
+        info.duration = None;
+        assert_eq!(caption_meta_line(&info).as_deref(), Some("📅 2024-11-02"));
+        info.upload_date = Some("unknown".to_string());
+        assert_eq!(caption_meta_line(&info), None);
+        info.duration = Some(65.0);
+        assert_eq!(caption_meta_line(&info).as_deref(), Some("⏱ 1:05"));
+    }
+
+    #[test]
+    fn test_build_caption_attribution() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            description: Some("é".repeat(5000)),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let source = "<a href=\"https://example.com/video\">Source</a>";
+
+        let default = build_caption(&info, &url, &CaptionOptions::default());
+        assert!(default.starts_with(&format!(
+            "<a href=\"{VIA_LINK}\">CrabberBot</a> 🦀 {source}\n\n<blockquote>"
+        )));
+
+        let custom = CaptionOptions {
+            attribution: Attribution::Link {
+                name: "Crabs & Co".to_string(),
+                url: Url::parse("https://t.me/selfhosted_bot").unwrap(),
+            },
+            ..CaptionOptions::default()
+        };
+        let caption = build_caption(&info, &url, &custom);
+        assert!(caption.starts_with(&format!(
+            "<a href=\"https://t.me/selfhosted_bot\">Crabs &amp; Co</a> 🦀 {source}\n\n"
+        )));
+        assert_eq!(caption.chars().count(), MediaInfo::TELEGRAM_CAPTION_LIMIT);
+
+        let off = CaptionOptions {
+            attribution: Attribution::Off,
+            ..CaptionOptions::default()
+        };
+        let caption = build_caption(&info, &url, &off);
+        assert!(caption.starts_with(&format!("{source}\n\n<blockquote>é")));
+        assert_eq!(caption.chars().count(), MediaInfo::TELEGRAM_CAPTION_LIMIT);
+        assert!(caption.ends_with("[...]</blockquote>"));
+    }
+
+    #[test]
+    fn test_remove_source_link() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            title: Some("Title".to_string()),
+            duration: Some(75.0),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+
+        let caption = build_caption(&info, &url, &CaptionOptions::default());
+        assert_eq!(
+            remove_source_link(&caption, &url),
+            format!("<a href=\"{VIA_LINK}\">CrabberBot</a> 🦀\n\n<blockquote>Title</blockquote>")
+        );
+
+        let options = CaptionOptions {
+            show_meta: true,
+            attribution: Attribution::Off,
+            ..CaptionOptions::default()
+        };
+        let caption = build_caption(&info, &url, &options);
+        assert_eq!(
+            remove_source_link(&caption, &url),
+            "⏱ 1:15\n\n<blockquote>Title</blockquote>"
+        );
+
+        let bare = CaptionOptions {
+            attribution: Attribution::Off,
+            ..CaptionOptions::default()
+        };
+        let caption = build_caption(&info, &url, &bare);
+        assert_eq!(
+            remove_source_link(&caption, &url),
+            "<blockquote>Title</blockquote>"
+        );
+    }
+
+    #[test]
+    fn test_build_caption_meta_line_counts_against_limit() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            description: Some("é".repeat(5000)),
+            upload_date: Some("20241102".to_string()),
+            duration: Some(201.0),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let options = CaptionOptions {
+            show_meta: true,
+            ..CaptionOptions::default()
+        };
+
+        let caption = build_caption(&info, &url, &options);
+
+        assert!(caption.contains("Source</a>\n📅 2024-11-02 · ⏱ 3:21\n\n<blockquote>"));
+        assert_eq!(caption.chars().count(), MediaInfo::TELEGRAM_CAPTION_LIMIT);
+        assert!(!build_caption(&info, &url, &CaptionOptions::default()).contains("📅"));
+    }
+
+    #[test]
+    fn test_build_caption_linkify_drops_anchor_that_would_overflow() {
+        // Fits as plain text, but anchors add markup that pushes it over the limit.
+        let filler = "a".repeat(840);
+        let info = MediaInfo {
+            id: "1".to_string(),
+            description: Some(format!("{filler} https://example.com/full-video")),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let kept = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(kept.contains("https://example.com/full-video</blockquote>"));
+
+        let linkified = build_caption(&info, &url, &CaptionLinks::Linkify.into());
+        assert!(linkified.chars().count() <= MediaInfo::TELEGRAM_CAPTION_LIMIT);
+        assert!(linkified.ends_with(&format!("{filler} [...]</blockquote>")));
+        assert_eq!(
+            linkified.matches("<a ").count(),
+            linkified.matches("</a>").count()
+        );
+    }
+
+    #[test]
+    fn test_build_caption_linkify_and_strip_descriptions() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            uploader: Some("TestUser".to_string()),
+            description: Some("Full video: youtube.com/watch?v=abc".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        assert!(
+            build_caption(&info, &url, &CaptionLinks::Linkify.into()).ends_with(
+                "<i>TestUser</i>\nFull video: <a href=\"https://youtube.com/watch?v=abc\">\
+                 youtube.com/watch?v=abc</a></blockquote>"
+            )
+        );
+        assert!(
+            build_caption(&info, &url, &CaptionLinks::Strip.into())
+                .ends_with("<i>TestUser</i>\nFull video:</blockquote>")
+        );
+    }
+
+    #[test]
+    fn test_build_caption_truncation_does_not_split_escapes() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            description: Some("&".repeat(1000)),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let caption = build_caption(&info, &url, &CaptionLinks::Keep.into());
+        assert!(caption.ends_with("&amp;[...]</blockquote>"));
+    }
+
+    #[test]
+    fn test_build_caption_preview_reports_byte_length() {
+        let info = MediaInfo {
+            id: "1".to_string(),
+            description: Some("Crème brûlée".to_string()),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let (caption, len) = info.build_caption_preview(&url, &CaptionLinks::Keep.into());
+        assert_eq!(
+            caption,
+            build_caption(&info, &url, &CaptionLinks::Keep.into())
+        );
+        assert_eq!(len, caption.len());
+        assert!(len > caption.chars().count());
+    }
+
+    #[tokio::test]
+    async fn test_yt_dlp_uses_custom_path_and_fails_if_invalid() {
+        let downloader = YtDlpDownloader {
+            yt_dlp_path: "/path/to/a/nonexistent/yt-dlp-binary".to_string(),
+            download_dir: PathBuf::from("/downloads"),
+            geo_bypass: GeoBypass::Disabled,
+            network: YtDlpNetwork::default(),
+            cookies: CookieProfiles::default(),
+            rolling_download_speed: Arc::new(Mutex::new(RollingAverage::new(4))),
+            children: ChildProcesses::new(),
+            version: None,
+            write_info_json: false,
+            windows_filenames: false,
+            verify_files: false,
+        };
+
+        let url = Url::parse("https://example.com").unwrap();
+
+        let result = downloader.get_media_metadata(&url).await;
+
+        assert!(result.is_err());
+
+        match result {
+            Err(err @ DownloadError::IoError { .. }) => {
+                let source = std::error::Error::source(&err)
+                    .and_then(|source| source.downcast_ref::<std::io::Error>())
+                    .expect("IoError should expose the io::Error as its source");
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+                assert_eq!(
+                    err.display_to_log(),
+                    format!("I/O error while running yt-dlp --dump-single-json: {source}")
+                );
+                assert_eq!(
+                    err.display_to_user(),
+                    "Sorry, I could not download the media. Please try again later."
+                );
+            }
+            _ => panic!("Expected IoError, but got something else."),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_geo_block_is_told_apart_from_other_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(
+            dir.path(),
+            "echo 'ERROR: [BBC] p0abc: This video is not available in your country' >&2\nexit 1",
+        );
+        let downloader = fake_downloader(&fake_yt_dlp, dir.path());
+        let url = Url::parse("https://www.bbc.co.uk/iplayer/episode/p0abc").unwrap();
+
+        let err = downloader.get_media_metadata(&url).await.unwrap_err();
+        assert!(matches!(err, DownloadError::GeoBlocked(_)));
+        assert_eq!(
+            err.display_to_user(),
+            "🌍 This content is not available in the server's region. Try a VPN or access it directly."
+        );
+        assert!(matches!(
+            DownloadError::from_yt_dlp_stderr("ERROR: Unsupported URL"),
+            DownloadError::CommandFailed(_)
+        ));
+    }
+
+    /// Write an executable fake yt-dlp into `dir` that runs `script`.
+    fn write_fake_yt_dlp(dir: &Path, script: &str) -> PathBuf {
+        let fake_yt_dlp = dir.join("yt-dlp");
+        std::fs::write(&fake_yt_dlp, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(
+            &fake_yt_dlp,
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+        fake_yt_dlp
+    }
+
+    fn fake_downloader(yt_dlp_path: &Path, download_dir: &Path) -> YtDlpDownloader {
+        YtDlpDownloader {
+            yt_dlp_path: yt_dlp_path.to_string_lossy().into_owned(),
+            download_dir: download_dir.to_path_buf(),
+            geo_bypass: GeoBypass::Disabled,
+            network: YtDlpNetwork::default(),
+            cookies: CookieProfiles::default(),
+            rolling_download_speed: Arc::new(Mutex::new(RollingAverage::new(4))),
+            children: ChildProcesses::new(),
+            version: None,
+            write_info_json: false,
+            windows_filenames: false,
+            verify_files: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_merges_streams_into_mp4() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(
+            dir.path(),
+            r#"printf '%s\n' "$@" > args.txt
+echo '{"id": "abc", "_filename": "out.abc.mp4", "ext": "mp4"}'"#,
+        );
+        let downloader = fake_downloader(&fake_yt_dlp, dir.path());
+        let mut info = MediaInfo {
+            id: "abc".to_string(),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+
+        let media = downloader.download_media(&mut info, &url).await.unwrap();
+
+        assert!(matches!(
+            media,
+            DownloadedMedia::Single(DownloadedItem {
+                media_type: MediaType::Video,
+                ..
+            })
+        ));
+        let args = std::fs::read_to_string(dir.path().join("args.txt")).unwrap();
+        let args: Vec<&str> = args.lines().collect();
+        assert!(
+            args.windows(2)
+                .any(|pair| pair == ["--merge-output-format", "mp4"])
+        );
+        assert!(args.windows(2).any(|pair| pair == ["-S", FORMAT_SORT]));
+        assert!(
+            args.windows(4)
+                .any(|window| window == ["--socket-timeout", "30", "--retries", "3"])
+        );
+        // No thumbnail was requested, so only the download phase is timed and logged.
+        assert!(info.timings.download.is_some());
+        assert_eq!(info.timings.thumbnail, None);
+        assert!(info.timings.to_string().starts_with("download "));
+    }
+
+    #[tokio::test]
+    async fn test_download_keeps_info_json_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(
+            dir.path(),
+            r#"printf '%s\n' "$@" > args.txt
+template=$(grep -m1 '%(id)s' args.txt)
+media=$(echo "$template" | sed 's/%(id)s/abc/; s/%(ext)s/mp4/')
+touch "$media"
+echo '{"id": "abc"}' > "$(echo "$template" | sed 's/%(id)s/abc/; s/%(ext)s/info.json/')"
+echo "{\"id\": \"abc\", \"_filename\": \"$media\", \"ext\": \"mp4\"}""#,
+        );
+        let downloader = fake_downloader(&fake_yt_dlp, dir.path()).with_info_json(true);
+        let mut info = MediaInfo {
+            id: "abc".to_string(),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+
+        let DownloadedMedia::Single(item) =
+            downloader.download_media(&mut info, &url).await.unwrap()
+        else {
+            panic!("expected a single item");
+        };
+        let info_json = item.info_json_filepath.expect("info.json should be kept");
+        assert!(info_json.exists());
+        assert_eq!(
+            info_json.with_extension("").with_extension("mp4"),
+            item.filepath
+        );
+        let args = std::fs::read_to_string(dir.path().join("args.txt")).unwrap();
+        assert!(args.lines().any(|arg| arg == "--write-info-json"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_download_is_reported_as_corrupted_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(
+            dir.path(),
+            r#"template=$(printf '%s\n' "$@" | grep -m1 '%(id)s')
+media=$(echo "$template" | sed 's/%(id)s/abc/; s/%(ext)s/mp4/')
+touch "$media"
+echo "{\"id\": \"abc\", \"_filename\": \"$media\", \"ext\": \"mp4\"}""#,
+        );
+        let downloader = YtDlpDownloader {
+            verify_files: true,
+            ..fake_downloader(&fake_yt_dlp, dir.path())
+        };
+        let mut info = MediaInfo {
+            id: "abc".to_string(),
+            ..Default::default()
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+
+        let err = downloader
+            .download_media(&mut info, &url)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            DownloadError::Corrupted {
+                source: CorruptFile::Empty,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.display_to_user(),
+            "Sorry, the source delivered a corrupted file. Please try again."
+        );
+        let left: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .collect();
+        assert_eq!(left, ["yt-dlp"]);
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_album_items_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(
+            dir.path(),
+            r#"printf 'jpeg' > out.first.jpg
+touch out.second.jpg
+echo '{"id": "first", "_filename": "out.first.jpg", "ext": "jpg"}'
+echo '{"id": "second", "_filename": "out.second.jpg", "ext": "jpg"}'"#,
+        );
+        let downloader = YtDlpDownloader {
+            verify_files: true,
+            ..fake_downloader(&fake_yt_dlp, dir.path())
+        };
+        let entry = |id: &str| MediaInfo {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        let mut info = MediaInfo {
+            id: "album".to_string(),
+            entries: Some(vec![entry("first"), entry("second")]),
+            ..Default::default()
+        };
+        let url = Url::parse("https://www.instagram.com/p/abc/").unwrap();
+
+        let DownloadedMedia::Group(items) =
+            downloader.download_media(&mut info, &url).await.unwrap()
+        else {
+            panic!("expected a group");
+        };
+
+        let positions: Vec<_> = items.iter().map(|item| item.playlist_index).collect();
+        assert_eq!(positions, [Some(1)]);
+        assert!(!dir.path().join("out.second.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_single_entry_is_unsupported_by_default() {
+        use crate::test_utils::{TestDownloader, create_test_info};
+
+        let downloader = TestDownloader::success(create_test_info());
+        let url = Url::parse("https://example.com/playlist").unwrap();
+
+        let result = downloader
+            .download_single_entry(&create_test_info(), &url)
+            .await;
+
+        assert!(matches!(result, Err(DownloadError::Unsupported(_))));
+        assert_eq!(downloader.downloads(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_download_single_entry_asks_for_its_playlist_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(
+            dir.path(),
+            r#"printf '%s\n' "$@" > args.txt
+echo '{"id": "second", "_filename": "out.second.jpg", "ext": "jpg"}'"#,
+        );
+        let downloader = fake_downloader(&fake_yt_dlp, dir.path());
+        let entry = MediaInfo {
+            id: "second".to_string(),
+            playlist_index: Some(2),
+            ..Default::default()
+        };
+        let url = Url::parse("https://www.instagram.com/p/abc/").unwrap();
+
+        let path = downloader
+            .download_single_entry(&entry, &url)
+            .await
+            .unwrap();
+
+        assert_eq!(path, dir.path().join("out.second.jpg"));
+        let args = std::fs::read_to_string(dir.path().join("args.txt")).unwrap();
+        let args: Vec<&str> = args.lines().collect();
+        assert!(
+            args.windows(2)
+                .any(|pair| pair == ["--playlist-items", "2"])
+        );
+
+        let unindexed = MediaInfo {
+            playlist_index: None,
+            ..entry
+        };
+        assert!(matches!(
+            downloader.download_single_entry(&unindexed, &url).await,
+            Err(DownloadError::ParsingFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_metadata_lists_one_playlist_item_past_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = format!(
+            r#"printf '%s\n' "$@" > '{}'; echo '{{"id": "abc"}}'"#,
+            dir.path().join("metadata_args.txt").display()
+        );
+        let downloader = fake_downloader(&write_fake_yt_dlp(dir.path(), &script), dir.path());
+        let url = Url::parse("https://www.youtube.com/playlist?list=PL123").unwrap();
+
+        downloader.get_media_metadata(&url).await.unwrap();
+
+        let args = std::fs::read_to_string(dir.path().join("metadata_args.txt")).unwrap();
+        let args: Vec<&str> = args.lines().collect();
+        assert!(args.contains(&"--yes-playlist"));
+        assert!(
+            args.windows(2)
+                .any(|pair| pair == ["--playlist-end", &(MAX_PLAYLIST_ITEMS + 1).to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_terminate_all_kills_running_yt_dlp() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_yt_dlp = write_fake_yt_dlp(dir.path(), "exec sleep 30");
+        let downloader = Arc::new(fake_downloader(&fake_yt_dlp, dir.path()));
+        let children = downloader.child_processes();
+
+        let request = tokio::spawn({
+            let downloader = downloader.clone();
+            async move {
+                let url = Url::parse("https://example.com/slow").unwrap();
+                downloader.get_media_metadata(&url).await
+            }
+        });
+        let started = Instant::now();
+        while children.active_children() == 0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "yt-dlp never started"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        children.terminate_all(Duration::from_secs(2)).await;
+
+        assert_eq!(children.active_children(), 0);
+        let result = tokio::time::timeout(Duration::from_secs(1), request)
+            .await
+            .expect("request should finish once yt-dlp is gone")
+            .unwrap();
+        assert!(matches!(result, Err(DownloadError::CommandFailed(_))));
+    }
+
+    #[test]
+    fn test_output_filename_template() {
+        let uuid = "0b5f3f5e-1f3a-4a55-9d1e-0c1f6f0b7a11";
+        assert_eq!(
+            MediaInfo::output_filename_template(uuid, ""),
+            format!("{uuid}.%(id)s.%(ext)s")
+        );
+        assert_eq!(
+            MediaInfo::output_filename_template(uuid, "/downloads"),
+            format!("/downloads/{uuid}.%(id)s.%(ext)s")
+        );
+        assert_eq!(
+            MediaInfo::output_filename_template(uuid, "/downloads/"),
+            format!("/downloads/{uuid}.%(id)s.%(ext)s")
+        );
+    }
+
+    #[test]
+    fn test_expected_filepath() {
+        let path = |temp_dir| MediaInfo::expected_filepath("u", "abc", "mp4", temp_dir);
+        assert_eq!(path(""), "u.abc.mp4");
+        assert_eq!(path("tmp"), "tmp/u.abc.mp4");
+        assert_eq!(path("/tmp/req/"), "/tmp/req/u.abc.mp4");
+        assert_eq!(path("/tmp//"), "/tmp/u.abc.mp4");
+        assert_eq!(path("/"), "/u.abc.mp4");
+    }
+
+    #[test]
+    fn test_is_playlist_url() {
+        let playlist = Url::parse("https://www.youtube.com/playlist?list=PL123").unwrap();
+        let single = Url::parse("https://www.youtube.com/watch?v=abc").unwrap();
+        assert!(is_playlist_url(&playlist));
+        assert!(!is_playlist_url(&single));
+    }
+
+    #[test]
+    fn test_build_base_command_passes_cookies_for_host() {
+        let downloader = YtDlpDownloader {
+            yt_dlp_path: "yt-dlp".to_string(),
+            download_dir: PathBuf::from("/downloads"),
+            geo_bypass: GeoBypass::Disabled,
+            network: YtDlpNetwork::default(),
+            cookies: CookieProfiles::parse("instagram.com=/secrets/ig.txt").unwrap(),
+            rolling_download_speed: Arc::new(Mutex::new(RollingAverage::new(4))),
+            children: ChildProcesses::new(),
+            version: None,
+            write_info_json: false,
+            windows_filenames: false,
+            verify_files: false,
+        };
+        let args = |url: &str| -> Vec<String> {
+            downloader
+                .build_base_command(&Url::parse(url).unwrap())
+                .as_std()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let instagram = args("https://www.instagram.com/p/abc/");
+        assert!(instagram.ends_with(&["--cookies".to_string(), "/secrets/ig.txt".to_string()]));
+        assert!(!args("https://example.com/video").contains(&"--cookies".to_string()));
+    }
+
+    #[test]
+    fn test_build_base_command_passes_windows_filenames_when_enabled() {
+        let downloader = YtDlpDownloader {
+            yt_dlp_path: "yt-dlp".to_string(),
+            download_dir: PathBuf::from("/downloads"),
+            geo_bypass: GeoBypass::Disabled,
+            network: YtDlpNetwork::default(),
+            cookies: CookieProfiles::default(),
+            rolling_download_speed: Arc::new(Mutex::new(RollingAverage::new(4))),
+            children: ChildProcesses::new(),
+            version: None,
+            write_info_json: false,
+            windows_filenames: false,
+            verify_files: false,
+        };
+        let url = Url::parse("https://example.com/video").unwrap();
+        let has_flag = |downloader: &YtDlpDownloader| {
+            downloader
+                .build_base_command(&url)
+                .as_std()
+                .get_args()
+                .any(|arg| arg == "--windows-filenames")
+        };
+
+        assert!(!has_flag(&downloader));
+        assert!(has_flag(&downloader.with_windows_filenames(true)));
+    }
+
+    #[test]
+    fn test_geo_bypass_args() {
+        assert!(GeoBypass::Disabled.args().is_empty());
+        assert_eq!(GeoBypass::Enabled.args(), ["--geo-bypass"]);
+        assert_eq!(
+            GeoBypass::Country("US".to_string()).args(),
+            ["--geo-bypass-country", "US"]
+        );
+        assert_eq!(
+            GeoBypass::Country("US".to_string()).to_string(),
+            "enabled (country: US)"
+        );
+    }
+
+    #[test]
+    fn test_playlist_flag() {
+        let flag = |url: &str| playlist_flag(&Url::parse(url).unwrap());
+        assert_eq!(
+            flag("https://www.youtube.com/watch?v=abc"),
+            Some("--no-playlist")
+        );
+        assert_eq!(
+            flag("https://www.youtube.com/watch?v=abc&list=PL123"),
+            Some("--yes-playlist")
+        );
+        assert_eq!(
+            flag("https://www.youtube.com/playlist?list=PL123"),
+            Some("--yes-playlist")
+        );
+        assert_eq!(flag("https://www.instagram.com/p/ABC"), None);
+    }
+
+    #[test]
+    fn test_rolling_average_keeps_only_recent_samples() {
+        let mut average = RollingAverage::new(2);
+        assert_eq!(average.average(), None);
+        average.push(10.0);
+        average.push(20.0);
+        average.push(40.0);
+        assert_eq!(average.average(), Some(30.0));
+    }
+
+    #[test]
+    fn test_estimate_download_time_uses_rolling_speed() {
+        let downloader = YtDlpDownloader {
+            yt_dlp_path: "yt-dlp".to_string(),
+            download_dir: PathBuf::from("/downloads"),
+            geo_bypass: GeoBypass::Disabled,
+            network: YtDlpNetwork::default(),
+            cookies: CookieProfiles::default(),
+            rolling_download_speed: Arc::new(Mutex::new(RollingAverage::new(4))),
+            children: ChildProcesses::new(),
+            version: None,
+            write_info_json: false,
+            windows_filenames: false,
+            verify_files: false,
+        };
+        let info = MediaInfo {
+            filesize: Some(3_000_000),
+            ..Default::default()
+        };
+        assert_eq!(downloader.estimate_download_time(&info), None);
+
+        downloader
+            .rolling_download_speed
+            .lock()
+            .unwrap()
+            .push(1_000_000.0);
+        assert_eq!(
+            downloader.estimate_download_time(&info),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            downloader.estimate_download_time(&MediaInfo::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resolve_download_path_keeps_absolute_paths() {
+        let download_dir = Path::new("/downloads");
+        let filepath = "/downloads/video.mp4";
+
+        let resolved = YtDlpDownloader::resolve_download_path(download_dir, filepath);
+
+        assert_eq!(resolved, PathBuf::from("/downloads/video.mp4"));
+    }
+
+    #[test]
+    fn test_resolve_download_path_rebases_relative_paths_under_downloads_dir() {
+        let download_dir = Path::new("/downloads");
+        let filepath = "./video.mp4";
+
+        let resolved = YtDlpDownloader::resolve_download_path(download_dir, filepath);
 
         assert_eq!(resolved, PathBuf::from("/downloads/video.mp4"));
     }
@@ -698,6 +2868,71 @@ mod tests {
         assert_eq!(resolved, PathBuf::from("/downloads/video.mp4"));
     }
 
+    fn write_test_image(path: &Path, width: u32, height: u32) {
+        image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        })
+        .save(path)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_prepare_thumbnail_converts_webp_to_small_jpeg() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("uuid.media.webp");
+        write_test_image(&source, 1280, 720);
+
+        let prepared = prepare_thumbnail(&source).unwrap();
+
+        assert_eq!(prepared, temp_dir.path().join("uuid.media.thumb.jpg"));
+        let img = image::ImageReader::open(&prepared)
+            .unwrap()
+            .with_guessed_format()
+            .unwrap();
+        assert_eq!(img.format(), Some(image::ImageFormat::Jpeg));
+        assert_eq!(img.into_dimensions().unwrap(), (320, 180));
+        assert!(std::fs::metadata(&prepared).unwrap().len() <= THUMBNAIL_MAX_BYTES as u64);
+        assert!(source.exists());
+    }
+
+    #[test]
+    fn test_prepare_thumbnail_keeps_small_png_dimensions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("uuid.media.png");
+        write_test_image(&source, 90, 160);
+
+        let prepared = prepare_thumbnail(&source).unwrap();
+
+        let dimensions = image::image_dimensions(&prepared).unwrap();
+        assert_eq!(dimensions, (90, 160));
+    }
+
+    #[test]
+    fn test_prepare_cover_converts_to_full_size_jpeg_once() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("uuid.media.png");
+        write_test_image(&source, 1280, 720);
+
+        let cover = prepare_cover(&source).unwrap();
+
+        assert_eq!(cover, temp_dir.path().join("uuid.media.cover.jpg"));
+        assert_eq!(image::image_dimensions(&cover).unwrap(), (1280, 720));
+        assert!(!source.exists());
+        // Already a JPEG, so it is sent as it is.
+        assert_eq!(prepare_cover(&cover).unwrap(), cover);
+    }
+
+    #[test]
+    fn test_convert_thumbnail_drops_corrupt_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("uuid.media.webp");
+        std::fs::write(&source, b"not an image").unwrap();
+
+        assert_eq!(YtDlpDownloader::convert_thumbnail(&source), None);
+        assert!(!source.exists());
+        assert!(!temp_dir.path().join("uuid.media.thumb.jpg").exists());
+    }
+
     #[test]
     fn test_find_thumbnail_searches_downloads_dir() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -707,12 +2942,58 @@ mod tests {
         std::fs::write(&video_filepath, b"video").unwrap();
         std::fs::write(&thumbnail_filepath, b"thumbnail").unwrap();
 
-        let found =
-            YtDlpDownloader::find_thumbnail(download_dir, "test-id", "media", &video_filepath);
+        let found = YtDlpDownloader::find_thumbnail(&video_filepath);
 
         assert_eq!(found, Some(thumbnail_filepath));
     }
 
+    #[test]
+    fn test_find_thumbnail_and_info_json_follow_sanitized_names() {
+        // With `--windows-filenames`, yt-dlp writes the id "a:b" as "a：b".
+        let temp_dir = tempfile::tempdir().unwrap();
+        let download_dir = temp_dir.path();
+        let video_filepath = download_dir.join("uuid.a：b.mp4");
+        let thumbnail_filepath = download_dir.join("uuid.a：b.webp");
+        let info_json_filepath = download_dir.join("uuid.a：b.info.json");
+        std::fs::write(&video_filepath, b"video").unwrap();
+        assert_eq!(YtDlpDownloader::find_info_json(&video_filepath), None);
+        std::fs::write(&thumbnail_filepath, b"thumbnail").unwrap();
+        std::fs::write(&info_json_filepath, b"{}").unwrap();
+
+        assert_eq!(
+            YtDlpDownloader::find_thumbnail(&video_filepath),
+            Some(thumbnail_filepath)
+        );
+        assert_eq!(
+            YtDlpDownloader::find_info_json(&video_filepath),
+            Some(info_json_filepath)
+        );
+    }
+
+    #[test]
+    fn test_find_thumbnail_and_info_json_follow_underscored_names() {
+        // In an id, yt-dlp writes characters such as "|" or "*" as "_", so "a|b" is "a_b".
+        let temp_dir = tempfile::tempdir().unwrap();
+        let download_dir = temp_dir.path();
+        let video_filepath = download_dir.join("uuid.a_b.mp4");
+        let thumbnail_filepath = download_dir.join("uuid.a_b.jpg");
+        let info_json_filepath = download_dir.join("uuid.a_b.info.json");
+        std::fs::write(&video_filepath, b"video").unwrap();
+        std::fs::write(&thumbnail_filepath, b"thumbnail").unwrap();
+        std::fs::write(&info_json_filepath, b"{}").unwrap();
+        // A file for another id must not be taken for this one's thumbnail.
+        std::fs::write(download_dir.join("uuid.a_bc.jpg"), b"other").unwrap();
+
+        assert_eq!(
+            YtDlpDownloader::find_thumbnail(&video_filepath),
+            Some(thumbnail_filepath)
+        );
+        assert_eq!(
+            YtDlpDownloader::find_info_json(&video_filepath),
+            Some(info_json_filepath)
+        );
+    }
+
     #[tokio::test]
     async fn test_cleanup_orphaned_downloads_removes_uuid_media_artifacts() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -769,4 +3050,136 @@ mod tests {
         assert!(!target_part.exists());
         assert!(other_video.exists());
     }
+
+    const MB: u64 = 1_000_000;
+
+    /// Trimmed `formats` of a YouTube video: AAC and Opus audio, a progressive 360p, and
+    /// H.264, VP9 and AV1 video-only formats.
+    fn youtube_formats() -> MediaInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": "dQw4w9WgXcQ",
+            "filesize_approx": 48_400_000,
+            "formats": [
+                {"format_id": "sb0", "ext": "mhtml", "vcodec": "none", "acodec": "none"},
+                {"format_id": "139", "ext": "m4a", "vcodec": "none", "acodec": "mp4a.40.5", "filesize": 1_200_000},
+                {"format_id": "140", "ext": "m4a", "vcodec": "none", "acodec": "mp4a.40.2", "filesize": 3_400_000},
+                {"format_id": "251", "ext": "webm", "vcodec": "none", "acodec": "opus", "filesize": 3_600_000},
+                {"format_id": "18", "ext": "mp4", "vcodec": "avc1.42001E", "acodec": "mp4a.40.2", "height": 360, "filesize_approx": 9_000_000},
+                {"format_id": "134", "ext": "mp4", "vcodec": "avc1.4D401E", "acodec": "none", "height": 360, "filesize": 5_000_000},
+                {"format_id": "136", "ext": "mp4", "vcodec": "avc1.4d401f", "acodec": "none", "height": 720, "filesize": 20_000_000},
+                {"format_id": "137", "ext": "mp4", "vcodec": "avc1.640028", "acodec": "none", "height": 1080, "filesize": 45_000_000},
+                {"format_id": "248", "ext": "webm", "vcodec": "vp9", "acodec": "none", "height": 1080, "filesize": 30_000_000},
+                {"format_id": "399", "ext": "mp4", "vcodec": "av01.0.08M.08", "acodec": "none", "height": 1080, "filesize": 28_000_000},
+                {"format_id": "616", "ext": "mp4", "vcodec": "vp09.00.40.08", "acodec": "none", "height": 1080}
+            ]
+        }))
+        .unwrap()
+    }
+
+    /// Trimmed `formats` of a Twitter video: HLS audio and video-only streams, and
+    /// progressive mp4s whose codecs yt-dlp does not know.
+    fn twitter_formats() -> MediaInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": "1790000000000000000",
+            "formats": [
+                {"format_id": "hls-audio-32000-Audio", "ext": "mp4", "vcodec": "none", "acodec": "mp4a.40.5", "filesize_approx": 200_000},
+                {"format_id": "hls-audio-128000-Audio", "ext": "mp4", "vcodec": "none", "acodec": "mp4a.40.2", "filesize_approx": 800_000},
+                {"format_id": "hls-256", "ext": "mp4", "vcodec": "avc1.4D401E", "acodec": "none", "height": 270, "filesize_approx": 1_400_000},
+                {"format_id": "hls-2176", "ext": "mp4", "vcodec": "avc1.640020", "acodec": "none", "height": 720, "filesize_approx": 12_500_000},
+                {"format_id": "hls-10368", "ext": "mp4", "vcodec": "avc1.640032", "acodec": "none", "height": 1080, "filesize_approx": 61_000_000},
+                {"format_id": "http-288", "ext": "mp4", "height": 270, "filesize_approx": 1_700_000},
+                {"format_id": "http-2176", "ext": "mp4", "height": 720, "filesize_approx": 12_800_000},
+                {"format_id": "http-10368", "ext": "mp4", "height": 1080}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_select_format_youtube_merges_best_h264_with_largest_fitting_aac() {
+        let info = youtube_formats();
+        assert_eq!(info.select_format(50 * MB).as_deref(), Some("137+140"));
+        assert_eq!(info.select_format(48 * MB).as_deref(), Some("137+139"));
+        assert_eq!(info.select_format(30 * MB).as_deref(), Some("136+140"));
+    }
+
+    #[test]
+    fn test_select_format_youtube_never_picks_other_codecs() {
+        let info = youtube_formats();
+        // VP9 and AV1 1080p would fit, but only H.264 plays inline everywhere.
+        assert_eq!(info.select_format(40 * MB).as_deref(), Some("136+140"));
+        assert_eq!(info.select_format(8 * MB).as_deref(), Some("134+139"));
+    }
+
+    #[test]
+    fn test_select_format_youtube_prefers_progressive_of_same_height_when_larger() {
+        let info = youtube_formats();
+        assert_eq!(info.select_format(10 * MB).as_deref(), Some("18"));
+    }
+
+    #[test]
+    fn test_select_format_returns_none_when_nothing_fits() {
+        assert_eq!(youtube_formats().select_format(5 * MB), None);
+        assert_eq!(twitter_formats().select_format(MB), None);
+        assert_eq!(MediaInfo::default().select_format(50 * MB), None);
+    }
+
+    #[test]
+    fn test_select_format_twitter_mixes_hls_and_progressive() {
+        let info = twitter_formats();
+        // 1080p is too large for the HLS stream and of unknown size as an mp4.
+        assert_eq!(
+            info.select_format(50 * MB).as_deref(),
+            Some("hls-2176+hls-audio-128000-Audio")
+        );
+        assert_eq!(info.select_format(13 * MB).as_deref(), Some("http-2176"));
+        assert_eq!(info.select_format(2 * MB).as_deref(), Some("http-288"));
+        assert_eq!(
+            info.select_format(1_650_000).as_deref(),
+            Some("hls-256+hls-audio-32000-Audio")
+        );
+    }
+
+    #[test]
+    fn test_fit_format_to_only_changes_formats_that_do_not_fit() {
+        let mut info = youtube_formats();
+        info.fit_format_to(50 * MB);
+        assert_eq!(info.format_selector, None);
+
+        info.width = Some(1920);
+        info.height = Some(1080);
+        info.fit_format_to(30 * MB);
+        assert_eq!(info.format_selector.as_deref(), Some("136+140"));
+        assert_eq!(info.filesize, Some(23_400_000));
+        assert_eq!((info.width, info.height), (Some(1280), Some(720)));
+
+        let mut too_large = youtube_formats();
+        too_large.fit_format_to(5 * MB);
+        assert_eq!(too_large.format_selector, None);
+        assert_eq!(too_large.filesize, Some(48_400_000));
+    }
+
+    #[test]
+    fn test_playlist_total_filesize() {
+        let sized = |filesize| MediaInfo {
+            filesize,
+            ..MediaInfo::default()
+        };
+        let mut info = MediaInfo::default();
+        assert_eq!(info.playlist_total_filesize(), None);
+
+        info.entries = Some(vec![sized(None), sized(None)]);
+        assert_eq!(info.playlist_total_filesize(), None);
+
+        info.entries = Some(vec![sized(Some(3)), sized(None), sized(Some(4))]);
+        assert_eq!(info.playlist_total_filesize(), Some(7));
+    }
+
+    #[test]
+    fn test_fit_format_to_skips_playlists() {
+        let mut info = youtube_formats();
+        info.entries = Some(vec![youtube_formats()]);
+        info.fit_format_to(30 * MB);
+        assert_eq!(info.format_selector, None);
+    }
 }
@@ -0,0 +1,618 @@
+//! Running the bot without talking to Telegram, selected with `TELEGRAM_MODE=dryrun`.
+//!
+//! `NoopTelegramApi` stands in for `TeloxideApi`: every call is logged, optionally delayed
+//! to mimic Telegram's latency, recorded in a `CallJournal` and answered with made-up
+//! message and file ids. Startup still calls the Bot API for `getMe` and `setWebhook`,
+//! which `serve_stub_bot_api` answers locally. Together they let load tests and staging push
+//! URLs through the whole pipeline without sending anything to real chats.
+//!
+//! The journal is also a lighter alternative to `MockTelegramApi` in tests that only
+//! care about what was sent, not about setting up every expectation.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use teloxide::types::{
+    ChatAction, ChatId, ChatMemberStatus, InlineKeyboardMarkup, InputMedia, MessageId,
+    ReactionType, ThreadId, UserId,
+};
+use url::Url;
+
+use crate::downloader::MediaType;
+use crate::storage::CachedFile;
+use crate::telegram_api::{MediaSource, SentMedia, StatusMessage, TelegramApi};
+
+/// How the bot reaches Telegram, from `TELEGRAM_MODE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TelegramMode {
+    #[default]
+    Live,
+    /// No request leaves the process; see the module docs.
+    DryRun,
+}
+
+impl FromStr for TelegramMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "live" => Ok(Self::Live),
+            "dryrun" | "dry-run" => Ok(Self::DryRun),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One call made to a `NoopTelegramApi`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// The `TelegramApi` method, e.g. `send_video`.
+    pub method: &'static str,
+    /// `None` for calls not addressed to a chat, like answering a callback query.
+    pub chat_id: Option<ChatId>,
+    /// Forum topic the call was posted in, set through `in_thread`.
+    pub thread_id: Option<ThreadId>,
+    /// Human-readable arguments: the text, caption or file sent.
+    pub detail: String,
+}
+
+/// Calls recorded by a `NoopTelegramApi`, shared by its clones and `in_thread` copies.
+#[derive(Debug, Clone, Default)]
+pub struct CallJournal {
+    entries: Arc<Mutex<Vec<JournalEntry>>>,
+}
+
+impl CallJournal {
+    fn record(&self, entry: JournalEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Every call so far, in order.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// The method names of every call so far, in order.
+    pub fn methods(&self) -> Vec<&'static str> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().map(|entry| entry.method).collect()
+    }
+
+    /// Calls addressed to `chat_id`, in order.
+    pub fn for_chat(&self, chat_id: ChatId) -> Vec<JournalEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .filter(|entry| entry.chat_id == Some(chat_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// A `TelegramApi` that succeeds at everything without sending anything.
+#[derive(Clone, Default)]
+pub struct NoopTelegramApi {
+    journal: CallJournal,
+    latency: Duration,
+    /// Source of the message ids and file ids handed out, shared with `in_thread` copies.
+    next_id: Arc<AtomicI32>,
+    topic: Option<(ChatId, ThreadId)>,
+}
+
+impl NoopTelegramApi {
+    /// An API that waits `latency` before answering each call.
+    pub fn new(latency: Duration) -> Self {
+        Self {
+            latency,
+            ..Self::default()
+        }
+    }
+
+    /// The journal this API records into.
+    pub fn journal(&self) -> CallJournal {
+        self.journal.clone()
+    }
+
+    /// Log and record the call, wait the configured latency and return a fresh id.
+    async fn call(&self, method: &'static str, chat_id: Option<ChatId>, detail: String) -> i32 {
+        let thread_id = self
+            .topic
+            .filter(|(topic_chat_id, _)| Some(*topic_chat_id) == chat_id)
+            .map(|(_, thread_id)| thread_id);
+        match chat_id {
+            Some(chat_id) => log::info!("[dry run] {} to chat {}: {}", method, chat_id, detail),
+            None => log::info!("[dry run] {}: {}", method, detail),
+        }
+        self.journal.record(JournalEntry {
+            method,
+            chat_id,
+            thread_id,
+            detail,
+        });
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+fn file_id(id: i32) -> String {
+    format!("dryrun-file-{id}")
+}
+
+#[async_trait]
+impl TelegramApi for NoopTelegramApi {
+    async fn send_video(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+        _thumbnail_filepath: Option<PathBuf>,
+        _duration: Option<u32>,
+        _width: Option<u32>,
+        _height: Option<u32>,
+        _reply_markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(String, MessageId), teloxide::RequestError> {
+        let id = self
+            .call("send_video", Some(chat_id), format!("{media}: {caption}"))
+            .await;
+        Ok((file_id(id), MessageId(id)))
+    }
+
+    async fn send_photo(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+        _reply_markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(String, MessageId), teloxide::RequestError> {
+        let id = self
+            .call("send_photo", Some(chat_id), format!("{media}: {caption}"))
+            .await;
+        Ok((file_id(id), MessageId(id)))
+    }
+
+    async fn send_document(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+    ) -> Result<MessageId, teloxide::RequestError> {
+        let id = self
+            .call(
+                "send_document",
+                Some(chat_id),
+                format!("{media}: {caption}"),
+            )
+            .await;
+        Ok(MessageId(id))
+    }
+
+    async fn edit_message_reply_markup(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        _keyboard: InlineKeyboardMarkup,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "edit_message_reply_markup",
+            Some(chat_id),
+            format!("message {message_id}"),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn send_text_message(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        message: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call("send_text_message", Some(chat_id), message.to_string())
+            .await;
+        Ok(())
+    }
+
+    async fn send_status_message(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        text: &str,
+    ) -> Result<StatusMessage, teloxide::RequestError> {
+        let id = self
+            .call("send_status_message", Some(chat_id), text.to_string())
+            .await;
+        Ok(StatusMessage {
+            chat_id,
+            message_id: MessageId(id),
+        })
+    }
+
+    async fn edit_message_text(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "edit_message_text",
+            Some(chat_id),
+            format!("message {message_id}: {text}"),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "delete_message",
+            Some(chat_id),
+            format!("message {message_id}"),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn send_media_group(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        media: Vec<InputMedia>,
+    ) -> Result<Vec<SentMedia>, teloxide::RequestError> {
+        let id = self
+            .call(
+                "send_media_group",
+                Some(chat_id),
+                format!("{} items", media.len()),
+            )
+            .await;
+        Ok(media
+            .iter()
+            .enumerate()
+            .map(|(index, item)| SentMedia {
+                file_id: format!("{}-{}", file_id(id), index),
+                media_type: match item {
+                    InputMedia::Photo(_) => MediaType::Photo,
+                    _ => MediaType::Video,
+                },
+                message_id: MessageId(id),
+            })
+            .collect())
+    }
+
+    async fn send_chat_action(
+        &self,
+        chat_id: ChatId,
+        action: ChatAction,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call("send_chat_action", Some(chat_id), format!("{action:?}"))
+            .await;
+        Ok(())
+    }
+
+    async fn set_message_reaction(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        reaction: Option<ReactionType>,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "set_message_reaction",
+            Some(chat_id),
+            format!("message {message_id}: {reaction:?}"),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn send_cached_media_group(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        files: &[CachedFile],
+        caption: &str,
+    ) -> Result<Vec<MessageId>, teloxide::RequestError> {
+        let id = self
+            .call(
+                "send_cached_media_group",
+                Some(chat_id),
+                format!("{} items: {caption}", files.len()),
+            )
+            .await;
+        Ok(vec![MessageId(id); files.len()])
+    }
+
+    async fn send_audio(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        file_path: &Path,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call("send_audio", Some(chat_id), file_path.display().to_string())
+            .await;
+        Ok(())
+    }
+
+    async fn send_video_note(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        file_path: &Path,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "send_video_note",
+            Some(chat_id),
+            file_path.display().to_string(),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn send_invoice(
+        &self,
+        chat_id: ChatId,
+        title: &str,
+        _description: &str,
+        payload: &str,
+        price_amount: u32,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "send_invoice",
+            Some(chat_id),
+            format!("{title} ({payload}): {price_amount} stars"),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Every user is a plain member, so owner-only group features stay off.
+    async fn get_chat_member(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> Result<ChatMemberStatus, teloxide::RequestError> {
+        self.call("get_chat_member", Some(chat_id), format!("user {user_id}"))
+            .await;
+        Ok(ChatMemberStatus::Member)
+    }
+
+    async fn answer_callback_query(
+        &self,
+        callback_query_id: &str,
+        text: Option<String>,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "answer_callback_query",
+            None,
+            format!("{callback_query_id}: {}", text.unwrap_or_default()),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn answer_pre_checkout_query(
+        &self,
+        pre_checkout_query_id: &str,
+        ok: bool,
+        _error_message: Option<String>,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "answer_pre_checkout_query",
+            None,
+            format!("{pre_checkout_query_id}: ok={ok}"),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn send_text_with_keyboard(
+        &self,
+        chat_id: ChatId,
+        _message_id: MessageId,
+        text: &str,
+        _keyboard: InlineKeyboardMarkup,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call("send_text_with_keyboard", Some(chat_id), text.to_string())
+            .await;
+        Ok(())
+    }
+
+    async fn send_text_no_reply(
+        &self,
+        chat_id: ChatId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call("send_text_no_reply", Some(chat_id), text.to_string())
+            .await;
+        Ok(())
+    }
+
+    async fn reply_to_sent_message(
+        &self,
+        chat_id: ChatId,
+        _sent_message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call("reply_to_sent_message", Some(chat_id), text.to_string())
+            .await;
+        Ok(())
+    }
+
+    async fn refund_star_payment(
+        &self,
+        user_id: i64,
+        telegram_payment_charge_id: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.call(
+            "refund_star_payment",
+            None,
+            format!("user {user_id}: {telegram_payment_charge_id}"),
+        )
+        .await;
+        Ok(())
+    }
+
+    fn in_thread(&self, chat_id: ChatId, thread_id: Option<ThreadId>) -> Arc<dyn TelegramApi> {
+        Arc::new(Self {
+            topic: thread_id.map(|thread_id| (chat_id, thread_id)),
+            ..self.clone()
+        })
+    }
+}
+
+/// Serve the Bot API methods the dispatcher and webhook setup call on their own, on a
+/// local port, and return its base URL. `getMe` answers with a made-up bot named after
+/// the token's numeric id, so several dry-run bots still get distinct webhook paths;
+/// every other method just succeeds.
+pub async fn serve_stub_bot_api() -> std::io::Result<Url> {
+    let app = axum::Router::new().route(
+        "/{token}/{method}",
+        axum::routing::post(
+            |axum::extract::Path((token, method)): axum::extract::Path<(String, String)>| async move {
+                axum::Json(stub_response(&token, &method))
+            },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/", listener.local_addr()?)
+        .parse()
+        .expect("a socket address makes a valid URL");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            log::error!("Dry-run Bot API stub stopped: {}", e);
+        }
+    });
+    Ok(url)
+}
+
+fn stub_response(token: &str, method: &str) -> serde_json::Value {
+    let result = if method.eq_ignore_ascii_case("getme") {
+        // Teloxide sends the token as `bot<token>`.
+        let bot_id = token
+            .trim_start_matches("bot")
+            .split(':')
+            .next()
+            .and_then(|id| id.parse::<u64>().ok())
+            .unwrap_or(1);
+        serde_json::json!({
+            "id": bot_id,
+            "is_bot": true,
+            "first_name": "CrabberBot (dry run)",
+            "username": format!("dryrun_{bot_id}_bot"),
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+            "can_connect_to_business": false,
+            "has_main_web_app": false
+        })
+    } else {
+        serde_json::json!(true)
+    };
+    serde_json::json!({"ok": true, "result": result})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::requests::Requester;
+
+    #[tokio::test]
+    async fn test_records_calls_and_hands_out_distinct_ids() {
+        let api = NoopTelegramApi::default();
+        let journal = api.journal();
+
+        let (first_file, first_message) = api
+            .send_video(
+                ChatId(1),
+                MessageId(10),
+                MediaSource::Path("/tmp/a.mp4".into()),
+                "caption",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let status = api
+            .send_status_message(ChatId(2), MessageId(11), "Downloading…")
+            .await
+            .unwrap();
+        api.answer_callback_query("query", None).await.unwrap();
+
+        assert_ne!(first_file, file_id(status.message_id.0));
+        assert_ne!(first_message, status.message_id);
+        assert_eq!(
+            journal.methods(),
+            ["send_video", "send_status_message", "answer_callback_query"]
+        );
+        assert_eq!(journal.for_chat(ChatId(1))[0].detail, "/tmp/a.mp4: caption");
+        journal.clear();
+        assert!(journal.entries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_in_thread_shares_the_journal_and_tags_the_topic() {
+        let api = NoopTelegramApi::default();
+        let topic = ThreadId(MessageId(7));
+        let in_topic = api.in_thread(ChatId(1), Some(topic));
+
+        in_topic.send_text_no_reply(ChatId(1), "hi").await.unwrap();
+        in_topic
+            .send_text_no_reply(ChatId(2), "owner")
+            .await
+            .unwrap();
+
+        let thread_ids: Vec<_> = api
+            .journal()
+            .entries()
+            .iter()
+            .map(|entry| entry.thread_id)
+            .collect();
+        assert_eq!(thread_ids, [Some(topic), None]);
+    }
+
+    #[tokio::test]
+    async fn test_waits_the_configured_latency() {
+        let api = NoopTelegramApi::new(Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        api.send_chat_action(ChatId(1), ChatAction::Typing)
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_stub_bot_api_answers_get_me() {
+        let url = serve_stub_bot_api().await.unwrap();
+        let bot = teloxide::Bot::new("4242:SECRET").set_api_url(url);
+
+        let me = bot.get_me().await.unwrap();
+        assert_eq!(me.username(), "dryrun_4242_bot");
+        bot.delete_webhook().await.unwrap();
+    }
+
+    #[test]
+    fn test_parse_telegram_mode() {
+        assert_eq!("dryrun".parse(), Ok(TelegramMode::DryRun));
+        assert_eq!(" Live ".parse(), Ok(TelegramMode::Live));
+        assert!("offline".parse::<TelegramMode>().is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! Dispatcher error handler that, besides logging, reports errors to the owner chat.
+//!
+//! Reports are rate limited: at most one message per interval, carrying the counts of
+//! every error seen since the previous report. Errors still held back when the interval
+//! ends are sent by `OwnerErrorReporter::flush`, which runs on a timer.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use teloxide::error_handlers::ErrorHandler;
+use teloxide::types::ChatId;
+
+use crate::downloader::escape_html_text;
+use crate::telegram_api::TelegramApi;
+
+/// Longest debug string included in a report.
+const MAX_ERROR_DETAIL_CHARS: usize = 500;
+
+/// Errors collected since the last report, ready to be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorSummary {
+    /// Occurrences per error kind, sorted by kind.
+    pub counts: Vec<(String, u64)>,
+    /// Truncated debug string of the most recent error.
+    pub last_error: String,
+}
+
+impl ErrorSummary {
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Render the report as Telegram HTML.
+    pub fn to_html(&self, bot_username: &str) -> String {
+        let mut text = format!(
+            "⚠️ <b>{} dispatcher error(s)</b> in @{}\n",
+            self.total(),
+            escape_html_text(bot_username)
+        );
+        for (kind, count) in &self.counts {
+            text.push_str(&format!("• {}: {}\n", escape_html_text(kind), count));
+        }
+        text.push_str(&format!(
+            "Last error:\n<code>{}</code>",
+            escape_html_text(&self.last_error)
+        ));
+        text
+    }
+}
+
+/// Counts errors and decides when the next report may be sent.
+///
+/// The first error is reported immediately. Errors arriving within `interval` of the
+/// last report are only counted; they are included in the next report, sent by the first
+/// error or `flush` after the interval has passed.
+#[derive(Debug)]
+pub struct ErrorAggregator {
+    interval: Duration,
+    last_report: Option<Instant>,
+    counts: BTreeMap<String, u64>,
+    last_error: Option<String>,
+}
+
+impl ErrorAggregator {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: None,
+            counts: BTreeMap::new(),
+            last_error: None,
+        }
+    }
+
+    /// Record one error and return a summary if a report is due.
+    pub fn record(&mut self, kind: &str, detail: &str, now: Instant) -> Option<ErrorSummary> {
+        *self.counts.entry(kind.to_string()).or_default() += 1;
+        self.last_error = Some(truncate_chars(detail, MAX_ERROR_DETAIL_CHARS));
+
+        self.take_if_due(now)
+    }
+
+    /// A summary of the errors held back since the last report, once the interval has
+    /// passed.
+    pub fn flush(&mut self, now: Instant) -> Option<ErrorSummary> {
+        if self.counts.is_empty() {
+            return None;
+        }
+        self.take_if_due(now)
+    }
+
+    fn take_if_due(&mut self, now: Instant) -> Option<ErrorSummary> {
+        let due = self
+            .last_report
+            .is_none_or(|last| now.duration_since(last) >= self.interval);
+        if !due {
+            return None;
+        }
+        self.last_report = Some(now);
+        Some(ErrorSummary {
+            counts: std::mem::take(&mut self.counts).into_iter().collect(),
+            last_error: self.last_error.take().unwrap_or_default(),
+        })
+    }
+}
+
+/// Leading identifier of a `Debug` string, e.g. `Network` for `Network(reqwest::Error {..})`.
+fn error_kind(debug: &str) -> String {
+    let kind: String = debug
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    if kind.is_empty() {
+        "Unknown".to_string()
+    } else {
+        kind
+    }
+}
+
+fn truncate_chars(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}…", &s[..idx]),
+        None => s.to_string(),
+    }
+}
+
+/// Logs every dispatcher error and forwards rate-limited summaries to the owner chat.
+pub struct OwnerErrorReporter {
+    api: Arc<dyn TelegramApi>,
+    owner_chat_id: ChatId,
+    bot_username: String,
+    aggregator: Mutex<ErrorAggregator>,
+}
+
+impl OwnerErrorReporter {
+    pub fn new(
+        api: Arc<dyn TelegramApi>,
+        owner_chat_id: ChatId,
+        bot_username: String,
+        interval: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            api,
+            owner_chat_id,
+            bot_username,
+            aggregator: Mutex::new(ErrorAggregator::new(interval)),
+        })
+    }
+
+    async fn report(&self, detail: String) {
+        log::error!("An error has occurred in the dispatcher: {}", detail);
+        let summary = self
+            .aggregator
+            .lock()
+            .expect("error aggregator lock poisoned")
+            .record(&error_kind(&detail), &detail, Instant::now());
+        if let Some(summary) = summary {
+            self.send(summary).await;
+        }
+    }
+
+    /// Report the errors held back since the last report, if its interval has passed.
+    /// Without this, they would wait for the next error to arrive.
+    pub async fn flush(&self) {
+        let summary = self
+            .aggregator
+            .lock()
+            .expect("error aggregator lock poisoned")
+            .flush(Instant::now());
+        if let Some(summary) = summary {
+            self.send(summary).await;
+        }
+    }
+
+    async fn send(&self, summary: ErrorSummary) {
+        if self.owner_chat_id.0 == 0 {
+            return;
+        }
+        if let Err(e) = self
+            .api
+            .send_text_no_reply(self.owner_chat_id, &summary.to_html(&self.bot_username))
+            .await
+        {
+            log::error!("Failed to report dispatcher errors to the owner: {:?}", e);
+        }
+    }
+}
+
+impl<E> ErrorHandler<E> for OwnerErrorReporter
+where
+    E: std::error::Error,
+{
+    fn handle_error(self: Arc<Self>, error: E) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        #[cfg(feature = "sentry")]
+        sentry::capture_error(&error);
+        let detail = format!("{error:?}");
+        Box::pin(async move { self.report(detail).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telegram_api::MockTelegramApi;
+
+    const INTERVAL: Duration = Duration::from_secs(600);
+
+    #[derive(Debug, thiserror::Error)]
+    enum TestError {
+        #[error("network")]
+        Network,
+        #[error("api")]
+        Api,
+    }
+
+    #[test]
+    fn test_first_error_is_reported_immediately() {
+        let mut aggregator = ErrorAggregator::new(INTERVAL);
+        let summary = aggregator
+            .record("Network", "Network(timeout)", Instant::now())
+            .unwrap();
+        assert_eq!(summary.counts, vec![("Network".to_string(), 1)]);
+        assert_eq!(summary.last_error, "Network(timeout)");
+    }
+
+    #[test]
+    fn test_errors_within_interval_are_aggregated() {
+        let mut aggregator = ErrorAggregator::new(INTERVAL);
+        let start = Instant::now();
+        assert!(aggregator.record("Network", "first", start).is_some());
+
+        for i in 1..=3 {
+            let at = start + Duration::from_secs(i);
+            assert!(aggregator.record("Api", "api", at).is_none());
+        }
+        assert!(
+            aggregator
+                .record("Network", "latest", start + Duration::from_secs(10))
+                .is_none()
+        );
+
+        let summary = aggregator.record("Io", "io", start + INTERVAL).unwrap();
+        assert_eq!(
+            summary.counts,
+            vec![
+                ("Api".to_string(), 3),
+                ("Io".to_string(), 1),
+                ("Network".to_string(), 1),
+            ]
+        );
+        assert_eq!(summary.total(), 5);
+        assert_eq!(summary.last_error, "io");
+    }
+
+    #[test]
+    fn test_flush_sends_held_back_errors_after_interval() {
+        let mut aggregator = ErrorAggregator::new(INTERVAL);
+        let start = Instant::now();
+        assert!(aggregator.flush(start).is_none());
+        assert!(aggregator.record("Network", "first", start).is_some());
+        assert!(aggregator.record("Api", "api", start).is_none());
+
+        assert!(aggregator.flush(start + INTERVAL / 2).is_none());
+        let summary = aggregator.flush(start + INTERVAL).unwrap();
+        assert_eq!(summary.counts, vec![("Api".to_string(), 1)]);
+        assert!(aggregator.flush(start + INTERVAL * 2).is_none());
+    }
+
+    #[test]
+    fn test_report_resets_counts() {
+        let mut aggregator = ErrorAggregator::new(INTERVAL);
+        let start = Instant::now();
+        aggregator.record("Api", "a", start);
+        let summary = aggregator.record("Api", "b", start + INTERVAL * 2).unwrap();
+        assert_eq!(summary.counts, vec![("Api".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_long_detail_is_truncated() {
+        let mut aggregator = ErrorAggregator::new(INTERVAL);
+        let detail = "é".repeat(MAX_ERROR_DETAIL_CHARS + 10);
+        let summary = aggregator.record("Api", &detail, Instant::now()).unwrap();
+        assert_eq!(
+            summary.last_error.chars().count(),
+            MAX_ERROR_DETAIL_CHARS + 1
+        );
+        assert!(summary.last_error.ends_with('…'));
+    }
+
+    #[test]
+    fn test_error_kind_uses_leading_identifier() {
+        assert_eq!(error_kind("Network(reqwest::Error { .. })"), "Network");
+        assert_eq!(error_kind("RetryAfter(Seconds(5))"), "RetryAfter");
+        assert_eq!(error_kind("\"plain string\""), "Unknown");
+    }
+
+    #[test]
+    fn test_summary_html_escapes_detail() {
+        let summary = ErrorSummary {
+            counts: vec![("Api".to_string(), 2)],
+            last_error: "Api(<Unknown>)".to_string(),
+        };
+        let html = summary.to_html("crabberbot");
+        assert!(html.contains("2 dispatcher error(s)</b> in @crabberbot"));
+        assert!(html.contains("• Api: 2"));
+        assert!(html.contains("<code>Api(&lt;Unknown&gt;)</code>"));
+    }
+
+    #[tokio::test]
+    async fn test_reporter_sends_one_message_per_interval() {
+        let mut mock_api = MockTelegramApi::new();
+        mock_api
+            .expect_send_text_no_reply()
+            .withf(|chat_id, text| chat_id.0 == 999 && text.contains("Network: 1"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let reporter = OwnerErrorReporter::new(
+            Arc::new(mock_api),
+            ChatId(999),
+            "crabberbot".to_string(),
+            INTERVAL,
+        );
+        reporter.clone().handle_error(TestError::Network).await;
+        reporter.clone().handle_error(TestError::Network).await;
+        reporter.handle_error(TestError::Api).await;
+    }
+}
@@ -0,0 +1,190 @@
+//! Reply for messages that are neither commands nor links.
+//!
+//! Only private text messages get a hint; stickers, service messages and anything in
+//! groups are ignored. Albums arrive as one message per item, so a hint is sent at most
+//! once per `media_group_id`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cache::MemoryCache;
+use crate::handler::parse_link;
+use crate::telegram_api::TelegramApi;
+use teloxide::prelude::*;
+
+pub const UNHANDLED_MESSAGE_REPLY: &str = "That doesn't look like a link I can download. \
+    Send me the URL of a video or photo post, or type /help for a guide.";
+
+/// How long a media group is remembered. Album items arrive within a second or two.
+const MEDIA_GROUP_TTL: Duration = Duration::from_secs(60);
+
+/// Whether the text contains something that looks like a web link.
+fn contains_link(text: &str) -> bool {
+    text.split_whitespace()
+        .any(|word| parse_link(word).is_some_and(|url| matches!(url.scheme(), "http" | "https")))
+}
+
+/// Whether `message` is a private text (or captioned media) message without a link.
+pub fn is_unhandled_text(message: &Message) -> bool {
+    if !message.chat.is_private() {
+        return false;
+    }
+    message
+        .text()
+        .or_else(|| message.caption())
+        .is_some_and(|text| !text.trim().is_empty() && !contains_link(text))
+}
+
+/// Media group IDs seen recently, so an album is answered only once.
+pub struct RecentMediaGroups {
+    seen: MemoryCache<String, ()>,
+}
+
+impl Default for RecentMediaGroups {
+    fn default() -> Self {
+        Self {
+            seen: MemoryCache::new(MEDIA_GROUP_TTL),
+        }
+    }
+}
+
+impl RecentMediaGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true the first time `media_group_id` is seen within the TTL.
+    pub fn first_seen(&self, media_group_id: &str, now: Instant) -> bool {
+        self.seen
+            .insert_if_absent_at(media_group_id.to_string(), (), now)
+    }
+
+    pub fn evict_expired(&self) {
+        self.seen.evict_expired();
+    }
+}
+
+/// Endpoint for messages that passed `is_unhandled_text`.
+pub async fn handle_unhandled_message(
+    api: Arc<dyn TelegramApi>,
+    media_groups: Arc<RecentMediaGroups>,
+    message: Message,
+) -> ResponseResult<()> {
+    if let Some(group) = message.media_group_id()
+        && !media_groups.first_seen(&group.0, Instant::now())
+    {
+        return Ok(());
+    }
+    api.send_text_message(message.chat.id, message.id, UNHANDLED_MESSAGE_REPLY)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telegram_api::MockTelegramApi;
+
+    fn message(extra: serde_json::Value, chat_type: &str) -> Message {
+        let mut json = serde_json::json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": {"id": -100, "type": chat_type, "title": "Group"},
+            "from": {"id": 1, "is_bot": false, "first_name": "Test"}
+        });
+        if chat_type == "private" {
+            json["chat"] = serde_json::json!({"id": 1, "type": "private", "first_name": "Test"});
+        }
+        for (key, value) in extra.as_object().unwrap() {
+            json[key] = value.clone();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn album_item(message_id: i32) -> Message {
+        let mut msg = message(
+            serde_json::json!({
+                "media_group_id": "album-1",
+                "caption": "look at these",
+                "photo": [{"file_id": "f", "file_unique_id": "u", "width": 1, "height": 1}]
+            }),
+            "private",
+        );
+        msg.id = teloxide::types::MessageId(message_id);
+        msg
+    }
+
+    #[test]
+    fn test_plain_private_text_is_answered() {
+        assert!(is_unhandled_text(&message(
+            serde_json::json!({"text": "hello"}),
+            "private"
+        )));
+    }
+
+    #[test]
+    fn test_sticker_is_ignored() {
+        let sticker = message(
+            serde_json::json!({"sticker": {
+                "file_id": "f", "file_unique_id": "u", "type": "regular",
+                "width": 512, "height": 512, "is_animated": false, "is_video": false
+            }}),
+            "private",
+        );
+        assert!(!is_unhandled_text(&sticker));
+    }
+
+    #[test]
+    fn test_group_chat_text_is_ignored() {
+        assert!(!is_unhandled_text(&message(
+            serde_json::json!({"text": "hello"}),
+            "supergroup"
+        )));
+    }
+
+    #[test]
+    fn test_text_with_link_is_ignored() {
+        assert!(!is_unhandled_text(&message(
+            serde_json::json!({"text": "look https://example.com/video"}),
+            "private"
+        )));
+    }
+
+    #[test]
+    fn test_text_with_schemeless_link_is_ignored() {
+        assert!(!is_unhandled_text(&message(
+            serde_json::json!({"text": "see www.tiktok.com/@user/video/123"}),
+            "private"
+        )));
+    }
+
+    #[test]
+    fn test_media_group_expires() {
+        let groups = RecentMediaGroups::new();
+        let start = Instant::now();
+        assert!(groups.first_seen("a", start));
+        assert!(!groups.first_seen("a", start + Duration::from_secs(1)));
+        assert!(groups.first_seen("b", start + Duration::from_secs(1)));
+        assert!(groups.first_seen("a", start + MEDIA_GROUP_TTL));
+    }
+
+    #[tokio::test]
+    async fn test_album_is_answered_once() {
+        let mut mock_api = MockTelegramApi::new();
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == UNHANDLED_MESSAGE_REPLY)
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let api: Arc<dyn TelegramApi> = Arc::new(mock_api);
+        let groups = Arc::new(RecentMediaGroups::new());
+
+        for message_id in 1..=3 {
+            let item = album_item(message_id);
+            assert!(is_unhandled_text(&item));
+            handle_unhandled_message(api.clone(), groups.clone(), item)
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! `Storage` kept in a JSON file, selected with `STORAGE_URL=file:///path/to/state.json`.
+//! It is a `MemoryStorage` whose whole state is written to the file after every change and
+//! read back at startup, so the cache, quotas and purchases survive restarts without a
+//! database. Each write rewrites the file, which suits a small single-instance bot; the
+//! request log is not kept, as with `MemoryStorage`.
+
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::downloader::MediaType;
+use crate::handler::CallbackContext;
+use crate::maintenance::MaintenanceState;
+use crate::memory_storage::MemoryStorage;
+use crate::storage::{
+    ActivityReport, CacheMetadata, CacheSearchResult, CacheStats, CachedMedia, ChatSettings,
+    Delivery, PaymentRecord, RequestLogEntry, Storage,
+};
+use crate::subscription::{SubscriptionInfo, SubscriptionTier};
+
+pub struct FileStorage {
+    memory: MemoryStorage,
+    path: PathBuf,
+    /// Held while saving, so a slow write can't overwrite a newer state.
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl FileStorage {
+    /// Load the state saved in `path`, or start empty when the file doesn't exist yet.
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        let memory = match tokio::fs::read(path).await {
+            Ok(json) => MemoryStorage::from_json(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => MemoryStorage::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            memory,
+            path: path.to_path_buf(),
+            saving: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Replace the file with the current state. The state is written to a temporary file
+    /// first, so a crash mid-write leaves the previous state in place.
+    async fn save(&self) -> io::Result<()> {
+        let _saving = self.saving.lock().await;
+        let json = self.memory.to_json()?;
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, json).await?;
+        tokio::fs::rename(&temp_path, &self.path).await
+    }
+
+    /// Run a change to the state, then save it.
+    async fn saved<T>(&self, change: impl Future<Output = T>) -> T {
+        let result = change.await;
+        if let Err(e) = self.save().await {
+            log::error!("Failed to save storage to {:?}: {}", self.path, e);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    /// Only the last-used time changes here; it is saved with the next change.
+    async fn get_cached_media(&self, bot_id: i64, source_url: &str) -> Option<CachedMedia> {
+        self.memory.get_cached_media(bot_id, source_url).await
+    }
+
+    async fn store_cached_media(
+        &self,
+        bot_id: i64,
+        source_url: &str,
+        caption: &str,
+        files: &[(String, MediaType)],
+        audio_cache_path: Option<String>,
+        media_duration_secs: Option<i32>,
+        metadata: &CacheMetadata,
+    ) {
+        self.saved(self.memory.store_cached_media(
+            bot_id,
+            source_url,
+            caption,
+            files,
+            audio_cache_path,
+            media_duration_secs,
+            metadata,
+        ))
+        .await
+    }
+
+    async fn search_cache(&self, query: &str, limit: i64) -> Vec<CacheSearchResult> {
+        self.memory.search_cache(query, limit).await
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        self.memory.cache_stats().await
+    }
+
+    async fn activity_report(&self, window: Duration) -> ActivityReport {
+        self.memory.activity_report(window).await
+    }
+
+    async fn log_request(
+        &self,
+        chat_id: i64,
+        source_url: &str,
+        status: &str,
+        processing_time_ms: i64,
+        bytes_transferred: Option<i64>,
+    ) {
+        self.saved(self.memory.log_request(
+            chat_id,
+            source_url,
+            status,
+            processing_time_ms,
+            bytes_transferred,
+        ))
+        .await
+    }
+
+    async fn get_daily_download_count(&self, chat_id: i64) -> i64 {
+        self.memory.get_daily_download_count(chat_id).await
+    }
+
+    async fn get_top_urls(&self, limit: i64, since: DateTime<Utc>) -> Vec<String> {
+        self.memory.get_top_urls(limit, since).await
+    }
+
+    async fn recent_requests(&self, limit: i64) -> Vec<RequestLogEntry> {
+        self.memory.recent_requests(limit).await
+    }
+
+    async fn record_delivery(
+        &self,
+        chat_id: i64,
+        message_ids: &[i32],
+        source_url: &str,
+        cache_id: Option<i32>,
+    ) {
+        self.saved(
+            self.memory
+                .record_delivery(chat_id, message_ids, source_url, cache_id),
+        )
+        .await
+    }
+
+    async fn get_delivery(&self, chat_id: i64, message_id: i32) -> Option<Delivery> {
+        self.memory.get_delivery(chat_id, message_id).await
+    }
+
+    async fn get_subscription(&self, user_id: i64) -> SubscriptionInfo {
+        self.memory.get_subscription(user_id).await
+    }
+
+    async fn upsert_subscription(&self, user_id: i64, tier: SubscriptionTier, duration_days: i64) {
+        self.saved(
+            self.memory
+                .upsert_subscription(user_id, tier, duration_days),
+        )
+        .await
+    }
+
+    async fn get_chat_settings(&self, chat_id: i64) -> ChatSettings {
+        self.memory.get_chat_settings(chat_id).await
+    }
+
+    async fn set_source_as_button(&self, chat_id: i64, enabled: bool) {
+        self.saved(self.memory.set_source_as_button(chat_id, enabled))
+            .await
+    }
+
+    async fn migrate_chat(&self, old_chat_id: i64, new_chat_id: i64) {
+        self.saved(self.memory.migrate_chat(old_chat_id, new_chat_id))
+            .await
+    }
+
+    async fn get_maintenance(&self) -> MaintenanceState {
+        self.memory.get_maintenance().await
+    }
+
+    async fn set_maintenance(&self, state: &MaintenanceState) {
+        self.saved(self.memory.set_maintenance(state)).await
+    }
+
+    async fn record_payment(
+        &self,
+        user_id: i64,
+        telegram_charge_id: &str,
+        provider_charge_id: &str,
+        product: &str,
+        amount: i32,
+    ) {
+        self.saved(self.memory.record_payment(
+            user_id,
+            telegram_charge_id,
+            provider_charge_id,
+            product,
+            amount,
+        ))
+        .await
+    }
+
+    async fn consume_ai_seconds(&self, user_id: i64, seconds: i32) {
+        self.saved(self.memory.consume_ai_seconds(user_id, seconds))
+            .await
+    }
+
+    async fn add_topup_seconds(&self, user_id: i64, seconds: i32) {
+        self.saved(self.memory.add_topup_seconds(user_id, seconds))
+            .await
+    }
+
+    async fn record_premium_usage(
+        &self,
+        user_id: i64,
+        feature: &str,
+        source_url: &str,
+        duration_secs: i32,
+        units: f64,
+        cost_usd: f64,
+    ) {
+        self.saved(self.memory.record_premium_usage(
+            user_id,
+            feature,
+            source_url,
+            duration_secs,
+            units,
+            cost_usd,
+        ))
+        .await
+    }
+
+    async fn store_callback_context(&self, ctx: &CallbackContext) -> i32 {
+        self.saved(self.memory.store_callback_context(ctx)).await
+    }
+
+    async fn get_callback_context(&self, context_id: i32) -> Option<CallbackContext> {
+        self.memory.get_callback_context(context_id).await
+    }
+
+    async fn cache_transcript(&self, context_id: i32, transcript: &str, language: Option<String>) {
+        self.saved(
+            self.memory
+                .cache_transcript(context_id, transcript, language),
+        )
+        .await
+    }
+
+    async fn revoke_subscription(&self, user_id: i64) {
+        self.saved(self.memory.revoke_subscription(user_id)).await
+    }
+
+    async fn revoke_topup(&self, user_id: i64, seconds: i32) {
+        self.saved(self.memory.revoke_topup(user_id, seconds)).await
+    }
+
+    async fn get_latest_payment(&self, user_id: i64) -> Option<PaymentRecord> {
+        self.memory.get_latest_payment(user_id).await
+    }
+
+    async fn get_recent_payments(&self, user_id: i64, limit: i64) -> Vec<PaymentRecord> {
+        self.memory.get_recent_payments(user_id, limit).await
+    }
+
+    async fn has_ai_usage_since(&self, user_id: i64, since: DateTime<Utc>) -> bool {
+        self.memory.has_ai_usage_since(user_id, since).await
+    }
+
+    async fn cleanup_expired_callback_contexts(&self) {
+        self.saved(self.memory.cleanup_expired_callback_contexts())
+            .await
+    }
+
+    async fn expire_stale_topups(&self) {
+        self.saved(self.memory.expire_stale_topups()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_state_survives_reopening_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let storage = FileStorage::open(&path).await.unwrap();
+        assert!(storage.records_payments());
+        storage
+            .store_cached_media(
+                1,
+                "https://a.com",
+                "caption",
+                &[("file-id".to_string(), MediaType::Video)],
+                None,
+                Some(12),
+                &CacheMetadata::default(),
+            )
+            .await;
+        storage
+            .record_delivery(-100, &[7], "https://a.com", Some(1))
+            .await;
+        storage
+            .upsert_subscription(42, SubscriptionTier::Pro, 30)
+            .await;
+        storage
+            .record_payment(42, "charge", "", "sub_pro", 100)
+            .await;
+        drop(storage);
+
+        let reopened = FileStorage::open(&path).await.unwrap();
+        let cached = reopened.get_cached_media(1, "https://a.com").await.unwrap();
+        assert_eq!(cached.caption, "caption");
+        assert_eq!(cached.files[0].telegram_file_id, "file-id");
+        assert_eq!(
+            reopened.get_delivery(-100, 7).await.unwrap().cache_id,
+            Some(cached.id)
+        );
+        assert_eq!(
+            reopened.get_subscription(42).await.tier,
+            SubscriptionTier::Pro
+        );
+        assert_eq!(
+            reopened
+                .get_latest_payment(42)
+                .await
+                .unwrap()
+                .telegram_charge_id,
+            "charge"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(
+            FileStorage::open(&path).await.err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
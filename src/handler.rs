@@ -1,24 +1,43 @@
+use futures::FutureExt;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::borrow::Borrow;
+use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
-use std::time::Instant;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
 use teloxide::types::{
     ChatId, InputFile, InputMedia, InputMediaPhoto, InputMediaVideo, MessageId, ParseMode,
 };
 use url::Url;
+use uuid::Uuid;
 
 use teloxide::types::InlineKeyboardMarkup;
 
+use crate::chat_guard::UnreachableChatGuard;
+use crate::concurrency::{KeyedMutex, RequestPhase, RequestProgress};
 use crate::downloader::{
-    DownloadedItem, DownloadedMedia, Downloader, MediaInfo, MediaType, build_caption,
+    CaptionOptions, DownloadError, DownloadedItem, DownloadedMedia, Downloader, MediaInfo,
+    MediaType, append_timings_footer, build_caption, escape_html_text, remove_source_link,
 };
+use crate::hooks::{PostDownloadHook, apply_post_download_hooks};
+use crate::object_store::{ObjectStore, format_file_size, format_link_expiry};
 use crate::premium::audio_extractor::AudioExtractor;
-use crate::storage::{CachedMedia, Storage};
-use crate::telegram_api::{SentMedia, TelegramApi, resize_photo_if_needed};
-use crate::validator::validate_media_metadata;
+use crate::source_button::{source_button_row, source_keyboard};
+use crate::storage::{CacheMetadata, CachedMedia, Storage};
+use crate::telegram_api::{
+    MediaSource, SentMedia, StatusMessage, TelegramApi, is_chat_unreachable, resize_photo_if_needed,
+};
+use crate::uploads::PendingUploads;
+use crate::url_cleanup::{UrlCleanupRule, cleanup_url_with_rules, default_rules};
+use crate::url_normalizer::{expand_short_url, is_short_url};
+use crate::validator::{ValidationConfig, ValidationError, validate_media_metadata};
 
 /// Persisted context for a premium action callback button, stored in the DB.
 /// Decoupled from subscriptions — tracks the download destination and media info
 /// needed to serve the audio/transcribe/summarize callback when the user taps the button.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallbackContext {
     pub source_url: String,
     pub chat_id: i64,
@@ -39,28 +58,82 @@ pub struct DownloadContext {
     pub audio_cache_path: Option<PathBuf>,
     /// Message ID of the sent video, used to attach premium buttons to it.
     pub sent_message_id: Option<MessageId>,
+    /// The video carries a source button, which the premium buttons must keep.
+    pub source_as_button: bool,
+    /// First message the media was delivered in, which repeat requests point back to.
+    pub delivered_message_id: Option<MessageId>,
 }
 
-/// An RAII guard to ensure downloaded files are cleaned up.
-struct FileCleanupGuard {
+/// Removes downloaded files. `cleanup` deletes them before the request completes; if the
+/// request is cancelled first (e.g. by the overall timeout), `Drop` deletes them in the
+/// background as a safety net.
+pub(crate) struct FileCleanupGuard {
     paths: Vec<PathBuf>,
 }
 
 impl FileCleanupGuard {
-    fn from_downloaded_media(media: &DownloadedMedia) -> Self {
-        let paths = match media {
+    pub(crate) fn from_downloaded_media(media: &DownloadedMedia) -> Self {
+        Self {
+            paths: Self::media_paths(media),
+        }
+    }
+
+    /// Track the files of `media` instead, e.g. after post-download hooks replaced some.
+    fn track(&mut self, media: &DownloadedMedia) {
+        self.paths = Self::media_paths(media);
+    }
+
+    /// Also remove `path`, e.g. a file converted from the download.
+    pub(crate) fn add(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    fn media_paths(media: &DownloadedMedia) -> Vec<PathBuf> {
+        match media {
             DownloadedMedia::Single(item) => {
                 let mut paths = vec![item.filepath.clone()];
                 if let Some(thumb) = &item.thumbnail_filepath {
                     paths.push(thumb.clone());
                 }
+                paths.extend(item.info_json_filepath.clone());
                 paths
             }
-            DownloadedMedia::Group(items) => {
-                items.iter().map(|item| item.filepath.clone()).collect()
+            DownloadedMedia::Group(items) => items
+                .iter()
+                .flat_map(|item| {
+                    std::iter::once(item.filepath.clone()).chain(item.info_json_filepath.clone())
+                })
+                .collect(),
+        }
+    }
+
+    /// Combined size of the files, for traffic reporting. Files that can't be read count as 0.
+    async fn total_bytes(&self) -> u64 {
+        let mut total = 0;
+        for path in &self.paths {
+            match tokio::fs::metadata(path).await {
+                Ok(metadata) => total += metadata.len(),
+                Err(e) => log::warn!("Failed to stat {}: {}", path.display(), e),
             }
-        };
-        Self { paths }
+        }
+        total
+    }
+
+    pub(crate) async fn cleanup(mut self) {
+        remove_files(&std::mem::take(&mut self.paths)).await;
+    }
+}
+
+pub(crate) async fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        match tokio::fs::remove_file(path).await {
+            Ok(_) => log::info!("Successfully removed file: {}", path.display()),
+            // E.g. a conversion output that was never written.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("No file to remove at {}", path.display())
+            }
+            Err(e) => log::error!("Failed to remove file {}: {}", path.display(), e),
+        }
     }
 }
 
@@ -78,16 +151,7 @@ impl Drop for FileCleanupGuard {
 
         match tokio::runtime::Handle::try_current() {
             Ok(handle) => {
-                handle.spawn(async move {
-                    for path in &paths_to_delete {
-                        match tokio::fs::remove_file(path).await {
-                            Ok(_) => log::info!("Successfully removed file: {}", path.display()),
-                            Err(e) => {
-                                log::error!("Failed to remove file {}: {}", path.display(), e)
-                            }
-                        }
-                    }
-                });
+                handle.spawn(async move { remove_files(&paths_to_delete).await });
             }
             Err(_) => {
                 std::thread::spawn(move || {
@@ -118,92 +182,255 @@ async fn log_reply_failure(
     chat_id: ChatId,
     action: &str,
 ) {
-    if let Err(e) = result {
-        log::error!(
-            "Telegram reply failed: action={} chat_id={} error={:?}",
-            action,
-            chat_id,
-            e
-        );
+    match result {
+        // Logged once when the chat first turned out to be unreachable.
+        Err(e) if is_chat_unreachable(&e) => {
+            log::debug!(
+                "Telegram reply skipped: action={} chat_id={} error={}",
+                action,
+                chat_id,
+                e
+            );
+        }
+        Err(e) => {
+            log::error!(
+                "Telegram reply failed: action={} chat_id={} error={:?}",
+                action,
+                chat_id,
+                e
+            );
+            #[cfg(feature = "sentry")]
+            sentry::capture_message(
+                &format!("Telegram reply failed: action={action} error={e}"),
+                sentry::Level::Error,
+            );
+        }
+        Ok(()) => {}
     }
 }
 
-/// Creates a normalized URL for use as a cache key:
-/// - strips fragment and query params (preserving YouTube `v=` param)
-/// - removes `www.` prefix
-/// - removes trailing slash from path
-#[must_use]
-fn cleanup_url(original_url: &Url) -> Url {
-    let mut cleaned_url = original_url.clone();
-    cleaned_url.set_fragment(None);
-
-    // Normalize www. prefix so e.g. www.instagram.com and instagram.com share a cache entry
-    if let Some(host) = cleaned_url.host_str() {
-        if let Some(stripped) = host.strip_prefix("www.") {
-            let normalized = stripped.to_owned();
-            let _ = cleaned_url.set_host(Some(&normalized));
-        }
+/// `status` for the request log, or `blocked` when the chat stopped accepting messages.
+fn request_status(
+    telegram_api: &UnreachableChatGuard<'_>,
+    chat_id: ChatId,
+    status: &'static str,
+) -> &'static str {
+    if telegram_api.is_unreachable(chat_id) {
+        "blocked"
+    } else {
+        status
     }
+}
 
-    let is_youtube = cleaned_url
-        .host_str()
-        .is_some_and(|h| h.ends_with("youtube.com") || h == "youtu.be");
+/// Operator-tunable behaviour of the download pipeline, built once at startup.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Query-parameter rules used to normalize URLs into cache keys.
+    pub url_cleanup_rules: Vec<UrlCleanupRule>,
+    /// Ceiling for a whole request: metadata, download, transcode and upload.
+    pub request_timeout: Duration,
+    /// Metadata limits checked before downloading.
+    pub validation: ValidationConfig,
+    /// Where files too large for Telegram are uploaded instead; `None` points the user to
+    /// the source.
+    pub object_store: Option<Arc<dyn ObjectStore>>,
+    /// How captions are built.
+    pub caption: CaptionOptions,
+    /// Post-processing run on downloaded files before they are sent, in order.
+    pub post_download_hooks: Vec<Arc<dyn PostDownloadHook>>,
+    /// Client that expands links from `SHORTENER_HOSTS`, from `short_url_client`; `None`
+    /// passes them to yt-dlp unchanged.
+    pub short_url_client: Option<reqwest::Client>,
+    /// Captions sent to this chat, the owner's, end with the download phase timings.
+    pub timings_footer_chat: Option<ChatId>,
+    /// Downloads running longer than this tell the user they're still going; `None`
+    /// stays silent.
+    pub slow_download_warning: Option<Duration>,
+    /// Uploads go to a local Bot API server, which takes videos up to 2 GB, so large
+    /// videos are not sent as documents.
+    pub local_bot_api: bool,
+    /// The bot this pipeline sends with. Its media cache entries are kept apart, as a
+    /// Telegram file id only works for the bot that uploaded the file.
+    pub bot_id: i64,
+    /// Per-URL locks, so concurrent requests for the same link download it only once:
+    /// later requests wait for the first and are then answered from the media cache.
+    /// Kept per bot, like the media cache it waits on.
+    pub in_flight_urls: Arc<KeyedMutex<String>>,
+}
 
-    if is_youtube {
-        if let Some(video_id) = original_url
-            .query_pairs()
-            .find(|(key, _)| key == "v")
-            .map(|(_, value)| value)
-        {
-            cleaned_url.set_query(Some(&format!("v={}", video_id)));
-        } else {
-            cleaned_url.set_query(None);
+/// Default for `PipelineConfig::request_timeout`, above yt-dlp's own download timeout.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(360);
+/// Default for `PipelineConfig::slow_download_warning`.
+pub const DEFAULT_SLOW_DOWNLOAD_WARNING: Duration = Duration::from_secs(30);
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            url_cleanup_rules: default_rules(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            validation: ValidationConfig::default(),
+            object_store: None,
+            caption: CaptionOptions::default(),
+            post_download_hooks: Vec::new(),
+            short_url_client: None,
+            timings_footer_chat: None,
+            slow_download_warning: Some(DEFAULT_SLOW_DOWNLOAD_WARNING),
+            local_bot_api: false,
+            bot_id: 0,
+            in_flight_urls: Arc::default(),
         }
-    } else {
-        cleaned_url.set_query(None);
     }
+}
+
+/// Message suffix that asks for photos to also be sent as uncompressed documents.
+const ORIGINAL_QUALITY_SUFFIX: &str = "!hq";
+/// Maximum number of gallery photos re-sent as documents, to avoid flooding the chat.
+const MAX_ORIGINAL_DOCUMENTS: usize = 3;
+
+/// Per-request options parsed from the user's message.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DownloadOptions {
+    /// Also send photos as documents so Telegram does not recompress them.
+    pub original_quality: bool,
+    /// Send the source link as a button under single videos and photos, from the chat's
+    /// `/sourcebutton` setting rather than the message.
+    pub source_as_button: bool,
+}
+
+/// A bare `domain.tld/path` token, as pasted without a scheme.
+static SCHEMELESS_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[\w-]+(\.[\w-]+)*\.[a-zA-Z]{2,}/\S+$").expect("valid regex"));
+/// Paths like `18` or `v2.1`, as in "node.js/18", which name a version rather than a page.
+static VERSION_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^v?\d+(\.\d+)*/?$").expect("valid regex"));
+
+/// Parse a link, also accepting `www.tiktok.com/@user/video/123` without a scheme.
+pub fn parse_link(text: &str) -> Option<Url> {
+    if let Ok(url) = Url::parse(text) {
+        return Some(url);
+    }
+    if !SCHEMELESS_URL.is_match(text) {
+        return None;
+    }
+    let (_, path) = text.split_once('/')?;
+    if VERSION_PATH.is_match(path) {
+        return None;
+    }
+    let url = Url::parse(&format!("https://{text}")).ok()?;
+    url.host_str()
+        .is_some_and(|host| host.contains('.'))
+        .then_some(url)
+}
 
-    // Remove trailing slash from path (e.g. /p/ABC123/ -> /p/ABC123)
-    let path = cleaned_url.path().to_owned();
-    if path.len() > 1 && path.ends_with('/') {
-        let _ = cleaned_url.set_path(path.trim_end_matches('/'));
+/// A URL message, optionally followed by request flags such as `!hq`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlRequest {
+    pub url: Url,
+    pub options: DownloadOptions,
+}
+
+impl UrlRequest {
+    /// Parse message text of the form `<url>` or `<url> !hq`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (url_text, original_quality) = match text.strip_suffix(ORIGINAL_QUALITY_SUFFIX) {
+            Some(rest) => (rest.trim_end(), true),
+            None => (text, false),
+        };
+        let url = parse_link(url_text)?;
+        Some(Self {
+            url,
+            options: DownloadOptions {
+                original_quality,
+                ..DownloadOptions::default()
+            },
+        })
     }
+}
 
-    cleaned_url
+/// A regular YouTube video rather than a Short, which gets its own "too long" reply.
+fn is_youtube_long_form(url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let is_youtube = host == "youtu.be" || host == "youtube.com" || host.ends_with(".youtube.com");
+    is_youtube && !url.path().starts_with("/shorts/")
 }
 
-/// Step 1: Perform pre-download validation.
+/// Step 1: Perform pre-download validation. On failure the user has been told why, and
+/// the status to log the request with is returned.
 async fn pre_download_validation(
     url: &Url,
     chat_id: ChatId,
     message_id: MessageId,
     downloader: &dyn Downloader,
     telegram_api: &dyn TelegramApi,
-) -> Result<MediaInfo, ()> {
+    validation: &ValidationConfig,
+) -> Result<MediaInfo, &'static str> {
     log::info!("Beginning pre-download check for {}", url);
     match downloader.get_media_metadata(url).await {
-        Ok(info) => {
-            if let Err(validation_error) = validate_media_metadata(&info) {
-                log::warn!("Validation failed for {}: {}", url, validation_error);
-                log_reply_failure(
-                    telegram_api
-                        .send_text_message(chat_id, message_id, &validation_error.to_string())
-                        .await,
-                    chat_id,
-                    "validation_error",
-                )
-                .await;
-                Err(())
-            } else {
-                log::info!(
-                    "Pre-download checks passed for {}. Proceeding with download.",
-                    url
-                );
-                Ok(info)
+        Ok(mut info) => {
+            info.fit_format_to(validation.max_filesize_bytes);
+            let validation_result =
+                validate_media_metadata(&info, validation).map_err(|e| match e {
+                    ValidationError::TooLong { found, limit } if is_youtube_long_form(url) => {
+                        ValidationError::YouTubeTooLong { found, limit }
+                    }
+                    e => e,
+                });
+            match validation_result {
+                Err(validation_error) => {
+                    log::warn!("Validation failed for {}: {}", url, validation_error);
+                    log_reply_failure(
+                        telegram_api
+                            .send_text_message(chat_id, message_id, &validation_error.to_string())
+                            .await,
+                        chat_id,
+                        "validation_error",
+                    )
+                    .await;
+                    Err("validation_error")
+                }
+                Ok(warning) => {
+                    if let Some(warning) = warning {
+                        log::info!("Validation warning for {}: {:?}", url, warning);
+                        log_reply_failure(
+                            telegram_api
+                                .send_text_message(chat_id, message_id, &warning.to_string())
+                                .await,
+                            chat_id,
+                            "validation_warning",
+                        )
+                        .await;
+                    }
+                    log::info!(
+                        "Pre-download checks passed for {}. Proceeding with download.",
+                        url
+                    );
+                    Ok(info)
+                }
             }
         }
+        Err(e @ DownloadError::GeoBlocked(_)) => {
+            log::warn!(
+                "Geo-blocked metadata fetch for domain {}: {}",
+                url.host_str().unwrap_or("unknown"),
+                e.display_to_log()
+            );
+            log_reply_failure(
+                telegram_api
+                    .send_text_message(chat_id, message_id, e.display_to_user())
+                    .await,
+                chat_id,
+                "geo_blocked",
+            )
+            .await;
+            Err("geo_blocked")
+        }
         Err(e) => {
-            log::error!("Pre-download metadata fetch failed for {}: {}", url, e);
+            log::error!(
+                "Pre-download metadata fetch failed for {}: {}",
+                url,
+                e.display_to_log()
+            );
             log_reply_failure(
                 telegram_api.send_text_message(
                     chat_id,
@@ -215,58 +442,358 @@ async fn pre_download_validation(
                 "metadata_error",
             )
             .await;
-            Err(())
+            Err("validation_error")
         }
     }
 }
 
-/// Step 2: Download the media.
+/// Step 2: Download the media. The files are guarded from the moment they exist, so a
+/// request cancelled while hooks run still removes them.
+#[allow(clippy::too_many_arguments)]
 async fn download_step(
-    info: &MediaInfo,
+    info: &mut MediaInfo,
     url: &Url,
     chat_id: ChatId,
     message_id: MessageId,
     downloader: &dyn Downloader,
     telegram_api: &dyn TelegramApi,
-) -> Result<DownloadedMedia, ()> {
-    match downloader.download_media(info, url).await {
-        Ok(media) => Ok(media),
+    hooks: &[Arc<dyn PostDownloadHook>],
+    slow_download_warning: Option<Duration>,
+    status_message: &mut Option<StatusMessage>,
+) -> Result<(DownloadedMedia, FileCleanupGuard), DownloadError> {
+    let download = with_slow_download_warning(
+        downloader.download_media(info, url),
+        slow_download_warning,
+        status_message,
+        chat_id,
+        message_id,
+        telegram_api,
+    );
+    match download.await {
+        Ok(mut media) => {
+            let mut cleanup_guard = FileCleanupGuard::from_downloaded_media(&media);
+            if !hooks.is_empty() {
+                let started = Instant::now();
+                apply_post_download_hooks(hooks, &mut media).await;
+                info.timings.post_processing = Some(started.elapsed());
+                cleanup_guard.track(&media);
+            }
+            Ok((media, cleanup_guard))
+        }
+        Err(e) => {
+            log::error!("Download failed for {}: {}", url, e.display_to_log());
+            Err(e)
+        }
+    }
+}
+
+/// The hosted Bot API's upload limit for media sent from a file path.
+const TELEGRAM_MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Size on disk of a video too large for the hosted Bot API, which must go out as a
+/// document. yt-dlp's estimate is not used: it is often missing or off. A local Bot API
+/// server takes any video that passed validation.
+async fn oversized_video_bytes(item: &DownloadedItem, local_bot_api: bool) -> Option<u64> {
+    if item.media_type != MediaType::Video || local_bot_api {
+        return None;
+    }
+    let size = tokio::fs::metadata(&item.filepath).await.ok()?.len();
+    (size > TELEGRAM_MAX_UPLOAD_BYTES).then_some(size)
+}
+
+/// Fallback for videos over the Bot API upload limit: send the file as a document instead.
+/// Returns the sent message ID; documents have no video file_id, so nothing is cached.
+/// When Telegram rejects even the document, the file goes to `object_store` if configured.
+#[allow(clippy::too_many_arguments)]
+async fn send_large_video_as_document(
+    item: &DownloadedItem,
+    caption: &str,
+    filesize: Option<u64>,
+    source_url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+    object_store: Option<&dyn ObjectStore>,
+) -> Option<MessageId> {
+    log_reply_failure(
+        telegram_api
+            .send_text_message(
+                chat_id,
+                message_id,
+                "⚠️ This video is large and will be sent as a document.",
+            )
+            .await,
+        chat_id,
+        "large_video_notice",
+    )
+    .await;
+
+    match telegram_api
+        .send_document(
+            chat_id,
+            message_id,
+            MediaSource::Path(item.filepath.clone()),
+            caption,
+        )
+        .await
+    {
+        Ok(sent_id) => {
+            log::info!(
+                "Successfully sent large video as document to chat_id: {}",
+                chat_id
+            );
+            Some(sent_id)
+        }
         Err(e) => {
-            log::error!("Download failed for {}: {}", url, e);
-            let user_message = if matches!(e, crate::downloader::DownloadError::Timeout(_)) {
-                "Sorry, the download is taking too long. Please try a shorter video."
+            log::error!("Failed to send large video as document: {:?}", e);
+            let too_large = matches!(
+                e,
+                teloxide::RequestError::Api(teloxide::ApiError::RequestEntityTooLarge)
+            );
+            if too_large && let Some(object_store) = object_store {
+                offload_to_object_store(
+                    item,
+                    filesize,
+                    chat_id,
+                    message_id,
+                    telegram_api,
+                    object_store,
+                )
+                .await;
+                return None;
+            }
+            let user_message = if too_large {
+                format!(
+                    "Sorry, this file is too large for Telegram. Please download it directly from the source: {}",
+                    source_url
+                )
             } else {
-                "Sorry, I could not download the media. Please try again later."
+                "Sorry, I encountered an error while sending the media.".to_owned()
             };
             log_reply_failure(
                 telegram_api
-                    .send_text_message(chat_id, message_id, user_message)
+                    .send_text_message(chat_id, message_id, &user_message)
                     .await,
                 chat_id,
-                "download_error",
+                "send_document_error",
             )
             .await;
-            Err(())
+            None
+        }
+    }
+}
+
+/// Upload a file Telegram rejected to object storage and reply with a download link,
+/// falling back to pointing the user at the source when the upload fails.
+async fn offload_to_object_store(
+    item: &DownloadedItem,
+    filesize: Option<u64>,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+    object_store: &dyn ObjectStore,
+) {
+    let user_message = match object_store.upload(&item.filepath).await {
+        Ok(link) => {
+            log::info!("Offloaded {:?} to object storage", item.filepath);
+            let size = filesize
+                .map(|bytes| format!(" ({})", format_file_size(bytes)))
+                .unwrap_or_default();
+            format!(
+                "📦 This file is too large for Telegram{}, so I uploaded it for you:\n{}\n\nThe link expires in {}.",
+                size,
+                link.url,
+                format_link_expiry(link.expires_in)
+            )
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to offload {:?} to object storage: {}",
+                item.filepath,
+                e
+            );
+            "Sorry, this file is too large for Telegram and I couldn't upload it elsewhere."
+                .to_owned()
+        }
+    };
+    log_reply_failure(
+        telegram_api
+            .send_text_message(chat_id, message_id, &user_message)
+            .await,
+        chat_id,
+        "object_store_link",
+    )
+    .await;
+}
+
+/// Tell the user how long the download is expected to take. Returns the status
+/// message so it can be deleted once the download finishes.
+async fn send_download_status(
+    estimate: Duration,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+) -> Option<StatusMessage> {
+    let text = format!(
+        "⬇️ Downloading… (est. {} s)",
+        estimate.as_secs_f64().ceil().max(1.0) as u64
+    );
+    match telegram_api
+        .send_status_message(chat_id, message_id, &text)
+        .await
+    {
+        Ok(status) => Some(status),
+        Err(e) => {
+            log::warn!("Failed to send download status message: {}", e);
+            None
+        }
+    }
+}
+
+/// Status shown once a download has taken longer than `PipelineConfig::slow_download_warning`.
+const SLOW_DOWNLOAD_STATUS: &str =
+    "⏳ Still downloading, this is taking a while… Please be patient.";
+
+/// Await `download`, and if it is still running after `warn_after`, say so in the status
+/// message, sending one if there is none yet.
+async fn with_slow_download_warning<T>(
+    download: impl std::future::Future<Output = T>,
+    warn_after: Option<Duration>,
+    status_message: &mut Option<StatusMessage>,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+) -> T {
+    let Some(warn_after) = warn_after else {
+        return download.await;
+    };
+    let mut download = std::pin::pin!(download);
+    tokio::select! {
+        result = &mut download => result,
+        () = tokio::time::sleep(warn_after) => {
+            log::info!("Download for chat {} is slow, telling the user", chat_id);
+            // The download keeps running while the status is updated.
+            let (result, ()) = tokio::join!(
+                download,
+                show_slow_download_status(status_message, chat_id, message_id, telegram_api),
+            );
+            result
+        }
+    }
+}
+
+async fn show_slow_download_status(
+    status_message: &mut Option<StatusMessage>,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+) {
+    let result = match *status_message {
+        Some(status) => {
+            telegram_api
+                .edit_message_text(status.chat_id, status.message_id, SLOW_DOWNLOAD_STATUS)
+                .await
+        }
+        None => telegram_api
+            .send_status_message(chat_id, message_id, SLOW_DOWNLOAD_STATUS)
+            .await
+            .map(|status| *status_message = Some(status)),
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to show slow download status: {}", e);
+    }
+}
+
+/// Remove the download status message once the request is over. Sending failures have
+/// already been replied to, so there is nothing left to show in it.
+async fn finish_download_status(
+    status_message: Option<StatusMessage>,
+    telegram_api: &dyn TelegramApi,
+) {
+    let Some(status) = status_message else {
+        return;
+    };
+    // The user may have deleted it already, which is fine.
+    if let Err(e) = telegram_api
+        .delete_message(status.chat_id, status.message_id)
+        .await
+    {
+        log::warn!("Failed to remove download status message: {}", e);
+    }
+}
+
+/// Tell the user why the request failed: in the download status message if there is one,
+/// as a reply otherwise or if the status message can't be edited.
+async fn report_failure(
+    status_message: Option<StatusMessage>,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+    action: &str,
+    telegram_api: &dyn TelegramApi,
+) {
+    if let Some(status) = status_message {
+        match telegram_api
+            .edit_message_text(status.chat_id, status.message_id, text)
+            .await
+        {
+            Ok(()) => return,
+            Err(e) => log::warn!("Failed to update download status message: {}", e),
         }
     }
+    log_reply_failure(
+        telegram_api
+            .send_text_message(chat_id, message_id, text)
+            .await,
+        chat_id,
+        action,
+    )
+    .await;
 }
 
-/// Step 3 (Branch A): Handle sending a single media item. Returns (file_id, media_type, sent_message_id) on success.
+/// Step 3 (Branch A): Handle sending a single media item. Returns (file_id, media_type, sent_message_id)
+/// on success; file_id is `None` when a large video was sent as a document and cannot be cached.
+#[allow(clippy::too_many_arguments)]
 async fn send_single_item(
     item: &DownloadedItem,
     caption: &str,
+    info: &MediaInfo,
+    source_url: &Url,
     chat_id: ChatId,
     message_id: MessageId,
     telegram_api: &dyn TelegramApi,
-) -> Option<(String, MediaType, MessageId)> {
+    object_store: Option<&dyn ObjectStore>,
+    local_bot_api: bool,
+    source_as_button: bool,
+) -> Option<(Option<String>, MediaType, MessageId)> {
+    if let Some(filesize) = oversized_video_bytes(item, local_bot_api).await {
+        return send_large_video_as_document(
+            item,
+            caption,
+            Some(filesize),
+            source_url,
+            chat_id,
+            message_id,
+            telegram_api,
+            object_store,
+        )
+        .await
+        .map(|sent_id| (None, MediaType::Video, sent_id));
+    }
+
+    let (caption, reply_markup) = source_placement(caption, source_url, source_as_button);
     let result = match item.media_type {
         MediaType::Video => telegram_api
             .send_video(
                 chat_id,
                 message_id,
-                &item.filepath,
-                caption,
+                MediaSource::Path(item.filepath.clone()),
+                &caption,
                 item.thumbnail_filepath.clone(),
+                info.duration.map(|d| d.round() as u32),
+                info.width,
+                info.height,
+                reply_markup,
             )
             .await
             .map(|(file_id, sent_id)| (file_id, MediaType::Video, sent_id)),
@@ -288,7 +815,13 @@ async fn send_single_item(
             };
             let effective_path = resized.as_deref().unwrap_or(&item.filepath);
             let send_result = telegram_api
-                .send_photo(chat_id, message_id, effective_path, caption)
+                .send_photo(
+                    chat_id,
+                    message_id,
+                    effective_path.into(),
+                    &caption,
+                    reply_markup,
+                )
                 .await
                 .map(|(file_id, sent_id)| (file_id, MediaType::Photo, sent_id));
             if let Some(p) = resized {
@@ -299,9 +832,9 @@ async fn send_single_item(
     };
 
     match result {
-        Ok(sent) => {
+        Ok((file_id, media_type, sent_id)) => {
             log::info!("Successfully sent to chat_id: {}", chat_id);
-            Some(sent)
+            Some((Some(file_id), media_type, sent_id))
         }
         Err(e) => {
             log::error!("Failed to send: Error: {:?}", e);
@@ -322,85 +855,417 @@ async fn send_single_item(
     }
 }
 
-/// Step 3 (Branch B): Handle sending a media group. Returns file_ids on success.
-async fn send_media_group_step(
-    items: &[DownloadedItem],
+/// The caption and buttons of a single video or photo: the source link moves from the
+/// caption to a button when the chat asked for it.
+fn source_placement(
     caption: &str,
-    chat_id: ChatId,
-    message_id: MessageId,
-    telegram_api: &dyn TelegramApi,
-) -> Option<Vec<SentMedia>> {
-    let mut media_group: Vec<InputMedia> = Vec::new();
-    let mut temp_resized: Vec<PathBuf> = Vec::new();
+    source_url: &Url,
+    source_as_button: bool,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    if source_as_button {
+        (
+            remove_source_link(caption, source_url),
+            Some(source_keyboard(source_url)),
+        )
+    } else {
+        (caption.to_string(), None)
+    }
+}
 
-    for (i, item) in items.iter().enumerate() {
-        let item_caption = if i == 0 {
-            caption.to_owned()
-        } else {
-            String::new()
-        };
+/// 1-based positions of the `entry_count` playlist entries that produced no item, because
+/// yt-dlp reported no file for them or their file type is unsupported.
+fn missing_playlist_positions(entry_count: usize, items: &[DownloadedItem]) -> Vec<usize> {
+    (1..=entry_count)
+        .filter(|position| {
+            !items
+                .iter()
+                .any(|item| item.playlist_index == Some(*position))
+        })
+        .collect()
+}
 
-        let media = match item.media_type {
-            MediaType::Video => {
-                let input_file = InputFile::file(&item.filepath);
-                InputMedia::Video(
-                    InputMediaVideo::new(input_file)
-                        .parse_mode(ParseMode::Html)
-                        .caption(item_caption),
-                )
+fn format_missing_items_notice(missing: &[usize], entry_count: usize) -> String {
+    let positions = missing
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let label = if missing.len() == 1 { "item" } else { "items" };
+    format!(
+        "⚠️ {} of {} items could not be downloaded ({} {}).",
+        missing.len(),
+        entry_count,
+        label,
+        positions
+    )
+}
+
+/// Longest item title shown in a per-item media group caption.
+const MAX_ITEM_TITLE_CHARS: usize = 200;
+
+/// Captions for each media group item. Albums from a single post keep the whole caption on
+/// the first item only; playlists whose entries have distinct titles label every item with
+/// its title, the first one above the full caption when that fits.
+fn media_group_captions<T: Borrow<DownloadedItem>>(items: &[T], caption: &str) -> Vec<String> {
+    let titles: Option<Vec<&str>> = items
+        .iter()
+        .map(|item| {
+            item.borrow()
+                .title
+                .as_deref()
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+        })
+        .collect();
+    let distinct_titles = titles.filter(|titles| {
+        titles.len() > 1
+            && titles
+                .iter()
+                .enumerate()
+                .all(|(i, title)| !titles[..i].contains(title))
+    });
+    let Some(titles) = distinct_titles else {
+        return std::iter::once(caption.to_owned())
+            .chain(std::iter::repeat_n(
+                String::new(),
+                items.len().saturating_sub(1),
+            ))
+            .collect();
+    };
+
+    titles
+        .iter()
+        .enumerate()
+        .map(|(i, title)| {
+            let mut label: String = title.chars().take(MAX_ITEM_TITLE_CHARS).collect();
+            if label.len() < title.len() {
+                label.push('…');
             }
-            MediaType::Photo => {
-                let resized = match resize_photo_if_needed(&item.filepath) {
-                    Ok(resized) => resized,
-                    Err(e) => {
-                        log_reply_failure(
-                            telegram_api
-                                .send_text_message(chat_id, message_id, &e)
-                                .await,
-                            chat_id,
-                            "photo_policy_reject",
-                        )
-                        .await;
-                        continue;
-                    }
+            let label = format!("<b>{}</b>", escape_html_text(&label));
+            if i > 0 {
+                return label;
+            }
+            lead_caption(&label, caption)
+        })
+        .collect()
+}
+
+/// The full caption below an item's `label`, or the caption alone when both don't fit.
+fn lead_caption(label: &str, caption: &str) -> String {
+    if label.is_empty() {
+        return caption.to_owned();
+    }
+    let combined = format!("{label}\n{caption}");
+    if combined.chars().count() <= MediaInfo::TELEGRAM_CAPTION_LIMIT {
+        combined
+    } else {
+        caption.to_owned()
+    }
+}
+
+/// Reasons for the items left out of a media group, one per line.
+fn format_dropped_items(dropped: &[String]) -> String {
+    dropped
+        .iter()
+        .map(|reason| format!("\n• {reason}"))
+        .collect()
+}
+
+/// Whether Telegram rejected a request because of one of its files, e.g. a corrupt photo in
+/// an album, so sending the files one by one may still get the others through.
+fn is_file_rejection(error: &teloxide::RequestError) -> bool {
+    match error {
+        teloxide::RequestError::Api(
+            teloxide::ApiError::ImageProcessFailed
+            | teloxide::ApiError::RequestEntityTooLarge
+            | teloxide::ApiError::WrongFileId
+            | teloxide::ApiError::WrongFileIdOrUrl
+            | teloxide::ApiError::FileIdInvalid
+            | teloxide::ApiError::PhotoAsInputFileRequired,
+        ) => true,
+        teloxide::RequestError::Api(teloxide::ApiError::Unknown(message)) => {
+            let message = message.to_lowercase();
+            ["file", "media", "photo", "video"]
+                .iter()
+                .any(|word| message.contains(word))
+        }
+        _ => false,
+    }
+}
+
+/// A media group item that can be sent: its playlist position, the item and the file to
+/// upload, which may be a resized copy.
+type GroupItem<'a> = (usize, &'a DownloadedItem, PathBuf);
+
+/// Fallback for an album Telegram rejected: send each item on its own with the caption it
+/// had in the album, so one bad file doesn't cost the user the rest. The full caption goes
+/// on the first item that gets through. Returns the sent items and, for the others, why
+/// they are missing.
+async fn send_items_individually(
+    items: &[GroupItem<'_>],
+    captions: &[String],
+    caption: &str,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+) -> (Vec<SentMedia>, Vec<String>) {
+    let mut sent = Vec::new();
+    let mut failed = Vec::new();
+    for (i, ((position, item, path), item_caption)) in items.iter().zip(captions).enumerate() {
+        // Items after the first only carry their label until one has been sent.
+        let item_caption = if i > 0 && sent.is_empty() {
+            lead_caption(item_caption, caption)
+        } else {
+            item_caption.clone()
+        };
+        let media = MediaSource::Path(path.clone());
+        let result = match item.media_type {
+            MediaType::Video => {
+                telegram_api
+                    .send_video(
+                        chat_id,
+                        message_id,
+                        media,
+                        &item_caption,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                    )
+                    .await
+            }
+            MediaType::Photo => {
+                telegram_api
+                    .send_photo(chat_id, message_id, media, &item_caption, None)
+                    .await
+            }
+        };
+        match result {
+            Ok((file_id, sent_id)) => sent.push(SentMedia {
+                file_id,
+                media_type: item.media_type,
+                message_id: sent_id,
+            }),
+            Err(e) => {
+                log::error!(
+                    "Failed to send album item {} on its own: {:?}",
+                    item.filepath.display(),
+                    e
+                );
+                failed.push(format!("item {position}: Telegram did not accept the file"));
+            }
+        }
+    }
+    (sent, failed)
+}
+
+/// The items of a media group that reached the chat.
+#[derive(Debug, PartialEq)]
+struct SentGroup {
+    sent: Vec<SentMedia>,
+    /// Some items were left out or failed to send, so the result must not be cached.
+    incomplete: bool,
+}
+
+/// Step 3 (Branch B): Handle sending a media group. Returns the sent items on success.
+///
+/// Items that can't be sent, including empty or unreadable files, are left out and the
+/// user is told which. If a single item remains it is sent on its own with the full
+/// caption, since a one-item album looks odd and loses the reply preview. If Telegram
+/// rejects the album because of a file, the items are sent one by one instead.
+#[allow(clippy::too_many_arguments)]
+async fn send_media_group_step(
+    items: &[DownloadedItem],
+    caption: &str,
+    info: &MediaInfo,
+    source_url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+    object_store: Option<&dyn ObjectStore>,
+    local_bot_api: bool,
+) -> Option<SentGroup> {
+    let mut survivors: Vec<GroupItem> = Vec::new();
+    let mut dropped: Vec<String> = Vec::new();
+    let mut temp_resized: Vec<PathBuf> = Vec::new();
+
+    for (position, item) in items.iter().enumerate() {
+        let position = item.playlist_index.unwrap_or(position + 1);
+        // Telegram rejects a whole album over a single empty file.
+        match tokio::fs::metadata(&item.filepath).await {
+            Ok(metadata) if metadata.len() > 0 => {}
+            Ok(_) => {
+                dropped.push(format!("item {position}: the downloaded file is empty"));
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Cannot read {}: {}", item.filepath.display(), e);
+                dropped.push(format!(
+                    "item {position}: the downloaded file could not be read"
+                ));
+                continue;
+            }
+        }
+        let path = match item.media_type {
+            MediaType::Video => item.filepath.clone(),
+            MediaType::Photo => {
+                let resized = match resize_photo_if_needed(&item.filepath) {
+                    Ok(resized) => resized,
+                    Err(e) => {
+                        dropped.push(format!("item {position}: {e}"));
+                        continue;
+                    }
                 };
                 let path = resized.as_deref().unwrap_or(&item.filepath).to_path_buf();
                 if let Some(p) = resized {
                     temp_resized.push(p);
                 }
-                InputMedia::Photo(
-                    InputMediaPhoto::new(InputFile::file(path))
-                        .parse_mode(ParseMode::Html)
-                        .caption(item_caption),
-                )
+                path
             }
         };
-        media_group.push(media);
+        survivors.push((position, item, path));
     }
 
-    if media_group.is_empty() {
-        let msg = "Sorry, although multiple items were found, none were of a supported type for a media group.";
+    if survivors.len() < 2 {
+        for p in temp_resized.drain(..) {
+            remove_temp_file(p, "media group resize").await;
+        }
+    }
+    let notice = match survivors.as_slice() {
+        [] => Some(format!(
+            "Sorry, none of the {} items could be sent.{}",
+            items.len(),
+            format_dropped_items(&dropped)
+        )),
+        _ if !dropped.is_empty() => Some(format!(
+            "⚠️ Some items were left out:{}",
+            format_dropped_items(&dropped)
+        )),
+        _ => None,
+    };
+    if let Some(notice) = notice {
+        log::warn!("Media group for chat {}: {}", chat_id, notice);
         log_reply_failure(
             telegram_api
-                .send_text_message(chat_id, message_id, msg)
+                .send_text_message(chat_id, message_id, &notice)
                 .await,
             chat_id,
-            "empty_media_group",
+            "dropped_group_items",
         )
         .await;
-        return None;
+    }
+    let incomplete = !dropped.is_empty();
+    match survivors.as_slice() {
+        [] => return None,
+        [(_, item, _)] => {
+            let entry_info = item
+                .playlist_index
+                .and_then(|index| info.entries.as_ref()?.get(index.checked_sub(1)?))
+                .cloned()
+                .unwrap_or_default();
+            return send_single_item(
+                item,
+                caption,
+                &entry_info,
+                source_url,
+                chat_id,
+                message_id,
+                telegram_api,
+                object_store,
+                local_bot_api,
+                false,
+            )
+            .await
+            .map(|(file_id, media_type, message_id)| SentGroup {
+                sent: file_id
+                    .map(|file_id| SentMedia {
+                        file_id,
+                        media_type,
+                        message_id,
+                    })
+                    .into_iter()
+                    .collect(),
+                incomplete,
+            });
+        }
+        _ => {}
     }
 
-    let result = telegram_api
+    // Captions follow the items that are actually sent, so the full caption is never
+    // lost with a left-out first item.
+    let survivor_items: Vec<&DownloadedItem> = survivors.iter().map(|(_, item, _)| *item).collect();
+    let captions = media_group_captions(&survivor_items, caption);
+    let media_group: Vec<InputMedia> = survivors
+        .iter()
+        .zip(&captions)
+        .map(|((_, item, path), item_caption)| match item.media_type {
+            MediaType::Video => InputMedia::Video(
+                InputMediaVideo::new(InputFile::file(path))
+                    .parse_mode(ParseMode::Html)
+                    .caption(item_caption.clone()),
+            ),
+            MediaType::Photo => InputMedia::Photo(
+                InputMediaPhoto::new(InputFile::file(path))
+                    .parse_mode(ParseMode::Html)
+                    .caption(item_caption.clone()),
+            ),
+        })
+        .collect();
+
+    let (result, failed) = match telegram_api
         .send_media_group(chat_id, message_id, media_group)
-        .await;
+        .await
+    {
+        Err(e) if is_file_rejection(&e) => {
+            log::warn!(
+                "Telegram rejected the media group for chat {} ({:?}), sending its {} items one by one",
+                chat_id,
+                e,
+                survivors.len()
+            );
+            let (sent, failed) = send_items_individually(
+                &survivors,
+                &captions,
+                caption,
+                chat_id,
+                message_id,
+                telegram_api,
+            )
+            .await;
+            if sent.is_empty() {
+                (Err(e), failed)
+            } else {
+                (Ok(sent), failed)
+            }
+        }
+        result => (result, Vec::new()),
+    };
     for p in temp_resized {
         remove_temp_file(p, "media group resize").await;
     }
     match result {
         Ok(sent) => {
             log::info!("Successfully sent media group to chat_id: {}", chat_id);
-            Some(sent)
+            if !failed.is_empty() {
+                let notice = format!(
+                    "⚠️ Some items could not be sent:{}",
+                    format_dropped_items(&failed)
+                );
+                log::warn!("Media group for chat {}: {}", chat_id, notice);
+                log_reply_failure(
+                    telegram_api
+                        .send_text_message(chat_id, message_id, &notice)
+                        .await,
+                    chat_id,
+                    "failed_group_items",
+                )
+                .await;
+            }
+            Some(SentGroup {
+                sent,
+                incomplete: incomplete || !failed.is_empty(),
+            })
         }
         Err(e) => {
             log::error!("Failed to send media group: Error: {:?}", e);
@@ -421,47 +1286,103 @@ async fn send_media_group_step(
     }
 }
 
-/// Send cached media back to the user.
-/// Send cached media. For a single video returns `Ok(Some(sent_msg_id))` so the
-/// caller can attach premium buttons; all other cases return `Ok(None)`.
+/// Step 4 (optional): Re-send downloaded photos as documents in their original quality.
+/// Galleries are capped at `MAX_ORIGINAL_DOCUMENTS` and the user is told about the cutoff.
+async fn send_original_documents(
+    items: &[&DownloadedItem],
+    chat_id: ChatId,
+    message_id: MessageId,
+    telegram_api: &dyn TelegramApi,
+) {
+    let photos: Vec<&DownloadedItem> = items
+        .iter()
+        .copied()
+        .filter(|item| item.media_type == MediaType::Photo)
+        .collect();
+
+    for item in photos.iter().take(MAX_ORIGINAL_DOCUMENTS) {
+        if let Err(e) = telegram_api
+            .send_document(
+                chat_id,
+                message_id,
+                MediaSource::Path(item.filepath.clone()),
+                "Original quality",
+            )
+            .await
+        {
+            log::error!(
+                "Failed to send original-quality document {}: {:?}",
+                item.filepath.display(),
+                e
+            );
+        }
+    }
+
+    if photos.len() > MAX_ORIGINAL_DOCUMENTS {
+        let notice = format!(
+            "Only the first {} photos were sent in original quality.",
+            MAX_ORIGINAL_DOCUMENTS
+        );
+        log_reply_failure(
+            telegram_api
+                .send_text_message(chat_id, message_id, &notice)
+                .await,
+            chat_id,
+            "original_quality_cutoff",
+        )
+        .await;
+    }
+}
+
+/// Send cached media back to the user. Returns the messages it was sent in, so the
+/// caller can record the delivery and attach premium buttons to a single video.
 async fn send_cached_media(
     cached: &CachedMedia,
+    source_url: &Url,
     chat_id: ChatId,
     message_id: MessageId,
     telegram_api: &dyn TelegramApi,
-) -> Result<Option<MessageId>, ()> {
+    source_as_button: bool,
+) -> Result<Vec<MessageId>, ()> {
     if cached.files.len() == 1 {
         let file = &cached.files[0];
-        match file.media_type {
+        let media = MediaSource::FileId(file.telegram_file_id.clone());
+        let (caption, reply_markup) =
+            source_placement(&cached.caption, source_url, source_as_button);
+        let result = match file.media_type {
             MediaType::Video => {
-                match telegram_api
-                    .send_cached_video(chat_id, message_id, &file.telegram_file_id, &cached.caption)
+                telegram_api
+                    .send_video(
+                        chat_id,
+                        message_id,
+                        media,
+                        &caption,
+                        None,
+                        None,
+                        None,
+                        None,
+                        reply_markup,
+                    )
                     .await
-                {
-                    Ok(sent_id) => {
-                        log::info!("Successfully sent cached video to chat_id: {}", chat_id);
-                        Ok(Some(sent_id))
-                    }
-                    Err(e) => {
-                        log::error!("Failed to send cached video: {:?}", e);
-                        Err(())
-                    }
-                }
             }
             MediaType::Photo => {
-                match telegram_api
-                    .send_cached_photo(chat_id, message_id, &file.telegram_file_id, &cached.caption)
+                telegram_api
+                    .send_photo(chat_id, message_id, media, &caption, reply_markup)
                     .await
-                {
-                    Ok(_) => {
-                        log::info!("Successfully sent cached photo to chat_id: {}", chat_id);
-                        Ok(None)
-                    }
-                    Err(e) => {
-                        log::error!("Failed to send cached photo: {:?}", e);
-                        Err(())
-                    }
-                }
+            }
+        };
+        match result {
+            Ok((_, sent_id)) => {
+                log::info!(
+                    "Successfully sent cached {:?} to chat_id: {}",
+                    file.media_type,
+                    chat_id
+                );
+                Ok(vec![sent_id])
+            }
+            Err(e) => {
+                log::error!("Failed to send cached {:?}: {:?}", file.media_type, e);
+                Err(())
             }
         }
     } else {
@@ -469,12 +1390,12 @@ async fn send_cached_media(
             .send_cached_media_group(chat_id, message_id, &cached.files, &cached.caption)
             .await
         {
-            Ok(_) => {
+            Ok(sent_ids) => {
                 log::info!(
                     "Successfully sent cached media group to chat_id: {}",
                     chat_id
                 );
-                Ok(None)
+                Ok(sent_ids)
             }
             Err(e) => {
                 log::error!("Failed to send cached media group: {:?}", e);
@@ -484,6 +1405,29 @@ async fn send_cached_media(
     }
 }
 
+/// Record the messages `source_url` was delivered in, for the owner `/trace` command.
+async fn record_delivery(
+    storage: &dyn Storage,
+    chat_id: ChatId,
+    sent_ids: &[MessageId],
+    source_url: &str,
+    cache_id: Option<i32>,
+) {
+    let message_ids: Vec<i32> = sent_ids.iter().map(|id| id.0).collect();
+    storage
+        .record_delivery(chat_id.0, &message_ids, source_url, cache_id)
+        .await;
+}
+
+/// Runs the download pipeline for one request. A panic inside the pipeline is caught and
+/// reported to the user with a correlation id, so the caller can still clear the reaction
+/// and the bot keeps serving other requests. Downloaded files are removed by the cleanup
+/// guard while unwinding.
+///
+/// The whole pipeline is bounded by `config.request_timeout`. On expiry it is dropped,
+/// which kills a running yt-dlp (`kill_on_drop`) and lets the cleanup guard delete any
+/// downloaded files; the request is logged with status "timeout".
+#[allow(clippy::too_many_arguments)]
 pub async fn process_download_request(
     url: &Url,
     chat_id: ChatId,
@@ -492,13 +1436,179 @@ pub async fn process_download_request(
     telegram_api: &dyn TelegramApi,
     storage: &dyn Storage,
     audio_extractor: &dyn AudioExtractor,
+    config: &PipelineConfig,
+    pending_uploads: &PendingUploads,
+    options: DownloadOptions,
+    progress: &RequestProgress,
+) -> Option<DownloadContext> {
+    let start = Instant::now();
+    let telegram_api = &UnreachableChatGuard::new(telegram_api);
+    let expanded = match &config.short_url_client {
+        Some(client) if is_short_url(url) => match expand_short_url(url, client).await {
+            Ok(expanded) => {
+                log::info!("Expanded short URL {} to {}", url, expanded);
+                Some(expanded)
+            }
+            Err(e) => {
+                log::warn!("Passing {} on unexpanded: {}", url, e.display_to_log());
+                None
+            }
+        },
+        _ => None,
+    };
+    let url = expanded.as_ref().unwrap_or(url);
+    if let Some(host) = url.host_str() {
+        progress.set_domain(host.trim_start_matches("www."));
+    }
+    // Held out here, so a timed-out or panicked pipeline can still finish it.
+    let mut status_message = None;
+    let pipeline = run_download_pipeline(
+        url,
+        chat_id,
+        message_id,
+        downloader,
+        telegram_api,
+        storage,
+        audio_extractor,
+        config,
+        pending_uploads,
+        options,
+        progress,
+        &mut status_message,
+    );
+    let abort = match bound_request(
+        pipeline,
+        config.request_timeout,
+        "Download pipeline",
+        chat_id,
+        url,
+    )
+    .await
+    {
+        Ok(ctx) => return ctx,
+        Err(abort) => abort,
+    };
+    pending_uploads.abandon(chat_id, message_id);
+    storage
+        .log_request(
+            chat_id.0,
+            cleanup_url_with_rules(url, &config.url_cleanup_rules).as_str(),
+            request_status(telegram_api, chat_id, abort.status),
+            start.elapsed().as_millis() as i64,
+            None,
+        )
+        .await;
+    report_failure(
+        status_message,
+        chat_id,
+        message_id,
+        &abort.reply,
+        abort.action,
+        telegram_api,
+    )
+    .await;
+    None
+}
+
+/// Why a request run by `bound_request` did not finish.
+#[derive(Debug)]
+pub(crate) struct RequestAbort {
+    /// Status for the request log: "error" or "timeout".
+    pub status: &'static str,
+    /// What to tell the user.
+    pub reply: String,
+    /// Name of the reply in Telegram failure logs.
+    pub action: &'static str,
+}
+
+/// Run `request` within `timeout`, catching a panic. A panic is logged with a correlation
+/// id that the reply repeats. `what` names the request in the logs.
+pub(crate) async fn bound_request<T>(
+    request: impl Future<Output = T>,
+    timeout: Duration,
+    what: &str,
+    chat_id: ChatId,
+    url: &Url,
+) -> Result<T, RequestAbort> {
+    match tokio::time::timeout(timeout, AssertUnwindSafe(request).catch_unwind()).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(panic)) => {
+            let correlation_id = &Uuid::new_v4().simple().to_string()[..8];
+            log::error!(
+                "{} panicked: correlation_id={} chat_id={} url={} panic={}",
+                what,
+                correlation_id,
+                chat_id,
+                url,
+                panic_message(panic.as_ref())
+            );
+            Err(RequestAbort {
+                status: "error",
+                reply: format!(
+                    "Sorry, something went wrong on my side. Please try again later. \
+                     (error id: {correlation_id})"
+                ),
+                action: "internal_error",
+            })
+        }
+        Err(_) => {
+            log::error!(
+                "{} timed out after {}s: chat_id={} url={}",
+                what,
+                timeout.as_secs(),
+                chat_id,
+                url
+            );
+            Err(RequestAbort {
+                status: "timeout",
+                reply: "Sorry, the request timed out. Please try again.".to_string(),
+                action: "request_timeout",
+            })
+        }
+    }
+}
+
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic>")
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_download_pipeline(
+    url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    downloader: &dyn Downloader,
+    telegram_api: &UnreachableChatGuard<'_>,
+    storage: &dyn Storage,
+    audio_extractor: &dyn AudioExtractor,
+    config: &PipelineConfig,
+    pending_uploads: &PendingUploads,
+    options: DownloadOptions,
+    progress: &RequestProgress,
+    status_message: &mut Option<StatusMessage>,
 ) -> Option<DownloadContext> {
     let start = Instant::now();
-    let clean_url = cleanup_url(url);
+    let clean_url = cleanup_url_with_rules(url, &config.url_cleanup_rules);
     let clean_url_str = clean_url.as_str();
+    let _in_flight = config.in_flight_urls.lock(clean_url_str.to_string()).await;
 
-    // Cache check
-    if let Some(cached) = storage.get_cached_media(clean_url_str).await {
+    // Cache check. Cached photos only hold Telegram's recompressed copy, so an
+    // original-quality request has to download them again.
+    if let Some(cached) = storage
+        .get_cached_media(config.bot_id, clean_url_str)
+        .await
+        .filter(|cached| {
+            !options.original_quality
+                || cached
+                    .files
+                    .iter()
+                    .all(|file| file.media_type != MediaType::Photo)
+        })
+    {
         log::info!("Cache hit for {}", clean_url);
         let is_single_video =
             cached.files.len() == 1 && cached.files[0].media_type == MediaType::Video;
@@ -514,15 +1624,24 @@ pub async fn process_download_request(
                     "Cached audio file missing for {}, falling through to re-download",
                     clean_url
                 );
-            } else if let Ok(sent_message_id) =
-                send_cached_media(&cached, chat_id, message_id, telegram_api).await
+            } else if let Ok(sent_ids) = send_cached_media(
+                &cached,
+                &clean_url,
+                chat_id,
+                message_id,
+                telegram_api,
+                options.source_as_button,
+            )
+            .await
             {
+                record_delivery(storage, chat_id, &sent_ids, clean_url_str, Some(cached.id)).await;
                 storage
                     .log_request(
                         chat_id.0,
                         clean_url_str,
                         "cached",
                         start.elapsed().as_millis() as i64,
+                        None,
                     )
                     .await;
                 return Some(DownloadContext {
@@ -530,19 +1649,49 @@ pub async fn process_download_request(
                     has_video: true,
                     media_duration_secs: cached.media_duration_secs,
                     audio_cache_path: cached.audio_cache_path.map(PathBuf::from),
-                    sent_message_id,
+                    sent_message_id: sent_ids.first().copied(),
+                    source_as_button: options.source_as_button,
+                    delivered_message_id: sent_ids.first().copied(),
                 });
             }
-        } else if send_cached_media(&cached, chat_id, message_id, telegram_api)
-            .await
-            .is_ok()
+        } else if let Ok(sent_ids) = send_cached_media(
+            &cached,
+            &clean_url,
+            chat_id,
+            message_id,
+            telegram_api,
+            options.source_as_button,
+        )
+        .await
         {
+            record_delivery(storage, chat_id, &sent_ids, clean_url_str, Some(cached.id)).await;
             storage
                 .log_request(
                     chat_id.0,
                     clean_url_str,
                     "cached",
                     start.elapsed().as_millis() as i64,
+                    None,
+                )
+                .await;
+            return Some(DownloadContext {
+                source_url: clean_url,
+                has_video: false,
+                media_duration_secs: None,
+                audio_cache_path: None,
+                sent_message_id: None,
+                source_as_button: options.source_as_button,
+                delivered_message_id: sent_ids.first().copied(),
+            });
+        }
+        if telegram_api.is_unreachable(chat_id) {
+            storage
+                .log_request(
+                    chat_id.0,
+                    clean_url_str,
+                    "blocked",
+                    start.elapsed().as_millis() as i64,
+                    None,
                 )
                 .await;
             return None;
@@ -554,68 +1703,130 @@ pub async fn process_download_request(
         );
     }
 
-    let info =
-        match pre_download_validation(&clean_url, chat_id, message_id, downloader, telegram_api)
-            .await
-        {
-            Ok(info) => info,
-            Err(_) => {
-                storage
-                    .log_request(
-                        chat_id.0,
-                        clean_url_str,
-                        "validation_error",
-                        start.elapsed().as_millis() as i64,
-                    )
-                    .await;
-                return None;
-            }
-        };
-
-    let downloaded = match download_step(
-        &info,
+    progress.set_phase(RequestPhase::FetchingMetadata);
+    let mut info = match pre_download_validation(
         &clean_url,
         chat_id,
         message_id,
         downloader,
         telegram_api,
+        &config.validation,
     )
     .await
     {
-        Ok(media) => media,
-        Err(_) => {
+        Ok(info) => info,
+        Err(status) => {
             storage
                 .log_request(
                     chat_id.0,
                     clean_url_str,
-                    "error",
+                    request_status(telegram_api, chat_id, status),
                     start.elapsed().as_millis() as i64,
+                    None,
                 )
                 .await;
             return None;
         }
     };
 
-    let caption = build_caption(&info, &clean_url);
-    let _cleanup_guard = FileCleanupGuard::from_downloaded_media(&downloaded);
+    *status_message = match downloader.estimate_download_time(&info) {
+        Some(estimate) => send_download_status(estimate, chat_id, message_id, telegram_api).await,
+        None => None,
+    };
+    // Nobody would receive the media.
+    if telegram_api.is_unreachable(chat_id) {
+        storage
+            .log_request(
+                chat_id.0,
+                clean_url_str,
+                "blocked",
+                start.elapsed().as_millis() as i64,
+                None,
+            )
+            .await;
+        return None;
+    }
+    progress.set_phase(RequestPhase::Downloading);
+    let download_result = download_step(
+        &mut info,
+        &clean_url,
+        chat_id,
+        message_id,
+        downloader,
+        telegram_api,
+        &config.post_download_hooks,
+        config.slow_download_warning,
+        status_message,
+    )
+    .await;
 
-    // For a single video item, run upload and audio extraction concurrently.
-    // For groups or photos, just upload normally (no audio extraction).
-    let (file_ids, audio_cache_path, media_duration_secs, has_video, sent_message_id) =
-        match &downloaded {
-            DownloadedMedia::Single(item) if item.media_type == MediaType::Video => {
-                let (send_result, audio_result) = tokio::join!(
-                    send_single_item(item, &caption, chat_id, message_id, telegram_api),
-                    audio_extractor.extract_audio(
+    let (downloaded, cleanup_guard) = match download_result {
+        Ok(downloaded) => downloaded,
+        Err(e) => {
+            report_failure(
+                status_message.take(),
+                chat_id,
+                message_id,
+                e.display_to_user(),
+                "download_error",
+                telegram_api,
+            )
+            .await;
+            storage
+                .log_request(
+                    chat_id.0,
+                    clean_url_str,
+                    request_status(telegram_api, chat_id, "error"),
+                    start.elapsed().as_millis() as i64,
+                    None,
+                )
+                .await;
+            return None;
+        }
+    };
+
+    log::info!("Phase timings for {}: {}", clean_url, info.timings);
+    let caption = build_caption(&info, &clean_url, &config.caption);
+    // The footer is for the owner only, so it is sent but never cached.
+    let sent_caption = if config.timings_footer_chat == Some(chat_id) {
+        append_timings_footer(caption.clone(), &info.timings)
+    } else {
+        caption.clone()
+    };
+    let bytes_transferred = cleanup_guard.total_bytes().await as i64;
+
+    pending_uploads.start(chat_id, message_id);
+    progress.set_phase(RequestPhase::Uploading);
+    let mut complete = true;
+    // For a single video item, run upload and audio extraction concurrently.
+    // For groups or photos, just upload normally (no audio extraction).
+    let (file_ids, audio_cache_path, media_duration_secs, has_video, sent_message_id, sent_ids) =
+        match &downloaded {
+            DownloadedMedia::Single(item) if item.media_type == MediaType::Video => {
+                let (send_result, audio_result) = tokio::join!(
+                    send_single_item(
+                        item,
+                        &sent_caption,
+                        &info,
+                        &clean_url,
+                        chat_id,
+                        message_id,
+                        telegram_api,
+                        config.object_store.as_deref(),
+                        config.local_bot_api,
+                        options.source_as_button,
+                    ),
+                    audio_extractor.extract_audio(
                         &item.filepath,
                         info.title.clone(),
                         info.uploader.clone()
                     )
                 );
                 let (file_ids, sent_msg_id) = match send_result {
-                    Some((file_id, media_type, msg_id)) => {
-                        (Some(vec![(file_id, media_type)]), Some(msg_id))
-                    }
+                    Some((file_id, media_type, msg_id)) => (
+                        Some(file_id.map(|id| vec![(id, media_type)]).unwrap_or_default()),
+                        Some(msg_id),
+                    ),
                     None => (None, None),
                 };
                 let (audio_cache_path, media_duration_secs) = match audio_result {
@@ -631,35 +1842,109 @@ pub async fn process_download_request(
                     media_duration_secs,
                     true,
                     sent_msg_id,
+                    sent_msg_id.into_iter().collect(),
                 )
             }
             DownloadedMedia::Single(item) => {
-                let (file_ids, sent_msg_id) =
-                    match send_single_item(item, &caption, chat_id, message_id, telegram_api).await
-                    {
-                        Some((file_id, media_type, msg_id)) => {
-                            (Some(vec![(file_id, media_type)]), Some(msg_id))
-                        }
-                        None => (None, None),
-                    };
-                (file_ids, None, None, false, sent_msg_id)
+                let (file_ids, sent_msg_id) = match send_single_item(
+                    item,
+                    &sent_caption,
+                    &info,
+                    &clean_url,
+                    chat_id,
+                    message_id,
+                    telegram_api,
+                    config.object_store.as_deref(),
+                    config.local_bot_api,
+                    options.source_as_button,
+                )
+                .await
+                {
+                    Some((file_id, media_type, msg_id)) => (
+                        Some(file_id.map(|id| vec![(id, media_type)]).unwrap_or_default()),
+                        Some(msg_id),
+                    ),
+                    None => (None, None),
+                };
+                (
+                    file_ids,
+                    None,
+                    None,
+                    false,
+                    sent_msg_id,
+                    sent_msg_id.into_iter().collect(),
+                )
             }
             DownloadedMedia::Group(items) => {
-                let file_ids =
-                    send_media_group_step(items, &caption, chat_id, message_id, telegram_api)
-                        .await
-                        .map(|sent| {
-                            sent.into_iter()
-                                .map(|s| (s.file_id, s.media_type))
-                                .collect()
-                        });
-                (file_ids, None, None, false, None)
+                let sent = send_media_group_step(
+                    items,
+                    &sent_caption,
+                    &info,
+                    &clean_url,
+                    chat_id,
+                    message_id,
+                    telegram_api,
+                    config.object_store.as_deref(),
+                    config.local_bot_api,
+                )
+                .await;
+                let sent_ids: Vec<MessageId> = sent
+                    .iter()
+                    .flat_map(|group| &group.sent)
+                    .map(|s| s.message_id)
+                    .collect();
+                let entry_count = info.entries.as_ref().map_or(0, Vec::len);
+                let missing = missing_playlist_positions(entry_count, items);
+                // A cache hit would replay the gaps without telling anyone.
+                complete =
+                    sent.as_ref().is_some_and(|group| !group.incomplete) && missing.is_empty();
+                let file_ids = sent.map(|group| {
+                    group
+                        .sent
+                        .into_iter()
+                        .map(|s| (s.file_id, s.media_type))
+                        .collect()
+                });
+                if file_ids.is_some() && !missing.is_empty() {
+                    log::warn!(
+                        "{} of {} playlist items missing for {}: {:?}",
+                        missing.len(),
+                        entry_count,
+                        clean_url,
+                        missing
+                    );
+                    log_reply_failure(
+                        telegram_api
+                            .send_text_message(
+                                chat_id,
+                                message_id,
+                                &format_missing_items_notice(&missing, entry_count),
+                            )
+                            .await,
+                        chat_id,
+                        "missing_items_notice",
+                    )
+                    .await;
+                }
+                (file_ids, None, None, false, None, sent_ids)
             }
         };
+    if let Some(elapsed) = pending_uploads.finish(chat_id, message_id) {
+        log::info!("Upload to chat {} took {:?}", chat_id, elapsed);
+    }
+    finish_download_status(status_message.take(), telegram_api).await;
+
+    if options.original_quality && file_ids.is_some() {
+        let items: Vec<&DownloadedItem> = match &downloaded {
+            DownloadedMedia::Single(item) => vec![item],
+            DownloadedMedia::Group(items) => items.iter().collect(),
+        };
+        send_original_documents(&items, chat_id, message_id, telegram_api).await;
+    }
 
     let elapsed_ms = start.elapsed().as_millis() as i64;
 
-    if let Some(files) = &file_ids {
+    let outcome = if let Some(files) = &file_ids {
         if has_video && audio_cache_path.is_none() {
             log_reply_failure(
                 telegram_api.send_text_message(
@@ -673,34 +1958,59 @@ pub async fn process_download_request(
             )
             .await;
         }
+        // Videos sent as documents have no reusable file_id, so they are never cached,
+        // and neither are albums with items missing.
+        if !files.is_empty() && complete {
+            storage
+                .store_cached_media(
+                    config.bot_id,
+                    clean_url_str,
+                    &caption,
+                    files,
+                    audio_cache_path
+                        .as_deref()
+                        .and_then(|p| p.to_str())
+                        .map(String::from),
+                    media_duration_secs,
+                    &CacheMetadata::from(&info),
+                )
+                .await;
+        }
+        record_delivery(storage, chat_id, &sent_ids, clean_url_str, None).await;
         storage
-            .store_cached_media(
+            .log_request(
+                chat_id.0,
                 clean_url_str,
-                &caption,
-                files,
-                audio_cache_path
-                    .as_deref()
-                    .and_then(|p| p.to_str())
-                    .map(String::from),
-                media_duration_secs,
+                "success",
+                elapsed_ms,
+                Some(bytes_transferred),
             )
             .await;
-        storage
-            .log_request(chat_id.0, clean_url_str, "success", elapsed_ms)
-            .await;
         Some(DownloadContext {
             source_url: clean_url,
             has_video,
             media_duration_secs,
             audio_cache_path,
             sent_message_id,
+            // Videos sent as documents, without a file id, keep the link in the caption.
+            source_as_button: options.source_as_button && !files.is_empty(),
+            delivered_message_id: sent_ids.first().copied(),
         })
     } else {
         storage
-            .log_request(chat_id.0, clean_url_str, "error", elapsed_ms)
+            .log_request(
+                chat_id.0,
+                clean_url_str,
+                request_status(telegram_api, chat_id, "error"),
+                elapsed_ms,
+                Some(bytes_transferred),
+            )
             .await;
         None
-    }
+    };
+
+    cleanup_guard.cleanup().await;
+    outcome
 }
 
 /// Split long text into multiple messages (Telegram max ~4000 chars per message).
@@ -771,7 +2081,7 @@ pub async fn maybe_send_premium_buttons(
         return;
     }
 
-    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+    let mut rows = vec![vec![
         teloxide::types::InlineKeyboardButton::callback(
             "Extract Audio",
             format!("audio:{}", context_id),
@@ -781,7 +2091,12 @@ pub async fn maybe_send_premium_buttons(
             format!("txn:{}", context_id),
         ),
         teloxide::types::InlineKeyboardButton::callback("Summarize", format!("sum:{}", context_id)),
-    ]]);
+    ]];
+    // Editing the markup replaces the source button the video was sent with.
+    if ctx.source_as_button {
+        rows.push(source_button_row(&ctx.source_url));
+    }
+    let keyboard = InlineKeyboardMarkup::new(rows);
 
     if let Err(e) = api
         .edit_message_reply_markup(chat_id, sent_msg_id, keyboard)
@@ -795,12 +2110,13 @@ pub async fn maybe_send_premium_buttons(
 mod tests {
     use super::*;
     use crate::downloader::{DownloadError, MockDownloader};
+    use crate::object_store::{DEFAULT_LINK_EXPIRY, MockObjectStore, SignedLink};
     use crate::premium::audio_extractor::{AudioExtractionResult, MockAudioExtractor};
     use crate::storage::MockStorage;
     use crate::telegram_api::{MockTelegramApi, SentMedia};
-    use crate::test_utils::create_test_info;
+    use crate::test_utils::{TestDownloader, create_test_info};
     use mockall::predicate::*;
-    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use teloxide::types::InputMedia;
     use teloxide::types::{ChatId, MessageId};
     use url::Url;
@@ -808,11 +2124,18 @@ mod tests {
     /// Helper to create a MockStorage that returns no cache and expects log_request.
     fn create_default_mock_storage() -> MockStorage {
         let mut mock_storage = MockStorage::new();
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
         mock_storage
             .expect_store_cached_media()
-            .returning(|_, _, _, _, _: Option<i32>| ());
-        mock_storage.expect_log_request().returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _, _: Option<i32>, _| ());
+        mock_storage
+            .expect_log_request()
+            .returning(|_, _, _, _, _| ());
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
         mock_storage
     }
 
@@ -832,6 +2155,9 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_sends_video_on_success() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mock_storage = create_default_mock_storage();
         let test_url = Url::parse("https://instagram.com/p/valid_post").unwrap();
@@ -840,7 +2166,14 @@ mod tests {
             .expect_get_media_metadata()
             .with(eq(test_url.clone()))
             .times(1)
-            .returning(|_| Ok(create_test_info()));
+            .returning(|_| {
+                Ok(MediaInfo {
+                    duration: Some(12.6),
+                    width: Some(1080),
+                    height: Some(1920),
+                    ..create_test_info()
+                })
+            });
 
         mock_downloader
             .expect_download_media()
@@ -853,6 +2186,9 @@ mod tests {
                     filepath: PathBuf::from("/tmp/video.mp4"),
                     media_type: MediaType::Video,
                     thumbnail_filepath: Some(PathBuf::from("thumb.jpg")),
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
                 }))
             });
 
@@ -861,12 +2197,18 @@ mod tests {
             .with(
                 eq(ChatId(123)),
                 eq(MessageId(456)),
-                eq(Path::new("/tmp/video.mp4")),
+                eq(MediaSource::Path("/tmp/video.mp4".into())),
                 always(),
                 eq(Some(PathBuf::from("thumb.jpg"))),
+                eq(Some(13)),
+                eq(Some(1080)),
+                eq(Some(1920)),
+                eq(None),
             )
             .times(1)
-            .returning(|_, _, _, _, _| Ok(("file_id_video_123".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _, _, _, _, _| {
+                Ok(("file_id_video_123".to_string(), MessageId(0)))
+            });
 
         mock_telegram_api
             .expect_send_text_message()
@@ -880,6 +2222,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -887,6 +2233,9 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_sends_video_without_thumbnail_when_unavailable() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mock_storage = create_default_mock_storage();
         let test_url = Url::parse("https://instagram.com/p/valid_post_no_thumb").unwrap();
@@ -906,6 +2255,9 @@ mod tests {
                     filepath: PathBuf::from("/tmp/video.mp4"),
                     media_type: MediaType::Video,
                     thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
                 }))
             });
 
@@ -914,12 +2266,18 @@ mod tests {
             .with(
                 eq(ChatId(123)),
                 eq(MessageId(456)),
-                eq(Path::new("/tmp/video.mp4")),
+                eq(MediaSource::Path("/tmp/video.mp4".into())),
                 always(),
                 eq(None::<PathBuf>),
+                always(),
+                always(),
+                always(),
+                eq(None),
             )
             .times(1)
-            .returning(|_, _, _, _, _| Ok(("file_id_video_456".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _, _, _, _, _| {
+                Ok(("file_id_video_456".to_string(), MessageId(0)))
+            });
 
         mock_telegram_api
             .expect_send_text_message()
@@ -933,13 +2291,367 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    /// Runs a video download whose files exist on disk, with `send_video` returning
+    /// `send_result`, and returns the paths so the test can check they were removed.
+    async fn run_download_with_real_files(
+        send_result: fn() -> Result<(String, MessageId), teloxide::RequestError>,
+    ) -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let video = temp_dir.path().join("uuid.123.mp4");
+        let thumbnail = temp_dir.path().join("uuid.123.thumb.jpg");
+        std::fs::write(&video, b"video").unwrap();
+        std::fs::write(&thumbnail, b"thumb").unwrap();
+
+        let downloader = TestDownloader::success(create_test_info())
+            .with_video(video.clone(), Some(thumbnail.clone()));
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_video()
+            .times(1)
+            .returning(move |_, _, _, _, _, _, _, _, _| send_result());
+        mock_telegram_api
+            .expect_send_text_message()
+            .returning(|_, _, _| Ok(()));
+
+        process_download_request(
+            &Url::parse("https://instagram.com/p/cleanup").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &downloader,
+            &mock_telegram_api,
+            &create_default_mock_storage(),
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        (temp_dir, video, thumbnail)
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_guard_totals_file_sizes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, len: usize| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, vec![0u8; len]).unwrap();
+            path
+        };
+        let item = |filepath: PathBuf, thumbnail_filepath: Option<PathBuf>| DownloadedItem {
+            filepath,
+            media_type: MediaType::Video,
+            thumbnail_filepath,
+            title: None,
+            playlist_index: None,
+            info_json_filepath: None,
+        };
+
+        let single = DownloadedMedia::Single(item(
+            file("uuid.1.mp4", 1_000),
+            Some(file("uuid.1.jpg", 200)),
+        ));
+        let guard = FileCleanupGuard::from_downloaded_media(&single);
+        assert_eq!(guard.total_bytes().await, 1_200);
+        guard.cleanup().await;
+
+        let group = DownloadedMedia::Group(vec![
+            item(file("uuid.2.mp4", 300), None),
+            item(file("uuid.3.jpg", 40), None),
+            item(temp_dir.path().join("uuid.4.missing"), None),
+        ]);
+        let guard = FileCleanupGuard::from_downloaded_media(&group);
+        assert_eq!(guard.total_bytes().await, 340);
+        guard.cleanup().await;
+
+        let info_json = file("uuid.5.info.json", 10);
+        let with_info_json = DownloadedMedia::Group(vec![DownloadedItem {
+            info_json_filepath: Some(info_json.clone()),
+            ..item(file("uuid.5.mp4", 100), None)
+        }]);
+        FileCleanupGuard::from_downloaded_media(&with_info_json)
+            .cleanup()
+            .await;
+        assert!(!info_json.exists());
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_removes_files_before_returning() {
+        let (_dir, video, thumbnail) =
+            run_download_with_real_files(|| Ok(("file_id".to_string(), MessageId(1)))).await;
+        assert!(!video.exists());
+        assert!(!thumbnail.exists());
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_removes_files_after_send_failure() {
+        let (_dir, video, thumbnail) = run_download_with_real_files(|| {
+            Err(teloxide::RequestError::Api(teloxide::ApiError::Unknown(
+                "Bad Request".into(),
+            )))
+        })
+        .await;
+        assert!(!video.exists());
+        assert!(!thumbnail.exists());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_for_same_url_download_once() {
+        let downloader = TestDownloader::success(create_test_info())
+            .with_video(PathBuf::from("/tmp/coalesced.mp4"), None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_video()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| {
+                Ok(("file_id_coalesced".to_string(), MessageId(1)))
+            });
+        mock_telegram_api
+            .expect_send_video()
+            .withf(|chat_id, _, media, _, _, _, _, _, _| {
+                *chat_id == ChatId(2)
+                    && *media == MediaSource::FileId("file_id_coalesced".to_string())
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| {
+                Ok(("file_id_coalesced".to_string(), MessageId(2)))
+            });
+        mock_telegram_api
+            .expect_send_text_message()
+            .returning(|_, _, _| Ok(()));
+        let storage = crate::memory_storage::MemoryStorage::new();
+        let audio_extractor = create_failing_audio_extractor();
+        let config = PipelineConfig::default();
+        let uploads = PendingUploads::default();
+        let url = Url::parse("https://instagram.com/p/coalesced").unwrap();
+        let progress = RequestProgress::default();
+        let request = |chat_id| {
+            process_download_request(
+                &url,
+                ChatId(chat_id),
+                MessageId(10),
+                &downloader,
+                &mock_telegram_api,
+                &storage,
+                &audio_extractor,
+                &config,
+                &uploads,
+                DownloadOptions::default(),
+                &progress,
+            )
+        };
+
+        tokio::join!(request(1), request(2));
+        assert_eq!(downloader.metadata_lookups(), 1);
+        assert_eq!(downloader.downloads(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_reports_panics_to_user() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        mock_downloader
+            .expect_get_media_metadata()
+            .returning(|_| Ok(create_test_info()));
+        mock_downloader
+            .expect_download_media()
+            .returning(|_, _| panic!("entries unexpectedly missing"));
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|chat_id, message_id, text| {
+                *chat_id == ChatId(123)
+                    && *message_id == MessageId(456)
+                    && text.starts_with("Sorry, something went wrong on my side.")
+                    && text.contains("(error id: ")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "error")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+
+        let result = process_download_request(
+            &Url::parse("https://instagram.com/p/panics").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        assert!(result.is_none());
+    }
+
+    /// Downloader whose metadata lookup never completes, recording when it is dropped.
+    struct StalledDownloader {
+        cancelled: Arc<AtomicBool>,
+    }
+
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Downloader for StalledDownloader {
+        async fn get_media_metadata(&self, _url: &Url) -> Result<MediaInfo, DownloadError> {
+            let _cancelled = SetOnDrop(self.cancelled.clone());
+            std::future::pending().await
+        }
+
+        async fn download_media(
+            &self,
+            _info: &mut MediaInfo,
+            _url: &Url,
+        ) -> Result<DownloadedMedia, DownloadError> {
+            unreachable!("metadata never completes")
+        }
+
+        fn estimate_download_time(&self, _info: &MediaInfo) -> Option<Duration> {
+            None
+        }
+
+        async fn list_formats(&self, _url: &Url) -> Result<String, DownloadError> {
+            unreachable!("not used by the pipeline")
+        }
+
+        async fn download_thumbnail(
+            &self,
+            _info: &MediaInfo,
+            _url: &Url,
+        ) -> Result<PathBuf, DownloadError> {
+            unreachable!("not used by the pipeline")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_times_out() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let downloader = StalledDownloader {
+            cancelled: cancelled.clone(),
+        };
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == "Sorry, the request timed out. Please try again.")
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "timeout")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+        let config = PipelineConfig {
+            request_timeout: Duration::from_millis(50),
+            ..PipelineConfig::default()
+        };
+
+        let result = process_download_request(
+            &Url::parse("https://instagram.com/p/stalled").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &config,
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        assert!(result.is_none());
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    /// A post-download hook that never finishes.
+    #[derive(Debug)]
+    struct StalledHook;
+
+    #[async_trait::async_trait]
+    impl PostDownloadHook for StalledHook {
+        async fn apply(&self, _item: &mut DownloadedItem) -> Result<(), DownloadError> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_files_are_removed_when_a_hook_times_out() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let video = temp_dir.path().join("uuid.123.mp4");
+        std::fs::write(&video, b"video").unwrap();
+        let downloader =
+            TestDownloader::success(create_test_info()).with_video(video.clone(), None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .returning(|_, _, _| Ok(()));
+        let config = PipelineConfig {
+            request_timeout: Duration::from_millis(50),
+            post_download_hooks: vec![Arc::new(StalledHook)],
+            ..PipelineConfig::default()
+        };
+
+        process_download_request(
+            &Url::parse("https://instagram.com/p/stalled_hook").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &downloader,
+            &mock_telegram_api,
+            &create_default_mock_storage(),
+            &create_failing_audio_extractor(),
+            &config,
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
+
+        // The guard removes the files in a background task once the request is dropped.
+        for _ in 0..100 {
+            if !video.exists() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{} was not removed", video.display());
     }
 
     #[tokio::test]
     async fn test_process_download_request_sends_photo_on_success() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mock_storage = create_default_mock_storage();
         let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
@@ -959,6 +2671,9 @@ mod tests {
                     filepath: PathBuf::from("/tmp/photo.jpg"),
                     media_type: MediaType::Photo,
                     thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
                 }))
             });
 
@@ -967,11 +2682,12 @@ mod tests {
             .with(
                 eq(ChatId(123)),
                 eq(MessageId(456)),
-                eq(Path::new("/tmp/photo.jpg")),
+                eq(MediaSource::Path("/tmp/photo.jpg".into())),
                 always(),
+                eq(None),
             )
             .times(1)
-            .returning(|_, _, _, _| Ok(("file_id_photo_123".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _| Ok(("file_id_photo_123".to_string(), MessageId(0))));
 
         process_download_request(
             &test_url,
@@ -981,19 +2697,936 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
 
-    #[tokio::test]
-    async fn test_process_download_request_sends_media_group_on_multiple_items() {
-        let mut mock_downloader = MockDownloader::new();
-        let mut mock_telegram_api = MockTelegramApi::new();
-        let mock_storage = create_default_mock_storage();
-        let test_url = Url::parse("https://instagram.com/p/multiple_media").unwrap();
+    /// Advance the clock past `warn_after` while a download runs that only finishes once
+    /// `shown` is notified, i.e. once the slow-download status was shown.
+    async fn run_slow_download(
+        warn_after: Duration,
+        shown: &tokio::sync::Notify,
+        status_message: &mut Option<StatusMessage>,
+        telegram_api: &dyn TelegramApi,
+    ) -> &'static str {
+        let download = with_slow_download_warning(
+            async {
+                shown.notified().await;
+                "done"
+            },
+            Some(warn_after),
+            status_message,
+            ChatId(1),
+            MessageId(2),
+            telegram_api,
+        );
+        let (result, ()) = tokio::join!(download, tokio::time::advance(warn_after));
+        result
+    }
 
-        let mut pre_download_info = create_test_info();
-        pre_download_info.entries = Some(vec![create_test_info(), create_test_info()]);
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_download_sends_status_and_keeps_waiting() {
+        let shown = Arc::new(tokio::sync::Notify::new());
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let notify = shown.clone();
+        mock_telegram_api
+            .expect_send_status_message()
+            .with(eq(ChatId(1)), eq(MessageId(2)), eq(SLOW_DOWNLOAD_STATUS))
+            .times(1)
+            .returning(move |chat_id, _, _| {
+                notify.notify_one();
+                Ok(StatusMessage {
+                    chat_id,
+                    message_id: MessageId(9),
+                })
+            });
+        let mut status_message = None;
+
+        let result = run_slow_download(
+            Duration::from_secs(10),
+            &shown,
+            &mut status_message,
+            &mock_telegram_api,
+        )
+        .await;
+
+        assert_eq!(result, "done");
+        assert_eq!(
+            status_message,
+            Some(StatusMessage {
+                chat_id: ChatId(1),
+                message_id: MessageId(9)
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_download_edits_existing_status_only_when_slow() {
+        let shown = Arc::new(tokio::sync::Notify::new());
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let notify = shown.clone();
+        mock_telegram_api
+            .expect_edit_message_text()
+            .with(eq(ChatId(1)), eq(MessageId(9)), eq(SLOW_DOWNLOAD_STATUS))
+            .times(1)
+            .returning(move |_, _, _| {
+                notify.notify_one();
+                Ok(())
+            });
+        mock_telegram_api.expect_send_status_message().never();
+        let status = StatusMessage {
+            chat_id: ChatId(1),
+            message_id: MessageId(9),
+        };
+        let mut status_message = Some(status);
+
+        let fast = with_slow_download_warning(
+            async { "done" },
+            Some(Duration::from_secs(10)),
+            &mut status_message,
+            ChatId(1),
+            MessageId(2),
+            &mock_telegram_api,
+        )
+        .await;
+        let slow = run_slow_download(
+            Duration::from_secs(10),
+            &shown,
+            &mut status_message,
+            &mock_telegram_api,
+        )
+        .await;
+
+        assert_eq!((fast, slow), ("done", "done"));
+        assert_eq!(status_message, Some(status));
+    }
+
+    #[tokio::test]
+    async fn test_status_message_is_finished_in_the_chat_it_ended_up_in() {
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_delete_message()
+            .with(eq(ChatId(-1001)), eq(MessageId(9)))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let status = StatusMessage {
+            chat_id: ChatId(-1001),
+            message_id: MessageId(9),
+        };
+
+        finish_download_status(Some(status), &mock_telegram_api).await;
+    }
+
+    #[tokio::test]
+    async fn test_download_estimate_shows_and_removes_status_message() {
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
+        expect_single_photo_download(&mut mock_downloader);
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| Some(Duration::from_millis(14_200)));
+
+        let mut seq = mockall::Sequence::new();
+        mock_telegram_api
+            .expect_send_status_message()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq("⬇️ Downloading… (est. 15 s)"),
+            )
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|chat_id, _, _| {
+                Ok(StatusMessage {
+                    chat_id,
+                    message_id: MessageId(900),
+                })
+            });
+        mock_telegram_api
+            .expect_send_photo()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _, _| Ok(("file_id_photo_123".to_string(), MessageId(0))));
+        mock_telegram_api
+            .expect_delete_message()
+            .with(eq(ChatId(123)), eq(MessageId(900)))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(()));
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    /// A downloader whose download takes three seconds by its estimate, so the pipeline
+    /// shows a status message with id 900.
+    fn expect_status_message(
+        mock_downloader: &mut MockDownloader,
+        mock_telegram_api: &mut MockTelegramApi,
+    ) {
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| Some(Duration::from_secs(3)));
+        mock_telegram_api
+            .expect_send_status_message()
+            .times(1)
+            .returning(|chat_id, _, _| {
+                Ok(StatusMessage {
+                    chat_id,
+                    message_id: MessageId(900),
+                })
+            });
+    }
+
+    #[tokio::test]
+    async fn test_failed_download_is_reported_in_status_message() {
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        expect_status_message(&mut mock_downloader, &mut mock_telegram_api);
+        mock_downloader
+            .expect_get_media_metadata()
+            .returning(|_| Ok(create_test_info()));
+        mock_downloader
+            .expect_download_media()
+            .returning(|_, _| Err(DownloadError::GeoBlocked("blocked".to_string())));
+        mock_telegram_api
+            .expect_edit_message_text()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(900)),
+                eq(DownloadError::GeoBlocked(String::new()).display_to_user()),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api.expect_send_text_message().never();
+
+        process_download_request(
+            &Url::parse("https://instagram.com/p/geo").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &create_default_mock_storage(),
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_request_is_reported_in_status_message() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let video = media_file(&temp_dir, "uuid.123.mp4");
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        expect_status_message(&mut mock_downloader, &mut mock_telegram_api);
+        mock_downloader
+            .expect_get_media_metadata()
+            .returning(|_| Ok(create_test_info()));
+        mock_downloader
+            .expect_download_media()
+            .returning(move |_, _| {
+                Ok(DownloadedMedia::Single(DownloadedItem {
+                    filepath: video.clone(),
+                    media_type: MediaType::Video,
+                    thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
+                }))
+            });
+        mock_telegram_api
+            .expect_edit_message_text()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(900)),
+                eq("Sorry, the request timed out. Please try again."),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api.expect_send_text_message().never();
+        let config = PipelineConfig {
+            request_timeout: Duration::from_millis(50),
+            post_download_hooks: vec![Arc::new(StalledHook)],
+            ..PipelineConfig::default()
+        };
+
+        process_download_request(
+            &Url::parse("https://instagram.com/p/stalled_hook").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &create_default_mock_storage(),
+            &create_failing_audio_extractor(),
+            &config,
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_replies_once_and_removes_status_message() {
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
+        expect_single_photo_download(&mut mock_downloader);
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| Some(Duration::from_secs(3)));
+
+        let mut seq = mockall::Sequence::new();
+        mock_telegram_api
+            .expect_send_status_message()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|chat_id, _, _| {
+                Ok(StatusMessage {
+                    chat_id,
+                    message_id: MessageId(900),
+                })
+            });
+        mock_telegram_api
+            .expect_send_photo()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _, _| {
+                Err(teloxide::RequestError::Api(teloxide::ApiError::Unknown(
+                    "Bad Request".into(),
+                )))
+            });
+        mock_telegram_api
+            .expect_send_text_message()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_delete_message()
+            .with(eq(ChatId(123)), eq(MessageId(900)))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(()));
+        mock_telegram_api.expect_edit_message_text().never();
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_blocked_bot_sends_nothing_after_failed_media() {
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
+        expect_single_photo_download(&mut mock_downloader);
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| Some(Duration::from_secs(3)));
+
+        mock_telegram_api
+            .expect_send_status_message()
+            .times(1)
+            .returning(|chat_id, _, _| {
+                Ok(StatusMessage {
+                    chat_id,
+                    message_id: MessageId(900),
+                })
+            });
+        mock_telegram_api
+            .expect_send_photo()
+            .times(1)
+            .returning(|_, _, _, _, _| {
+                Err(teloxide::RequestError::Api(teloxide::ApiError::BotBlocked))
+            });
+        mock_telegram_api.expect_send_text_message().never();
+        mock_telegram_api.expect_edit_message_text().never();
+        mock_telegram_api.expect_delete_message().never();
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_storage.expect_store_cached_media().never();
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "blocked")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+
+        let ctx = process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        assert!(ctx.is_none());
+    }
+
+    /// Expect the download of a 60 MiB video, written sparse to `dir`. yt-dlp gives no
+    /// size estimate, so only the file on disk tells it is large.
+    fn expect_large_video_download(
+        mock_downloader: &mut MockDownloader,
+        dir: &std::path::Path,
+    ) -> PathBuf {
+        let filepath = dir.join("large.mp4");
+        std::fs::File::create(&filepath)
+            .unwrap()
+            .set_len(60 * 1024 * 1024)
+            .unwrap();
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(|_| {
+                Ok(MediaInfo {
+                    filesize: None,
+                    ..create_test_info()
+                })
+            });
+        let downloaded = filepath.clone();
+        mock_downloader
+            .expect_download_media()
+            .times(1)
+            .returning(move |_, _| {
+                Ok(DownloadedMedia::Single(DownloadedItem {
+                    filepath: downloaded.clone(),
+                    media_type: MediaType::Video,
+                    thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
+                }))
+            });
+        filepath
+    }
+
+    #[tokio::test]
+    async fn test_large_video_is_sent_as_document_and_not_cached() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
+        let test_url = Url::parse("https://youtube.com/watch?v=large").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = expect_large_video_download(&mut mock_downloader, dir.path());
+
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_storage.expect_store_cached_media().times(0);
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "success")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+
+        let mut seq = mockall::Sequence::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.contains("sent as a document"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_send_document()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq(MediaSource::Path(filepath)),
+                always(),
+            )
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| Ok(MessageId(789)));
+        mock_telegram_api.expect_send_video().times(0);
+        mock_telegram_api
+            .expect_send_text_message()
+            .returning(|_, _, _| Ok(()));
+
+        let ctx = process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await
+        .expect("large video should still produce a download context");
+        assert_eq!(ctx.sent_message_id, Some(MessageId(789)));
+    }
+
+    #[tokio::test]
+    async fn test_large_video_document_too_large_points_to_source() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://youtube.com/watch?v=large").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        expect_large_video_download(&mut mock_downloader, dir.path());
+
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.contains("sent as a document"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_send_document()
+            .times(1)
+            .returning(|_, _, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::RequestEntityTooLarge,
+                ))
+            });
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| {
+                text.contains("download it directly")
+                    && text.contains("https://youtube.com/watch?v=large")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let ctx = process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        assert!(ctx.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_large_video_document_too_large_is_offloaded_to_object_store() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://youtube.com/watch?v=large").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        expect_large_video_download(&mut mock_downloader, dir.path());
+
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.contains("sent as a document"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_send_document()
+            .times(1)
+            .returning(|_, _, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::RequestEntityTooLarge,
+                ))
+            });
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| {
+                text.contains("https://bucket.example/offloaded/video.mp4?X-Amz-Signature=abc")
+                    && text.contains("(62.9 MB)")
+                    && text.contains("expires in 24 hours")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let mut mock_object_store = MockObjectStore::new();
+        mock_object_store.expect_upload().times(1).returning(|_| {
+            Ok(SignedLink {
+                url: Url::parse("https://bucket.example/offloaded/video.mp4?X-Amz-Signature=abc")
+                    .unwrap(),
+                expires_in: DEFAULT_LINK_EXPIRY,
+            })
+        });
+        let config = PipelineConfig {
+            object_store: Some(Arc::new(mock_object_store)),
+            ..PipelineConfig::default()
+        };
+
+        let ctx = process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &config,
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        assert!(ctx.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_large_video_is_sent_as_video_through_local_bot_api() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://youtube.com/watch?v=large").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        expect_large_video_download(&mut mock_downloader, dir.path());
+
+        mock_telegram_api.expect_send_document().times(0);
+        mock_telegram_api
+            .expect_send_video()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| {
+                Ok(("file_id_large".to_string(), MessageId(789)))
+            });
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| !text.contains("sent as a document"))
+            .returning(|_, _, _| Ok(()));
+        let config = PipelineConfig {
+            local_bot_api: true,
+            ..PipelineConfig::default()
+        };
+
+        let ctx = process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &config,
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(ctx.sent_message_id, Some(MessageId(789)));
+    }
+
+    fn expect_single_photo_download(mock_downloader: &mut MockDownloader) {
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(|_| Ok(create_test_info()));
+        mock_downloader
+            .expect_download_media()
+            .times(1)
+            .returning(|_, _| {
+                Ok(DownloadedMedia::Single(DownloadedItem {
+                    filepath: PathBuf::from("/tmp/photo.jpg"),
+                    media_type: MediaType::Photo,
+                    thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
+                }))
+            });
+    }
+
+    #[tokio::test]
+    async fn test_timings_footer_is_sent_but_not_cached() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        expect_single_photo_download(&mut mock_downloader);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_photo()
+            .withf(|_, _, _, caption, _| caption.contains("⏱"))
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(("file_id_photo".to_string(), MessageId(1))));
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_storage
+            .expect_store_cached_media()
+            .withf(|_, _, caption, _, _, _, _| !caption.contains("⏱"))
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| ());
+        mock_storage
+            .expect_log_request()
+            .returning(|_, _, _, _, _| ());
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
+        let config = PipelineConfig {
+            timings_footer_chat: Some(ChatId(123)),
+            ..PipelineConfig::default()
+        };
+
+        process_download_request(
+            &Url::parse("https://instagram.com/p/valid_photo").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &config,
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_original_quality_sends_photo_and_document() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
+        expect_single_photo_download(&mut mock_downloader);
+
+        mock_telegram_api
+            .expect_send_photo()
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(("file_id_photo_123".to_string(), MessageId(0))));
+        mock_telegram_api
+            .expect_send_document()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq(MediaSource::Path("/tmp/photo.jpg".into())),
+                eq("Original quality"),
+            )
+            .times(1)
+            .returning(|_, _, _, _| Ok(MessageId(0)));
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions {
+                original_quality: true,
+                ..DownloadOptions::default()
+            },
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_without_original_quality_skips_document() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
+        expect_single_photo_download(&mut mock_downloader);
+
+        mock_telegram_api
+            .expect_send_photo()
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(("file_id_photo_123".to_string(), MessageId(0))));
+        mock_telegram_api.expect_send_document().times(0);
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_source_as_button_moves_link_from_caption_to_button() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/valid_photo").unwrap();
+        expect_single_photo_download(&mut mock_downloader);
+
+        let expected_keyboard = source_keyboard(&test_url);
+        mock_telegram_api
+            .expect_send_photo()
+            .withf(move |_, _, _, caption, markup| {
+                !caption.contains(">Source</a>") && markup.as_ref() == Some(&expected_keyboard)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(("file_id_photo_123".to_string(), MessageId(0))));
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions {
+                source_as_button: true,
+                ..DownloadOptions::default()
+            },
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[test]
+    fn test_url_request_parses_original_quality_suffix() {
+        let request = UrlRequest::parse("https://instagram.com/p/abc !hq").unwrap();
+        assert_eq!(request.url.as_str(), "https://instagram.com/p/abc");
+        assert!(request.options.original_quality);
+
+        let request = UrlRequest::parse("https://instagram.com/p/abc").unwrap();
+        assert!(!request.options.original_quality);
+
+        assert!(UrlRequest::parse("not a url !hq").is_none());
+    }
+
+    #[test]
+    fn test_parse_link_accepts_schemeless_urls() {
+        let cases = [
+            (
+                "www.tiktok.com/@user/video/123",
+                "https://www.tiktok.com/@user/video/123",
+            ),
+            (
+                "instagram.com/reel/abc?igsh=x",
+                "https://instagram.com/reel/abc?igsh=x",
+            ),
+            ("youtu.be/dQw4w9WgXcQ", "https://youtu.be/dQw4w9WgXcQ"),
+            ("x.com/user/status/1", "https://x.com/user/status/1"),
+            ("https://example.com/video", "https://example.com/video"),
+        ];
+        for (input, expected) in cases {
+            let url = parse_link(input).unwrap_or_else(|| panic!("rejected {input}"));
+            assert_eq!(url.as_str(), expected, "input {input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_link_rejects_non_links() {
+        let cases = [
+            "hello",
+            "user@example.com",
+            "user@example.com/path",
+            "node.js/18",
+            "python.org/v3.12",
+            "example.com",
+            "localhost/video",
+            "www.example.c0m/path",
+            "look at example.com/video",
+        ];
+        for input in cases {
+            assert!(parse_link(input).is_none(), "accepted {input}");
+        }
+    }
+
+    #[test]
+    fn test_url_request_parse_schemeless_with_flag() {
+        let request = UrlRequest::parse("instagram.com/p/abc !hq").unwrap();
+        assert_eq!(request.url.as_str(), "https://instagram.com/p/abc");
+        assert!(request.options.original_quality);
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_sends_media_group_on_multiple_items() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/multiple_media").unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let item1 = media_file(&temp_dir, "item1.mp4");
+        let item2 = media_file(&temp_dir, "item2.jpg");
+
+        let mut pre_download_info = create_test_info();
+        pre_download_info.entries = Some(vec![create_test_info(), create_test_info()]);
 
         let info_for_get = pre_download_info.clone();
         mock_downloader
@@ -1006,41 +3639,629 @@ mod tests {
             .expect_download_media()
             .withf(|info, _url| info.entries.is_some())
             .times(1)
-            .returning(|_, _| {
+            .returning(move |_, _| {
                 Ok(DownloadedMedia::Group(vec![
                     DownloadedItem {
-                        filepath: PathBuf::from("/tmp/item1.mp4"),
+                        filepath: item1.clone(),
                         media_type: MediaType::Video,
                         thumbnail_filepath: None,
+                        title: None,
+                        playlist_index: Some(1),
+                        info_json_filepath: None,
                     },
                     DownloadedItem {
-                        filepath: PathBuf::from("/tmp/item2.jpg"),
+                        filepath: item2.clone(),
                         media_type: MediaType::Photo,
                         thumbnail_filepath: None,
+                        title: None,
+                        playlist_index: Some(2),
+                        info_json_filepath: None,
                     },
                 ]))
             });
 
         mock_telegram_api
             .expect_send_media_group()
-            .withf(|_, _, media_vec: &Vec<InputMedia>| {
-                media_vec.len() == 2
-                    && matches!(&media_vec[0], InputMedia::Video(v) if v.caption.as_ref().is_some_and(|c| !c.is_empty()))
-                    && matches!(&media_vec[1], InputMedia::Photo(p) if p.caption.as_ref().is_some_and(|c| c.is_empty()))
-            })
+            .withf(|_, _, media_vec: &Vec<InputMedia>| {
+                media_vec.len() == 2
+                    && matches!(&media_vec[0], InputMedia::Video(v) if v.caption.as_ref().is_some_and(|c| !c.is_empty()))
+                    && matches!(&media_vec[1], InputMedia::Photo(p) if p.caption.as_ref().is_some_and(|c| c.is_empty()))
+            })
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(vec![
+                    SentMedia {
+                        file_id: "file_id_group_1".to_string(),
+                        media_type: MediaType::Video,
+                        message_id: MessageId(1),
+                    },
+                    SentMedia {
+                        file_id: "file_id_group_2".to_string(),
+                        media_type: MediaType::Photo,
+                        message_id: MessageId(2),
+                    },
+                ])
+            });
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_album_with_dropped_item_is_not_cached() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_storage.expect_store_cached_media().never();
+        mock_storage
+            .expect_log_request()
+            .returning(|_, _, _, _, _| ());
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
+        let test_url = Url::parse("https://instagram.com/p/partial_album").unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let item1 = media_file(&temp_dir, "item1.mp4");
+        let item2 = media_file(&temp_dir, "item2.mp4");
+        let empty = temp_dir.path().join("item3.mp4");
+        std::fs::write(&empty, b"").unwrap();
+
+        let mut info = create_test_info();
+        info.entries = Some(vec![
+            create_test_info(),
+            create_test_info(),
+            create_test_info(),
+        ]);
+        mock_downloader
+            .expect_get_media_metadata()
+            .returning(move |_| Ok(info.clone()));
+        mock_downloader
+            .expect_download_media()
+            .times(1)
+            .returning(move |_, _| {
+                Ok(DownloadedMedia::Group(
+                    [&item1, &item2, &empty]
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, path)| DownloadedItem {
+                            filepath: path.clone(),
+                            media_type: MediaType::Video,
+                            thumbnail_filepath: None,
+                            title: None,
+                            playlist_index: Some(i + 1),
+                            info_json_filepath: None,
+                        })
+                        .collect(),
+                ))
+            });
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.contains("item 3"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_send_media_group()
+            .withf(|_, _, media_vec: &Vec<InputMedia>| media_vec.len() == 2)
+            .times(1)
+            .returning(|_, _, _| {
+                Ok((1..=2)
+                    .map(|id| SentMedia {
+                        file_id: format!("file_id_{id}"),
+                        media_type: MediaType::Video,
+                        message_id: MessageId(id),
+                    })
+                    .collect())
+            });
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_media_group_with_one_survivor_is_sent_as_single_item() {
+        let url = Url::parse("https://instagram.com/p/multiple_media").unwrap();
+        let mut info = create_test_info();
+        info.entries = Some(vec![
+            create_test_info(),
+            MediaInfo {
+                duration: Some(7.0),
+                width: Some(720),
+                height: Some(1280),
+                ..create_test_info()
+            },
+        ]);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let item2 = media_file(&temp_dir, "item2.mp4");
+        let items = [DownloadedItem {
+            filepath: item2.clone(),
+            media_type: MediaType::Video,
+            thumbnail_filepath: None,
+            title: None,
+            playlist_index: Some(2),
+            info_json_filepath: None,
+        }];
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api.expect_send_media_group().never();
+        mock_telegram_api
+            .expect_send_video()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq(MediaSource::Path(item2)),
+                eq("full caption"),
+                eq(None),
+                eq(Some(7)),
+                eq(Some(720)),
+                eq(Some(1280)),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("file_id_2".to_string(), MessageId(9))));
+
+        let sent = send_media_group_step(
+            &items,
+            "full caption",
+            &info,
+            &url,
+            ChatId(123),
+            MessageId(456),
+            &mock_telegram_api,
+            None,
+            false,
+        )
+        .await;
+
+        assert_eq!(
+            sent,
+            Some(SentGroup {
+                sent: vec![SentMedia {
+                    file_id: "file_id_2".to_string(),
+                    media_type: MediaType::Video,
+                    message_id: MessageId(9),
+                }],
+                incomplete: false,
+            })
+        );
+    }
+
+    /// Just the headers of a 10000x4801 JPEG: within the width and height limits but over
+    /// the pixel limit, so it is rejected without decoding.
+    const OVERSIZED_JPEG_HEADER: &[u8] = &[
+        0xFF, 0xD8, // SOI
+        0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x12, 0xC1, 0x27, 0x10, 0x01, 0x01, 0x11, 0x00, // SOF0
+        0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, // SOS
+        0xFF, 0xD9, // EOI
+    ];
+
+    #[tokio::test]
+    async fn test_media_group_with_no_survivors_explains_why() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let items: Vec<DownloadedItem> = (1..=2)
+            .map(|index| {
+                let filepath = temp_dir.path().join(format!("uuid.{index}.jpg"));
+                std::fs::write(&filepath, OVERSIZED_JPEG_HEADER).unwrap();
+                DownloadedItem {
+                    filepath,
+                    media_type: MediaType::Photo,
+                    thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: Some(index),
+                    info_json_filepath: None,
+                }
+            })
+            .collect();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api.expect_send_media_group().never();
+        mock_telegram_api.expect_send_photo().never();
+        mock_telegram_api
+            .expect_send_text_message()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq("Sorry, none of the 2 items could be sent.\n\
+                    • item 1: Photo dimensions 10000x4801 exceed the configured safety limit.\n\
+                    • item 2: Photo dimensions 10000x4801 exceed the configured safety limit."),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let sent = send_media_group_step(
+            &items,
+            "caption",
+            &create_test_info(),
+            &Url::parse("https://instagram.com/p/multiple_media").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_telegram_api,
+            None,
+            false,
+        )
+        .await;
+
+        assert_eq!(sent, None);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_media_group_falls_back_to_individual_sends() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let items: Vec<DownloadedItem> = [
+            (media_file(&temp_dir, "1.mp4"), MediaType::Video),
+            (temp_dir.path().join("2.mp4"), MediaType::Video),
+            (media_file(&temp_dir, "3.jpg"), MediaType::Photo),
+            (media_file(&temp_dir, "4.mp4"), MediaType::Video),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, (filepath, media_type))| DownloadedItem {
+            filepath,
+            media_type,
+            thumbnail_filepath: None,
+            title: None,
+            playlist_index: Some(index + 1),
+            info_json_filepath: None,
+        })
+        .collect();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq("⚠️ Some items were left out:\n• item 2: the downloaded file could not be read"),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_send_text_message()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq("⚠️ Some items could not be sent:\n• item 3: Telegram did not accept the file"),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api
+            .expect_send_media_group()
+            .withf(|_, _, media| media.len() == 3)
+            .times(1)
+            .returning(|_, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::ImageProcessFailed,
+                ))
+            });
+        let first = items[0].filepath.clone();
+        mock_telegram_api
+            .expect_send_video()
+            .withf(move |_, _, media, caption, _, _, _, _, _| {
+                *media == MediaSource::Path(first.clone()) && caption == "caption"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("file_1".to_string(), MessageId(1))));
+        mock_telegram_api
+            .expect_send_photo()
+            .withf(|_, _, _, caption, _| caption.is_empty())
+            .times(1)
+            .returning(|_, _, _, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::ImageProcessFailed,
+                ))
+            });
+        mock_telegram_api
+            .expect_send_video()
+            .withf(|_, _, _, caption, _, _, _, _, _| caption.is_empty())
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("file_4".to_string(), MessageId(4))));
+
+        let sent = send_media_group_step(
+            &items,
+            "caption",
+            &create_test_info(),
+            &Url::parse("https://instagram.com/p/multiple_media").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_telegram_api,
+            None,
+            false,
+        )
+        .await;
+
+        let sent = sent.unwrap();
+        assert!(sent.incomplete);
+        let file_ids: Vec<String> = sent.sent.into_iter().map(|s| s.file_id).collect();
+        assert_eq!(file_ids, ["file_1", "file_4"]);
+    }
+
+    #[tokio::test]
+    async fn test_caption_moves_to_first_item_sent_on_its_own() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let items: Vec<DownloadedItem> = ["1.jpg", "2.jpg", "3.jpg"]
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| DownloadedItem {
+                filepath: media_file(&temp_dir, name),
+                media_type: MediaType::Photo,
+                thumbnail_filepath: None,
+                title: None,
+                playlist_index: Some(index + 1),
+                info_json_filepath: None,
+            })
+            .collect();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_media_group()
+            .times(1)
+            .returning(|_, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::ImageProcessFailed,
+                ))
+            });
+        let first = items[0].filepath.clone();
+        mock_telegram_api
+            .expect_send_photo()
+            .withf(move |_, _, media, _, _| *media == MediaSource::Path(first.clone()))
+            .times(1)
+            .returning(|_, _, _, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::ImageProcessFailed,
+                ))
+            });
+        let mut seq = mockall::Sequence::new();
+        mock_telegram_api
+            .expect_send_photo()
+            .withf(|_, _, _, caption, _| caption == "caption")
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _, _| Ok(("file_2".to_string(), MessageId(2))));
+        mock_telegram_api
+            .expect_send_photo()
+            .withf(|_, _, _, caption, _| caption.is_empty())
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _, _| Ok(("file_3".to_string(), MessageId(3))));
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.ends_with("• item 1: Telegram did not accept the file"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let sent = send_media_group_step(
+            &items,
+            "caption",
+            &create_test_info(),
+            &Url::parse("https://instagram.com/p/multiple_media").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_telegram_api,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert!(sent.incomplete);
+        assert_eq!(sent.sent.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_media_group_drops_empty_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let empty = temp_dir.path().join("empty.mp4");
+        std::fs::write(&empty, b"").unwrap();
+        let items: Vec<DownloadedItem> = [empty, media_file(&temp_dir, "2.mp4")]
+            .into_iter()
+            .map(|filepath| DownloadedItem {
+                filepath,
+                media_type: MediaType::Video,
+                thumbnail_filepath: None,
+                title: None,
+                playlist_index: None,
+                info_json_filepath: None,
+            })
+            .collect();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text.ends_with("• item 1: the downloaded file is empty"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_telegram_api.expect_send_media_group().never();
+        mock_telegram_api
+            .expect_send_video()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("file_2".to_string(), MessageId(2))));
+
+        let sent = send_media_group_step(
+            &items,
+            "caption",
+            &create_test_info(),
+            &Url::parse("https://instagram.com/p/multiple_media").unwrap(),
+            ChatId(123),
+            MessageId(456),
+            &mock_telegram_api,
+            None,
+            false,
+        )
+        .await;
+
+        let sent = sent.unwrap();
+        assert_eq!(sent.sent.len(), 1);
+        assert!(sent.incomplete);
+    }
+
+    #[test]
+    fn test_is_file_rejection() {
+        let api = |error| teloxide::RequestError::Api(error);
+        assert!(is_file_rejection(&api(
+            teloxide::ApiError::ImageProcessFailed
+        )));
+        assert!(is_file_rejection(&api(teloxide::ApiError::Unknown(
+            "Bad Request: MEDIA_EMPTY".to_string()
+        ))));
+        assert!(!is_file_rejection(&api(teloxide::ApiError::BotBlocked)));
+        assert!(!is_file_rejection(&api(teloxide::ApiError::Unknown(
+            "Bad Request: chat not found".to_string()
+        ))));
+    }
+
+    /// A non-empty file standing in for downloaded media.
+    fn media_file(dir: &tempfile::TempDir, name: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, b"media").unwrap();
+        path
+    }
+
+    fn titled_items(titles: &[Option<&str>]) -> Vec<DownloadedItem> {
+        titles
+            .iter()
+            .map(|title| DownloadedItem {
+                filepath: PathBuf::from("/tmp/item.mp4"),
+                media_type: MediaType::Video,
+                thumbnail_filepath: None,
+                title: title.map(str::to_string),
+                playlist_index: None,
+                info_json_filepath: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_media_group_captions_label_distinct_playlist_titles() {
+        let long_title = "x".repeat(MAX_ITEM_TITLE_CHARS + 10);
+        let items = titled_items(&[Some("Part 1"), Some("Tom & Jerry"), Some(&long_title)]);
+
+        let captions = media_group_captions(&items, "<a href=\"x\">Source</a>");
+
+        assert_eq!(
+            captions,
+            vec![
+                "<b>Part 1</b>\n<a href=\"x\">Source</a>".to_string(),
+                "<b>Tom &amp; Jerry</b>".to_string(),
+                format!("<b>{}…</b>", "x".repeat(MAX_ITEM_TITLE_CHARS)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_media_group_captions_keep_full_caption_when_label_does_not_fit() {
+        let items = titled_items(&[Some("Part 1"), Some("Part 2")]);
+        let caption = "a".repeat(MediaInfo::TELEGRAM_CAPTION_LIMIT - 5);
+
+        let captions = media_group_captions(&items, &caption);
+
+        assert_eq!(captions, vec![caption, "<b>Part 2</b>".to_string()]);
+    }
+
+    #[test]
+    fn test_media_group_captions_fall_back_for_identical_or_missing_titles() {
+        let expected = vec!["caption".to_string(), String::new()];
+        for titles in [
+            [Some("Same"), Some("Same")],
+            [Some("Title"), None],
+            [Some("Title"), Some("  ")],
+            [None, None],
+        ] {
+            assert_eq!(
+                media_group_captions(&titled_items(&titles), "caption"),
+                expected,
+                "titles: {titles:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_media_group_reports_missing_playlist_items() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mock_storage = create_default_mock_storage();
+        let test_url = Url::parse("https://instagram.com/p/partial_gallery").unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let files = [1, 3].map(|position| {
+            (
+                position,
+                media_file(&temp_dir, &format!("item{position}.mp4")),
+            )
+        });
+
+        let mut info = create_test_info();
+        info.entries = Some(vec![create_test_info(); 3]);
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(move |_| Ok(info.clone()));
+        // The second entry had no file in yt-dlp's output.
+        mock_downloader
+            .expect_download_media()
+            .times(1)
+            .returning(move |_, _| {
+                Ok(DownloadedMedia::Group(
+                    files
+                        .iter()
+                        .map(|(position, filepath)| DownloadedItem {
+                            filepath: filepath.clone(),
+                            media_type: MediaType::Video,
+                            thumbnail_filepath: None,
+                            title: None,
+                            playlist_index: Some(*position),
+                            info_json_filepath: None,
+                        })
+                        .collect(),
+                ))
+            });
+        mock_telegram_api
+            .expect_send_media_group()
             .times(1)
             .returning(|_, _, _| {
                 Ok(vec![
                     SentMedia {
-                        file_id: "file_id_group_1".to_string(),
+                        file_id: "file_id_1".to_string(),
                         media_type: MediaType::Video,
+                        message_id: MessageId(2),
                     },
                     SentMedia {
-                        file_id: "file_id_group_2".to_string(),
-                        media_type: MediaType::Photo,
+                        file_id: "file_id_3".to_string(),
+                        media_type: MediaType::Video,
+                        message_id: MessageId(3),
                     },
                 ])
             });
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == "⚠️ 1 of 3 items could not be downloaded (item 2).")
+            .times(1)
+            .returning(|_, _, _| Ok(()));
 
         process_download_request(
             &test_url,
@@ -1050,18 +4271,35 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
 
+    #[test]
+    fn test_format_missing_items_notice() {
+        assert_eq!(
+            format_missing_items_notice(&[2, 5], 6),
+            "⚠️ 2 of 6 items could not be downloaded (items 2, 5)."
+        );
+    }
+
     #[tokio::test]
     async fn test_process_download_request_stops_if_pre_check_fails() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/too_long").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
 
         mock_downloader
             .expect_get_media_metadata()
@@ -1083,9 +4321,72 @@ mod tests {
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "validation_error")
+            .withf(|_, _, status, _, _| status == "validation_error")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_warns_and_downloads_slightly_long_media() {
+        let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        let test_url = Url::parse("https://instagram.com/p/slightly_long").unwrap();
+
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(|_| {
+                let mut info = create_test_info();
+                info.duration = Some(1890.0);
+                Ok(info)
+            });
+
+        let mut seq = mockall::Sequence::new();
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, msg| msg.contains("slightly over the usual 30 minute limit"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Ok(()));
+        mock_downloader
+            .expect_download_media()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err(DownloadError::CommandFailed("yt-dlp exploded".to_string())));
+        mock_telegram_api
+            .expect_send_text_message()
+            .withf(|_, _, msg| msg.contains("could not download the media"))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "error")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1095,6 +4396,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1102,11 +4407,16 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_sends_error_on_download_failure() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/invalid_post").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
 
         mock_downloader
             .expect_get_media_metadata()
@@ -1132,9 +4442,9 @@ mod tests {
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "error")
+            .withf(|_, _, status, _, _| status == "error")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1144,6 +4454,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1151,11 +4465,16 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_sends_timeout_message_on_timeout() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/slow_video").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
 
         mock_downloader
             .expect_get_media_metadata()
@@ -1181,9 +4500,9 @@ mod tests {
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "error")
+            .withf(|_, _, status, _, _| status == "error")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1193,6 +4512,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1200,11 +4523,16 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_sends_generic_error_on_metadata_failure() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/private_post").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
 
         mock_downloader
             .expect_get_media_metadata()
@@ -1230,9 +4558,141 @@ mod tests {
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "validation_error")
+            .withf(|_, _, status, _, _| status == "validation_error")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+    }
+
+    /// Run a request for `url`, whose metadata reports an hour-long video, and return
+    /// the reply.
+    async fn too_long_reply(url: &str) -> String {
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        let test_url = Url::parse(url).unwrap();
+
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(|_| {
+                let mut info = create_test_info();
+                info.duration = Some(3600.0);
+                Ok(info)
+            });
+        mock_downloader.expect_download_media().times(0);
+        let reply = Arc::new(std::sync::Mutex::new(String::new()));
+        let sent = reply.clone();
+        mock_telegram_api
+            .expect_send_text_message()
+            .times(1)
+            .returning(move |_, _, text| {
+                *sent.lock().unwrap() = text.to_string();
+                Ok(())
+            });
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "validation_error")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+
+        process_download_request(
+            &test_url,
+            ChatId(123),
+            MessageId(456),
+            &mock_downloader,
+            &mock_telegram_api,
+            &mock_storage,
+            &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
+        )
+        .await;
+        reply.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_too_long_youtube_video_gets_tailored_reply() {
+        for url in [
+            "https://www.youtube.com/watch?v=abc",
+            "https://m.youtube.com/watch?v=abc",
+            "https://youtu.be/abc",
+        ] {
+            assert_eq!(
+                too_long_reply(url).await,
+                "I only download YouTube Shorts or videos under 30 minutes, and this one is 60 minutes. \
+                 Share a clip of the part you want, or send the Short instead.",
+                "{url}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_too_long_short_or_other_site_gets_generic_reply() {
+        for url in [
+            "https://www.youtube.com/shorts/abc",
+            "https://vimeo.com/123",
+        ] {
+            assert_eq!(
+                too_long_reply(url).await,
+                "The media is too long: 60 minutes is over the 30 minute limit.",
+                "{url}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_download_request_explains_geo_block() {
+        let mut mock_downloader = MockDownloader::new();
+        let mut mock_telegram_api = MockTelegramApi::new();
+        let mut mock_storage = MockStorage::new();
+        let test_url = Url::parse("https://www.bbc.co.uk/iplayer/episode/p0abc").unwrap();
+
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
+        mock_downloader
+            .expect_get_media_metadata()
+            .times(1)
+            .returning(|_| {
+                Err(DownloadError::GeoBlocked(
+                    "ERROR: This video is not available in your country".to_string(),
+                ))
+            });
+        mock_downloader.expect_download_media().times(0);
+        mock_telegram_api
+            .expect_send_text_message()
+            .with(
+                eq(ChatId(123)),
+                eq(MessageId(456)),
+                eq("🌍 This content is not available in the server's region. Try a VPN or access it directly."),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "geo_blocked")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1242,6 +4702,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1249,13 +4713,20 @@ mod tests {
     #[tokio::test]
     async fn test_cache_send_failure_falls_through_to_download() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
         let test_url = Url::parse("https://instagram.com/p/stale_cache").unwrap();
 
         // Cache returns data but send fails (e.g. stale file_id)
-        mock_storage.expect_get_cached_media().returning(|_| {
+        mock_storage.expect_get_cached_media().returning(|_, _| {
             Some(CachedMedia {
+                id: 1,
                 caption: "old caption".to_string(),
                 files: vec![crate::storage::CachedFile {
                     telegram_file_id: "stale_file_id".to_string(),
@@ -1267,9 +4738,10 @@ mod tests {
         });
 
         mock_telegram_api
-            .expect_send_cached_video()
+            .expect_send_video()
+            .withf(|_, _, media, _, _, _, _, _, _| media.path().is_none())
             .times(1)
-            .returning(|_, _, _, _| {
+            .returning(|_, _, _, _, _, _, _, _, _| {
                 Err(teloxide::RequestError::Api(teloxide::ApiError::Unknown(
                     "Bad Request: wrong file_id".to_string(),
                 )))
@@ -1289,13 +4761,16 @@ mod tests {
                     filepath: PathBuf::from("/tmp/video.mp4"),
                     media_type: MediaType::Video,
                     thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
                 }))
             });
 
         mock_telegram_api
             .expect_send_video()
             .times(1)
-            .returning(|_, _, _, _, _| Ok(("fresh_file_id".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("fresh_file_id".to_string(), MessageId(0))));
 
         mock_telegram_api
             .expect_send_text_message()
@@ -1304,13 +4779,13 @@ mod tests {
         mock_storage
             .expect_store_cached_media()
             .times(1)
-            .returning(|_, _, _, _, _: Option<i32>| ());
+            .returning(|_, _, _, _, _, _: Option<i32>, _| ());
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "success")
+            .withf(|_, _, status, _, _| status == "success")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1320,6 +4795,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1327,11 +4806,16 @@ mod tests {
     #[tokio::test]
     async fn test_send_failure_after_download_logs_error() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/send_fail").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
 
         mock_downloader
             .expect_get_media_metadata()
@@ -1342,13 +4826,16 @@ mod tests {
                 filepath: PathBuf::from("/tmp/video.mp4"),
                 media_type: MediaType::Video,
                 thumbnail_filepath: None,
+                title: None,
+                playlist_index: None,
+                info_json_filepath: None,
             }))
         });
 
         mock_telegram_api
             .expect_send_video()
             .times(1)
-            .returning(|_, _, _, _, _| {
+            .returning(|_, _, _, _, _, _, _, _, _| {
                 Err(teloxide::RequestError::Api(teloxide::ApiError::Unknown(
                     "Request Entity Too Large".to_string(),
                 )))
@@ -1364,9 +4851,9 @@ mod tests {
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "error")
+            .withf(|_, _, status, _, _| status == "error")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1376,6 +4863,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1385,14 +4876,18 @@ mod tests {
         let mock_downloader = MockDownloader::new();
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
         let test_url = Url::parse("https://instagram.com/p/cached_post").unwrap();
 
         mock_storage
             .expect_get_cached_media()
-            .with(eq("https://instagram.com/p/cached_post"))
+            .with(eq(0), eq("https://instagram.com/p/cached_post"))
             .times(1)
-            .returning(|_| {
+            .returning(|_, _| {
                 Some(CachedMedia {
+                    id: 1,
                     caption: "cached caption".to_string(),
                     files: vec![crate::storage::CachedFile {
                         telegram_file_id: "cached_file_id".to_string(),
@@ -1404,21 +4899,28 @@ mod tests {
             });
 
         mock_telegram_api
-            .expect_send_cached_video()
+            .expect_send_video()
             .with(
                 eq(ChatId(123)),
                 eq(MessageId(456)),
-                eq("cached_file_id"),
+                eq(MediaSource::FileId("cached_file_id".to_string())),
                 eq("cached caption"),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq(None),
             )
             .times(1)
-            .returning(|_, _, _, _| Ok(MessageId(789)));
+            .returning(|_, _, _, _, _, _, _, _, _| {
+                Ok(("cached_file_id".to_string(), MessageId(789)))
+            });
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "cached")
+            .withf(|_, _, status, _, _| status == "cached")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         // Audio extraction runs concurrently; failing is non-fatal
         let ctx = process_download_request(
@@ -1429,6 +4931,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
 
@@ -1451,13 +4957,17 @@ mod tests {
         let mock_downloader = MockDownloader::new();
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
         let test_url = Url::parse("https://instagram.com/p/cached_video").unwrap();
 
         mock_storage
             .expect_get_cached_media()
             .times(1)
-            .returning(move |_| {
+            .returning(move |_, _| {
                 Some(CachedMedia {
+                    id: 1,
                     caption: "video caption".to_string(),
                     files: vec![crate::storage::CachedFile {
                         telegram_file_id: "cached_video_id".to_string(),
@@ -1469,15 +4979,15 @@ mod tests {
             });
 
         mock_telegram_api
-            .expect_send_cached_video()
+            .expect_send_video()
             .times(1)
-            .returning(|_, _, _, _| Ok(MessageId(101)));
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("cached".to_string(), MessageId(101))));
 
         mock_storage
             .expect_log_request()
-            .withf(|_, _, status, _| status == "cached")
+            .withf(|_, _, status, _, _| status == "cached")
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         let ctx = process_download_request(
             &test_url,
@@ -1487,6 +4997,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
 
@@ -1502,6 +5016,9 @@ mod tests {
         // If the DB has an audio path but the file is gone, we should re-download
         // the video from scratch rather than serving a degraded cached version.
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/cached_video").unwrap();
@@ -1509,8 +5026,9 @@ mod tests {
         mock_storage
             .expect_get_cached_media()
             .times(1)
-            .returning(|_| {
+            .returning(|_, _| {
                 Some(CachedMedia {
+                    id: 1,
                     caption: "video caption".to_string(),
                     files: vec![crate::storage::CachedFile {
                         telegram_file_id: "cached_video_id".to_string(),
@@ -1522,8 +5040,11 @@ mod tests {
                 })
             });
 
-        // send_cached_video must NOT be called — we fall through to fresh download
-        mock_telegram_api.expect_send_cached_video().times(0);
+        // The cached file id must NOT be sent — we fall through to fresh download
+        mock_telegram_api
+            .expect_send_video()
+            .withf(|_, _, media, _, _, _, _, _, _| media.path().is_none())
+            .times(0);
 
         // Falls through to normal download pipeline
         mock_downloader
@@ -1538,23 +5059,26 @@ mod tests {
                     filepath: PathBuf::from("/tmp/video.mp4"),
                     media_type: MediaType::Video,
                     thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
                 }))
             });
         mock_telegram_api
             .expect_send_video()
             .times(1)
-            .returning(|_, _, _, _, _| Ok(("fresh_file_id".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("fresh_file_id".to_string(), MessageId(0))));
         mock_telegram_api
             .expect_send_text_message()
             .returning(|_, _, _| Ok(()));
         mock_storage
             .expect_store_cached_media()
             .times(1)
-            .returning(|_, _, _, _, _| ());
+            .returning(|_, _, _, _, _, _, _| ());
         mock_storage
             .expect_log_request()
             .times(1)
-            .returning(|_, _, _, _| ());
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1564,6 +5088,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1573,10 +5101,14 @@ mod tests {
         let mock_downloader = MockDownloader::new();
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_record_delivery()
+            .returning(|_, _, _, _| ());
         let test_url = Url::parse("https://instagram.com/p/cached_photo").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| {
+        mock_storage.expect_get_cached_media().returning(|_, _| {
             Some(CachedMedia {
+                id: 1,
                 caption: "photo caption".to_string(),
                 files: vec![crate::storage::CachedFile {
                     telegram_file_id: "cached_photo_id".to_string(),
@@ -1588,17 +5120,20 @@ mod tests {
         });
 
         mock_telegram_api
-            .expect_send_cached_photo()
+            .expect_send_photo()
             .with(
                 eq(ChatId(123)),
                 eq(MessageId(456)),
-                eq("cached_photo_id"),
+                eq(MediaSource::FileId("cached_photo_id".to_string())),
                 eq("photo caption"),
+                eq(None),
             )
             .times(1)
-            .returning(|_, _, _, _| Ok(()));
+            .returning(|_, _, _, _, _| Ok(("cached_photo_id".to_string(), MessageId(789))));
 
-        mock_storage.expect_log_request().returning(|_, _, _, _| ());
+        mock_storage
+            .expect_log_request()
+            .returning(|_, _, _, _, _| ());
 
         process_download_request(
             &test_url,
@@ -1608,6 +5143,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1619,8 +5158,9 @@ mod tests {
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/cached_group").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| {
+        mock_storage.expect_get_cached_media().returning(|_, _| {
             Some(CachedMedia {
+                id: 17,
                 caption: "group caption".to_string(),
                 files: vec![
                     crate::storage::CachedFile {
@@ -1641,11 +5181,24 @@ mod tests {
             .expect_send_cached_media_group()
             .withf(|_, _, files, caption| files.len() == 2 && caption == "group caption")
             .times(1)
-            .returning(|_, _, _, _| Ok(()));
+            .returning(|_, _, _, _| Ok(vec![MessageId(7), MessageId(8)]));
 
-        mock_storage.expect_log_request().returning(|_, _, _, _| ());
+        mock_storage
+            .expect_record_delivery()
+            .withf(|chat_id, message_ids, source_url, cache_id| {
+                *chat_id == 123
+                    && message_ids == [7, 8]
+                    && source_url == "https://instagram.com/p/cached_group"
+                    && *cache_id == Some(17)
+            })
+            .times(1)
+            .returning(|_, _, _, _| ());
 
-        process_download_request(
+        mock_storage
+            .expect_log_request()
+            .returning(|_, _, _, _, _| ());
+
+        let ctx = process_download_request(
             &test_url,
             ChatId(123),
             MessageId(456),
@@ -1653,18 +5206,30 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
-        .await;
+        .await
+        .unwrap();
+        assert!(!ctx.has_video);
+        assert_eq!(ctx.delivered_message_id, Some(MessageId(7)));
     }
 
     #[tokio::test]
     async fn test_cache_miss_downloads_and_stores() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mut mock_storage = MockStorage::new();
         let test_url = Url::parse("https://instagram.com/p/new_post").unwrap();
 
-        mock_storage.expect_get_cached_media().returning(|_| None);
+        mock_storage
+            .expect_get_cached_media()
+            .returning(|_, _| None);
 
         mock_downloader
             .expect_get_media_metadata()
@@ -1675,13 +5240,16 @@ mod tests {
                 filepath: PathBuf::from("/tmp/video.mp4"),
                 media_type: MediaType::Video,
                 thumbnail_filepath: None,
+                title: None,
+                playlist_index: None,
+                info_json_filepath: None,
             }))
         });
 
         mock_telegram_api
             .expect_send_video()
             .times(1)
-            .returning(|_, _, _, _, _| Ok(("new_file_id".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("new_file_id".to_string(), MessageId(0))));
 
         mock_telegram_api
             .expect_send_text_message()
@@ -1689,20 +5257,28 @@ mod tests {
 
         mock_storage
             .expect_store_cached_media()
-            .withf(|url, _caption, files, _audio, _dur| {
+            .withf(|_, url, _caption, files, _audio, _dur, _metadata| {
                 url == "https://instagram.com/p/new_post"
                     && files.len() == 1
                     && files[0].0 == "new_file_id"
             })
             .times(1)
-            .returning(|_, _, _, _, _| ());
+            .returning(|_, _, _, _, _, _, _| ());
 
         mock_storage
-            .expect_log_request()
-            .withf(|_, _, status, _| status == "success")
+            .expect_record_delivery()
+            .withf(|chat_id, message_ids, _, cache_id| {
+                *chat_id == 123 && message_ids == [0] && cache_id.is_none()
+            })
             .times(1)
             .returning(|_, _, _, _| ());
 
+        mock_storage
+            .expect_log_request()
+            .withf(|_, _, status, _, _| status == "success")
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+
         process_download_request(
             &test_url,
             ChatId(123),
@@ -1711,6 +5287,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await;
     }
@@ -1718,6 +5298,9 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_returns_audio_context_on_extraction_success() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mock_storage = create_default_mock_storage();
         let test_url = Url::parse("https://instagram.com/p/valid_post").unwrap();
@@ -1736,13 +5319,16 @@ mod tests {
                     filepath: PathBuf::from("/tmp/video.mp4"),
                     media_type: MediaType::Video,
                     thumbnail_filepath: None,
+                    title: None,
+                    playlist_index: None,
+                    info_json_filepath: None,
                 }))
             });
 
         mock_telegram_api
             .expect_send_video()
             .times(1)
-            .returning(|_, _, _, _, _| Ok(("file_id_123".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _, _, _, _, _| Ok(("file_id_123".to_string(), MessageId(0))));
 
         let mut mock_audio = MockAudioExtractor::new();
         mock_audio.expect_extract_audio().returning(|_, _, _| {
@@ -1760,6 +5346,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &mock_audio,
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await
         .expect("expected Some(DownloadContext)");
@@ -1775,6 +5365,9 @@ mod tests {
     #[tokio::test]
     async fn test_process_download_request_photo_returns_no_video_context() {
         let mut mock_downloader = MockDownloader::new();
+        mock_downloader
+            .expect_estimate_download_time()
+            .returning(|_| None);
         let mut mock_telegram_api = MockTelegramApi::new();
         let mock_storage = create_default_mock_storage();
         let test_url = Url::parse("https://instagram.com/p/photo_post").unwrap();
@@ -1788,13 +5381,16 @@ mod tests {
                 filepath: PathBuf::from("/tmp/photo.jpg"),
                 media_type: MediaType::Photo,
                 thumbnail_filepath: None,
+                title: None,
+                playlist_index: None,
+                info_json_filepath: None,
             }))
         });
 
         mock_telegram_api
             .expect_send_photo()
             .times(1)
-            .returning(|_, _, _, _| Ok(("photo_file_id".to_string(), MessageId(0))));
+            .returning(|_, _, _, _, _| Ok(("photo_file_id".to_string(), MessageId(0))));
 
         let ctx = process_download_request(
             &test_url,
@@ -1804,6 +5400,10 @@ mod tests {
             &mock_telegram_api,
             &mock_storage,
             &create_failing_audio_extractor(),
+            &PipelineConfig::default(),
+            &PendingUploads::default(),
+            DownloadOptions::default(),
+            &RequestProgress::default(),
         )
         .await
         .expect("expected Some(DownloadContext)");
@@ -1872,6 +5472,8 @@ mod tests {
             media_duration_secs: audio_cache_path.as_ref().map(|_| 60),
             sent_message_id: if has_video { Some(MessageId(99)) } else { None },
             audio_cache_path,
+            source_as_button: false,
+            delivered_message_id: Some(MessageId(99)),
         }
     }
 
@@ -1941,4 +5543,26 @@ mod tests {
         let ctx = make_download_ctx(true, Some(PathBuf::from("/tmp/audio.mp3")));
         maybe_send_premium_buttons(ChatId(1), ctx, &api, &storage).await;
     }
+
+    #[tokio::test]
+    async fn test_maybe_send_premium_buttons_keeps_source_button() {
+        let mut storage = MockStorage::new();
+        storage.expect_store_callback_context().returning(|_| 42);
+
+        let mut api = MockTelegramApi::new();
+        api.expect_edit_message_reply_markup()
+            .withf(|_, _, keyboard| {
+                keyboard.inline_keyboard.len() == 2
+                    && keyboard.inline_keyboard[1]
+                        == source_button_row(&"https://example.com/video".parse().unwrap())
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let ctx = DownloadContext {
+            source_as_button: true,
+            ..make_download_ctx(true, Some(PathBuf::from("/tmp/audio.mp3")))
+        };
+        maybe_send_premium_buttons(ChatId(1), ctx, &api, &storage).await;
+    }
 }
@@ -0,0 +1,189 @@
+//! Post-processing of downloaded files before they are sent, e.g. compression.
+//!
+//! Hooks run in order on every downloaded item. A hook that fails is logged and the
+//! item is sent as the previous hooks left it.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::child_processes::ChildProcesses;
+use crate::downloader::{DownloadError, DownloadedItem, DownloadedMedia, MediaType};
+
+/// Longest ffmpeg may take to compress one video, well within the request timeout.
+const COMPRESSION_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait PostDownloadHook: Send + Sync + std::fmt::Debug {
+    /// Process `item` in place. A hook that replaces the file updates `item.filepath` and
+    /// removes the file it replaced.
+    async fn apply(&self, item: &mut DownloadedItem) -> Result<(), DownloadError>;
+}
+
+/// Run `hooks` in order on every item of `media`.
+pub async fn apply_post_download_hooks(
+    hooks: &[Arc<dyn PostDownloadHook>],
+    media: &mut DownloadedMedia,
+) {
+    if hooks.is_empty() {
+        return;
+    }
+    let items = match media {
+        DownloadedMedia::Single(item) => std::slice::from_mut(item),
+        DownloadedMedia::Group(items) => items.as_mut_slice(),
+    };
+    for item in items {
+        for hook in hooks {
+            if let Err(e) = hook.apply(item).await {
+                log::warn!(
+                    "Post-download hook {:?} failed for {}: {}",
+                    hook,
+                    item.filepath.display(),
+                    e.display_to_log()
+                );
+            }
+        }
+    }
+}
+
+/// Re-encodes videos with ffmpeg at a video bitrate of at most `max_bitrate_kbps`,
+/// configured with `COMPRESSION_MAX_BITRATE_KBPS`. Audio is copied as is.
+#[derive(Debug)]
+pub struct FfmpegCompressionHook {
+    pub max_bitrate_kbps: u32,
+    /// Registry ffmpeg runs in, so shutdown can stop it.
+    pub children: ChildProcesses,
+}
+
+impl FfmpegCompressionHook {
+    /// `<uuid>.<id>.mp4` becomes `<uuid>.<id>.compressed.mp4`, keeping the download's
+    /// uuid prefix so orphan cleanup still finds it.
+    fn output_path(input: &std::path::Path) -> PathBuf {
+        let stem = input
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        input.with_file_name(format!("{stem}.compressed.mp4"))
+    }
+}
+
+#[async_trait]
+impl PostDownloadHook for FfmpegCompressionHook {
+    async fn apply(&self, item: &mut DownloadedItem) -> Result<(), DownloadError> {
+        if item.media_type != MediaType::Video {
+            return Ok(());
+        }
+        let output_path = Self::output_path(&item.filepath);
+        let mut command = tokio::process::Command::new("ffmpeg");
+        command
+            .arg("-i")
+            .arg(&item.filepath)
+            .args(["-c:v", "libx264", "-b:v"])
+            .arg(format!("{}k", self.max_bitrate_kbps))
+            .args(["-c:a", "copy", "-movflags", "+faststart", "-y"])
+            .arg(&output_path)
+            .kill_on_drop(true);
+        let output = tokio::time::timeout(COMPRESSION_TIMEOUT, self.children.output(&mut command))
+            .await
+            .map_err(|_| DownloadError::Timeout(COMPRESSION_TIMEOUT.as_secs()))
+            .and_then(|output| {
+                output.map_err(|source| DownloadError::IoError {
+                    context: "running ffmpeg compression",
+                    source,
+                })
+            });
+        let failure = match output {
+            Ok(output) if output.status.success() => None,
+            Ok(output) => Some(DownloadError::CommandFailed(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            )),
+            Err(e) => Some(e),
+        };
+        if let Some(e) = failure {
+            if let Err(e) = tokio::fs::remove_file(&output_path).await {
+                log::debug!(
+                    "No partial output to remove at {}: {}",
+                    output_path.display(),
+                    e
+                );
+            }
+            return Err(e);
+        }
+
+        let original = std::mem::replace(&mut item.filepath, output_path);
+        if let Err(e) = tokio::fs::remove_file(&original).await {
+            log::warn!(
+                "Failed to remove uncompressed file {}: {}",
+                original.display(),
+                e
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn item(path: &str, media_type: MediaType) -> DownloadedItem {
+        DownloadedItem {
+            filepath: PathBuf::from(path),
+            media_type,
+            thumbnail_filepath: None,
+            title: None,
+            playlist_index: None,
+            info_json_filepath: None,
+        }
+    }
+
+    #[test]
+    fn test_compressed_output_keeps_download_prefix() {
+        assert_eq!(
+            FfmpegCompressionHook::output_path(Path::new("/tmp/uuid.abc.mp4")),
+            Path::new("/tmp/uuid.abc.compressed.mp4")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compression_skips_photos() {
+        let hook = FfmpegCompressionHook {
+            max_bitrate_kbps: 500,
+            children: ChildProcesses::new(),
+        };
+        let mut photo = item("/nonexistent/uuid.abc.jpg", MediaType::Photo);
+        hook.apply(&mut photo).await.unwrap();
+        assert_eq!(photo.filepath, Path::new("/nonexistent/uuid.abc.jpg"));
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_in_order_on_every_item_and_failures_are_skipped() {
+        let mut failing = MockPostDownloadHook::new();
+        failing
+            .expect_apply()
+            .times(2)
+            .returning(|_| Err(DownloadError::CommandFailed("boom".to_string())));
+        let mut renaming = MockPostDownloadHook::new();
+        renaming.expect_apply().times(2).returning(|item| {
+            item.filepath.set_extension("out");
+            Ok(())
+        });
+        let hooks: Vec<Arc<dyn PostDownloadHook>> = vec![Arc::new(failing), Arc::new(renaming)];
+        let mut media = DownloadedMedia::Group(vec![
+            item("a.mp4", MediaType::Video),
+            item("b.jpg", MediaType::Photo),
+        ]);
+
+        apply_post_download_hooks(&hooks, &mut media).await;
+
+        let DownloadedMedia::Group(items) = media else {
+            panic!("expected a group");
+        };
+        let paths: Vec<_> = items.iter().map(|item| item.filepath.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("a.out"), PathBuf::from("b.out")]);
+    }
+}
@@ -1,15 +1,49 @@
+pub mod about;
+pub mod blackhole;
+pub mod bot_profile;
+pub mod cache;
+pub mod cache_warmer;
+pub mod caption_links;
+pub mod chat_guard;
+pub mod child_processes;
+pub mod command_menu;
 pub mod commands;
 pub mod concurrency;
 pub mod config;
+pub mod cookies;
+pub mod dashboard;
 pub mod downloader;
+pub mod dry_run;
+pub mod error_reporter;
+pub mod fallback;
+pub mod file_storage;
 pub mod handler;
+pub mod hooks;
+pub mod maintenance;
+pub mod memory_storage;
+pub mod object_store;
+pub mod permissions;
 pub mod premium;
+pub mod probe;
+pub mod quota;
+pub mod rate_limiter;
+pub mod recent_requests;
 pub mod retry;
+pub mod roundify;
+pub mod server;
+pub mod source_button;
 pub mod storage;
+pub mod storage_metrics;
 pub mod subscription;
+pub mod supervisor;
 pub mod telegram_api;
 pub mod terms;
+pub mod thumb;
+pub mod uploads;
+pub mod url_cleanup;
+pub mod url_normalizer;
 pub mod validator;
+pub mod webhook;
 
 pub use downloader::{DownloadError, Downloader};
 pub use handler::{maybe_send_premium_buttons, process_download_request, send_long_text};
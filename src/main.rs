@@ -2,34 +2,69 @@ use log::LevelFilter;
 use std::collections::HashSet;
 use std::io::Write;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use reqwest::Client;
-use sqlx::postgres::PgPoolOptions;
+use teloxide::dispatching::UpdateHandler;
 use teloxide::prelude::*;
-use teloxide::types::MessageKind;
+use teloxide::types::{ChatMemberUpdated, Me, MessageKind};
+use teloxide::update_listeners::webhooks;
 use teloxide::utils::command::BotCommands;
-use url::Url;
+use tokio::signal::unix::SignalKind;
 
 // Use our library crate
+use crabberbot::about::{AboutInfo, GIT_COMMIT_SHA};
+use crabberbot::bot_profile::{self, BOT_DESCRIPTION, DesiredProfile, bot_name_for};
+use crabberbot::cache::EVICTION_INTERVAL;
+use crabberbot::cache_warmer::{CacheWarmer, PrefetchingDownloader};
+use crabberbot::child_processes::{CHILD_TERMINATION_GRACE, ChildProcesses};
+use crabberbot::command_menu::{Command, OwnerCommand, command_menus};
 use crabberbot::commands::{
-    handle_callback_query, handle_grant, handle_pre_checkout_query, handle_refund,
-    handle_refunded_payment, handle_refundme, handle_reply, handle_subscribe,
-    handle_successful_payment, handle_support,
+    handle_callback_query, handle_feedback, handle_findcached, handle_grant, handle_info,
+    handle_maintenance, handle_pre_checkout_query, handle_queue, handle_refund,
+    handle_refunded_payment, handle_refundme, handle_reply, handle_stats, handle_subscribe,
+    handle_successful_payment, handle_support, handle_trace,
 };
-use crabberbot::concurrency::ConcurrencyLimiter;
+use crabberbot::concurrency::{BotChat, ConcurrencyLimiter};
 use crabberbot::config::AppConfig;
-use crabberbot::downloader::{Downloader, YtDlpDownloader, cleanup_orphaned_downloads};
-use crabberbot::handler::{maybe_send_premium_buttons, process_download_request};
+use crabberbot::dashboard::DashboardState;
+use crabberbot::downloader::{
+    CaptionOptions, Downloader, YtDlpDownloader, cleanup_orphaned_downloads,
+};
+use crabberbot::dry_run::{NoopTelegramApi, TelegramMode, serve_stub_bot_api};
+use crabberbot::error_reporter::OwnerErrorReporter;
+use crabberbot::fallback::{RecentMediaGroups, handle_unhandled_message, is_unhandled_text};
+use crabberbot::handler::{
+    PipelineConfig, UrlRequest, maybe_send_premium_buttons, process_download_request,
+};
+use crabberbot::hooks::{FfmpegCompressionHook, PostDownloadHook};
+use crabberbot::maintenance::{Maintenance, maintenance_refusal, refuse_during_maintenance};
+use crabberbot::object_store::{ObjectStore, S3ObjectStore};
+use crabberbot::permissions::Permissions;
 use crabberbot::premium::audio_extractor::{AudioExtractor, FfmpegAudioExtractor};
 use crabberbot::premium::summarizer::{GeminiSummarizer, Summarizer};
 use crabberbot::premium::transcriber::{DeepgramTranscriber, Transcriber};
-use crabberbot::storage::{PostgresStorage, Storage};
-use crabberbot::telegram_api::{TelegramApi, TeloxideApi};
+use crabberbot::quota::{DailyDownloadQuota, check_daily_quota, handle_quota};
+use crabberbot::rate_limiter::{COMMAND_RATE_LIMITED_MESSAGE, RateLimiter};
+use crabberbot::recent_requests::{
+    RecentRequests, RecentUpdates, is_redelivery, reply_with_earlier_delivery,
+};
+use crabberbot::roundify::{FfmpegVideoNoteConverter, RoundifyRequest, process_roundify_request};
+use crabberbot::server;
+use crabberbot::source_button::handle_sourcebutton;
+use crabberbot::storage::{PostgresStorage, Storage, StorageBackend, StorageUrl, create_storage};
+use crabberbot::storage_metrics::StorageCounters;
+use crabberbot::supervisor::{Supervisor, TASK_RESTART_DELAY};
+use crabberbot::telegram_api::{TelegramApi, TeloxideApi, topic_thread_id};
 use crabberbot::terms;
+use crabberbot::thumb::{ThumbRequest, handle_thumb};
+use crabberbot::uploads::PendingUploads;
+use crabberbot::url_cleanup::cleanup_url_with_rules;
+use crabberbot::url_normalizer::short_url_client;
+use crabberbot::validator::ValidationConfig;
+use crabberbot::webhook::webhook_url_for;
 
-const OVERALL_REQUEST_TIMEOUT: Duration = Duration::from_secs(360);
-
+#[allow(clippy::too_many_arguments)]
 async fn handle_command(
     _bot: Bot,
     api: Arc<dyn TelegramApi>,
@@ -38,8 +73,19 @@ async fn handle_command(
     command: Command,
     owner_chat_id: i64,
     execution_environment: String,
+    daily_quota: DailyDownloadQuota,
+    rate_limiter: Arc<RateLimiter>,
+    about: Arc<AboutInfo>,
+    permissions: Arc<Permissions>,
 ) -> ResponseResult<()> {
     log_update_context("command", &message);
+    let api = api.in_thread(message.chat.id, topic_thread_id(&message));
+    if !rate_limiter.check_command(message.chat.id.0) {
+        log::info!("Rate-limited command from chat_id: {}", message.chat.id);
+        api.send_text_message(message.chat.id, message.id, COMMAND_RATE_LIMITED_MESSAGE)
+            .await?;
+        return Ok(());
+    }
     let comprehensive_guide = indoc::formatdoc! { "
 Hello there! I am CrabberBot, your friendly media downloader.
 
@@ -48,6 +94,7 @@ I can download videos and photos from various platforms like Instagram, TikTok,
 <b>How to use me</b>
 To download media, simply send me the URL of the media you want to download.
 Example: <code>https://www.youtube.com/shorts/tPEE9ZwTmy0</code>
+Add <code>!hq</code> after the link to also receive photos as uncompressed files.
 
 I'll try my best to fetch the media and send it back to you. I also include the original caption (limited to 1024 characters).
 If you encounter any issues, please double-check the URL or try again later. Not all links may be supported, or there might be temporary issues.
@@ -58,7 +105,7 @@ If you encounter any issues, please double-check the URL or try again later. Not
     };
 
     match command {
-        Command::Start => {
+        Command::Start | Command::Help => {
             api.send_text_message(message.chat.id, message.id, &comprehensive_guide)
                 .await?;
         }
@@ -68,6 +115,10 @@ If you encounter any issues, please double-check the URL or try again later. Not
             api.send_text_message(message.chat.id, message.id, &value)
                 .await?;
         }
+        Command::About => {
+            api.send_text_message(message.chat.id, message.id, &about.render())
+                .await?;
+        }
         Command::Environment => {
             let value = format!("CrabberBot environment {0}", execution_environment);
             api.send_text_message(message.chat.id, message.id, &value)
@@ -83,18 +134,61 @@ If you encounter any issues, please double-check the URL or try again later. Not
         Command::Support(text) => {
             handle_support(api, storage, message, text, owner_chat_id).await?;
         }
+        Command::Feedback(text) => {
+            handle_feedback(api, rate_limiter, message, text, owner_chat_id).await?;
+        }
         Command::Refundme => {
             handle_refundme(api, storage, message).await?;
         }
+        Command::Quota => {
+            handle_quota(api, storage, message, daily_quota).await?;
+        }
+        Command::Sourcebutton(args) => {
+            handle_sourcebutton(api, storage, permissions, message, args).await?;
+        }
+        // Valid links are routed to `handle_url` before reaching this handler.
+        Command::Dl(_) => {
+            api.send_text_message(
+                message.chat.id,
+                message.id,
+                "Usage: /dl &lt;link&gt;, e.g. <code>/dl https://www.youtube.com/shorts/tPEE9ZwTmy0</code>",
+            )
+            .await?;
+        }
+        // Valid links are routed to `handle_roundify` before reaching this handler.
+        Command::Roundify(_) => {
+            api.send_text_message(
+                message.chat.id,
+                message.id,
+                "Usage: /roundify &lt;link&gt;, e.g. <code>/roundify https://www.youtube.com/shorts/tPEE9ZwTmy0</code>",
+            )
+            .await?;
+        }
+        // Valid links are routed to `handle_thumb` before reaching this handler.
+        Command::Thumb(_) => {
+            api.send_text_message(
+                message.chat.id,
+                message.id,
+                "Usage: /thumb &lt;link&gt;, e.g. <code>/thumb https://www.youtube.com/shorts/tPEE9ZwTmy0</code>",
+            )
+            .await?;
+        }
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_owner_command(
-    _bot: Bot,
+    maintenance: Arc<Maintenance>,
     api: Arc<dyn TelegramApi>,
+    downloader: Arc<dyn Downloader>,
     storage: Arc<dyn Storage>,
+    pending_uploads: Arc<PendingUploads>,
+    storage_metrics: Arc<StorageCounters>,
+    child_processes: ChildProcesses,
+    pipeline_config: Arc<PipelineConfig>,
+    download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
     message: Message,
     command: OwnerCommand,
     owner_chat_id: i64,
@@ -108,21 +202,72 @@ async fn handle_owner_command(
         OwnerCommand::Refund(args) => {
             handle_refund(api, storage, message, args, owner_chat_id).await?
         }
+        OwnerCommand::Findcached(args) => {
+            handle_findcached(api, storage, message, args, owner_chat_id).await?
+        }
+        OwnerCommand::Trace(args) => {
+            handle_trace(api, storage, message, args, owner_chat_id).await?
+        }
+        OwnerCommand::Maintenance(args) => {
+            handle_maintenance(api, storage, maintenance, message, args, owner_chat_id).await?
+        }
+        OwnerCommand::Stats => {
+            handle_stats(
+                api,
+                storage,
+                pending_uploads,
+                storage_metrics,
+                child_processes,
+                message,
+                owner_chat_id,
+            )
+            .await?
+        }
+        OwnerCommand::Queue => {
+            handle_queue(
+                api,
+                download_limiter,
+                child_processes,
+                message,
+                owner_chat_id,
+            )
+            .await?
+        }
+        OwnerCommand::Info(args) => {
+            let include_formats = log::log_enabled!(log::Level::Debug);
+            handle_info(
+                api,
+                downloader,
+                message,
+                args,
+                owner_chat_id,
+                include_formats,
+                pipeline_config.caption.clone(),
+            )
+            .await?
+        }
     }
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_url(
-    _bot: Bot,
     downloader: Arc<dyn Downloader>,
     api: Arc<dyn TelegramApi>,
-    download_limiter: Arc<ConcurrencyLimiter>,
+    download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
     storage: Arc<dyn Storage>,
     audio_extractor: Arc<dyn AudioExtractor>,
+    pipeline_config: Arc<PipelineConfig>,
+    pending_uploads: Arc<PendingUploads>,
+    recent_requests: Arc<RecentRequests>,
+    daily_quota: DailyDownloadQuota,
+    me: Me,
     message: Message,
-    url: Url,
+    request: UrlRequest,
 ) -> ResponseResult<()> {
+    let UrlRequest { url, mut options } = request;
     let chat_id = message.chat.id;
+    let api = api.in_thread(chat_id, topic_thread_id(&message));
     log::info!(
         "request_context action=url update_message_id={} chat_id={} user_id={:?} url={}",
         message.id,
@@ -131,7 +276,38 @@ async fn handle_url(
         url
     );
 
-    let _guard = match download_limiter.try_lock(chat_id) {
+    let normalized_url = cleanup_url_with_rules(&url, &pipeline_config.url_cleanup_rules);
+    // Forgotten again unless the request is delivered, so a refused or failed one can be
+    // retried right away.
+    let Some(claim) = recent_requests.claim(chat_id, normalized_url.as_str(), Instant::now())
+    else {
+        log::info!(
+            "Dropping duplicate request chat_id={} url={}",
+            chat_id,
+            normalized_url
+        );
+        return Ok(());
+    };
+    // An earlier delivery may have been the compressed photos an original-quality
+    // request asks to replace.
+    if !options.original_quality
+        && reply_with_earlier_delivery(
+            &recent_requests,
+            api.as_ref(),
+            chat_id,
+            normalized_url.as_str(),
+        )
+        .await
+    {
+        claim.accept();
+        return Ok(());
+    }
+
+    // Keyed per bot so the same user can use several of our bots at once.
+    let guard = match download_limiter.try_lock(BotChat {
+        bot_id: me.id,
+        chat_id,
+    }) {
         Some(guard) => guard,
         None => {
             api.send_text_message(
@@ -143,6 +319,10 @@ async fn handle_url(
             return Ok(());
         }
     };
+    if let Some(refusal) = check_daily_quota(storage.as_ref(), daily_quota, chat_id).await {
+        api.send_text_message(chat_id, message.id, &refusal).await?;
+        return Ok(());
+    }
     api.send_chat_action(chat_id, teloxide::types::ChatAction::Typing)
         .await?;
     api.set_message_reaction(
@@ -153,53 +333,121 @@ async fn handle_url(
         }),
     )
     .await?;
+    options.source_as_button = storage.get_chat_settings(chat_id.0).await.source_as_button;
 
-    let result = tokio::time::timeout(
-        OVERALL_REQUEST_TIMEOUT,
-        process_download_request(
-            &url,
-            chat_id,
-            message.id,
-            downloader.as_ref(),
-            api.as_ref(),
-            storage.as_ref(),
-            audio_extractor.as_ref(),
-        ),
+    let download_ctx = process_download_request(
+        &url,
+        chat_id,
+        message.id,
+        downloader.as_ref(),
+        api.as_ref(),
+        storage.as_ref(),
+        audio_extractor.as_ref(),
+        &pipeline_config,
+        &pending_uploads,
+        options,
+        &guard.progress(),
     )
     .await;
 
-    let download_ctx = match result {
-        Err(_) => {
-            log::error!("Overall request timed out for {}", url);
-            if let Err(e) = api
-                .send_text_message(
-                    chat_id,
-                    message.id,
-                    "Sorry, the request timed out. Please try again.",
-                )
-                .await
-            {
-                log::error!(
-                    "Telegram reply failed: action=request_timeout chat_id={} error={:?}",
-                    chat_id,
-                    e
-                );
-            }
-            None
-        }
-        Ok(ctx) => ctx,
-    };
-
     api.set_message_reaction(chat_id, message.id, None).await?;
 
     // Send premium buttons if we have a download context with video + cached audio
     if let Some(ctx) = download_ctx {
+        claim.accept();
+        if let Some(delivered) = ctx.delivered_message_id {
+            recent_requests.record_delivery(
+                chat_id,
+                normalized_url.as_str(),
+                delivered,
+                Instant::now(),
+            );
+        }
         maybe_send_premium_buttons(chat_id, ctx, &*api, &*storage).await;
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
+async fn handle_roundify(
+    downloader: Arc<dyn Downloader>,
+    api: Arc<dyn TelegramApi>,
+    download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
+    storage: Arc<dyn Storage>,
+    pipeline_config: Arc<PipelineConfig>,
+    child_processes: ChildProcesses,
+    rate_limiter: Arc<RateLimiter>,
+    daily_quota: DailyDownloadQuota,
+    me: Me,
+    message: Message,
+    request: RoundifyRequest,
+) -> ResponseResult<()> {
+    let chat_id = message.chat.id;
+    let api = api.in_thread(chat_id, topic_thread_id(&message));
+    log::info!(
+        "request_context action=roundify update_message_id={} chat_id={} user_id={:?} url={}",
+        message.id,
+        chat_id,
+        message.from.as_ref().map(|user| user.id.0),
+        request.url
+    );
+    if !rate_limiter.check_command(chat_id.0) {
+        log::info!("Rate-limited command from chat_id: {}", chat_id);
+        api.send_text_message(chat_id, message.id, COMMAND_RATE_LIMITED_MESSAGE)
+            .await?;
+        return Ok(());
+    }
+
+    let guard = match download_limiter.try_lock(BotChat {
+        bot_id: me.id,
+        chat_id,
+    }) {
+        Some(guard) => guard,
+        None => {
+            api.send_text_message(
+                chat_id,
+                message.id,
+                "I'm already working on a request for you. Please wait until it's finished!",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if let Some(host) = request.url.host_str() {
+        guard.progress().set_domain(host.trim_start_matches("www."));
+    }
+    if let Some(refusal) = check_daily_quota(storage.as_ref(), daily_quota, chat_id).await {
+        api.send_text_message(chat_id, message.id, &refusal).await?;
+        return Ok(());
+    }
+    api.set_message_reaction(
+        chat_id,
+        message.id,
+        Some(teloxide::types::ReactionType::Emoji {
+            emoji: "👀".to_string(),
+        }),
+    )
+    .await?;
+
+    process_roundify_request(
+        &request.url,
+        chat_id,
+        message.id,
+        downloader.as_ref(),
+        api.as_ref(),
+        storage.as_ref(),
+        &FfmpegVideoNoteConverter {
+            children: child_processes,
+        },
+        &pipeline_config,
+    )
+    .await?;
+
+    api.set_message_reaction(chat_id, message.id, None).await?;
+    Ok(())
+}
+
 fn log_update_context(action: &str, message: &Message) {
     log::info!(
         "request_context action={} update_message_id={} chat_id={} user_id={:?}",
@@ -210,50 +458,54 @@ fn log_update_context(action: &str, message: &Message) {
     );
 }
 
-// Required catch-all branch — silently ignore messages that are neither commands nor URLs.
-async fn handle_unhandled_message(
-    _bot: Bot,
-    _downloader: Arc<dyn Downloader>,
-    _api: Arc<dyn TelegramApi>,
-    _message: Message,
+/// The bot was added, removed, promoted or demoted in a chat, so the admin statuses cached
+/// for it may no longer match what the bot sees.
+async fn handle_my_chat_member(
+    permissions: Arc<Permissions>,
+    update: ChatMemberUpdated,
 ) -> ResponseResult<()> {
+    log::info!(
+        "Bot is now {:?} in chat {}",
+        update.new_chat_member.status(),
+        update.chat.id
+    );
+    permissions.forget_chat(update.chat.id);
     Ok(())
 }
 
-#[derive(BotCommands, Clone)]
-#[command(
-    rename_rule = "lowercase",
-    description = "These commands are supported:"
-)]
-enum Command {
-    #[command(description = "start interaction and display a guide.")]
-    Start,
-    #[command(description = "show bot version.")]
-    Version,
-    #[command(description = "show bot environment.")]
-    Environment,
-    #[command(description = "subscribe or buy AI Video Minutes top-up.")]
-    Subscribe,
-    #[command(description = "view Terms of Service.")]
-    Terms,
-    #[command(description = "contact customer support or get help with a payment issue.")]
-    Support(String),
-    #[command(description = "request a refund for your most recent purchase.")]
-    Refundme,
+/// A group was upgraded to a supergroup, which has a new chat id: carry the chat's
+/// settings and quota over, and forget what was tied to the old id.
+async fn handle_chat_migration(
+    storage: Arc<dyn Storage>,
+    permissions: Arc<Permissions>,
+    download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
+    me: Me,
+    message: Message,
+    new_chat_id: ChatId,
+) -> ResponseResult<()> {
+    let old_chat_id = message.chat.id;
+    log::info!(
+        "Chat {} migrated to supergroup {}",
+        old_chat_id,
+        new_chat_id
+    );
+    storage.migrate_chat(old_chat_id.0, new_chat_id.0).await;
+    download_limiter.forget(&BotChat {
+        bot_id: me.id,
+        chat_id: old_chat_id,
+    });
+    permissions.forget_chat(old_chat_id);
+    Ok(())
 }
 
-/// Owner-only commands. Never registered with Telegram (no autocomplete),
-/// handled in a separate dptree branch that pre-filters on owner chat_id.
-#[derive(BotCommands, Clone)]
-#[command(rename_rule = "lowercase")]
-enum OwnerCommand {
-    Grant(String),
-    Reply(String),
-    Refund(String),
+// Required catch-all branch — silently ignore stickers, service messages and group chatter.
+async fn ignore_message() -> ResponseResult<()> {
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let started = Instant::now();
     let mut builder = pretty_env_logger::formatted_builder();
 
     builder.filter_level(LevelFilter::Info);
@@ -273,9 +525,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     builder.init();
 
     let version = env!("CARGO_PACKAGE_VERSION");
-    log::info!("Starting CrabberBot version {}", version);
+    log::info!(
+        "Starting CrabberBot version {} ({})",
+        version,
+        GIT_COMMIT_SHA
+    );
 
     let config = AppConfig::from_env()?;
+    // Flushes pending events when dropped at the end of main.
+    #[cfg(feature = "sentry")]
+    let _sentry = config.sentry_dsn.as_deref().map(|dsn| {
+        log::info!("Reporting errors to Sentry");
+        sentry::init((
+            dsn,
+            sentry::ClientOptions {
+                release: Some(format!("crabberbot@{version}").into()),
+                environment: Some(config.execution_environment.clone().into()),
+                ..Default::default()
+            },
+        ))
+    });
+    #[cfg(not(feature = "sentry"))]
+    if config.sentry_dsn.is_some() {
+        log::warn!("SENTRY_DSN is set, but this build has no Sentry support (feature \"sentry\")");
+    }
     if config.deepgram_api_key.is_empty() || config.gemini_api_key.is_empty() {
         log::warn!(
             "DEEPGRAM_API_KEY and/or GEMINI_API_KEY not set — transcription and summarization will be unavailable"
@@ -296,42 +569,67 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
-    let pool = PgPoolOptions::new()
-        .max_connections(config.postgres_max_connections)
-        .min_connections(config.postgres_min_connections)
-        .acquire_timeout(config.postgres_acquire_timeout)
-        .connect(&config.database_url)
-        .await
-        .expect("Failed to connect to database");
-    PostgresStorage::run_migrations(&pool)
-        .await
-        .expect("Failed to run database migrations");
-    log::info!("Database connected and migrations applied.");
-    let storage: Arc<dyn Storage> = Arc::new(PostgresStorage::new(pool.clone()));
+    let StorageBackend {
+        storage,
+        pool,
+        metrics: storage_metrics,
+    } = create_storage(&config.storage_settings(), config.storage_required).await?;
+    let pool_connected = pool.is_some();
+    if pool_connected {
+        log::info!("Database connected and migrations applied.");
+    }
 
     let audio_cache_dir = config.audio_cache_dir.clone();
-    let cleanup_pool = pool.clone();
+    let cache_max_entries = config.cache_max_entries;
     let cleanup_storage = storage.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(3600));
-        loop {
-            interval.tick().await;
-            PostgresStorage::cleanup_expired(&cleanup_pool, 7).await;
-            cleanup_storage.cleanup_expired_callback_contexts().await;
-            cleanup_storage.expire_stale_topups().await;
-            cleanup_audio_cache(&cleanup_pool, &audio_cache_dir).await;
-        }
-    });
+    // Restarts the background loops below if they panic; dropping it stops them.
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_supervised(
+        "cleanup",
+        move || {
+            let pool = pool.clone();
+            let audio_cache_dir = audio_cache_dir.clone();
+            let cleanup_storage = cleanup_storage.clone();
+            async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(3600));
+                loop {
+                    interval.tick().await;
+                    if let Some(pool) = &pool {
+                        PostgresStorage::cleanup_expired(pool, 7).await;
+                        if let Some(max_entries) = cache_max_entries {
+                            PostgresStorage::evict_lru(pool, max_entries).await;
+                        }
+                    }
+                    cleanup_storage.cleanup_expired_callback_contexts().await;
+                    cleanup_storage.expire_stale_topups().await;
+                    if let Some(pool) = &pool {
+                        cleanup_audio_cache(pool, &audio_cache_dir).await;
+                    }
+                }
+            }
+        },
+        TASK_RESTART_DELAY,
+    );
 
     let client = Client::new();
-    let bot = Bot::from_env_with_client(client.clone());
 
-    let downloader: Arc<dyn Downloader> = Arc::new(
-        YtDlpDownloader::new(config.yt_dlp_path.clone(), config.downloads_dir.clone()).await,
-    );
-    let api: Arc<dyn TelegramApi> = Arc::new(TeloxideApi::new(bot.clone()));
-    let download_limiter = Arc::new(ConcurrencyLimiter::new());
-    let premium_limiter = Arc::new(ConcurrencyLimiter::new());
+    let yt_dlp = YtDlpDownloader::new(
+        config.yt_dlp_path.clone(),
+        config.downloads_dir.clone(),
+        config.geo_bypass.clone(),
+        config.yt_dlp_network.clone(),
+        config.cookies.clone(),
+    )
+    .await
+    .with_info_json(config.yt_dlp_write_info_json)
+    .with_windows_filenames(config.yt_dlp_windows_filenames);
+    let child_processes = yt_dlp.child_processes();
+    let yt_dlp_version = yt_dlp.version().map(str::to_string);
+    let prefetching_downloader = Arc::new(PrefetchingDownloader::new(Arc::new(yt_dlp)));
+    let downloader: Arc<dyn Downloader> = prefetching_downloader.clone();
+    let warmed_downloader = prefetching_downloader.clone();
+    let download_limiter: Arc<ConcurrencyLimiter<BotChat>> = Arc::new(ConcurrencyLimiter::new());
+    let premium_limiter: Arc<ConcurrencyLimiter> = Arc::new(ConcurrencyLimiter::new());
     let audio_extractor: Arc<dyn AudioExtractor> =
         Arc::new(FfmpegAudioExtractor::new(3, config.audio_cache_dir.clone()));
     let transcriber: Arc<dyn Transcriber> = Arc::new(DeepgramTranscriber::new(
@@ -344,54 +642,339 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.gemini_model.clone(),
     ));
 
+    let media_groups = Arc::new(RecentMediaGroups::new());
+    let pending_uploads = Arc::new(PendingUploads::new());
+    let recent_requests = Arc::new(RecentRequests::new(config.dedup_window));
+    let maintenance = Arc::new(Maintenance::load(storage.as_ref()).await);
+    let evicted_media_groups = media_groups.clone();
+    let evicted_requests = recent_requests.clone();
+    let rate_limiter = Arc::new(RateLimiter::new(config.command_rate_limit));
+    let evicted_rate_limits = rate_limiter.clone();
+    let permissions = Arc::new(Permissions::new());
+    let evicted_permissions = permissions.clone();
+    supervisor.spawn_supervised(
+        "eviction",
+        move || {
+            let evicted_media_groups = evicted_media_groups.clone();
+            let evicted_requests = evicted_requests.clone();
+            let prefetching_downloader = prefetching_downloader.clone();
+            let evicted_rate_limits = evicted_rate_limits.clone();
+            let evicted_permissions = evicted_permissions.clone();
+            async move {
+                let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    evicted_media_groups.evict_expired();
+                    evicted_requests.evict_expired();
+                    prefetching_downloader.evict_expired();
+                    evicted_rate_limits.evict_expired();
+                    evicted_permissions.evict_expired();
+                }
+            }
+        },
+        TASK_RESTART_DELAY,
+    );
+    let pipeline_config = Arc::new(PipelineConfig {
+        url_cleanup_rules: config.url_cleanup_rules.clone(),
+        request_timeout: config.request_timeout,
+        slow_download_warning: config.slow_download_warning,
+        local_bot_api: config.use_local_bot_api,
+        // Both set per bot once its id is known.
+        bot_id: 0,
+        in_flight_urls: Arc::default(),
+        validation: ValidationConfig {
+            warn_margin_percent: config.validation_warn_margin_percent,
+            ..ValidationConfig::for_bot_api(config.use_local_bot_api)
+        },
+        caption: CaptionOptions {
+            links: config.caption_links,
+            show_meta: config.caption_show_meta,
+            attribution: config.attribution.clone(),
+        },
+        object_store: config.object_store.clone().map(|object_store_config| {
+            log::info!(
+                "Offloading files too large for Telegram to bucket {}",
+                object_store_config.bucket
+            );
+            Arc::new(S3ObjectStore::new(client.clone(), object_store_config))
+                as Arc<dyn ObjectStore>
+        }),
+        post_download_hooks: post_download_hooks(&config, &child_processes),
+        short_url_client: Some(short_url_client()?),
+        timings_footer_chat: (config.owner_chat_id != 0).then_some(ChatId(config.owner_chat_id)),
+    });
+    let about = Arc::new(AboutInfo {
+        version: version.to_string(),
+        commit: GIT_COMMIT_SHA.to_string(),
+        execution_environment: config.execution_environment.clone(),
+        storage_backend: if pool_connected {
+            "PostgreSQL"
+        } else if config.storage_url == StorageUrl::Disabled {
+            "disabled"
+        } else if matches!(config.storage_url, StorageUrl::File(_)) {
+            "file"
+        } else {
+            "in-memory"
+        },
+        owner_configured: config.owner_chat_id != 0,
+        validation: pipeline_config.validation.clone(),
+        yt_dlp_version,
+    });
+
+    let dry_run_bot_api = match config.telegram_mode {
+        TelegramMode::DryRun => {
+            let url = serve_stub_bot_api().await?;
+            log::warn!(
+                "Dry-run mode: nothing is sent to Telegram; Bot API calls are logged and answered by a stub at {}",
+                url
+            );
+            Some(url)
+        }
+        TelegramMode::Live => None,
+    };
+
     let addr = ([0, 0, 0, 0], config.port).into();
-    let url = config.webhook_url.clone();
+    let mut routers = Vec::with_capacity(config.bot_tokens.len());
+    let mut stop_flags = Vec::with_capacity(config.bot_tokens.len());
+    let mut dispatchers = tokio::task::JoinSet::new();
+    let mut shutdown_tokens = Vec::with_capacity(config.bot_tokens.len());
+    let mut bot_ids = Vec::with_capacity(config.bot_tokens.len());
 
-    log::info!("Setting webhook {}", url);
-    let listener = teloxide::update_listeners::webhooks::axum(
-        bot.clone(),
-        teloxide::update_listeners::webhooks::Options::new(addr, url.clone()),
-    )
-    .await
-    .expect("Failed to set webhook");
-    log::info!("Successfully set webhook {}", url);
+    for token in &config.bot_tokens {
+        let mut bot = Bot::with_client(token, client.clone());
+        if let Some(api_url) = dry_run_bot_api
+            .as_ref()
+            .or(config.telegram_api_url.as_ref())
+        {
+            bot = bot.set_api_url(api_url.clone());
+        }
+        let me = bot.get_me().await.expect("Failed to fetch bot info.");
+        let url = webhook_url_for(&config.webhook_url, me.username(), config.bot_tokens.len());
+        let bot_id = me.id.0 as i64;
+        bot_ids.push(bot_id);
 
-    bot.set_my_commands(Command::bot_commands())
-        .await
-        .expect("Failed to set bot commands.");
-    log::info!("Successfully set bot commands.");
+        log::info!("Setting webhook {}", url);
+        let mut webhook_options = webhooks::Options::new(addr, url.clone());
+        if let Some(secret) = &config.webhook_secret {
+            webhook_options = webhook_options.secret_token(secret.clone());
+        }
+        let (listener, stop_flag, router) = webhooks::axum_to_router(bot.clone(), webhook_options)
+            .await
+            .expect("Failed to set webhook");
+        log::info!("Successfully set webhook {}", url);
+        routers.push(router);
+        stop_flags.push(stop_flag);
 
-    let bot_description = "Your friendly media downloader from various platforms like Instagram, TikTok, YouTube, and more!";
-    bot.set_my_description()
-        .description(bot_description)
-        .await
-        .expect("Failed to set bot description.");
-    log::info!("Successfully set bot description.");
+        let api: Arc<dyn TelegramApi> = if dry_run_bot_api.is_some() {
+            Arc::new(NoopTelegramApi::new(config.dry_run_latency))
+        } else {
+            Arc::new(TeloxideApi::new(bot.clone()))
+        };
+        // Additional bots keep the name they were given in BotFather.
+        let is_primary_bot = routers.len() == 1;
+        if dry_run_bot_api.is_none() {
+            bot_profile::sync(
+                &bot,
+                &DesiredProfile {
+                    name: is_primary_bot.then(|| bot_name_for(&config.webhook_url).to_string()),
+                    description: BOT_DESCRIPTION.to_string(),
+                    command_menus: command_menus(config.owner_chat_id),
+                },
+            )
+            .await;
+        }
+        let error_reporter = OwnerErrorReporter::new(
+            api.clone(),
+            ChatId(config.owner_chat_id),
+            me.username().to_string(),
+            config.error_report_interval,
+        );
+        // Without an interval every error is reported at once, so nothing is held back.
+        if !config.error_report_interval.is_zero() {
+            let flushed_reporter = error_reporter.clone();
+            let error_report_interval = config.error_report_interval;
+            supervisor.spawn_supervised(
+                &format!("error report flush for @{}", me.username()),
+                move || {
+                    let flushed_reporter = flushed_reporter.clone();
+                    async move {
+                        let mut interval = tokio::time::interval(error_report_interval);
+                        loop {
+                            interval.tick().await;
+                            flushed_reporter.flush().await;
+                        }
+                    }
+                },
+                TASK_RESTART_DELAY,
+            );
+        }
+        let mut dispatcher = Dispatcher::builder(bot, schema())
+            .error_handler(error_reporter)
+            .dependencies(dptree::deps![
+                downloader.clone(),
+                api,
+                download_limiter.clone(),
+                premium_limiter.clone(),
+                storage.clone(),
+                audio_extractor.clone(),
+                transcriber.clone(),
+                summarizer.clone(),
+                Arc::new(PipelineConfig {
+                    bot_id,
+                    in_flight_urls: Arc::default(),
+                    ..(*pipeline_config).clone()
+                }),
+                media_groups.clone(),
+                pending_uploads.clone(),
+                storage_metrics.clone(),
+                child_processes.clone(),
+                recent_requests.clone(),
+                maintenance.clone(),
+                rate_limiter.clone(),
+                permissions.clone(),
+                Arc::new(RecentUpdates::default()),
+                about.clone(),
+                config.daily_quota,
+                config.owner_chat_id,
+                config.execution_environment.clone()
+            ])
+            .build();
+        shutdown_tokens.push(dispatcher.shutdown_token());
+        dispatchers.spawn(async move {
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text(
+                        "An error has occurred in the dispatcher",
+                    ),
+                )
+                .await;
+        });
+    }
 
-    let bot_name = if config.webhook_url.as_str().contains("test") {
-        "CrabberBot TEST"
-    } else {
-        "CrabberBot | Video Downloader"
-    };
-    log::info!("Successfully set bot name. {}", bot_name);
+    let cache_warmer = Arc::new(CacheWarmer::new(
+        storage.clone(),
+        warmed_downloader,
+        bot_ids,
+    ));
+    supervisor.spawn_supervised(
+        "cache warmer",
+        move || {
+            let cache_warmer = cache_warmer.clone();
+            async move {
+                cache_warmer.run().await;
+            }
+        },
+        TASK_RESTART_DELAY,
+    );
+
+    // All bots share one listener; it shuts down once every dispatcher has stopped.
+    let tcp_listener = tokio::net::TcpListener::bind(addr).await?;
+    let dashboard = config
+        .dashboard_credentials
+        .clone()
+        .map(|credentials| DashboardState {
+            credentials,
+            about: about.clone(),
+            started,
+            download_limiter: download_limiter.clone(),
+            storage: storage.clone(),
+        });
+    let server = tokio::spawn(server::serve(
+        tcp_listener,
+        server::app(routers, dashboard),
+        stop_flags,
+    ));
+
+    // Stop the dispatchers on ^C or SIGTERM, as sent by `docker stop`, and stop running
+    // downloads right away, so in-flight requests finish quickly and no yt-dlp is left
+    // behind when we exit.
+    let shutdown_children = child_processes.clone();
+    tokio::spawn(async move {
+        let signal = shutdown_signal().await;
+        log::info!("{} received, shutting down", signal);
+        for token in &shutdown_tokens {
+            if token.shutdown().is_err() {
+                log::info!("Dispatcher isn't running, nothing to shut down");
+            }
+        }
+        shutdown_children
+            .terminate_all(CHILD_TERMINATION_GRACE)
+            .await;
+    });
 
+    while let Some(result) = dispatchers.join_next().await {
+        result?;
+    }
+    server.await??;
+    child_processes.terminate_all(CHILD_TERMINATION_GRACE).await;
+
+    Ok(())
+}
+
+/// Wait for ^C or SIGTERM and name the one that came.
+async fn shutdown_signal() -> &'static str {
+    let mut terminate =
+        tokio::signal::unix::signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            result.expect("Failed to listen for ^C");
+            "^C"
+        }
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+fn schema() -> UpdateHandler<teloxide::RequestError> {
     let successful_payment_filter =
         dptree::filter(|msg: Message| msg.successful_payment().is_some());
     let refunded_payment_filter =
         dptree::filter(|msg: Message| matches!(msg.kind, MessageKind::RefundedPayment(_)));
 
+    // Placed before each handler that starts new work, which is then never reached.
+    let maintenance_gate =
+        || dptree::filter_map(maintenance_refusal).endpoint(refuse_during_maintenance);
     let owner_commands = dptree::entry()
         .filter(|msg: Message, oid: i64| msg.chat.id.0 == oid)
         .filter_command::<OwnerCommand>()
         .endpoint(handle_owner_command);
+    let download_command = dptree::entry()
+        .filter_command::<Command>()
+        .filter_map(|command: Command| match command {
+            Command::Dl(text) => UrlRequest::parse(&text),
+            _ => None,
+        })
+        .branch(maintenance_gate())
+        .endpoint(handle_url);
+    let roundify_command = dptree::entry()
+        .filter_command::<Command>()
+        .filter_map(|command: Command| match command {
+            Command::Roundify(text) => RoundifyRequest::parse(&text),
+            _ => None,
+        })
+        .branch(maintenance_gate())
+        .endpoint(handle_roundify);
+    let thumb_command = dptree::entry()
+        .filter_command::<Command>()
+        .filter_map(|command: Command| match command {
+            Command::Thumb(text) => ThumbRequest::parse(&text),
+            _ => None,
+        })
+        .branch(maintenance_gate())
+        .endpoint(handle_thumb);
     let commands = dptree::entry()
         .filter_command::<Command>()
         .endpoint(handle_command);
     let urls = dptree::entry()
-        .filter_map(|msg: Message| msg.text().and_then(|text| Url::parse(text).ok()))
+        .filter_map(|msg: Message| msg.text().and_then(UrlRequest::parse))
+        .branch(maintenance_gate())
         .endpoint(handle_url);
+    let unhandled_text =
+        dptree::filter(|msg: Message| is_unhandled_text(&msg)).endpoint(handle_unhandled_message);
 
-    let handler = dptree::entry()
+    dptree::entry()
+        // Telegram redelivers updates the webhook was slow to answer; handle each only once.
+        .branch(dptree::filter(is_redelivery).endpoint(ignore_message))
         .branch(
             Update::filter_message()
                 .branch(
@@ -406,40 +989,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             handle_refunded_payment(api, storage, msg).await
                         }),
                 )
+                .branch(
+                    dptree::filter_map(|msg: Message| msg.migrate_to_chat_id().copied())
+                        .endpoint(handle_chat_migration),
+                )
                 .branch(owner_commands)
+                .branch(download_command)
+                .branch(roundify_command)
+                .branch(thumb_command)
                 .branch(commands)
                 .branch(urls)
-                .branch(dptree::entry().endpoint(handle_unhandled_message)),
+                .branch(unhandled_text)
+                .branch(dptree::entry().endpoint(ignore_message)),
         )
         .branch(
             Update::filter_callback_query().endpoint(handle_callback_query),
         )
         .branch(
             Update::filter_pre_checkout_query().endpoint(handle_pre_checkout_query),
-        );
-
-    Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![
-            downloader,
-            api,
-            download_limiter,
-            premium_limiter,
-            storage,
-            audio_extractor,
-            transcriber,
-            summarizer,
-            config.owner_chat_id,
-            config.execution_environment.clone()
-        ])
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch_with_listener(
-            listener,
-            LoggingErrorHandler::with_custom_text("An error has occurred in the dispatcher"),
         )
-        .await;
+        .branch(Update::filter_my_chat_member().endpoint(handle_my_chat_member))
+}
 
-    Ok(())
+/// Post-download hooks enabled by the environment, in the order they run.
+fn post_download_hooks(
+    config: &AppConfig,
+    children: &ChildProcesses,
+) -> Vec<Arc<dyn PostDownloadHook>> {
+    let mut hooks: Vec<Arc<dyn PostDownloadHook>> = Vec::new();
+    if config.compression_max_bitrate_kbps > 0 {
+        log::info!(
+            "Compressing videos to at most {} kbps",
+            config.compression_max_bitrate_kbps
+        );
+        hooks.push(Arc::new(FfmpegCompressionHook {
+            max_bitrate_kbps: config.compression_max_bitrate_kbps,
+            children: children.clone(),
+        }));
+    }
+    hooks
 }
 
 /// Delete audio cache files older than 2 hours.
@@ -471,13 +1059,12 @@ async fn cleanup_audio_cache(pool: &sqlx::PgPool, audio_cache_dir: &std::path::P
                 if referenced.contains(path_str.as_ref()) {
                     continue; // live cache entry — leave it alone
                 }
-                if let Ok(metadata) = entry.metadata().await {
-                    if let Ok(modified) = metadata.modified() {
-                        if modified.elapsed().unwrap_or_default() > Duration::from_secs(7200) {
-                            let _ = tokio::fs::remove_file(&path).await;
-                            log::info!("Removed orphaned audio cache: {:?}", path);
-                        }
-                    }
+                if let Ok(metadata) = entry.metadata().await
+                    && let Ok(modified) = metadata.modified()
+                    && modified.elapsed().unwrap_or_default() > Duration::from_secs(7200)
+                {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    log::info!("Removed orphaned audio cache: {:?}", path);
                 }
             }
             Ok(None) => break,
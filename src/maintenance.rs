@@ -0,0 +1,237 @@
+//! Maintenance mode: while it is on, e.g. during a yt-dlp breakage, new downloads are
+//! refused with a short notice instead of being attempted. The owner's chat is exempt, so
+//! fixes can be tried out.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+
+use crate::downloader::escape_html_text;
+use crate::storage::Storage;
+use crate::telegram_api::{TelegramApi, topic_thread_id};
+
+/// Sent while maintenance is on and `/maintenance on` was given no message.
+pub const DEFAULT_MAINTENANCE_MESSAGE: &str =
+    "🛠 I'm down for maintenance right now. Please try again later!";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    /// Shown instead of `DEFAULT_MAINTENANCE_MESSAGE`.
+    pub message: Option<String>,
+}
+
+impl MaintenanceState {
+    /// `/maintenance` arguments: `on [message]` or `off`.
+    pub fn parse(args: &str) -> Option<Self> {
+        let args = args.trim();
+        let (switch, message) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let message = Some(message.trim())
+            .filter(|m| !m.is_empty())
+            .map(String::from);
+        match switch.to_ascii_lowercase().as_str() {
+            "on" => Some(Self {
+                enabled: true,
+                message,
+            }),
+            "off" if message.is_none() => Some(Self::default()),
+            _ => None,
+        }
+    }
+
+    /// What users are told while maintenance is on; `None` when it is off.
+    pub fn refusal(&self) -> Option<&str> {
+        self.enabled.then(|| {
+            self.message
+                .as_deref()
+                .unwrap_or(DEFAULT_MAINTENANCE_MESSAGE)
+        })
+    }
+}
+
+/// The maintenance state shared by all handlers and bots.
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    state: RwLock<MaintenanceState>,
+}
+
+impl Maintenance {
+    /// The state last set with `/maintenance`, so it survives restarts.
+    pub async fn load(storage: &dyn Storage) -> Self {
+        let state = storage.get_maintenance().await;
+        if let Some(refusal) = state.refusal() {
+            log::warn!("Starting in maintenance mode: {}", refusal);
+        }
+        Self {
+            state: RwLock::new(state),
+        }
+    }
+
+    pub fn state(&self) -> MaintenanceState {
+        self.state
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub async fn set(&self, state: MaintenanceState, storage: &dyn Storage) {
+        storage.set_maintenance(&state).await;
+        *self.state.write().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+}
+
+/// What a chat asking for new work is told during maintenance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceRefusal(pub String);
+
+/// dptree filter for requests to refuse: maintenance is on and they are not from the
+/// owner's chat. Chat admins, as checked by `Permissions::is_admin`, are refused too: they
+/// only administer their own chat, and every user administers their private chat with the
+/// bot, so exempting them would leave maintenance mode refusing almost nobody.
+pub fn maintenance_refusal(
+    message: Message,
+    maintenance: Arc<Maintenance>,
+    owner_chat_id: i64,
+) -> Option<MaintenanceRefusal> {
+    if owner_chat_id != 0 && message.chat.id.0 == owner_chat_id {
+        return None;
+    }
+    maintenance
+        .state()
+        .refusal()
+        .map(|refusal| MaintenanceRefusal(refusal.to_string()))
+}
+
+/// Answer a request refused by `maintenance_refusal`. No lock is taken and nothing is
+/// downloaded.
+pub async fn refuse_during_maintenance(
+    api: Arc<dyn TelegramApi>,
+    message: Message,
+    refusal: MaintenanceRefusal,
+) -> ResponseResult<()> {
+    let chat_id = message.chat.id;
+    log::info!("Refusing request in chat {} during maintenance", chat_id);
+    api.in_thread(chat_id, topic_thread_id(&message))
+        .send_text_message(chat_id, message.id, &escape_html_text(&refusal.0))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_storage::MemoryStorage;
+    use crate::telegram_api::MockTelegramApi;
+
+    fn test_message(chat_id: i64) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 7,
+            "date": 1_700_000_000,
+            "chat": {"id": chat_id, "type": "private", "first_name": "Test"},
+            "text": "https://example.com/video"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_maintenance_args() {
+        assert_eq!(
+            MaintenanceState::parse("on"),
+            Some(MaintenanceState {
+                enabled: true,
+                message: None
+            })
+        );
+        assert_eq!(
+            MaintenanceState::parse(" ON  Back at 6pm, sorry! "),
+            Some(MaintenanceState {
+                enabled: true,
+                message: Some("Back at 6pm, sorry!".to_string())
+            })
+        );
+        assert_eq!(
+            MaintenanceState::parse("off"),
+            Some(MaintenanceState::default())
+        );
+        assert_eq!(MaintenanceState::parse("off now"), None);
+        assert_eq!(MaintenanceState::parse(""), None);
+        assert_eq!(MaintenanceState::parse("maybe"), None);
+    }
+
+    #[test]
+    fn test_refusal_uses_custom_message_when_given() {
+        assert_eq!(MaintenanceState::default().refusal(), None);
+        assert_eq!(
+            MaintenanceState::parse("on").unwrap().refusal(),
+            Some(DEFAULT_MAINTENANCE_MESSAGE)
+        );
+        assert_eq!(
+            MaintenanceState::parse("on Back soon").unwrap().refusal(),
+            Some("Back soon")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_survives_restart() {
+        let storage = MemoryStorage::new();
+        let maintenance = Maintenance::load(&storage).await;
+        assert_eq!(maintenance.state(), MaintenanceState::default());
+
+        let on = MaintenanceState::parse("on Back soon").unwrap();
+        maintenance.set(on.clone(), &storage).await;
+        assert_eq!(maintenance.state(), on);
+
+        assert_eq!(Maintenance::load(&storage).await.state(), on);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_refused_during_maintenance_except_for_owner() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use teloxide::dispatching::DpHandlerDescription;
+        use teloxide::dptree;
+
+        let downloads = Arc::new(AtomicU32::new(0));
+        let handler: dptree::Handler<'_, ResponseResult<()>, DpHandlerDescription> =
+            dptree::entry()
+                .branch(dptree::filter_map(maintenance_refusal).endpoint(refuse_during_maintenance))
+                .branch(
+                    dptree::entry().endpoint(|downloads: Arc<AtomicU32>| async move {
+                        downloads.fetch_add(1, Ordering::SeqCst);
+                        Ok(())
+                    }),
+                );
+        let mut api = MockTelegramApi::new();
+        api.expect_in_thread().returning(|_, _| {
+            let mut api = MockTelegramApi::new();
+            api.expect_send_text_message()
+                .withf(|chat_id, _, text| *chat_id == ChatId(1) && text == "Back soon")
+                .times(1)
+                .returning(|_, _, _| Ok(()));
+            Arc::new(api)
+        });
+        let api: Arc<dyn TelegramApi> = Arc::new(api);
+        let storage = MemoryStorage::new();
+        let maintenance = Arc::new(Maintenance::default());
+        let owner_chat_id: i64 = 99;
+        let dispatch = |chat_id: i64| {
+            handler.dispatch(dptree::deps![
+                test_message(chat_id),
+                api.clone(),
+                maintenance.clone(),
+                owner_chat_id,
+                downloads.clone()
+            ])
+        };
+
+        assert!(dispatch(1).await.is_break());
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+        maintenance
+            .set(MaintenanceState::parse("on Back soon").unwrap(), &storage)
+            .await;
+        assert!(dispatch(1).await.is_break());
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+        assert!(dispatch(owner_chat_id).await.is_break());
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,620 @@
+//! In-process `Storage` used when Postgres is unavailable at startup and
+//! `STORAGE_REQUIRED=false`. Nothing survives a restart: the media cache and
+//! subscriptions live only as long as the process, so no purchases are taken.
+//! `FileStorage` saves its state to disk to keep it across restarts.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::downloader::MediaType;
+use crate::handler::CallbackContext;
+use crate::maintenance::MaintenanceState;
+use crate::storage::{
+    ActivityReport, CacheMetadata, CacheSearchResult, CacheStats, CachedFile, CachedMedia,
+    ChatSettings, Delivery, PaymentRecord, RequestLogEntry, Storage,
+};
+use crate::subscription::{SubscriptionInfo, SubscriptionTier};
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    id: i32,
+    caption: String,
+    files: Vec<(String, MediaType)>,
+    audio_cache_path: Option<String>,
+    media_duration_secs: Option<i32>,
+    metadata: CacheMetadata,
+    created_at: DateTime<Utc>,
+    last_used_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredPayment {
+    user_id: i64,
+    record: PaymentRecord,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredDelivery {
+    source_url: String,
+    cache_id: Option<i32>,
+    delivered_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredContext {
+    context: CallbackContext,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Inner {
+    /// Keyed by bot id and source URL.
+    #[serde(with = "pairs")]
+    cache: HashMap<(i64, String), CacheEntry>,
+    next_cache_id: i32,
+    subscriptions: HashMap<i64, SubscriptionInfo>,
+    payments: Vec<StoredPayment>,
+    usage: Vec<(i64, DateTime<Utc>)>,
+    /// Chat and time of each delivered download, pruned to the current UTC day.
+    downloads: Vec<(i64, DateTime<Utc>)>,
+    callback_contexts: HashMap<i32, StoredContext>,
+    next_context_id: i32,
+    chat_settings: HashMap<i64, ChatSettings>,
+    /// Keyed by chat and message id.
+    #[serde(with = "pairs")]
+    deliveries: HashMap<(i64, i32), StoredDelivery>,
+    maintenance: MaintenanceState,
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    inner: Mutex<Inner>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore the state written by `to_json`.
+    pub fn from_json(json: &[u8]) -> serde_json::Result<Self> {
+        Ok(Self {
+            inner: Mutex::new(serde_json::from_slice(json)?),
+        })
+    }
+
+    /// The whole state, for `FileStorage` to save.
+    pub fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(&*self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("memory storage lock poisoned")
+    }
+}
+
+/// JSON object keys must be strings, so maps with tuple keys are written as pair lists.
+mod pairs {
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+fn start_of_utc_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    now.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc()
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get_cached_media(&self, bot_id: i64, source_url: &str) -> Option<CachedMedia> {
+        let mut inner = self.lock();
+        let entry = inner.cache.get_mut(&(bot_id, source_url.to_owned()))?;
+        entry.last_used_at = Utc::now();
+        if entry.files.is_empty() {
+            return None;
+        }
+        Some(CachedMedia {
+            id: entry.id,
+            caption: entry.caption.clone(),
+            files: entry
+                .files
+                .iter()
+                .map(|(file_id, media_type)| CachedFile {
+                    telegram_file_id: file_id.clone(),
+                    media_type: *media_type,
+                })
+                .collect(),
+            audio_cache_path: entry.audio_cache_path.clone(),
+            media_duration_secs: entry.media_duration_secs,
+        })
+    }
+
+    async fn store_cached_media(
+        &self,
+        bot_id: i64,
+        source_url: &str,
+        caption: &str,
+        files: &[(String, MediaType)],
+        audio_cache_path: Option<String>,
+        media_duration_secs: Option<i32>,
+        metadata: &CacheMetadata,
+    ) {
+        let mut inner = self.lock();
+        let now = Utc::now();
+        // Like the Postgres upsert, storing a URL again keeps its id and creation time.
+        let key = (bot_id, source_url.to_owned());
+        let (id, created_at) = match inner.cache.get(&key) {
+            Some(entry) => (entry.id, entry.created_at),
+            None => {
+                inner.next_cache_id += 1;
+                (inner.next_cache_id, now)
+            }
+        };
+        inner.cache.insert(
+            key,
+            CacheEntry {
+                id,
+                caption: caption.to_owned(),
+                files: files.to_vec(),
+                audio_cache_path,
+                media_duration_secs,
+                metadata: metadata.clone(),
+                created_at,
+                last_used_at: now,
+            },
+        );
+    }
+
+    async fn search_cache(&self, query: &str, limit: i64) -> Vec<CacheSearchResult> {
+        let needle = query.to_lowercase();
+        let matches =
+            |value: Option<&str>| value.is_some_and(|v| v.to_lowercase().contains(&needle));
+        let inner = self.lock();
+        let mut results: Vec<CacheSearchResult> = inner
+            .cache
+            .iter()
+            .filter(|((_, url), entry)| {
+                matches(entry.metadata.uploader.as_deref())
+                    || matches(entry.metadata.title.as_deref())
+                    || matches(entry.metadata.extractor.as_deref())
+                    || matches(Some(&entry.caption))
+                    || matches(Some(url))
+            })
+            .map(|((_, url), entry)| CacheSearchResult {
+                source_url: url.clone(),
+                metadata: entry.metadata.clone(),
+                last_used_at: entry.last_used_at,
+            })
+            .collect();
+        results.sort_by_key(|r| std::cmp::Reverse(r.last_used_at));
+        results.truncate(limit.max(0) as usize);
+        results
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats {
+            entries: inner.cache.len() as i64,
+            files: inner.cache.values().map(|e| e.files.len() as i64).sum(),
+        }
+    }
+
+    /// Requests are only written to the log here, so there is no activity to report.
+    async fn activity_report(&self, _window: std::time::Duration) -> ActivityReport {
+        ActivityReport::default()
+    }
+
+    async fn log_request(
+        &self,
+        chat_id: i64,
+        source_url: &str,
+        status: &str,
+        processing_time_ms: i64,
+        bytes_transferred: Option<i64>,
+    ) {
+        log::info!(
+            "request chat_id={} url={} status={} processing_time_ms={} bytes_transferred={:?}",
+            chat_id,
+            source_url,
+            status,
+            processing_time_ms,
+            bytes_transferred
+        );
+        if matches!(status, "success" | "cached") {
+            let now = Utc::now();
+            let mut inner = self.lock();
+            inner
+                .downloads
+                .retain(|(_, at)| *at >= start_of_utc_day(now));
+            inner.downloads.push((chat_id, now));
+        }
+    }
+
+    async fn get_top_urls(&self, _limit: i64, _since: DateTime<Utc>) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Nothing to list either, for the same reason.
+    async fn recent_requests(&self, _limit: i64) -> Vec<RequestLogEntry> {
+        Vec::new()
+    }
+
+    async fn record_delivery(
+        &self,
+        chat_id: i64,
+        message_ids: &[i32],
+        source_url: &str,
+        cache_id: Option<i32>,
+    ) {
+        let mut inner = self.lock();
+        let now = Utc::now();
+        for &message_id in message_ids {
+            inner
+                .deliveries
+                .entry((chat_id, message_id))
+                .or_insert_with(|| StoredDelivery {
+                    source_url: source_url.to_owned(),
+                    cache_id,
+                    delivered_at: now,
+                });
+        }
+    }
+
+    async fn get_delivery(&self, chat_id: i64, message_id: i32) -> Option<Delivery> {
+        let inner = self.lock();
+        let delivery = inner.deliveries.get(&(chat_id, message_id))?;
+        let cached_at = delivery.cache_id.and_then(|id| {
+            inner
+                .cache
+                .values()
+                .find(|entry| entry.id == id)
+                .map(|entry| entry.created_at)
+        });
+        Some(Delivery {
+            source_url: delivery.source_url.clone(),
+            cache_id: delivery.cache_id,
+            delivered_at: delivery.delivered_at,
+            cached_at,
+        })
+    }
+
+    async fn get_daily_download_count(&self, chat_id: i64) -> i64 {
+        let since = start_of_utc_day(Utc::now());
+        self.lock()
+            .downloads
+            .iter()
+            .filter(|(id, at)| *id == chat_id && *at >= since)
+            .count() as i64
+    }
+
+    async fn get_chat_settings(&self, chat_id: i64) -> ChatSettings {
+        self.lock()
+            .chat_settings
+            .get(&chat_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    async fn set_source_as_button(&self, chat_id: i64, enabled: bool) {
+        self.lock()
+            .chat_settings
+            .entry(chat_id)
+            .or_default()
+            .source_as_button = enabled;
+    }
+
+    async fn get_maintenance(&self) -> MaintenanceState {
+        self.lock().maintenance.clone()
+    }
+
+    async fn set_maintenance(&self, state: &MaintenanceState) {
+        self.lock().maintenance = state.clone();
+    }
+
+    async fn migrate_chat(&self, old_chat_id: i64, new_chat_id: i64) {
+        let mut inner = self.lock();
+        if let Some(settings) = inner.chat_settings.remove(&old_chat_id) {
+            inner.chat_settings.entry(new_chat_id).or_insert(settings);
+        }
+        for (chat_id, _) in &mut inner.downloads {
+            if *chat_id == old_chat_id {
+                *chat_id = new_chat_id;
+            }
+        }
+        // Their message ids belong to the old group, so they can't be moved.
+        inner
+            .deliveries
+            .retain(|(chat_id, _), _| *chat_id != old_chat_id);
+    }
+
+    async fn get_subscription(&self, user_id: i64) -> SubscriptionInfo {
+        self.lock()
+            .subscriptions
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(SubscriptionInfo::free_default)
+    }
+
+    async fn upsert_subscription(&self, user_id: i64, tier: SubscriptionTier, duration_days: i64) {
+        let mut inner = self.lock();
+        let sub = inner
+            .subscriptions
+            .entry(user_id)
+            .or_insert_with(SubscriptionInfo::free_default);
+        sub.ai_seconds_limit = tier.ai_seconds_limit();
+        sub.tier = tier;
+        sub.ai_seconds_used = 0;
+        sub.expires_at = Some(Utc::now() + TimeDelta::days(duration_days));
+    }
+
+    /// A subscription bought here would be lost on the next restart.
+    fn records_payments(&self) -> bool {
+        false
+    }
+
+    async fn record_payment(
+        &self,
+        user_id: i64,
+        telegram_charge_id: &str,
+        _provider_charge_id: &str,
+        product: &str,
+        amount: i32,
+    ) {
+        let mut inner = self.lock();
+        if inner
+            .payments
+            .iter()
+            .any(|p| p.record.telegram_charge_id == telegram_charge_id)
+        {
+            return;
+        }
+        inner.payments.push(StoredPayment {
+            user_id,
+            record: PaymentRecord {
+                telegram_charge_id: telegram_charge_id.to_owned(),
+                product: product.to_owned(),
+                amount,
+                created_at: Utc::now(),
+            },
+        });
+    }
+
+    async fn consume_ai_seconds(&self, user_id: i64, seconds: i32) {
+        if let Some(sub) = self.lock().subscriptions.get_mut(&user_id) {
+            let monthly_left = (sub.ai_seconds_limit - sub.ai_seconds_used).max(0);
+            sub.ai_seconds_used = (sub.ai_seconds_used + seconds).min(sub.ai_seconds_limit);
+            sub.topup_seconds_available =
+                (sub.topup_seconds_available - (seconds - monthly_left).max(0)).max(0);
+        }
+    }
+
+    async fn add_topup_seconds(&self, user_id: i64, seconds: i32) {
+        let mut inner = self.lock();
+        let sub = inner
+            .subscriptions
+            .entry(user_id)
+            .or_insert_with(SubscriptionInfo::free_default);
+        sub.topup_seconds_available += seconds;
+        sub.last_topup_at = Some(Utc::now());
+    }
+
+    async fn record_premium_usage(
+        &self,
+        user_id: i64,
+        _feature: &str,
+        _source_url: &str,
+        _duration_secs: i32,
+        _units: f64,
+        _cost_usd: f64,
+    ) {
+        self.lock().usage.push((user_id, Utc::now()));
+    }
+
+    async fn store_callback_context(&self, ctx: &CallbackContext) -> i32 {
+        let mut inner = self.lock();
+        inner.next_context_id += 1;
+        let id = inner.next_context_id;
+        inner.callback_contexts.insert(
+            id,
+            StoredContext {
+                context: ctx.clone(),
+                created_at: Utc::now(),
+            },
+        );
+        id
+    }
+
+    async fn get_callback_context(&self, context_id: i32) -> Option<CallbackContext> {
+        self.lock()
+            .callback_contexts
+            .get(&context_id)
+            .map(|stored| stored.context.clone())
+    }
+
+    async fn cache_transcript(&self, context_id: i32, transcript: &str, language: Option<String>) {
+        if let Some(stored) = self.lock().callback_contexts.get_mut(&context_id) {
+            stored.context.transcript = Some(transcript.to_owned());
+            stored.context.transcript_language = language;
+        }
+    }
+
+    async fn revoke_subscription(&self, user_id: i64) {
+        if let Some(sub) = self.lock().subscriptions.get_mut(&user_id) {
+            sub.tier = SubscriptionTier::Free;
+            sub.ai_seconds_limit = 0;
+            sub.expires_at = None;
+        }
+    }
+
+    async fn revoke_topup(&self, user_id: i64, seconds: i32) {
+        if let Some(sub) = self.lock().subscriptions.get_mut(&user_id) {
+            sub.topup_seconds_available = (sub.topup_seconds_available - seconds).max(0);
+        }
+    }
+
+    async fn get_latest_payment(&self, user_id: i64) -> Option<PaymentRecord> {
+        self.get_recent_payments(user_id, 1)
+            .await
+            .into_iter()
+            .next()
+    }
+
+    async fn get_recent_payments(&self, user_id: i64, limit: i64) -> Vec<PaymentRecord> {
+        let inner = self.lock();
+        let mut payments: Vec<PaymentRecord> = inner
+            .payments
+            .iter()
+            .filter(|p| p.user_id == user_id)
+            .map(|p| p.record.clone())
+            .collect();
+        payments.sort_by_key(|p| std::cmp::Reverse(p.created_at));
+        payments.truncate(limit.max(0) as usize);
+        payments
+    }
+
+    async fn has_ai_usage_since(&self, user_id: i64, since: DateTime<Utc>) -> bool {
+        self.lock()
+            .usage
+            .iter()
+            .any(|(uid, at)| *uid == user_id && *at > since)
+    }
+
+    async fn cleanup_expired_callback_contexts(&self) {
+        let cutoff = Utc::now() - TimeDelta::hours(24);
+        self.lock()
+            .callback_contexts
+            .retain(|_, stored| stored.created_at >= cutoff);
+    }
+
+    async fn expire_stale_topups(&self) {
+        let cutoff = Utc::now() - TimeDelta::days(crate::terms::TOPUP_EXPIRY_DAYS);
+        for sub in self.lock().subscriptions.values_mut() {
+            if sub.last_topup_at.is_some_and(|t| t < cutoff) {
+                sub.topup_seconds_available = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cache_round_trip() {
+        let storage = MemoryStorage::new();
+        assert!(
+            storage
+                .get_cached_media(1, "https://a.com/1")
+                .await
+                .is_none()
+        );
+
+        storage
+            .store_cached_media(
+                1,
+                "https://a.com/1",
+                "caption",
+                &[("file".to_string(), MediaType::Photo)],
+                None,
+                None,
+                &CacheMetadata::default(),
+            )
+            .await;
+
+        let cached = storage
+            .get_cached_media(1, "https://a.com/1")
+            .await
+            .unwrap();
+        assert_eq!(cached.caption, "caption");
+        assert_eq!(cached.files[0].telegram_file_id, "file");
+        // Another bot can't send the first bot's file ids.
+        assert!(
+            storage
+                .get_cached_media(2, "https://a.com/1")
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_daily_download_count_ignores_failures() {
+        let storage = MemoryStorage::new();
+        storage
+            .log_request(1, "https://a.com/1", "success", 10, None)
+            .await;
+        storage
+            .log_request(1, "https://a.com/2", "cached", 10, None)
+            .await;
+        storage
+            .log_request(1, "https://a.com/3", "error", 10, None)
+            .await;
+        storage
+            .log_request(2, "https://a.com/1", "success", 10, None)
+            .await;
+
+        assert_eq!(storage.get_daily_download_count(1).await, 2);
+        assert_eq!(storage.get_daily_download_count(2).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_chat_drops_old_deliveries() {
+        let storage = MemoryStorage::new();
+        storage
+            .record_delivery(-1, &[7, 8], "https://a.com/1", None)
+            .await;
+        storage
+            .record_delivery(-1001, &[8], "https://a.com/2", None)
+            .await;
+
+        storage.migrate_chat(-1, -1001).await;
+
+        assert!(storage.get_delivery(-1, 7).await.is_none());
+        assert!(storage.get_delivery(-1001, 7).await.is_none());
+        assert_eq!(
+            storage.get_delivery(-1001, 8).await.unwrap().source_url,
+            "https://a.com/2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_topup_is_consumed_after_monthly_quota() {
+        let storage = MemoryStorage::new();
+        storage
+            .upsert_subscription(1, SubscriptionTier::Basic, 30)
+            .await;
+        storage.add_topup_seconds(1, 100).await;
+
+        storage.consume_ai_seconds(1, 3_650).await;
+
+        let sub = storage.get_subscription(1).await;
+        assert_eq!(sub.ai_seconds_used, 3_600);
+        assert_eq!(sub.topup_seconds_available, 50);
+    }
+}
@@ -0,0 +1,149 @@
+//! Whether a user administers the group they write in, for actions only admins may take.
+//!
+//! Asking Telegram on every message would be too chatty, so answers are cached per chat
+//! and user for `ADMIN_STATUS_TTL`.
+
+use std::time::Duration;
+
+use teloxide::types::{ChatId, UserId};
+
+use crate::cache::MemoryCache;
+use crate::telegram_api::TelegramApi;
+
+/// How long a user's admin status is trusted before Telegram is asked again.
+pub const ADMIN_STATUS_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+pub struct Permissions {
+    admins: MemoryCache<(ChatId, UserId), bool>,
+}
+
+impl Permissions {
+    pub fn new() -> Self {
+        Self {
+            admins: MemoryCache::new(ADMIN_STATUS_TTL),
+        }
+    }
+
+    /// Whether `user_id` is the owner or an administrator of `chat_id`. Users administer
+    /// their private chat with the bot. When Telegram can't be asked, the user counts as a
+    /// regular member and the answer is not cached.
+    pub async fn is_admin(&self, api: &dyn TelegramApi, chat_id: ChatId, user_id: UserId) -> bool {
+        if chat_id.is_user() {
+            return true;
+        }
+        if let Some(is_admin) = self.admins.get(&(chat_id, user_id)) {
+            return is_admin;
+        }
+        match api.get_chat_member(chat_id, user_id).await {
+            Ok(status) => {
+                let is_admin = status.is_privileged();
+                self.admins.insert((chat_id, user_id), is_admin);
+                is_admin
+            }
+            Err(e) => {
+                log::warn!(
+                    "Could not check whether user {} administers chat {}: {}",
+                    user_id,
+                    chat_id,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Drop the cached statuses for `chat_id`, e.g. after the bot's own membership changed.
+    pub fn forget_chat(&self, chat_id: ChatId) {
+        self.admins.remove_where(|(chat, _)| *chat == chat_id);
+    }
+
+    pub fn evict_expired(&self) {
+        self.admins.evict_expired();
+    }
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telegram_api::MockTelegramApi;
+    use std::time::Instant;
+    use teloxide::types::ChatMemberStatus;
+
+    const GROUP: ChatId = ChatId(-100);
+    const USER: UserId = UserId(7);
+
+    fn api_answering(status: ChatMemberStatus, times: usize) -> MockTelegramApi {
+        let mut api = MockTelegramApi::new();
+        api.expect_get_chat_member()
+            .times(times)
+            .returning(move |_, _| Ok(status));
+        api
+    }
+
+    #[tokio::test]
+    async fn test_admins_and_owners_are_admins_members_are_not() {
+        for (status, expected) in [
+            (ChatMemberStatus::Owner, true),
+            (ChatMemberStatus::Administrator, true),
+            (ChatMemberStatus::Member, false),
+            (ChatMemberStatus::Restricted, false),
+        ] {
+            let permissions = Permissions::new();
+            let api = api_answering(status, 1);
+            assert_eq!(
+                permissions.is_admin(&api, GROUP, USER).await,
+                expected,
+                "{status:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_is_cached_until_ttl_expires() {
+        let permissions = Permissions::new();
+        let api = api_answering(ChatMemberStatus::Administrator, 1);
+        assert!(permissions.is_admin(&api, GROUP, USER).await);
+        assert!(permissions.is_admin(&api, GROUP, USER).await);
+
+        permissions
+            .admins
+            .insert_at((GROUP, USER), true, Instant::now() - ADMIN_STATUS_TTL);
+        let api = api_answering(ChatMemberStatus::Member, 1);
+        assert!(!permissions.is_admin(&api, GROUP, USER).await);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_not_cached_and_private_chats_skip_telegram() {
+        let permissions = Permissions::new();
+        let mut api = MockTelegramApi::new();
+        api.expect_get_chat_member().times(1).returning(|_, _| {
+            Err(teloxide::RequestError::Api(
+                teloxide::ApiError::ChatNotFound,
+            ))
+        });
+        assert!(!permissions.is_admin(&api, GROUP, USER).await);
+        assert!(permissions.admins.is_empty());
+
+        let api = api_answering(ChatMemberStatus::Member, 0);
+        assert!(permissions.is_admin(&api, ChatId(7), USER).await);
+    }
+
+    #[tokio::test]
+    async fn test_forget_chat_only_drops_that_chat() {
+        let permissions = Permissions::new();
+        permissions.admins.insert((GROUP, USER), true);
+        permissions.admins.insert((ChatId(-200), USER), true);
+
+        permissions.forget_chat(GROUP);
+
+        assert_eq!(permissions.admins.get(&(GROUP, USER)), None);
+        assert_eq!(permissions.admins.get(&(ChatId(-200), USER)), Some(true));
+    }
+}
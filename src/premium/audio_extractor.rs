@@ -5,6 +5,8 @@ use async_trait::async_trait;
 use thiserror::Error;
 use tokio::sync::Semaphore;
 
+use crate::probe::{self, ProbeError};
+
 #[derive(Debug, Error)]
 pub enum AudioExtractionError {
     #[error("ffprobe failed: {0}")]
@@ -56,35 +58,17 @@ impl AudioExtractor for FfmpegAudioExtractor {
         let _permit = self.semaphore.acquire().await.expect("semaphore closed");
 
         // Step 1: ffprobe to get duration
-        let ffprobe_output = tokio::process::Command::new("ffprobe")
-            .args([
-                "-v",
-                "quiet",
-                "-show_entries",
-                "format=duration",
-                "-of",
-                "json",
-            ])
-            .arg(video_path)
-            .output()
+        let duration_secs = probe::media_duration(video_path)
             .await
-            .map_err(|e| AudioExtractionError::FfprobeError(e.to_string()))?;
-
-        if !ffprobe_output.status.success() {
-            let stderr = String::from_utf8_lossy(&ffprobe_output.stderr).to_string();
-            return Err(AudioExtractionError::FfprobeError(stderr));
-        }
-
-        let ffprobe_json: serde_json::Value = serde_json::from_slice(&ffprobe_output.stdout)
-            .map_err(|e| AudioExtractionError::ParseError(e.to_string()))?;
-
-        let duration_secs = ffprobe_json["format"]["duration"]
-            .as_str()
-            .and_then(|s| s.parse::<f64>().ok())
-            .map(|d| d.round() as i32)
-            .ok_or_else(|| {
-                AudioExtractionError::ParseError("missing duration in ffprobe output".to_string())
-            })?;
+            .map_err(|e| match e {
+                ProbeError::InvalidOutput(_) | ProbeError::MissingDuration => {
+                    AudioExtractionError::ParseError(e.to_string())
+                }
+                ProbeError::Spawn(source) => AudioExtractionError::FfprobeError(source.to_string()),
+                ProbeError::Failed(stderr) => AudioExtractionError::FfprobeError(stderr),
+                ProbeError::Timeout(_) => AudioExtractionError::FfprobeError(e.to_string()),
+            })?
+            .round() as i32;
 
         // Step 2: ffmpeg to extract audio
         let audio_filename = format!("{}.mp3", uuid::Uuid::new_v4());
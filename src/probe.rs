@@ -0,0 +1,181 @@
+//! ffprobe helpers: the duration of a media file, and a sanity check of downloaded files.
+//!
+//! yt-dlp sometimes exits successfully with an empty or cut-off file when a site drops the
+//! connection. `verify_media_file` catches those before they are uploaded.
+
+use std::path::Path;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::downloader::MediaType;
+
+/// ffprobe only reads the container headers; a run this long is stuck, e.g. on a file
+/// on a hung network mount.
+const FFPROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum ProbeError {
+    #[error("could not run ffprobe")]
+    Spawn(#[source] std::io::Error),
+    #[error("ffprobe timed out after {0} seconds")]
+    Timeout(u64),
+    #[error("ffprobe failed: {0}")]
+    Failed(String),
+    #[error("failed to parse ffprobe output")]
+    InvalidOutput(#[source] serde_json::Error),
+    #[error("missing duration in ffprobe output")]
+    MissingDuration,
+}
+
+/// Duration of the media at `path` in seconds, as reported by ffprobe.
+pub async fn media_duration(path: &Path) -> Result<f64, ProbeError> {
+    let mut command = tokio::process::Command::new("ffprobe");
+    command
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(FFPROBE_TIMEOUT, command.output())
+        .await
+        .map_err(|_| ProbeError::Timeout(FFPROBE_TIMEOUT.as_secs()))?
+        .map_err(ProbeError::Spawn)?;
+    if !output.status.success() {
+        return Err(ProbeError::Failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(ProbeError::InvalidOutput)?;
+    json["format"]["duration"]
+        .as_str()
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or(ProbeError::MissingDuration)
+}
+
+/// Why a downloaded file can't be sent.
+#[derive(Debug, Error)]
+pub enum CorruptFile {
+    #[error("file is missing or unreadable")]
+    Unreadable(#[source] std::io::Error),
+    #[error("file is empty")]
+    Empty,
+    #[error("container does not parse")]
+    Unparseable(#[source] ProbeError),
+    #[error("video has no duration")]
+    NoDuration,
+}
+
+/// Check that a downloaded file is worth uploading: it is not empty and, for videos,
+/// ffprobe parses the container and finds a positive duration. When ffprobe can't be run
+/// at all or hangs, videos are only checked for size.
+pub async fn verify_media_file(path: &Path, media_type: MediaType) -> Result<(), CorruptFile> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .map_err(CorruptFile::Unreadable)?;
+    if metadata.len() == 0 {
+        return Err(CorruptFile::Empty);
+    }
+    if media_type != MediaType::Video {
+        return Ok(());
+    }
+    match media_duration(path).await {
+        Ok(duration) if duration > 0.0 => Ok(()),
+        Ok(_) | Err(ProbeError::MissingDuration) => Err(CorruptFile::NoDuration),
+        Err(e @ (ProbeError::Spawn(_) | ProbeError::Timeout(_))) => {
+            log::warn!("Skipping the check of {}: {}", path.display(), e);
+            Ok(())
+        }
+        Err(e) => Err(CorruptFile::Unparseable(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 0.1 s mono 8 kHz PCM WAV file: the smallest media ffprobe reports a duration for.
+    fn tiny_wav() -> Vec<u8> {
+        let samples = vec![0u8; 800];
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&8000u32.to_le_bytes()); // sample rate
+        wav.extend_from_slice(&8000u32.to_le_bytes()); // byte rate
+        wav.extend_from_slice(&1u16.to_le_bytes()); // block align
+        wav.extend_from_slice(&8u16.to_le_bytes()); // bits per sample
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&samples);
+        wav
+    }
+
+    fn file_with(contents: &[u8]) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), contents).unwrap();
+        file
+    }
+
+    #[tokio::test]
+    async fn test_empty_file_is_corrupt() {
+        let file = file_with(b"");
+        for media_type in [MediaType::Video, MediaType::Photo] {
+            assert!(matches!(
+                verify_media_file(file.path(), media_type).await,
+                Err(CorruptFile::Empty)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            verify_media_file(&dir.path().join("gone.mp4"), MediaType::Video).await,
+            Err(CorruptFile::Unreadable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_photos_are_only_checked_for_size() {
+        let file = file_with(b"not really a jpeg");
+        assert!(
+            verify_media_file(file.path(), MediaType::Photo)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffprobe installed"]
+    async fn test_playable_video_passes() {
+        let file = file_with(&tiny_wav());
+        assert!(
+            verify_media_file(file.path(), MediaType::Video)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffprobe installed"]
+    async fn test_truncated_video_is_corrupt() {
+        // An MP4 cut off after its first box, before any media or index.
+        let file = file_with(b"\0\0\0\x18ftypisom\0\0\x02\0isomiso2");
+        assert!(
+            verify_media_file(file.path(), MediaType::Video)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Per-chat daily download quota, configured with `DAILY_QUOTA_PER_USER`.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use teloxide::prelude::*;
+
+use crate::storage::Storage;
+use crate::telegram_api::TelegramApi;
+
+/// Downloads allowed per chat per UTC day; 0 disables the quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DailyDownloadQuota(pub u32);
+
+impl DailyDownloadQuota {
+    pub fn is_enabled(self) -> bool {
+        self.0 > 0
+    }
+}
+
+/// Time left until the quota resets at the next midnight UTC.
+fn time_until_reset(now: DateTime<Utc>) -> TimeDelta {
+    let next_midnight = (now.date_naive() + TimeDelta::days(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    next_midnight - now
+}
+
+fn format_reset(now: DateTime<Utc>) -> String {
+    let remaining = time_until_reset(now);
+    format!(
+        "Quota resets at midnight UTC (in {}h {}m).",
+        remaining.num_hours(),
+        remaining.num_minutes() % 60
+    )
+}
+
+fn format_quota_status(used: i64, quota: DailyDownloadQuota, now: DateTime<Utc>) -> String {
+    format!(
+        "📊 Downloads today: {} / {}. {}",
+        used,
+        quota.0,
+        format_reset(now)
+    )
+}
+
+fn format_quota_exceeded(quota: DailyDownloadQuota, now: DateTime<Utc>) -> String {
+    format!(
+        "You've reached today's limit of {} downloads. {}",
+        quota.0,
+        format_reset(now)
+    )
+}
+
+/// Reply to refuse a download, or `None` when the chat still has quota left.
+pub async fn check_daily_quota(
+    storage: &dyn Storage,
+    quota: DailyDownloadQuota,
+    chat_id: ChatId,
+) -> Option<String> {
+    if !quota.is_enabled() {
+        return None;
+    }
+    let used = storage.get_daily_download_count(chat_id.0).await;
+    (used >= i64::from(quota.0)).then(|| format_quota_exceeded(quota, Utc::now()))
+}
+
+/// `/quota`: show today's downloads against the configured quota.
+pub async fn handle_quota(
+    api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
+    message: Message,
+    quota: DailyDownloadQuota,
+) -> ResponseResult<()> {
+    let text = if quota.is_enabled() {
+        let used = storage.get_daily_download_count(message.chat.id.0).await;
+        format_quota_status(used, quota, Utc::now())
+    } else {
+        "No download quota is configured for this bot.".to_string()
+    };
+    api.send_text_message(message.chat.id, message.id, &text)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+    use crate::telegram_api::MockTelegramApi;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    fn message() -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": {"id": 5, "type": "private", "first_name": "Test"},
+            "from": {"id": 5, "is_bot": false, "first_name": "Test"},
+            "text": "/quota"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format_quota_status() {
+        assert_eq!(
+            format_quota_status(3, DailyDownloadQuota(10), at("2026-03-01T19:37:00Z")),
+            "📊 Downloads today: 3 / 10. Quota resets at midnight UTC (in 4h 23m)."
+        );
+    }
+
+    #[test]
+    fn test_time_until_reset_just_after_midnight() {
+        let remaining = time_until_reset(at("2026-03-01T00:00:30Z"));
+        assert_eq!(remaining, TimeDelta::seconds(24 * 60 * 60 - 30));
+    }
+
+    #[tokio::test]
+    async fn test_check_daily_quota() {
+        let mut mock_storage = MockStorage::new();
+        mock_storage
+            .expect_get_daily_download_count()
+            .returning(|chat_id| if chat_id == 1 { 10 } else { 9 });
+
+        let refusal = check_daily_quota(&mock_storage, DailyDownloadQuota(10), ChatId(1)).await;
+        assert!(
+            refusal
+                .unwrap()
+                .starts_with("You've reached today's limit of 10")
+        );
+        assert!(
+            check_daily_quota(&mock_storage, DailyDownloadQuota(10), ChatId(2))
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_quota_skips_storage() {
+        let mut mock_storage = MockStorage::new();
+        mock_storage.expect_get_daily_download_count().never();
+        let mut mock_api = MockTelegramApi::new();
+        mock_api
+            .expect_send_text_message()
+            .withf(|_, _, text| text == "No download quota is configured for this bot.")
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        assert!(
+            check_daily_quota(&mock_storage, DailyDownloadQuota(0), ChatId(1))
+                .await
+                .is_none()
+        );
+        handle_quota(
+            Arc::new(mock_api),
+            Arc::new(mock_storage),
+            message(),
+            DailyDownloadQuota(0),
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Per-chat sliding-window rate limits.
+//!
+//! Only bot commands are limited here: download requests are already bounded by the
+//! per-chat concurrency limiter, the duplicate-request window and the daily quota.
+//! `/feedback` has its own, much stricter limit on top of the command limit.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use teloxide::types::{ChatId, UserId};
+
+use crate::cache::MemoryCache;
+
+/// Default for `COMMAND_RATE_LIMIT`.
+pub const DEFAULT_COMMAND_RATE_LIMIT: u32 = 20;
+
+pub const COMMAND_RATE_LIMITED_MESSAGE: &str =
+    "⏱ You're sending commands too fast. Please slow down.";
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Each user can send one `/feedback` message per interval.
+pub const FEEDBACK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    commands: DashMap<ChatId, VecDeque<Instant>>,
+    /// Commands allowed per chat per minute; 0 disables the limit.
+    command_limit: u32,
+    /// Users whose feedback was relayed within the last `FEEDBACK_INTERVAL`.
+    feedback: MemoryCache<UserId, ()>,
+}
+
+impl RateLimiter {
+    pub fn new(command_limit: u32) -> Self {
+        Self {
+            commands: DashMap::new(),
+            command_limit,
+            feedback: MemoryCache::new(FEEDBACK_INTERVAL),
+        }
+    }
+
+    /// Whether the user may send feedback, i.e. none of theirs was relayed within
+    /// `FEEDBACK_INTERVAL`.
+    pub fn feedback_allowed(&self, user_id: UserId) -> bool {
+        self.feedback_allowed_at(user_id, Instant::now())
+    }
+
+    fn feedback_allowed_at(&self, user_id: UserId, now: Instant) -> bool {
+        self.feedback.get_at(&user_id, now).is_none()
+    }
+
+    /// Start the user's `FEEDBACK_INTERVAL`, once their feedback reached the owner.
+    pub fn record_feedback(&self, user_id: UserId) {
+        self.record_feedback_at(user_id, Instant::now());
+    }
+
+    fn record_feedback_at(&self, user_id: UserId, now: Instant) {
+        self.feedback.insert_at(user_id, (), now);
+    }
+
+    /// Forget chats with no command or feedback left in their window.
+    pub fn evict_expired(&self) {
+        self.evict_expired_at(Instant::now());
+    }
+
+    fn evict_expired_at(&self, now: Instant) {
+        self.commands.retain(|_, hits| {
+            hits.back()
+                .is_some_and(|&hit| now.duration_since(hit) < RATE_WINDOW)
+        });
+        self.feedback.evict_expired();
+    }
+
+    /// Record a command and return false if the chat is over its per-minute limit.
+    pub fn check_command(&self, chat_id: i64) -> bool {
+        self.check_command_at(ChatId(chat_id), Instant::now())
+    }
+
+    fn check_command_at(&self, chat_id: ChatId, now: Instant) -> bool {
+        if self.command_limit == 0 {
+            return true;
+        }
+        let mut hits = self.commands.entry(chat_id).or_default();
+        while hits
+            .front()
+            .is_some_and(|&hit| now.duration_since(hit) >= RATE_WINDOW)
+        {
+            hits.pop_front();
+        }
+        if hits.len() >= self.command_limit as usize {
+            return false;
+        }
+        hits.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_over_limit_are_rejected_until_window_passes() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.check_command_at(ChatId(1), start));
+        assert!(limiter.check_command_at(ChatId(1), start + Duration::from_secs(1)));
+        assert!(!limiter.check_command_at(ChatId(1), start + Duration::from_secs(2)));
+        assert!(limiter.check_command_at(ChatId(2), start + Duration::from_secs(2)));
+        assert!(limiter.check_command_at(ChatId(1), start + RATE_WINDOW));
+    }
+
+    #[test]
+    fn test_idle_chats_are_evicted() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        limiter.check_command_at(ChatId(1), start);
+        limiter.check_command_at(ChatId(2), start + Duration::from_secs(30));
+
+        limiter.evict_expired_at(start + RATE_WINDOW);
+        assert!(!limiter.commands.contains_key(&ChatId(1)));
+        assert!(limiter.commands.contains_key(&ChatId(2)));
+    }
+
+    #[test]
+    fn test_feedback_is_limited_to_one_per_interval() {
+        let limiter = RateLimiter::new(0);
+        let start = Instant::now();
+        assert!(limiter.feedback_allowed_at(UserId(1), start));
+        limiter.record_feedback_at(UserId(1), start);
+        assert!(!limiter.feedback_allowed_at(UserId(1), start + Duration::from_secs(60)));
+        assert!(limiter.feedback_allowed_at(UserId(2), start + Duration::from_secs(60)));
+        assert!(limiter.feedback_allowed_at(UserId(1), start + FEEDBACK_INTERVAL));
+    }
+
+    #[test]
+    fn test_zero_limit_disables_rate_limiting() {
+        let limiter = RateLimiter::new(0);
+        assert!((0..100).all(|_| limiter.check_command(1)));
+    }
+}
@@ -0,0 +1,329 @@
+//! Drops rapid duplicate requests, e.g. when a user double-taps send on the same link,
+//! and updates Telegram delivers twice.
+//!
+//! A URL sent again later the same day is answered with a pointer to the message it was
+//! delivered in, rather than with the media once more; other chats are served from the
+//! persistent media cache.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, MessageId, Update, UpdateId};
+
+use crate::cache::MemoryCache;
+use crate::telegram_api::TelegramApi;
+
+/// Default for `DEDUP_WINDOW_SECS`.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(60);
+/// How long a delivery is pointed back to instead of sending the media again.
+pub const RECENT_DELIVERY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Most deliveries remembered across all chats.
+pub const MAX_RECENT_DELIVERIES: usize = 10_000;
+
+/// Reply to the earlier delivery of a URL that is requested again in the same chat.
+pub const EARLIER_DELIVERY_TEXT: &str = "I sent this here earlier ⤴";
+
+/// When each chat last requested each normalized URL, and the message it was delivered in.
+#[derive(Debug)]
+pub struct RecentRequests {
+    requests: MemoryCache<(ChatId, String), ()>,
+    deliveries: MemoryCache<(ChatId, String), MessageId>,
+}
+
+impl RecentRequests {
+    /// A zero `window` disables deduplication.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            requests: MemoryCache::new(window),
+            deliveries: MemoryCache::new(RECENT_DELIVERY_TTL),
+        }
+    }
+
+    /// Record a request and return false if the same chat sent the same URL within the window.
+    pub fn first_seen(&self, chat_id: ChatId, url: &str, now: Instant) -> bool {
+        self.requests
+            .insert_if_absent_at((chat_id, url.to_string()), (), now)
+    }
+
+    /// Like `first_seen`, but the request is only remembered once `RequestClaim::accept`
+    /// is called, so one that is refused or fails can be sent again right away.
+    pub fn claim(&self, chat_id: ChatId, url: &str, now: Instant) -> Option<RequestClaim<'_>> {
+        self.first_seen(chat_id, url, now).then(|| RequestClaim {
+            recent: self,
+            key: Some((chat_id, url.to_string())),
+        })
+    }
+
+    /// Remember that `url` was delivered to the chat in `message_id`. Once full, the oldest
+    /// tenth is forgotten at once.
+    pub fn record_delivery(&self, chat_id: ChatId, url: &str, message_id: MessageId, now: Instant) {
+        self.deliveries
+            .insert_at((chat_id, url.to_string()), message_id, now);
+        if self.deliveries.len() > MAX_RECENT_DELIVERIES {
+            self.deliveries
+                .shrink_to(MAX_RECENT_DELIVERIES - MAX_RECENT_DELIVERIES / 10, now);
+        }
+    }
+
+    /// The message `url` was last delivered to the chat in, if that was recent.
+    pub fn last_delivery(&self, chat_id: ChatId, url: &str) -> Option<MessageId> {
+        self.deliveries.get(&(chat_id, url.to_string()))
+    }
+
+    pub fn forget_delivery(&self, chat_id: ChatId, url: &str, now: Instant) {
+        self.deliveries.remove_at(&(chat_id, url.to_string()), now);
+    }
+
+    pub fn evict_expired(&self) {
+        self.requests.evict_expired();
+        self.deliveries.evict_expired();
+    }
+}
+
+/// A request held back from being sent again within the dedup window. Dropping it without
+/// `accept`, e.g. on an early return, forgets the request.
+#[must_use]
+pub struct RequestClaim<'a> {
+    recent: &'a RecentRequests,
+    key: Option<(ChatId, String)>,
+}
+
+impl RequestClaim<'_> {
+    /// The request went through, so repeats within the window are dropped.
+    pub fn accept(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for RequestClaim<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.recent.requests.remove_at(&key, Instant::now());
+        }
+    }
+}
+
+/// Answer a repeat request for `url` by replying to the message it was delivered in
+/// earlier, and return whether that worked. When the message is gone, e.g. because the
+/// user deleted it, the delivery is forgotten and the caller serves the request anew.
+pub async fn reply_with_earlier_delivery(
+    recent_requests: &RecentRequests,
+    api: &dyn TelegramApi,
+    chat_id: ChatId,
+    url: &str,
+) -> bool {
+    let Some(delivered) = recent_requests.last_delivery(chat_id, url) else {
+        return false;
+    };
+    match api
+        .reply_to_sent_message(chat_id, delivered, EARLIER_DELIVERY_TEXT)
+        .await
+    {
+        Ok(()) => {
+            log::info!(
+                "Pointed chat {} to message {} for {}",
+                chat_id,
+                delivered,
+                url
+            );
+            true
+        }
+        Err(e) => {
+            log::info!(
+                "Earlier delivery of {} to chat {} is unavailable, sending again: {}",
+                url,
+                chat_id,
+                e
+            );
+            recent_requests.forget_delivery(chat_id, url, Instant::now());
+            false
+        }
+    }
+}
+
+/// How long a bot remembers the updates it handled. Telegram redelivers an update when
+/// the webhook answers slowly, usually within a minute or two.
+pub const UPDATE_REPLAY_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Most update ids remembered per bot.
+pub const MAX_RECENT_UPDATES: usize = 10_000;
+
+/// Ids of the updates one bot has handled. Update ids are only unique per bot, so each
+/// bot has its own.
+#[derive(Debug)]
+pub struct RecentUpdates {
+    updates: MemoryCache<UpdateId, ()>,
+    max_entries: usize,
+}
+
+impl Default for RecentUpdates {
+    fn default() -> Self {
+        Self::new(UPDATE_REPLAY_WINDOW, MAX_RECENT_UPDATES)
+    }
+}
+
+impl RecentUpdates {
+    pub fn new(window: Duration, max_entries: usize) -> Self {
+        Self {
+            updates: MemoryCache::new(window),
+            max_entries,
+        }
+    }
+
+    /// Record an update and return false if it was seen within the window. Once full, the
+    /// oldest tenth is forgotten at once rather than one entry per update.
+    pub fn first_seen(&self, id: UpdateId, now: Instant) -> bool {
+        let first = self.updates.insert_if_absent_at(id, (), now);
+        if first && self.updates.len() > self.max_entries {
+            self.updates
+                .shrink_to(self.max_entries - self.max_entries / 10, now);
+        }
+        first
+    }
+}
+
+/// dptree filter matching updates that were already delivered, recording the others.
+pub fn is_redelivery(update: Update, recent_updates: Arc<RecentUpdates>) -> bool {
+    let redelivered = !recent_updates.first_seen(update.id, Instant::now());
+    if redelivered {
+        log::info!("Ignoring redelivered update {}", update.id.0);
+    }
+    redelivered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_within_window_is_dropped() {
+        let recent = RecentRequests::new(DEFAULT_DEDUP_WINDOW);
+        let start = Instant::now();
+        assert!(recent.first_seen(ChatId(1), "https://a.com/x", start));
+        assert!(!recent.first_seen(ChatId(1), "https://a.com/x", start + Duration::from_secs(5)));
+        assert!(recent.first_seen(ChatId(2), "https://a.com/x", start + Duration::from_secs(5)));
+        assert!(recent.first_seen(ChatId(1), "https://a.com/y", start + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_request_is_accepted_again_after_window() {
+        let recent = RecentRequests::new(DEFAULT_DEDUP_WINDOW);
+        let start = Instant::now();
+        assert!(recent.first_seen(ChatId(1), "https://a.com/x", start));
+        assert!(recent.first_seen(ChatId(1), "https://a.com/x", start + DEFAULT_DEDUP_WINDOW));
+        assert_eq!(recent.requests.len(), 1);
+    }
+
+    #[test]
+    fn test_unaccepted_claim_is_forgotten() {
+        let recent = RecentRequests::new(DEFAULT_DEDUP_WINDOW);
+        let now = Instant::now();
+        let claim = recent.claim(ChatId(1), "https://a.com/x", now).unwrap();
+        assert!(recent.claim(ChatId(1), "https://a.com/x", now).is_none());
+        drop(claim);
+
+        let claim = recent.claim(ChatId(1), "https://a.com/x", now).unwrap();
+        claim.accept();
+        assert!(recent.claim(ChatId(1), "https://a.com/x", now).is_none());
+    }
+
+    fn test_update(id: u32) -> Update {
+        serde_json::from_value(serde_json::json!({
+            "update_id": id,
+            "message": {
+                "message_id": 7,
+                "date": 1_700_000_000,
+                "chat": {"id": 1, "type": "private", "first_name": "Test"},
+                "text": "https://example.com/video"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_update_is_handled_once() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use teloxide::dispatching::DpHandlerDescription;
+        use teloxide::dptree;
+
+        let downloads = Arc::new(AtomicU32::new(0));
+        let handler: dptree::Handler<'_, (), DpHandlerDescription> = dptree::entry()
+            .branch(dptree::filter(is_redelivery).endpoint(|| async {}))
+            .branch(
+                dptree::entry().endpoint(|downloads: Arc<AtomicU32>| async move {
+                    downloads.fetch_add(1, Ordering::SeqCst);
+                }),
+            );
+        let recent_updates = Arc::new(RecentUpdates::default());
+        for update in [test_update(1), test_update(1), test_update(2)] {
+            let deps = dptree::deps![update, recent_updates.clone(), downloads.clone()];
+            assert!(handler.dispatch(deps).await.is_break());
+        }
+
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_recent_updates_are_capped() {
+        let recent = RecentUpdates::new(UPDATE_REPLAY_WINDOW, 10);
+        let start = Instant::now();
+        for id in 0..11 {
+            assert!(recent.first_seen(UpdateId(id), start + Duration::from_millis(id.into())));
+        }
+        assert_eq!(recent.updates.len(), 9);
+        assert!(!recent.first_seen(UpdateId(10), start));
+        // The oldest were forgotten and would be handled again.
+        assert!(recent.first_seen(UpdateId(0), start));
+    }
+
+    #[tokio::test]
+    async fn test_repeat_request_replies_to_earlier_delivery() {
+        let recent = RecentRequests::new(DEFAULT_DEDUP_WINDOW);
+        recent.record_delivery(ChatId(1), "https://a.com/x", MessageId(7), Instant::now());
+        let mut api = crate::telegram_api::MockTelegramApi::new();
+        api.expect_reply_to_sent_message()
+            .withf(|chat_id, message_id, text| {
+                *chat_id == ChatId(1)
+                    && *message_id == MessageId(7)
+                    && text == EARLIER_DELIVERY_TEXT
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        assert!(reply_with_earlier_delivery(&recent, &api, ChatId(1), "https://a.com/x").await);
+    }
+
+    #[tokio::test]
+    async fn test_request_without_earlier_delivery_is_served() {
+        let recent = RecentRequests::new(DEFAULT_DEDUP_WINDOW);
+        recent.record_delivery(ChatId(2), "https://a.com/x", MessageId(7), Instant::now());
+        recent.record_delivery(ChatId(1), "https://a.com/y", MessageId(8), Instant::now());
+        let api = crate::telegram_api::MockTelegramApi::new();
+
+        assert!(!reply_with_earlier_delivery(&recent, &api, ChatId(1), "https://a.com/x").await);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_earlier_delivery_is_forgotten() {
+        let recent = RecentRequests::new(DEFAULT_DEDUP_WINDOW);
+        recent.record_delivery(ChatId(1), "https://a.com/x", MessageId(7), Instant::now());
+        let mut api = crate::telegram_api::MockTelegramApi::new();
+        api.expect_reply_to_sent_message()
+            .times(1)
+            .returning(|_, _, _| {
+                Err(teloxide::RequestError::Api(
+                    teloxide::ApiError::MessageToReplyNotFound,
+                ))
+            });
+
+        assert!(!reply_with_earlier_delivery(&recent, &api, ChatId(1), "https://a.com/x").await);
+        assert_eq!(recent.last_delivery(ChatId(1), "https://a.com/x"), None);
+    }
+
+    #[test]
+    fn test_zero_window_disables_dedup() {
+        let recent = RecentRequests::new(Duration::ZERO);
+        let now = Instant::now();
+        assert!(recent.first_seen(ChatId(1), "https://a.com/x", now));
+        assert!(recent.first_seen(ChatId(1), "https://a.com/x", now));
+    }
+}
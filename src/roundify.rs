@@ -0,0 +1,423 @@
+//! `/roundify`: turns a video into a round Telegram video note.
+//!
+//! Video notes are square, at most 640 pixels wide and at most a minute long. The source
+//! is downloaded as usual, then cropped to its centre square and scaled down with ffmpeg.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use teloxide::types::{ChatId, MessageId};
+use url::Url;
+
+use crate::child_processes::ChildProcesses;
+use crate::downloader::{DownloadError, DownloadedMedia, Downloader, MediaType};
+use crate::handler::{FileCleanupGuard, PipelineConfig, bound_request, parse_link};
+use crate::storage::Storage;
+use crate::telegram_api::TelegramApi;
+use crate::url_cleanup::cleanup_url_with_rules;
+use crate::validator::{
+    MAX_VIDEO_NOTE_SECONDS, ValidationConfig, ValidationError, validate_media_metadata,
+    validate_video_note,
+};
+
+/// The link of a `/roundify` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundifyRequest {
+    pub url: Url,
+}
+
+impl RoundifyRequest {
+    pub fn parse(text: &str) -> Option<Self> {
+        parse_link(text.trim()).map(|url| Self { url })
+    }
+}
+
+/// Telegram shows video notes at up to 640x640.
+const MAX_VIDEO_NOTE_SIDE: u32 = 640;
+/// Longest ffmpeg may take to convert a clip of at most a minute.
+const VIDEO_NOTE_CONVERSION_TIMEOUT: Duration = Duration::from_secs(120);
+/// Reply when the conversion fails for a reason the user can't do anything about.
+const VIDEO_NOTE_FAILED_TEXT: &str = "Sorry, I could not turn this video into a round video.";
+
+/// Arguments for ffmpeg to turn `input` into a video note at `output`: the centred square
+/// (rounded down to even sides for libx264), at most `MAX_VIDEO_NOTE_SIDE` pixels, cut
+/// after `MAX_VIDEO_NOTE_SECONDS` in case the source didn't report its duration.
+fn video_note_ffmpeg_args(input: &Path, output: &Path) -> Vec<OsString> {
+    // Quoted, as the commas inside min() would otherwise separate filters.
+    let side = "'2*trunc(min(iw,ih)/2)'";
+    let filter = format!(
+        "crop={side}:{side},scale='min({MAX_VIDEO_NOTE_SIDE},iw)':'min({MAX_VIDEO_NOTE_SIDE},ih)'"
+    );
+    let mut args: Vec<OsString> = vec!["-i".into(), input.into(), "-vf".into(), filter.into()];
+    args.extend(
+        [
+            "-t",
+            &MAX_VIDEO_NOTE_SECONDS.to_string(),
+            "-c:v",
+            "libx264",
+            "-c:a",
+            "aac",
+            "-movflags",
+            "+faststart",
+            "-y",
+        ]
+        .map(OsString::from),
+    );
+    args.push(output.into());
+    args
+}
+
+/// `<uuid>.<id>.mp4` becomes `<uuid>.<id>.round.mp4`, keeping the download's uuid prefix
+/// so orphan cleanup still finds it.
+fn video_note_path(input: &Path) -> PathBuf {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    input.with_file_name(format!("{stem}.round.mp4"))
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait VideoNoteConverter: Send + Sync {
+    /// Write the video note made from `input` to `output`. The caller removes `output`,
+    /// also when the conversion fails.
+    async fn convert(&self, input: &Path, output: &Path) -> Result<(), DownloadError>;
+}
+
+/// Converts with ffmpeg, registered with `children` so shutdown can stop it.
+#[derive(Debug, Clone)]
+pub struct FfmpegVideoNoteConverter {
+    pub children: ChildProcesses,
+}
+
+#[async_trait]
+impl VideoNoteConverter for FfmpegVideoNoteConverter {
+    async fn convert(&self, input: &Path, output: &Path) -> Result<(), DownloadError> {
+        let mut command = tokio::process::Command::new("ffmpeg");
+        command
+            .args(video_note_ffmpeg_args(input, output))
+            .kill_on_drop(true);
+        let result = tokio::time::timeout(
+            VIDEO_NOTE_CONVERSION_TIMEOUT,
+            self.children.output(&mut command),
+        )
+        .await
+        .map_err(|_| DownloadError::Timeout(VIDEO_NOTE_CONVERSION_TIMEOUT.as_secs()))?
+        .map_err(|source| DownloadError::IoError {
+            context: "running ffmpeg for a video note",
+            source,
+        })?;
+        if !result.status.success() {
+            return Err(DownloadError::CommandFailed(
+                String::from_utf8_lossy(&result.stderr).into_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Download the video at `url` and reply with it as a video note, within the request
+/// timeout. Problems are reported to the user and the request is logged, so a delivered
+/// video note counts towards the daily quota; only Telegram errors are returned.
+#[allow(clippy::too_many_arguments)]
+pub async fn process_roundify_request(
+    url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    downloader: &dyn Downloader,
+    api: &dyn TelegramApi,
+    storage: &dyn Storage,
+    converter: &dyn VideoNoteConverter,
+    config: &PipelineConfig,
+) -> Result<(), teloxide::RequestError> {
+    let start = Instant::now();
+    let roundify = roundify(
+        url,
+        chat_id,
+        message_id,
+        downloader,
+        api,
+        converter,
+        &config.validation,
+    );
+    let (status, result) =
+        match bound_request(roundify, config.request_timeout, "Roundify", chat_id, url).await {
+            Ok(Ok(status)) => (status, Ok(())),
+            Ok(Err(e)) => ("error", Err(e)),
+            Err(abort) => (
+                abort.status,
+                api.send_text_message(chat_id, message_id, &abort.reply)
+                    .await,
+            ),
+        };
+    storage
+        .log_request(
+            chat_id.0,
+            cleanup_url_with_rules(url, &config.url_cleanup_rules).as_str(),
+            status,
+            start.elapsed().as_millis() as i64,
+            None,
+        )
+        .await;
+    result
+}
+
+/// The work of `process_roundify_request`, returning the status to log.
+async fn roundify(
+    url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    downloader: &dyn Downloader,
+    api: &dyn TelegramApi,
+    converter: &dyn VideoNoteConverter,
+    validation: &ValidationConfig,
+) -> Result<&'static str, teloxide::RequestError> {
+    let mut info = match downloader.get_media_metadata(url).await {
+        Ok(info) => info,
+        Err(e) => {
+            log::error!(
+                "Failed to get metadata for {} to roundify: {}",
+                url,
+                e.display_to_log()
+            );
+            return api
+                .send_text_message(chat_id, message_id, e.display_to_user())
+                .await
+                .map(|()| "error");
+        }
+    };
+    if let Err(e) = validate_media_metadata(&info, validation)
+        .map(|_| ())
+        .and_then(|()| validate_video_note(&info))
+    {
+        log::info!("Not roundifying {}: {}", url, e);
+        return api
+            .send_text_message(chat_id, message_id, &e.to_string())
+            .await
+            .map(|()| "validation_error");
+    }
+    info.fit_format_to(validation.max_filesize_bytes);
+
+    let media = match downloader.download_media(&mut info, url).await {
+        Ok(media) => media,
+        Err(e) => {
+            log::error!(
+                "Failed to download {} to roundify: {}",
+                url,
+                e.display_to_log()
+            );
+            return api
+                .send_text_message(chat_id, message_id, e.display_to_user())
+                .await
+                .map(|()| "error");
+        }
+    };
+    let mut cleanup_guard = FileCleanupGuard::from_downloaded_media(&media);
+    let item = match media {
+        DownloadedMedia::Single(item) => item,
+        DownloadedMedia::Group(_) => {
+            cleanup_guard.cleanup().await;
+            return api
+                .send_text_message(
+                    chat_id,
+                    message_id,
+                    &ValidationError::VideoNotePlaylist.to_string(),
+                )
+                .await
+                .map(|()| "validation_error");
+        }
+    };
+
+    let result = if item.media_type != MediaType::Video {
+        api.send_text_message(chat_id, message_id, "This link isn't a video.")
+            .await
+            .map(|()| "validation_error")
+    } else {
+        let video_note = video_note_path(&item.filepath);
+        cleanup_guard.add(video_note.clone());
+        match converter.convert(&item.filepath, &video_note).await {
+            Ok(()) => api
+                .send_video_note(chat_id, message_id, &video_note)
+                .await
+                .map(|()| "success"),
+            Err(e) => {
+                log::error!(
+                    "Failed to convert {} to a video note: {}",
+                    item.filepath.display(),
+                    e.display_to_log()
+                );
+                api.send_text_message(chat_id, message_id, VIDEO_NOTE_FAILED_TEXT)
+                    .await
+                    .map(|()| "error")
+            }
+        }
+    };
+    cleanup_guard.cleanup().await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::MockDownloader;
+    use crate::storage::MockStorage;
+    use crate::telegram_api::MockTelegramApi;
+    use crate::test_utils::{TestDownloader, create_test_info};
+    use mockall::predicate::*;
+
+    fn expect_logged_status(status: &'static str) -> MockStorage {
+        let mut storage = MockStorage::new();
+        storage
+            .expect_log_request()
+            .withf(move |chat_id, url, logged, _, _| {
+                *chat_id == 1 && url.starts_with("https://example.com/") && logged == status
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+        storage
+    }
+
+    #[test]
+    fn test_ffmpeg_crops_centre_square_and_caps_duration() {
+        let args = video_note_ffmpeg_args(
+            Path::new("/tmp/uuid.abc.mp4"),
+            Path::new("/tmp/uuid.abc.round.mp4"),
+        );
+        assert_eq!(
+            args,
+            [
+                "-i",
+                "/tmp/uuid.abc.mp4",
+                "-vf",
+                "crop='2*trunc(min(iw,ih)/2)':'2*trunc(min(iw,ih)/2)',scale='min(640,iw)':'min(640,ih)'",
+                "-t",
+                "60",
+                "-c:v",
+                "libx264",
+                "-c:a",
+                "aac",
+                "-movflags",
+                "+faststart",
+                "-y",
+                "/tmp/uuid.abc.round.mp4"
+            ]
+            .map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn test_video_note_keeps_download_prefix() {
+        assert_eq!(
+            video_note_path(Path::new("/tmp/uuid.abc.mp4")),
+            Path::new("/tmp/uuid.abc.round.mp4")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_too_long_video_is_rejected_before_download() {
+        let url = Url::parse("https://example.com/long").unwrap();
+        let mut info = create_test_info();
+        info.duration = Some(95.0);
+        let downloader = TestDownloader::success(info);
+        let mut api = MockTelegramApi::new();
+        api.expect_send_text_message()
+            .with(
+                eq(ChatId(1)),
+                eq(MessageId(2)),
+                eq("Round videos can be at most 60 seconds long, but this video is 95 seconds."),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        api.expect_send_video_note().never();
+
+        process_roundify_request(
+            &url,
+            ChatId(1),
+            MessageId(2),
+            &downloader,
+            &api,
+            &expect_logged_status("validation_error"),
+            &MockVideoNoteConverter::new(),
+            &PipelineConfig::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(downloader.downloads(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_panic_is_reported_and_logged() {
+        let url = Url::parse("https://example.com/video").unwrap();
+        let mut downloader = MockDownloader::new();
+        downloader
+            .expect_get_media_metadata()
+            .returning(|_| panic!("boom"));
+        let mut api = MockTelegramApi::new();
+        api.expect_send_text_message()
+            .withf(|chat_id, message_id, text| {
+                *chat_id == ChatId(1)
+                    && *message_id == MessageId(2)
+                    && text.starts_with("Sorry, something went wrong on my side.")
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        process_roundify_request(
+            &url,
+            ChatId(1),
+            MessageId(2),
+            &downloader,
+            &api,
+            &expect_logged_status("error"),
+            &MockVideoNoteConverter::new(),
+            &PipelineConfig::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_converted_video_is_sent_as_video_note_and_files_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let video = dir.path().join("uuid.abc.mp4");
+        let thumbnail = dir.path().join("uuid.abc.jpg");
+        std::fs::write(&video, b"video").unwrap();
+        std::fs::write(&thumbnail, b"thumb").unwrap();
+        let video_note = video_note_path(&video);
+        let downloader = TestDownloader::success(create_test_info())
+            .with_video(video.clone(), Some(thumbnail.clone()));
+        let mut converter = MockVideoNoteConverter::new();
+        converter
+            .expect_convert()
+            .with(eq(video.clone()), eq(video_note.clone()))
+            .times(1)
+            .returning(|_, output| {
+                std::fs::write(output, b"round").unwrap();
+                Ok(())
+            });
+        let mut api = MockTelegramApi::new();
+        api.expect_send_video_note()
+            .with(eq(ChatId(1)), eq(MessageId(2)), eq(video_note.clone()))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        process_roundify_request(
+            &Url::parse("https://example.com/video").unwrap(),
+            ChatId(1),
+            MessageId(2),
+            &downloader,
+            &api,
+            &expect_logged_status("success"),
+            &converter,
+            &PipelineConfig::default(),
+        )
+        .await
+        .unwrap();
+        for path in [video, thumbnail, video_note] {
+            assert!(!path.exists(), "{} was not removed", path.display());
+        }
+    }
+}
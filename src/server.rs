@@ -0,0 +1,82 @@
+//! The HTTP app served on `PORT`: every bot's webhook, plus routes of our own.
+
+use std::future::Future;
+
+use axum::Router;
+use axum::routing::get;
+use tokio::net::TcpListener;
+
+use crate::dashboard::{self, DashboardState};
+use crate::webhook::merge_webhook_routers;
+
+/// The webhook routes of every bot together with `/healthz`, which answers `ok` as long as
+/// the listener is up, and `/dashboard` when it is configured.
+pub fn app(
+    webhook_routers: impl IntoIterator<Item = Router>,
+    dashboard: Option<DashboardState>,
+) -> Router {
+    let app = merge_webhook_routers(webhook_routers).route("/healthz", get(healthz));
+    match dashboard {
+        Some(state) => app.merge(dashboard::router(state)),
+        None => app,
+    }
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Serve `app` on `listener` until every one of `stop_flags` has completed, i.e. until all
+/// dispatchers have shut down.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    stop_flags: Vec<impl Future<Output = ()> + Send + 'static>,
+) -> std::io::Result<()> {
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            for stop_flag in stop_flags {
+                stop_flag.await;
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use teloxide::update_listeners::webhooks::{Options, axum_no_setup};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_own_routes_are_served_next_to_webhooks() {
+        let options = Options::new(
+            ([127, 0, 0, 1], 8080).into(),
+            "https://bots.example.com/webhook/crabberbot"
+                .parse()
+                .unwrap(),
+        );
+        let (_listener, _stop, router) = axum_no_setup(options);
+        let app = app([router], None);
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/healthz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 64)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"ok");
+
+        let update = Request::post("/webhook/crabberbot")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"update_id": 1}"#))
+            .unwrap();
+        let response = app.oneshot(update).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,165 @@
+//! `/sourcebutton`: per-chat choice between the "Source" link in the caption and a
+//! "🔗 Source" button under the media.
+//!
+//! Telegram doesn't allow buttons on albums, so media groups keep the link in the caption
+//! whatever the setting.
+
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use url::Url;
+
+use crate::permissions::Permissions;
+use crate::storage::Storage;
+use crate::telegram_api::TelegramApi;
+
+const USAGE: &str = "Use <code>/sourcebutton on</code> to get the source link as a button under videos and photos, or <code>/sourcebutton off</code> to keep it in the caption.";
+
+/// The button row linking to `source_url`.
+pub fn source_button_row(source_url: &Url) -> Vec<InlineKeyboardButton> {
+    vec![InlineKeyboardButton::url("🔗 Source", source_url.clone())]
+}
+
+/// A keyboard with just the source button.
+pub fn source_keyboard(source_url: &Url) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![source_button_row(source_url)])
+}
+
+/// `on` or `off`, in any case.
+fn parse_toggle(args: &str) -> Option<bool> {
+    match args.trim().to_ascii_lowercase().as_str() {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// `/sourcebutton [on|off]`: show or change the setting. In groups only admins may change it.
+pub async fn handle_sourcebutton(
+    api: Arc<dyn TelegramApi>,
+    storage: Arc<dyn Storage>,
+    permissions: Arc<Permissions>,
+    message: Message,
+    args: String,
+) -> ResponseResult<()> {
+    let chat_id = message.chat.id;
+    let text = if args.trim().is_empty() {
+        let current = if storage.get_chat_settings(chat_id.0).await.source_as_button {
+            "a button under the media"
+        } else {
+            "in the caption"
+        };
+        format!("The source link is currently {current}.\n{USAGE}")
+    } else if let Some(enabled) = parse_toggle(&args) {
+        let may_change = match message.from.as_ref() {
+            Some(user) => permissions.is_admin(api.as_ref(), chat_id, user.id).await,
+            None => false,
+        };
+        if may_change {
+            storage.set_source_as_button(chat_id.0, enabled).await;
+            log::info!("Chat {} set source_as_button={}", chat_id, enabled);
+            if enabled {
+                "Done: the source link will be a button under videos and photos. Albums keep it in the caption, since Telegram doesn't allow buttons on them.".to_string()
+            } else {
+                "Done: the source link will be in the caption.".to_string()
+            }
+        } else {
+            "Only group admins can change this setting.".to_string()
+        }
+    } else {
+        USAGE.to_string()
+    };
+    api.send_text_message(chat_id, message.id, &text).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_storage::MemoryStorage;
+    use crate::telegram_api::MockTelegramApi;
+    use teloxide::types::ChatMemberStatus;
+
+    fn message(chat: serde_json::Value, text: &str) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": chat,
+            "from": {"id": 5, "is_bot": false, "first_name": "Test"},
+            "text": text
+        }))
+        .unwrap()
+    }
+
+    fn expect_reply(api: &mut MockTelegramApi, chat_id: ChatId, prefix: &'static str) {
+        api.expect_send_text_message()
+            .withf(move |chat, _, text| *chat == chat_id && text.starts_with(prefix))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+    }
+
+    #[test]
+    fn test_parse_toggle() {
+        assert_eq!(parse_toggle(" ON "), Some(true));
+        assert_eq!(parse_toggle("off"), Some(false));
+        assert_eq!(parse_toggle("maybe"), None);
+    }
+
+    #[tokio::test]
+    async fn test_private_chat_turns_the_button_on() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let private = serde_json::json!({"id": 5, "type": "private", "first_name": "Test"});
+        let mut api = MockTelegramApi::new();
+        expect_reply(
+            &mut api,
+            ChatId(5),
+            "Done: the source link will be a button",
+        );
+        expect_reply(&mut api, ChatId(5), "The source link is currently a button");
+        let api: Arc<dyn TelegramApi> = Arc::new(api);
+        let permissions = Arc::new(Permissions::new());
+
+        handle_sourcebutton(
+            api.clone(),
+            storage.clone(),
+            permissions.clone(),
+            message(private.clone(), "/sourcebutton on"),
+            "on".to_string(),
+        )
+        .await
+        .unwrap();
+        assert!(storage.get_chat_settings(5).await.source_as_button);
+
+        handle_sourcebutton(
+            api,
+            storage,
+            permissions,
+            message(private, "/sourcebutton"),
+            String::new(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_group_members_cannot_change_it() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let group = serde_json::json!({"id": -100, "type": "group", "title": "Group"});
+        let mut api = MockTelegramApi::new();
+        api.expect_get_chat_member()
+            .returning(|_, _| Ok(ChatMemberStatus::Member));
+        expect_reply(&mut api, ChatId(-100), "Only group admins");
+
+        handle_sourcebutton(
+            Arc::new(api),
+            storage.clone(),
+            Arc::new(Permissions::new()),
+            message(group, "/sourcebutton on"),
+            "on".to_string(),
+        )
+        .await
+        .unwrap();
+        assert!(!storage.get_chat_settings(-100).await.source_as_button);
+    }
+}
@@ -1,12 +1,25 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use thiserror::Error;
 
-use crate::downloader::MediaType;
+use crate::blackhole::BlackholeStorage;
+use crate::downloader::{MediaInfo, MediaType};
+use crate::file_storage::FileStorage;
 use crate::handler::CallbackContext;
+use crate::maintenance::MaintenanceState;
+use crate::memory_storage::MemoryStorage;
+use crate::storage_metrics::{StorageCounters, StorageMetrics};
 use crate::subscription::{SubscriptionInfo, SubscriptionTier};
 
 /// A payment record returned for self-service refund eligibility checks and owner tooling.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentRecord {
     pub telegram_charge_id: String,
     pub product: String,
@@ -16,6 +29,8 @@ pub struct PaymentRecord {
 
 #[derive(Debug, Clone)]
 pub struct CachedMedia {
+    /// The `media_cache` row, recorded with each delivery for `/trace`.
+    pub id: i32,
     pub caption: String,
     pub files: Vec<CachedFile>,
     /// Path to the extracted audio file on disk, if it was extracted and still exists.
@@ -24,6 +39,114 @@ pub struct CachedMedia {
     pub media_duration_secs: Option<i32>,
 }
 
+/// Descriptive metadata stored alongside a cache entry for analytics and `/findcached`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    pub uploader: Option<String>,
+    pub title: Option<String>,
+    pub extractor: Option<String>,
+    pub duration: Option<f64>,
+}
+
+impl From<&MediaInfo> for CacheMetadata {
+    fn from(info: &MediaInfo) -> Self {
+        Self {
+            uploader: info
+                .uploader
+                .clone()
+                .or_else(|| info.playlist_uploader.clone()),
+            title: info.title.clone(),
+            extractor: info.extractor.clone(),
+            duration: info.duration,
+        }
+    }
+}
+
+/// A cache entry returned by `Storage::search_cache`.
+#[derive(Debug, Clone)]
+pub struct CacheSearchResult {
+    pub source_url: String,
+    pub metadata: CacheMetadata,
+    pub last_used_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// One row of the request log, as listed on the operator dashboard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestLogEntry {
+    pub chat_id: i64,
+    pub source_url: String,
+    pub status: String,
+    pub processing_time_ms: Option<i64>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The message a download was delivered in, looked up by the owner `/trace` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub source_url: String,
+    /// The cache entry that served it, `None` for a fresh download.
+    pub cache_id: Option<i32>,
+    pub delivered_at: chrono::DateTime<chrono::Utc>,
+    /// When the cache entry was first stored, `None` if it has expired since.
+    pub cached_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Size of the media cache, reported by the owner `/stats` command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: i64,
+    pub files: i64,
+}
+
+/// Number of failed requests for one host within an activity window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainFailures {
+    pub domain: String,
+    pub failures: i64,
+}
+
+/// Request volume and latency over a trailing window, reported by the owner `/stats` command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActivityReport {
+    pub total_requests: i64,
+    pub cache_hits: i64,
+    pub fresh_downloads: i64,
+    /// `None` when no request in the window recorded a processing time.
+    pub median_processing_ms: Option<f64>,
+    pub p95_processing_ms: Option<f64>,
+    /// Hosts with the most `error` requests, most failures first.
+    pub top_failing_domains: Vec<DomainFailures>,
+    /// Total size of the files downloaded and uploaded.
+    pub bytes_transferred: i64,
+    /// Hosts whose downloads moved the most data, largest first.
+    pub top_traffic_domains: Vec<DomainTraffic>,
+    /// Hosts that refused the server's region most often (`geo_blocked` requests).
+    pub top_geo_blocked_domains: Vec<DomainFailures>,
+}
+
+/// Per-chat preferences, changed with commands such as `/sourcebutton`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    /// Put the source link in a button under single videos and photos instead of the caption.
+    pub source_as_button: bool,
+}
+
+/// Data moved for one host within an activity window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainTraffic {
+    pub domain: String,
+    pub bytes: i64,
+}
+
+/// How many hosts `ActivityReport::top_failing_domains` lists.
+pub const TOP_FAILING_DOMAINS: i64 = 5;
+
+/// How many hosts `ActivityReport::top_traffic_domains` lists.
+pub const TOP_TRAFFIC_DOMAINS: i64 = 5;
+
+/// How many hosts `ActivityReport::top_geo_blocked_domains` lists.
+pub const TOP_GEO_BLOCKED_DOMAINS: i64 = 5;
+
 #[derive(Debug, Clone)]
 pub struct CachedFile {
     pub telegram_file_id: String,
@@ -33,28 +156,74 @@ pub struct CachedFile {
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait Storage: Send + Sync {
-    async fn get_cached_media(&self, source_url: &str) -> Option<CachedMedia>;
+    /// The media `bot_id` cached for `source_url`. Each bot has its own entries, as a
+    /// Telegram file id only works for the bot that uploaded the file.
+    async fn get_cached_media(&self, bot_id: i64, source_url: &str) -> Option<CachedMedia>;
+    #[allow(clippy::too_many_arguments)]
     async fn store_cached_media(
         &self,
+        bot_id: i64,
         source_url: &str,
         caption: &str,
         files: &[(String, MediaType)],
         audio_cache_path: Option<String>,
         media_duration_secs: Option<i32>,
+        metadata: &CacheMetadata,
     );
+    /// Case-insensitive substring search over cached entries, most recently used first.
+    /// Rows cached before metadata was recorded are still matched on caption and URL.
+    async fn search_cache(&self, query: &str, limit: i64) -> Vec<CacheSearchResult>;
+    async fn cache_stats(&self) -> CacheStats;
+    /// Summarise the requests logged within the last `window`.
+    async fn activity_report(&self, window: Duration) -> ActivityReport;
+    /// `bytes_transferred` is the size of the downloaded files, which are also what is
+    /// uploaded; `None` when nothing was downloaded.
     async fn log_request(
         &self,
         chat_id: i64,
         source_url: &str,
         status: &str,
         processing_time_ms: i64,
+        bytes_transferred: Option<i64>,
+    );
+    /// Media delivered to the chat (fresh downloads and cache hits) since midnight UTC.
+    async fn get_daily_download_count(&self, chat_id: i64) -> i64;
+    /// The `limit` most requested URLs since `since`, most requested first.
+    async fn get_top_urls(&self, limit: i64, since: chrono::DateTime<chrono::Utc>) -> Vec<String>;
+    /// The `limit` most recent requests, newest first.
+    async fn recent_requests(&self, limit: i64) -> Vec<RequestLogEntry>;
+    /// Record the messages in `chat_id` that delivered `source_url`, and the cache entry
+    /// they were sent from, if any.
+    async fn record_delivery(
+        &self,
+        chat_id: i64,
+        message_ids: &[i32],
+        source_url: &str,
+        cache_id: Option<i32>,
     );
+    async fn get_delivery(&self, chat_id: i64, message_id: i32) -> Option<Delivery>;
 
     // Subscription management
     async fn get_subscription(&self, user_id: i64) -> SubscriptionInfo;
     async fn upsert_subscription(&self, user_id: i64, tier: SubscriptionTier, duration_days: i64);
 
+    // Chat settings
+    async fn get_chat_settings(&self, chat_id: i64) -> ChatSettings;
+    async fn set_source_as_button(&self, chat_id: i64, enabled: bool);
+    /// Move a chat's settings and download history to its new id after a group was
+    /// upgraded to a supergroup. Settings already stored for `new_chat_id` are kept.
+    async fn migrate_chat(&self, old_chat_id: i64, new_chat_id: i64);
+
+    // Maintenance mode, loaded at startup
+    async fn get_maintenance(&self) -> MaintenanceState;
+    async fn set_maintenance(&self, state: &MaintenanceState);
+
     // Payment recording
+    /// Whether payments are kept. A storage that drops them must not take any, as
+    /// purchases could never be credited.
+    fn records_payments(&self) -> bool {
+        true
+    }
     async fn record_payment(
         &self,
         user_id: i64,
@@ -99,6 +268,162 @@ pub trait Storage: Send + Sync {
     async fn expire_stale_topups(&self);
 }
 
+/// Postgres pool settings, taken from `AppConfig` at startup.
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub database_url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    /// Server-side `statement_timeout` applied to every connection, if set.
+    pub statement_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Error)]
+pub enum StorageInitError {
+    #[error("Failed to connect to Postgres: {0}")]
+    Connect(#[from] sqlx::Error),
+    #[error("Failed to run database migrations: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("Failed to read the storage file {path:?}: {source}")]
+    File {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// What `create_storage` builds, from `AppConfig::storage_settings`.
+#[derive(Debug, Clone)]
+pub enum StorageSettings {
+    Postgres(PoolSettings),
+    Memory,
+    File(PathBuf),
+    Disabled,
+}
+
+/// Storage backend selected by the scheme of `STORAGE_URL`. Only backends implemented
+/// here have a scheme; anything else is an `UnknownScheme` error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageUrl {
+    /// `postgres://` or `postgresql://`; the whole URL is the connection string.
+    Postgres(String),
+    /// `memory://`: nothing persists across restarts.
+    Memory,
+    /// `file:///path/to/state.json`: the state is saved to that file, see `FileStorage`.
+    File(PathBuf),
+    /// `none://`: nothing is stored at all, see `BlackholeStorage`.
+    Disabled,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum StorageUrlError {
+    #[error("storage URL is not a valid URL")]
+    Invalid,
+    #[error(
+        "unknown storage URL scheme `{0}`, expected postgres://, memory://, file:// or none://"
+    )]
+    UnknownScheme(String),
+}
+
+impl FromStr for StorageUrl {
+    type Err = StorageUrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = url::Url::parse(s).map_err(|_| StorageUrlError::Invalid)?;
+        match url.scheme() {
+            "postgres" | "postgresql" => Ok(Self::Postgres(s.to_string())),
+            "memory" => Ok(Self::Memory),
+            "file" => url
+                .to_file_path()
+                .map(Self::File)
+                .map_err(|()| StorageUrlError::Invalid),
+            "none" => Ok(Self::Disabled),
+            scheme => Err(StorageUrlError::UnknownScheme(scheme.to_string())),
+        }
+    }
+}
+
+/// The storage chosen at startup. `pool` is `None` when running on the in-memory fallback.
+pub struct StorageBackend {
+    pub storage: Arc<dyn Storage>,
+    pub pool: Option<PgPool>,
+    /// Counters of the `StorageMetrics` wrapping `storage`.
+    pub metrics: Arc<StorageCounters>,
+}
+
+impl StorageBackend {
+    fn new(backend: impl Storage + 'static, pool: Option<PgPool>) -> Self {
+        let storage = StorageMetrics::new(backend);
+        Self {
+            metrics: storage.counters(),
+            storage: Arc::new(storage),
+            pool,
+        }
+    }
+}
+
+/// Connect to Postgres, apply migrations and ping the database.
+pub async fn connect_postgres(settings: &PoolSettings) -> Result<PgPool, StorageInitError> {
+    let mut connect_options = PgConnectOptions::from_str(&settings.database_url)?;
+    if let Some(timeout) = settings.statement_timeout {
+        connect_options =
+            connect_options.options([("statement_timeout", timeout.as_millis().to_string())]);
+    }
+    let pool = PgPoolOptions::new()
+        .max_connections(settings.max_connections)
+        .min_connections(settings.min_connections)
+        .acquire_timeout(settings.acquire_timeout)
+        .connect_with(connect_options)
+        .await?;
+    PostgresStorage::run_migrations(&pool).await?;
+    sqlx::query("SELECT 1").execute(&pool).await?;
+    Ok(pool)
+}
+
+/// Build the storage backend at startup. When Postgres is unreachable and
+/// `storage_required` is false, fall back to `MemoryStorage` instead of failing.
+pub async fn create_storage(
+    settings: &StorageSettings,
+    storage_required: bool,
+) -> Result<StorageBackend, StorageInitError> {
+    let settings = match settings {
+        StorageSettings::Postgres(settings) => settings,
+        StorageSettings::Memory => {
+            log::warn!("Using in-memory storage; cache and payments will not persist");
+            return Ok(StorageBackend::new(MemoryStorage::new(), None));
+        }
+        StorageSettings::File(path) => {
+            let storage =
+                FileStorage::open(path)
+                    .await
+                    .map_err(|source| StorageInitError::File {
+                        path: path.clone(),
+                        source,
+                    })?;
+            log::info!("Using file storage at {:?}", path);
+            return Ok(StorageBackend::new(storage, None));
+        }
+        StorageSettings::Disabled => {
+            log::warn!("Storage is disabled; nothing will be cached and payments are not recorded");
+            return Ok(StorageBackend::new(BlackholeStorage, None));
+        }
+    };
+    match connect_postgres(settings).await {
+        Ok(pool) => Ok(StorageBackend::new(
+            PostgresStorage::new(pool.clone()),
+            Some(pool),
+        )),
+        Err(e) if !storage_required => {
+            log::warn!(
+                "{} — continuing with in-memory storage; cache and payments will not persist",
+                e
+            );
+            Ok(StorageBackend::new(MemoryStorage::new(), None))
+        }
+        Err(e) => Err(e),
+    }
+}
+
 pub struct PostgresStorage {
     pool: PgPool,
 }
@@ -112,6 +437,34 @@ impl PostgresStorage {
         sqlx::migrate!("./migrations").run(pool).await
     }
 
+    async fn move_chat_rows(&self, old_chat_id: i64, new_chat_id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE chat_settings SET chat_id = $2, updated_at = NOW() \
+             WHERE chat_id = $1 AND NOT EXISTS (SELECT 1 FROM chat_settings WHERE chat_id = $2)",
+        )
+        .bind(old_chat_id)
+        .bind(new_chat_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM chat_settings WHERE chat_id = $1")
+            .bind(old_chat_id)
+            .execute(&mut *tx)
+            .await?;
+        // The daily quota counts these rows.
+        sqlx::query("UPDATE requests SET chat_id = $2 WHERE chat_id = $1")
+            .bind(old_chat_id)
+            .bind(new_chat_id)
+            .execute(&mut *tx)
+            .await?;
+        // Their message ids belong to the old group, so they can't be moved.
+        sqlx::query("DELETE FROM deliveries WHERE chat_id = $1")
+            .bind(old_chat_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
     pub async fn cleanup_expired(pool: &PgPool, ttl_days: i64) {
         // Collect audio file paths to delete before removing DB rows
         let expired_audio: Vec<(Option<String>,)> = sqlx::query_as(
@@ -136,26 +489,122 @@ impl PostgresStorage {
                     "Cache cleanup: removed {} expired entries",
                     r.rows_affected()
                 );
-                for path in expired_audio.into_iter().filter_map(|(p,)| p) {
-                    if let Err(e) = tokio::fs::remove_file(&path).await {
-                        if e.kind() != std::io::ErrorKind::NotFound {
-                            log::warn!("Failed to delete expired audio file {}: {}", path, e);
-                        }
+                remove_audio_files(expired_audio.into_iter().filter_map(|(p,)| p)).await;
+            }
+            Err(e) => log::error!("Cache cleanup failed: {}", e),
+        }
+
+        // Traces outlive their cache entry but are kept no longer than the cache TTL.
+        let result = sqlx::query(
+            "DELETE FROM deliveries WHERE created_at < NOW() - make_interval(days => $1::int)",
+        )
+        .bind(ttl_days)
+        .execute(pool)
+        .await;
+        match result {
+            Ok(r) => log::info!(
+                "Delivery cleanup: removed {} expired deliveries",
+                r.rows_affected()
+            ),
+            Err(e) => log::error!("Delivery cleanup failed: {}", e),
+        }
+    }
+
+    /// Delete the least-recently-used cache entries beyond `max_entries`.
+    /// Works in batches of `EVICTION_BATCH_SIZE` rows so no single statement holds
+    /// locks on a large part of the table. Returns the number of evicted entries.
+    pub async fn evict_lru(pool: &PgPool, max_entries: i64) -> u64 {
+        const EVICTION_BATCH_SIZE: i64 = 1000;
+        let mut evicted = 0u64;
+        loop {
+            let result: Result<Vec<(Option<String>,)>, _> = sqlx::query_as(
+                "DELETE FROM media_cache WHERE id IN ( \
+                     SELECT id FROM media_cache ORDER BY last_used_at DESC, id DESC \
+                     OFFSET $1 LIMIT $2 \
+                 ) RETURNING audio_cache_path",
+            )
+            .bind(max_entries)
+            .bind(EVICTION_BATCH_SIZE)
+            .fetch_all(pool)
+            .await;
+
+            match result {
+                Ok(rows) => {
+                    let batch = rows.len() as u64;
+                    evicted += batch;
+                    remove_audio_files(rows.into_iter().filter_map(|(p,)| p)).await;
+                    if batch < EVICTION_BATCH_SIZE as u64 {
+                        break;
                     }
                 }
+                Err(e) => {
+                    log::error!("Cache eviction failed: {}", e);
+                    break;
+                }
             }
-            Err(e) => log::error!("Cache cleanup failed: {}", e),
+        }
+        if evicted > 0 {
+            log::info!(
+                "Cache eviction: removed {} entries beyond the {} entry cap",
+                evicted,
+                max_entries
+            );
+        }
+        evicted
+    }
+
+    /// Hosts with the most requests logged with `status` in the last `window_secs`,
+    /// most first.
+    async fn domains_by_status(
+        &self,
+        status: &str,
+        window_secs: f64,
+        limit: i64,
+    ) -> Vec<DomainFailures> {
+        let domains: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT COALESCE(lower(substring(source_url from '^[^:]+://(?:www\\.)?([^/?#:]+)')), \
+                             'unknown') AS domain, \
+                    COUNT(*) \
+             FROM requests \
+             WHERE status = $1 AND created_at >= NOW() - make_interval(secs => $2) \
+             GROUP BY domain \
+             ORDER BY 2 DESC, 1 \
+             LIMIT $3",
+        )
+        .bind(status)
+        .bind(window_secs)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read {} domains: {}", status, e);
+            Vec::new()
+        });
+        domains
+            .into_iter()
+            .map(|(domain, failures)| DomainFailures { domain, failures })
+            .collect()
+    }
+}
+
+async fn remove_audio_files(paths: impl Iterator<Item = String>) {
+    for path in paths {
+        if let Err(e) = tokio::fs::remove_file(&path).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            log::warn!("Failed to delete expired audio file {}: {}", path, e);
         }
     }
 }
 
 #[async_trait]
 impl Storage for PostgresStorage {
-    async fn get_cached_media(&self, source_url: &str) -> Option<CachedMedia> {
+    async fn get_cached_media(&self, bot_id: i64, source_url: &str) -> Option<CachedMedia> {
         let cache_row: Option<(i32, String, Option<String>, Option<i32>)> = sqlx::query_as(
             "SELECT id, caption, audio_cache_path, media_duration_secs \
-                 FROM media_cache WHERE source_url = $1",
+                 FROM media_cache WHERE bot_id = $1 AND source_url = $2",
         )
+        .bind(bot_id)
         .bind(source_url)
         .fetch_optional(&self.pool)
         .await
@@ -205,6 +654,7 @@ impl Storage for PostgresStorage {
         }
 
         Some(CachedMedia {
+            id: cache_id,
             caption,
             files,
             audio_cache_path,
@@ -214,11 +664,13 @@ impl Storage for PostgresStorage {
 
     async fn store_cached_media(
         &self,
+        bot_id: i64,
         source_url: &str,
         caption: &str,
         files: &[(String, MediaType)],
         audio_cache_path: Option<String>,
         media_duration_secs: Option<i32>,
+        metadata: &CacheMetadata,
     ) {
         let mut tx = match self.pool.begin().await {
             Ok(tx) => tx,
@@ -229,16 +681,23 @@ impl Storage for PostgresStorage {
         };
 
         let result: Result<(i32,), _> = sqlx::query_as(
-            "INSERT INTO media_cache (source_url, caption, audio_cache_path, media_duration_secs) \
-             VALUES ($1, $2, $3, $4) \
-             ON CONFLICT (source_url) DO UPDATE \
-             SET caption = $2, audio_cache_path = $3, media_duration_secs = $4, last_used_at = NOW() \
+            "INSERT INTO media_cache \
+             (bot_id, source_url, caption, audio_cache_path, media_duration_secs, uploader, title, extractor, duration) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+             ON CONFLICT (bot_id, source_url) DO UPDATE \
+             SET caption = $3, audio_cache_path = $4, media_duration_secs = $5, \
+                 uploader = $6, title = $7, extractor = $8, duration = $9, last_used_at = NOW() \
              RETURNING id",
         )
+        .bind(bot_id)
         .bind(source_url)
         .bind(caption)
         .bind(audio_cache_path)
         .bind(media_duration_secs)
+        .bind(&metadata.uploader)
+        .bind(&metadata.title)
+        .bind(&metadata.extractor)
+        .bind(metadata.duration)
         .fetch_one(&mut *tx)
         .await;
 
@@ -293,21 +752,153 @@ impl Storage for PostgresStorage {
         log::info!("Cached {} file(s) for {}", files.len(), source_url);
     }
 
+    async fn search_cache(&self, query: &str, limit: i64) -> Vec<CacheSearchResult> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{}%", escaped);
+        let rows: Vec<(
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<f64>,
+            chrono::DateTime<chrono::Utc>,
+        )> = sqlx::query_as(
+            "SELECT source_url, uploader, title, extractor, duration, last_used_at \
+             FROM media_cache \
+             WHERE uploader ILIKE $1 OR title ILIKE $1 OR extractor ILIKE $1 \
+                OR caption ILIKE $1 OR source_url ILIKE $1 \
+             ORDER BY last_used_at DESC LIMIT $2",
+        )
+        .bind(pattern)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Cache search failed for {:?}: {}", query, e);
+            vec![]
+        });
+
+        rows.into_iter()
+            .map(
+                |(source_url, uploader, title, extractor, duration, last_used_at)| {
+                    CacheSearchResult {
+                        source_url,
+                        metadata: CacheMetadata {
+                            uploader,
+                            title,
+                            extractor,
+                            duration,
+                        },
+                        last_used_at,
+                    }
+                },
+            )
+            .collect()
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        let result: Result<(i64, i64), _> = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM media_cache), (SELECT COUNT(*) FROM cached_files)",
+        )
+        .fetch_one(&self.pool)
+        .await;
+        match result {
+            Ok((entries, files)) => CacheStats { entries, files },
+            Err(e) => {
+                log::error!("Failed to read cache stats: {}", e);
+                CacheStats::default()
+            }
+        }
+    }
+
+    async fn activity_report(&self, window: Duration) -> ActivityReport {
+        let window_secs = window.as_secs_f64();
+        let totals: Result<(i64, i64, i64, Option<f64>, Option<f64>, i64), _> = sqlx::query_as(
+            "SELECT COUNT(*), \
+                    COUNT(*) FILTER (WHERE status = 'cached'), \
+                    COUNT(*) FILTER (WHERE status = 'success'), \
+                    percentile_cont(0.5) WITHIN GROUP (ORDER BY processing_time_ms), \
+                    percentile_cont(0.95) WITHIN GROUP (ORDER BY processing_time_ms), \
+                    COALESCE(SUM(bytes_transferred), 0)::BIGINT \
+             FROM requests \
+             WHERE created_at >= NOW() - make_interval(secs => $1)",
+        )
+        .bind(window_secs)
+        .fetch_one(&self.pool)
+        .await;
+        let (total_requests, cache_hits, fresh_downloads, median, p95, bytes_transferred) =
+            match totals {
+                Ok(row) => row,
+                Err(e) => {
+                    log::error!("Failed to read request activity: {}", e);
+                    return ActivityReport::default();
+                }
+            };
+
+        let domains = self
+            .domains_by_status("error", window_secs, TOP_FAILING_DOMAINS)
+            .await;
+        let geo_blocked = self
+            .domains_by_status("geo_blocked", window_secs, TOP_GEO_BLOCKED_DOMAINS)
+            .await;
+
+        let traffic: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT COALESCE(lower(substring(source_url from '^[^:]+://(?:www\\.)?([^/?#:]+)')), \
+                             'unknown') AS domain, \
+                    SUM(bytes_transferred)::BIGINT \
+             FROM requests \
+             WHERE bytes_transferred IS NOT NULL \
+               AND created_at >= NOW() - make_interval(secs => $1) \
+             GROUP BY domain \
+             ORDER BY 2 DESC, 1 \
+             LIMIT $2",
+        )
+        .bind(window_secs)
+        .bind(TOP_TRAFFIC_DOMAINS)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read traffic by domain: {}", e);
+            Vec::new()
+        });
+
+        ActivityReport {
+            total_requests,
+            cache_hits,
+            fresh_downloads,
+            median_processing_ms: median,
+            p95_processing_ms: p95,
+            top_failing_domains: domains,
+            bytes_transferred,
+            top_traffic_domains: traffic
+                .into_iter()
+                .map(|(domain, bytes)| DomainTraffic { domain, bytes })
+                .collect(),
+            top_geo_blocked_domains: geo_blocked,
+        }
+    }
+
     async fn log_request(
         &self,
         chat_id: i64,
         source_url: &str,
         status: &str,
         processing_time_ms: i64,
+        bytes_transferred: Option<i64>,
     ) {
         if let Err(e) = sqlx::query(
-            "INSERT INTO requests (chat_id, source_url, status, processing_time_ms) \
-             VALUES ($1, $2, $3, $4)",
+            "INSERT INTO requests (chat_id, source_url, status, processing_time_ms, \
+                                   bytes_transferred) \
+             VALUES ($1, $2, $3, $4, $5)",
         )
         .bind(chat_id)
         .bind(source_url)
         .bind(status)
         .bind(processing_time_ms)
+        .bind(bytes_transferred)
         .execute(&self.pool)
         .await
         {
@@ -315,6 +906,132 @@ impl Storage for PostgresStorage {
         }
     }
 
+    async fn get_daily_download_count(&self, chat_id: i64) -> i64 {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM requests \
+             WHERE chat_id = $1 AND status IN ('success', 'cached') \
+               AND created_at >= date_trunc('day', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'",
+        )
+        .bind(chat_id)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Failed to count daily downloads for {}: {}", chat_id, e);
+            0
+        })
+    }
+
+    async fn get_top_urls(&self, limit: i64, since: chrono::DateTime<chrono::Utc>) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT source_url FROM requests \
+             WHERE created_at >= $1 \
+             GROUP BY source_url \
+             ORDER BY COUNT(*) DESC, source_url \
+             LIMIT $2",
+        )
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read most requested URLs: {}", e);
+            Vec::new()
+        })
+    }
+
+    async fn recent_requests(&self, limit: i64) -> Vec<RequestLogEntry> {
+        let rows: Result<
+            Vec<(
+                i64,
+                String,
+                String,
+                Option<i64>,
+                chrono::DateTime<chrono::Utc>,
+            )>,
+            _,
+        > = sqlx::query_as(
+            "SELECT chat_id, source_url, status, processing_time_ms, created_at \
+                 FROM requests ORDER BY created_at DESC, id DESC LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await;
+        match rows {
+            Ok(rows) => rows
+                .into_iter()
+                .map(
+                    |(chat_id, source_url, status, processing_time_ms, created_at)| {
+                        RequestLogEntry {
+                            chat_id,
+                            source_url,
+                            status,
+                            processing_time_ms,
+                            created_at,
+                        }
+                    },
+                )
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to read recent requests: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn record_delivery(
+        &self,
+        chat_id: i64,
+        message_ids: &[i32],
+        source_url: &str,
+        cache_id: Option<i32>,
+    ) {
+        if message_ids.is_empty() {
+            return;
+        }
+        let result = sqlx::query(
+            "INSERT INTO deliveries (chat_id, message_id, source_url, cache_id) \
+             SELECT $1, message_id, $3, $4 FROM UNNEST($2::int[]) AS message_id \
+             ON CONFLICT (chat_id, message_id) DO NOTHING",
+        )
+        .bind(chat_id)
+        .bind(message_ids)
+        .bind(source_url)
+        .bind(cache_id)
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            log::error!("Failed to record delivery of {}: {}", source_url, e);
+        }
+    }
+
+    async fn get_delivery(&self, chat_id: i64, message_id: i32) -> Option<Delivery> {
+        let row: Option<(
+            String,
+            Option<i32>,
+            chrono::DateTime<chrono::Utc>,
+            Option<chrono::DateTime<chrono::Utc>>,
+        )> = sqlx::query_as(
+            "SELECT d.source_url, d.cache_id, d.created_at, c.created_at \
+                 FROM deliveries d LEFT JOIN media_cache c ON c.id = d.cache_id \
+                 WHERE d.chat_id = $1 AND d.message_id = $2",
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("Delivery lookup failed: {}", e);
+            e
+        })
+        .ok()?;
+        row.map(|(source_url, cache_id, delivered_at, cached_at)| Delivery {
+            source_url,
+            cache_id,
+            delivered_at,
+            cached_at,
+        })
+    }
+
     async fn get_subscription(&self, user_id: i64) -> SubscriptionInfo {
         let row: Option<(
             String,
@@ -374,6 +1091,77 @@ impl Storage for PostgresStorage {
         }
     }
 
+    async fn get_chat_settings(&self, chat_id: i64) -> ChatSettings {
+        let row: Option<(bool,)> =
+            sqlx::query_as("SELECT source_as_button FROM chat_settings WHERE chat_id = $1")
+                .bind(chat_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| {
+                    log::error!("Failed to get chat settings for {}: {}", chat_id, e);
+                    e
+                })
+                .ok()
+                .flatten();
+        row.map(|(source_as_button,)| ChatSettings { source_as_button })
+            .unwrap_or_default()
+    }
+
+    async fn set_source_as_button(&self, chat_id: i64, enabled: bool) {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO chat_settings (chat_id, source_as_button, updated_at) \
+             VALUES ($1, $2, NOW()) \
+             ON CONFLICT (chat_id) DO UPDATE SET source_as_button = $2, updated_at = NOW()",
+        )
+        .bind(chat_id)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("Failed to update chat settings for {}: {}", chat_id, e);
+        }
+    }
+
+    async fn get_maintenance(&self) -> MaintenanceState {
+        let row: Option<(bool, Option<String>)> =
+            sqlx::query_as("SELECT enabled, message FROM maintenance")
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| {
+                    log::error!("Failed to get maintenance mode: {}", e);
+                    e
+                })
+                .ok()
+                .flatten();
+        row.map(|(enabled, message)| MaintenanceState { enabled, message })
+            .unwrap_or_default()
+    }
+
+    async fn set_maintenance(&self, state: &MaintenanceState) {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO maintenance (enabled, message, updated_at) VALUES ($1, $2, NOW()) \
+             ON CONFLICT (id) DO UPDATE SET enabled = $1, message = $2, updated_at = NOW()",
+        )
+        .bind(state.enabled)
+        .bind(&state.message)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("Failed to update maintenance mode: {}", e);
+        }
+    }
+
+    async fn migrate_chat(&self, old_chat_id: i64, new_chat_id: i64) {
+        if let Err(e) = self.move_chat_rows(old_chat_id, new_chat_id).await {
+            log::error!(
+                "Failed to migrate chat {} to {}: {}",
+                old_chat_id,
+                new_chat_id,
+                e
+            );
+        }
+    }
+
     async fn record_payment(
         &self,
         user_id: i64,
@@ -681,3 +1469,598 @@ impl Storage for PostgresStorage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// These tests need a real Postgres and are skipped unless `TEST_DATABASE_URL` is set.
+    /// Run with `cargo test -- --ignored` and `TEST_DATABASE_URL` set.
+    fn test_database_url() -> String {
+        std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set")
+    }
+
+    async fn test_storage() -> PostgresStorage {
+        let url = test_database_url();
+        let pool = PgPool::connect(&url)
+            .await
+            .expect("Failed to connect to TEST_DATABASE_URL");
+        PostgresStorage::run_migrations(&pool)
+            .await
+            .expect("Failed to run migrations");
+        PostgresStorage::new(pool)
+    }
+
+    /// A pool whose connections use a fresh schema, for tests that need an empty table.
+    async fn isolated_pool() -> PgPool {
+        let url = test_database_url();
+        let schema = format!("test_{}", unique_token());
+        let admin = PgPool::connect(&url)
+            .await
+            .expect("Failed to connect to TEST_DATABASE_URL");
+        sqlx::query(&format!("CREATE SCHEMA {schema}"))
+            .execute(&admin)
+            .await
+            .unwrap();
+        let options = PgConnectOptions::from_str(&url)
+            .unwrap()
+            .options([("search_path", schema)]);
+        let pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+        PostgresStorage::run_migrations(&pool)
+            .await
+            .expect("Failed to run migrations");
+        pool
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_evict_lru_keeps_most_recent_entries() {
+        let pool = isolated_pool().await;
+        let (max_entries, extra) = (5, 3);
+        for i in 0..(max_entries + extra) {
+            sqlx::query(
+                "INSERT INTO media_cache (source_url, caption, last_used_at) \
+                 VALUES ($1, '', NOW() - make_interval(mins => $2::int))",
+            )
+            .bind(format!("https://example.com/{i}"))
+            .bind(i)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let evicted = PostgresStorage::evict_lru(&pool, max_entries).await;
+        assert_eq!(evicted, extra as u64);
+
+        let remaining: Vec<(String,)> =
+            sqlx::query_as("SELECT source_url FROM media_cache ORDER BY last_used_at DESC")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let expected: Vec<String> = (0..max_entries)
+            .map(|i| format!("https://example.com/{i}"))
+            .collect();
+        assert_eq!(
+            remaining.into_iter().map(|(u,)| u).collect::<Vec<_>>(),
+            expected
+        );
+        let stats = PostgresStorage::new(pool).cache_stats().await;
+        assert_eq!(stats.entries, max_entries);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_chat_settings_round_trip() {
+        let pool = isolated_pool().await;
+        let storage = PostgresStorage::new(pool);
+        assert_eq!(storage.get_chat_settings(1).await, ChatSettings::default());
+
+        storage.set_source_as_button(1, true).await;
+        assert!(storage.get_chat_settings(1).await.source_as_button);
+        assert!(!storage.get_chat_settings(2).await.source_as_button);
+
+        storage.set_source_as_button(1, false).await;
+        assert!(!storage.get_chat_settings(1).await.source_as_button);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_maintenance_round_trip() {
+        let pool = isolated_pool().await;
+        let storage = PostgresStorage::new(pool);
+        assert_eq!(storage.get_maintenance().await, MaintenanceState::default());
+
+        let on = MaintenanceState {
+            enabled: true,
+            message: Some("Back at 6pm".to_string()),
+        };
+        storage.set_maintenance(&on).await;
+        assert_eq!(storage.get_maintenance().await, on);
+
+        storage.set_maintenance(&MaintenanceState::default()).await;
+        assert_eq!(storage.get_maintenance().await, MaintenanceState::default());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_migrate_chat_moves_settings_and_quota_and_drops_deliveries() {
+        let pool = isolated_pool().await;
+        let storage = PostgresStorage::new(pool);
+        storage.set_source_as_button(-1, true).await;
+        storage
+            .log_request(-1, "https://a.com/1", "success", 1, None)
+            .await;
+        storage
+            .record_delivery(-1, &[7], "https://a.com/1", None)
+            .await;
+
+        storage.migrate_chat(-1, -1001).await;
+
+        assert!(storage.get_delivery(-1, 7).await.is_none());
+        assert!(storage.get_delivery(-1001, 7).await.is_none());
+        assert!(storage.get_chat_settings(-1001).await.source_as_button);
+        assert_eq!(storage.get_chat_settings(-1).await, ChatSettings::default());
+        assert_eq!(storage.get_daily_download_count(-1001).await, 1);
+        assert_eq!(storage.get_daily_download_count(-1).await, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_recent_requests_lists_newest_first() {
+        let pool = isolated_pool().await;
+        let storage = PostgresStorage::new(pool);
+        for (i, status) in ["success", "error", "cached"].into_iter().enumerate() {
+            storage
+                .log_request(
+                    1,
+                    &format!("https://a.com/{i}"),
+                    status,
+                    10 * i as i64,
+                    None,
+                )
+                .await;
+        }
+
+        let requests = storage.recent_requests(2).await;
+        let summary: Vec<(&str, &str, Option<i64>)> = requests
+            .iter()
+            .map(|r| {
+                (
+                    r.source_url.as_str(),
+                    r.status.as_str(),
+                    r.processing_time_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("https://a.com/2", "cached", Some(20)),
+                ("https://a.com/1", "error", Some(10))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_deliveries_trace_back_to_the_cache_entry() {
+        let pool = isolated_pool().await;
+        let storage = PostgresStorage::new(pool);
+        storage
+            .store_cached_media(
+                1,
+                "https://a.com/1",
+                "caption",
+                &[("file".to_string(), MediaType::Photo)],
+                None,
+                None,
+                &CacheMetadata::default(),
+            )
+            .await;
+        let cache_id = storage
+            .get_cached_media(1, "https://a.com/1")
+            .await
+            .unwrap()
+            .id;
+        storage
+            .record_delivery(5, &[10, 11], "https://a.com/1", Some(cache_id))
+            .await;
+        storage
+            .record_delivery(5, &[12], "https://a.com/2", None)
+            .await;
+
+        let cached = storage.get_delivery(5, 11).await.unwrap();
+        assert_eq!(cached.source_url, "https://a.com/1");
+        assert_eq!(cached.cache_id, Some(cache_id));
+        assert!(cached.cached_at.is_some());
+        let fresh = storage.get_delivery(5, 12).await.unwrap();
+        assert_eq!((fresh.cache_id, fresh.cached_at), (None, None));
+        assert_eq!(storage.get_delivery(6, 10).await, None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_cleanup_expired_prunes_old_deliveries() {
+        let pool = isolated_pool().await;
+        let storage = PostgresStorage::new(pool.clone());
+        storage
+            .record_delivery(5, &[10], "https://a.com/old", None)
+            .await;
+        storage
+            .record_delivery(5, &[11], "https://a.com/new", None)
+            .await;
+        sqlx::query(
+            "UPDATE deliveries SET created_at = NOW() - INTERVAL '8 days' WHERE message_id = 10",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        PostgresStorage::cleanup_expired(&pool, 7).await;
+
+        assert_eq!(storage.get_delivery(5, 10).await, None);
+        assert!(storage.get_delivery(5, 11).await.is_some());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_daily_download_count_counts_todays_deliveries() {
+        let pool = isolated_pool().await;
+        let rows: [(i64, &str, &str); 5] = [
+            (1, "success", "0 seconds"),
+            (1, "cached", "0 seconds"),
+            (1, "error", "0 seconds"),
+            (1, "success", "2 days"),
+            (2, "success", "0 seconds"),
+        ];
+        for (chat_id, status, age) in rows {
+            sqlx::query(
+                "INSERT INTO requests (chat_id, source_url, status, processing_time_ms, created_at) \
+                 VALUES ($1, 'https://a.com/1', $2, 1, NOW() - $3::interval)",
+            )
+            .bind(chat_id)
+            .bind(status)
+            .bind(age)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let storage = PostgresStorage::new(pool);
+
+        assert_eq!(storage.get_daily_download_count(1).await, 2);
+        assert_eq!(storage.get_daily_download_count(2).await, 1);
+        assert_eq!(storage.get_daily_download_count(3).await, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_get_top_urls_orders_by_request_count() {
+        let pool = isolated_pool().await;
+        let rows: [(&str, i32); 6] = [
+            ("https://a.com/popular", 1),
+            ("https://a.com/popular", 2),
+            ("https://a.com/popular", 3),
+            ("https://a.com/second", 1),
+            ("https://a.com/second", 2),
+            ("https://a.com/once", 1),
+        ];
+        for (url, hours_ago) in rows {
+            sqlx::query(
+                "INSERT INTO requests (chat_id, source_url, status, processing_time_ms, created_at) \
+                 VALUES (1, $1, 'success', 1, NOW() - make_interval(hours => $2))",
+            )
+            .bind(url)
+            .bind(hours_ago)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let storage = PostgresStorage::new(pool);
+
+        assert_eq!(
+            storage
+                .get_top_urls(2, chrono::Utc::now() - chrono::TimeDelta::days(1))
+                .await,
+            ["https://a.com/popular", "https://a.com/second"]
+        );
+        // Only the most recent request of each URL is within the last 90 minutes.
+        assert_eq!(
+            storage
+                .get_top_urls(10, chrono::Utc::now() - chrono::TimeDelta::minutes(90))
+                .await,
+            [
+                "https://a.com/once",
+                "https://a.com/popular",
+                "https://a.com/second"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_activity_report_summarises_window() {
+        let pool = isolated_pool().await;
+        let rows: [(&str, &str, i64, i32, Option<i64>); 8] = [
+            ("https://www.tiktok.com/@a/video/1", "cached", 100, 1, None),
+            (
+                "https://youtube.com/watch?v=1",
+                "success",
+                200,
+                2,
+                Some(3_000),
+            ),
+            (
+                "https://youtube.com/watch?v=2",
+                "success",
+                300,
+                3,
+                Some(4_000),
+            ),
+            (
+                "https://www.tiktok.com/@a/video/2",
+                "error",
+                400,
+                4,
+                Some(500),
+            ),
+            ("https://tiktok.com/@b/video/3", "error", 500, 5, None),
+            ("https://example.com:8080/x", "error", 600, 6, None),
+            (
+                "https://www.bbc.co.uk/iplayer/1",
+                "geo_blocked",
+                600,
+                7,
+                None,
+            ),
+            // Outside the 24h window.
+            (
+                "https://old.example.com/",
+                "error",
+                100_000,
+                48,
+                Some(9_000),
+            ),
+        ];
+        for (url, status, ms, hours_ago, bytes) in rows {
+            sqlx::query(
+                "INSERT INTO requests (chat_id, source_url, status, processing_time_ms, \
+                                       bytes_transferred, created_at) \
+                 VALUES (1, $1, $2, $3, $4, NOW() - make_interval(hours => $5))",
+            )
+            .bind(url)
+            .bind(status)
+            .bind(ms)
+            .bind(bytes)
+            .bind(hours_ago)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let storage = PostgresStorage::new(pool);
+
+        let report = storage
+            .activity_report(Duration::from_secs(24 * 60 * 60))
+            .await;
+        assert_eq!(report.total_requests, 7);
+        assert_eq!(report.cache_hits, 1);
+        assert_eq!(report.fresh_downloads, 2);
+        assert_eq!(report.median_processing_ms, Some(400.0));
+        assert_eq!(report.p95_processing_ms, Some(600.0));
+        assert_eq!(
+            report.top_failing_domains,
+            vec![
+                DomainFailures {
+                    domain: "tiktok.com".to_string(),
+                    failures: 2,
+                },
+                DomainFailures {
+                    domain: "example.com".to_string(),
+                    failures: 1,
+                },
+            ]
+        );
+        assert_eq!(report.bytes_transferred, 7_500);
+        assert_eq!(
+            report.top_traffic_domains,
+            vec![
+                DomainTraffic {
+                    domain: "youtube.com".to_string(),
+                    bytes: 7_000,
+                },
+                DomainTraffic {
+                    domain: "tiktok.com".to_string(),
+                    bytes: 500,
+                },
+            ]
+        );
+        assert_eq!(
+            report.top_geo_blocked_domains,
+            vec![DomainFailures {
+                domain: "bbc.co.uk".to_string(),
+                failures: 1,
+            }]
+        );
+
+        let week = storage
+            .activity_report(Duration::from_secs(7 * 24 * 60 * 60))
+            .await;
+        assert_eq!(week.total_requests, 8);
+        assert_eq!(week.top_failing_domains.len(), 3);
+        assert_eq!(week.bytes_transferred, 16_500);
+
+        let empty = storage.activity_report(Duration::from_secs(0)).await;
+        assert_eq!(empty, ActivityReport::default());
+    }
+
+    fn unreachable_settings() -> PoolSettings {
+        PoolSettings {
+            database_url: "postgres://crabberbot@127.0.0.1:1/crabberbot".to_string(),
+            max_connections: 1,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(1),
+            statement_timeout: Some(Duration::from_secs(5)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_storage_required_fails_fast() {
+        let result = create_storage(&StorageSettings::Postgres(unreachable_settings()), true).await;
+        assert!(matches!(result, Err(StorageInitError::Connect(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_storage_optional_degrades_to_memory() {
+        let backend = create_storage(&StorageSettings::Postgres(unreachable_settings()), false)
+            .await
+            .expect("optional storage should fall back");
+        assert!(backend.pool.is_none());
+        assert!(
+            backend
+                .storage
+                .get_cached_media(1, "https://a.com")
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_storage_without_settings_uses_memory() {
+        let backend = create_storage(&StorageSettings::Memory, true)
+            .await
+            .unwrap();
+        assert!(backend.pool.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_storage_disabled_forgets_everything() {
+        let backend = create_storage(&StorageSettings::Disabled, true)
+            .await
+            .unwrap();
+        assert!(backend.pool.is_none());
+        let files = [("file-id".to_string(), MediaType::Video)];
+        backend
+            .storage
+            .store_cached_media(
+                1,
+                "https://a.com",
+                "caption",
+                &files,
+                None,
+                None,
+                &CacheMetadata::default(),
+            )
+            .await;
+        assert!(
+            backend
+                .storage
+                .get_cached_media(1, "https://a.com")
+                .await
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_storage_url_selects_backend_by_scheme() {
+        let url = "postgres://crabberbot:secret@db:5432/crabberbot";
+        assert_eq!(
+            url.parse::<StorageUrl>(),
+            Ok(StorageUrl::Postgres(url.to_string()))
+        );
+        assert!(matches!(
+            "postgresql://db/crabberbot".parse::<StorageUrl>(),
+            Ok(StorageUrl::Postgres(_))
+        ));
+        assert_eq!("memory://".parse::<StorageUrl>(), Ok(StorageUrl::Memory));
+        assert_eq!("none://".parse::<StorageUrl>(), Ok(StorageUrl::Disabled));
+        assert_eq!(
+            "file:///var/lib/crabberbot/state.json".parse::<StorageUrl>(),
+            Ok(StorageUrl::File(PathBuf::from(
+                "/var/lib/crabberbot/state.json"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_storage_url_rejects_unknown_schemes() {
+        for (url, scheme) in [
+            ("redis://localhost:6379", "redis"),
+            ("sqlite://crabberbot.db", "sqlite"),
+            ("mysql://db/crabberbot", "mysql"),
+        ] {
+            assert_eq!(
+                url.parse::<StorageUrl>(),
+                Err(StorageUrlError::UnknownScheme(scheme.to_string()))
+            );
+        }
+        assert_eq!(
+            "not a url".parse::<StorageUrl>(),
+            Err(StorageUrlError::Invalid)
+        );
+    }
+
+    fn unique_token() -> String {
+        uuid::Uuid::new_v4().simple().to_string()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_store_cached_media_records_metadata() {
+        let storage = test_storage().await;
+        let token = unique_token();
+        let url = format!("https://example.com/{token}");
+        let metadata = CacheMetadata {
+            uploader: Some(format!("creator_{token}")),
+            title: Some("A title".to_string()),
+            extractor: Some("youtube".to_string()),
+            duration: Some(42.5),
+        };
+
+        storage
+            .store_cached_media(
+                1,
+                &url,
+                "caption",
+                &[("file_id".to_string(), MediaType::Video)],
+                None,
+                None,
+                &metadata,
+            )
+            .await;
+
+        let results = storage.search_cache(&format!("CREATOR_{token}"), 10).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].source_url, url);
+        assert_eq!(results[0].metadata, metadata);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_search_cache_matches_rows_without_metadata() {
+        let storage = test_storage().await;
+        let token = unique_token();
+        let url = format!("https://example.com/legacy/{token}");
+        sqlx::query("INSERT INTO media_cache (source_url, caption) VALUES ($1, $2)")
+            .bind(&url)
+            .bind(format!("old caption {token}"))
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+
+        let results = storage.search_cache(&token, 10).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].source_url, url);
+        assert_eq!(results[0].metadata, CacheMetadata::default());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_search_cache_treats_wildcards_literally() {
+        let storage = test_storage().await;
+        assert!(
+            storage
+                .search_cache("%_no_such_entry_%", 10)
+                .await
+                .is_empty()
+        );
+    }
+}
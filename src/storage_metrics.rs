@@ -0,0 +1,481 @@
+//! Counters and timings for every `Storage` call, reported by the owner `/stats` command.
+//!
+//! `StorageMetrics` wraps any backend, so Postgres and the in-memory fallback are measured
+//! the same way without either knowing about it.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+
+use crate::downloader::MediaType;
+use crate::handler::CallbackContext;
+use crate::maintenance::MaintenanceState;
+use crate::storage::{
+    ActivityReport, CacheMetadata, CacheSearchResult, CacheStats, CachedMedia, ChatSettings,
+    Delivery, PaymentRecord, RequestLogEntry, Storage,
+};
+use crate::subscription::{SubscriptionInfo, SubscriptionTier};
+
+/// How many operations `StorageMetricsSnapshot::slowest_operations` lists.
+pub const SLOWEST_STORAGE_OPERATIONS: usize = 5;
+
+/// Calls to one `Storage` method and the time they took.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OperationTimings {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl OperationTimings {
+    pub fn mean(&self) -> Duration {
+        self.total
+            .checked_div(self.calls.try_into().unwrap_or(u32::MAX))
+            .unwrap_or_default()
+    }
+}
+
+/// Point-in-time view of the storage metrics, for reporting.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageMetricsSnapshot {
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cache_stores: u64,
+    /// Operations with the highest mean duration, slowest first.
+    pub slowest_operations: Vec<(&'static str, OperationTimings)>,
+}
+
+/// Totals since startup, shared between the `StorageMetrics` wrapper and `/stats`.
+#[derive(Debug, Default)]
+pub struct StorageCounters {
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    cache_stores: AtomicU64,
+    operations: DashMap<&'static str, OperationTimings>,
+}
+
+impl StorageCounters {
+    fn record(&self, operation: &'static str, elapsed: Duration) {
+        let mut timings = self.operations.entry(operation).or_default();
+        timings.calls += 1;
+        timings.total += elapsed;
+        timings.max = timings.max.max(elapsed);
+    }
+
+    pub fn snapshot(&self) -> StorageMetricsSnapshot {
+        let mut operations: Vec<(&'static str, OperationTimings)> = self
+            .operations
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+        operations.sort_by(|a, b| b.1.mean().cmp(&a.1.mean()).then(a.0.cmp(b.0)));
+        operations.truncate(SLOWEST_STORAGE_OPERATIONS);
+        StorageMetricsSnapshot {
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            cache_stores: self.cache_stores.load(Ordering::Relaxed),
+            slowest_operations: operations,
+        }
+    }
+}
+
+/// A `Storage` that forwards to `inner` and records every call in its counters.
+pub struct StorageMetrics<S> {
+    inner: S,
+    counters: Arc<StorageCounters>,
+}
+
+impl<S: Storage> StorageMetrics<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn counters(&self) -> Arc<StorageCounters> {
+        self.counters.clone()
+    }
+
+    async fn timed<T>(&self, operation: &'static str, call: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let result = call.await;
+        self.counters.record(operation, start.elapsed());
+        result
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for StorageMetrics<S> {
+    async fn get_cached_media(&self, bot_id: i64, source_url: &str) -> Option<CachedMedia> {
+        let cached = self
+            .timed(
+                "get_cached_media",
+                self.inner.get_cached_media(bot_id, source_url),
+            )
+            .await;
+        let counter = if cached.is_some() {
+            &self.counters.cache_hits
+        } else {
+            &self.counters.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    async fn store_cached_media(
+        &self,
+        bot_id: i64,
+        source_url: &str,
+        caption: &str,
+        files: &[(String, MediaType)],
+        audio_cache_path: Option<String>,
+        media_duration_secs: Option<i32>,
+        metadata: &CacheMetadata,
+    ) {
+        self.timed(
+            "store_cached_media",
+            self.inner.store_cached_media(
+                bot_id,
+                source_url,
+                caption,
+                files,
+                audio_cache_path,
+                media_duration_secs,
+                metadata,
+            ),
+        )
+        .await;
+        self.counters.cache_stores.fetch_add(1, Ordering::Relaxed);
+    }
+
+    async fn search_cache(&self, query: &str, limit: i64) -> Vec<CacheSearchResult> {
+        self.timed("search_cache", self.inner.search_cache(query, limit))
+            .await
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        self.timed("cache_stats", self.inner.cache_stats()).await
+    }
+
+    async fn activity_report(&self, window: Duration) -> ActivityReport {
+        self.timed("activity_report", self.inner.activity_report(window))
+            .await
+    }
+
+    async fn log_request(
+        &self,
+        chat_id: i64,
+        source_url: &str,
+        status: &str,
+        processing_time_ms: i64,
+        bytes_transferred: Option<i64>,
+    ) {
+        self.timed(
+            "log_request",
+            self.inner.log_request(
+                chat_id,
+                source_url,
+                status,
+                processing_time_ms,
+                bytes_transferred,
+            ),
+        )
+        .await
+    }
+
+    async fn get_daily_download_count(&self, chat_id: i64) -> i64 {
+        self.timed(
+            "get_daily_download_count",
+            self.inner.get_daily_download_count(chat_id),
+        )
+        .await
+    }
+
+    async fn get_top_urls(&self, limit: i64, since: DateTime<Utc>) -> Vec<String> {
+        self.timed("get_top_urls", self.inner.get_top_urls(limit, since))
+            .await
+    }
+
+    async fn recent_requests(&self, limit: i64) -> Vec<RequestLogEntry> {
+        self.timed("recent_requests", self.inner.recent_requests(limit))
+            .await
+    }
+
+    async fn record_delivery(
+        &self,
+        chat_id: i64,
+        message_ids: &[i32],
+        source_url: &str,
+        cache_id: Option<i32>,
+    ) {
+        self.timed(
+            "record_delivery",
+            self.inner
+                .record_delivery(chat_id, message_ids, source_url, cache_id),
+        )
+        .await
+    }
+
+    async fn get_delivery(&self, chat_id: i64, message_id: i32) -> Option<Delivery> {
+        self.timed("get_delivery", self.inner.get_delivery(chat_id, message_id))
+            .await
+    }
+
+    async fn get_chat_settings(&self, chat_id: i64) -> ChatSettings {
+        self.timed("get_chat_settings", self.inner.get_chat_settings(chat_id))
+            .await
+    }
+
+    async fn set_source_as_button(&self, chat_id: i64, enabled: bool) {
+        self.timed(
+            "set_source_as_button",
+            self.inner.set_source_as_button(chat_id, enabled),
+        )
+        .await
+    }
+
+    async fn get_maintenance(&self) -> MaintenanceState {
+        self.timed("get_maintenance", self.inner.get_maintenance())
+            .await
+    }
+
+    async fn set_maintenance(&self, state: &MaintenanceState) {
+        self.timed("set_maintenance", self.inner.set_maintenance(state))
+            .await
+    }
+
+    async fn migrate_chat(&self, old_chat_id: i64, new_chat_id: i64) {
+        self.timed(
+            "migrate_chat",
+            self.inner.migrate_chat(old_chat_id, new_chat_id),
+        )
+        .await
+    }
+
+    async fn get_subscription(&self, user_id: i64) -> SubscriptionInfo {
+        self.timed("get_subscription", self.inner.get_subscription(user_id))
+            .await
+    }
+
+    async fn upsert_subscription(&self, user_id: i64, tier: SubscriptionTier, duration_days: i64) {
+        self.timed(
+            "upsert_subscription",
+            self.inner.upsert_subscription(user_id, tier, duration_days),
+        )
+        .await
+    }
+
+    fn records_payments(&self) -> bool {
+        self.inner.records_payments()
+    }
+
+    async fn record_payment(
+        &self,
+        user_id: i64,
+        telegram_charge_id: &str,
+        provider_charge_id: &str,
+        product: &str,
+        amount: i32,
+    ) {
+        self.timed(
+            "record_payment",
+            self.inner.record_payment(
+                user_id,
+                telegram_charge_id,
+                provider_charge_id,
+                product,
+                amount,
+            ),
+        )
+        .await
+    }
+
+    async fn consume_ai_seconds(&self, user_id: i64, seconds: i32) {
+        self.timed(
+            "consume_ai_seconds",
+            self.inner.consume_ai_seconds(user_id, seconds),
+        )
+        .await
+    }
+
+    async fn add_topup_seconds(&self, user_id: i64, seconds: i32) {
+        self.timed(
+            "add_topup_seconds",
+            self.inner.add_topup_seconds(user_id, seconds),
+        )
+        .await
+    }
+
+    async fn record_premium_usage(
+        &self,
+        user_id: i64,
+        feature: &str,
+        source_url: &str,
+        duration_secs: i32,
+        units: f64,
+        cost_usd: f64,
+    ) {
+        self.timed(
+            "record_premium_usage",
+            self.inner.record_premium_usage(
+                user_id,
+                feature,
+                source_url,
+                duration_secs,
+                units,
+                cost_usd,
+            ),
+        )
+        .await
+    }
+
+    async fn store_callback_context(&self, ctx: &CallbackContext) -> i32 {
+        self.timed(
+            "store_callback_context",
+            self.inner.store_callback_context(ctx),
+        )
+        .await
+    }
+
+    async fn get_callback_context(&self, context_id: i32) -> Option<CallbackContext> {
+        self.timed(
+            "get_callback_context",
+            self.inner.get_callback_context(context_id),
+        )
+        .await
+    }
+
+    async fn cache_transcript(&self, context_id: i32, transcript: &str, language: Option<String>) {
+        self.timed(
+            "cache_transcript",
+            self.inner
+                .cache_transcript(context_id, transcript, language),
+        )
+        .await
+    }
+
+    async fn revoke_subscription(&self, user_id: i64) {
+        self.timed(
+            "revoke_subscription",
+            self.inner.revoke_subscription(user_id),
+        )
+        .await
+    }
+
+    async fn revoke_topup(&self, user_id: i64, seconds: i32) {
+        self.timed("revoke_topup", self.inner.revoke_topup(user_id, seconds))
+            .await
+    }
+
+    async fn get_latest_payment(&self, user_id: i64) -> Option<PaymentRecord> {
+        self.timed("get_latest_payment", self.inner.get_latest_payment(user_id))
+            .await
+    }
+
+    async fn get_recent_payments(&self, user_id: i64, limit: i64) -> Vec<PaymentRecord> {
+        self.timed(
+            "get_recent_payments",
+            self.inner.get_recent_payments(user_id, limit),
+        )
+        .await
+    }
+
+    async fn has_ai_usage_since(&self, user_id: i64, since: DateTime<Utc>) -> bool {
+        self.timed(
+            "has_ai_usage_since",
+            self.inner.has_ai_usage_since(user_id, since),
+        )
+        .await
+    }
+
+    async fn cleanup_expired_callback_contexts(&self) {
+        self.timed(
+            "cleanup_expired_callback_contexts",
+            self.inner.cleanup_expired_callback_contexts(),
+        )
+        .await
+    }
+
+    async fn expire_stale_topups(&self) {
+        self.timed("expire_stale_topups", self.inner.expire_stale_topups())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn test_counts_cache_hits_misses_and_stores() {
+        let storage = StorageMetrics::new(MemoryStorage::new());
+        let counters = storage.counters();
+
+        assert!(
+            storage
+                .get_cached_media(1, "https://a.com/1")
+                .await
+                .is_none()
+        );
+        storage
+            .store_cached_media(
+                1,
+                "https://a.com/1",
+                "caption",
+                &[("file".to_string(), MediaType::Video)],
+                None,
+                None,
+                &CacheMetadata::default(),
+            )
+            .await;
+        assert!(
+            storage
+                .get_cached_media(1, "https://a.com/1")
+                .await
+                .is_some()
+        );
+
+        let snapshot = counters.snapshot();
+        assert_eq!(
+            (
+                snapshot.cache_hits,
+                snapshot.cache_misses,
+                snapshot.cache_stores
+            ),
+            (1, 1, 1)
+        );
+        let (_, timings) = snapshot
+            .slowest_operations
+            .iter()
+            .find(|(operation, _)| *operation == "get_cached_media")
+            .expect("get_cached_media is timed");
+        assert_eq!(timings.calls, 2);
+    }
+
+    #[test]
+    fn test_snapshot_lists_slowest_operations_by_mean() {
+        let counters = StorageCounters::default();
+        for (operation, ms) in [("a", 10), ("b", 50), ("b", 10), ("c", 20)] {
+            counters.record(operation, Duration::from_millis(ms));
+        }
+        let names: Vec<&str> = counters
+            .snapshot()
+            .slowest_operations
+            .iter()
+            .map(|(operation, _)| *operation)
+            .collect();
+        assert_eq!(names, ["b", "c", "a"]);
+        assert_eq!(
+            OperationTimings::default().mean(),
+            Duration::ZERO,
+            "no calls"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
 
@@ -12,7 +13,7 @@ pub const TOPUP_PRICE_STARS: u32 = 50;
 /// AI seconds granted by one top-up purchase (60 minutes).
 pub const TOPUP_SECONDS: i32 = 3600;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SubscriptionTier {
     Free,
     Basic,
@@ -71,7 +72,7 @@ impl FromStr for SubscriptionTier {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionInfo {
     pub tier: SubscriptionTier,
     pub ai_seconds_used: i32,
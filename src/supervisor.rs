@@ -0,0 +1,240 @@
+//! Keeps background tasks running when they panic.
+//!
+//! A task spawned with plain `tokio::spawn` that panics just disappears, and whatever it
+//! did (cache cleanup, eviction, ...) silently stops. `Supervisor` runs each task from a
+//! factory, and when it panics logs the panic and starts a fresh one after a delay. A task
+//! that keeps crashing is reported as a crash loop and restarted with exponential
+//! backoff.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+
+use crate::handler::panic_message;
+
+/// Wait before restarting a task that panicked, unless it is crash-looping.
+pub const TASK_RESTART_DELAY: Duration = Duration::from_secs(30);
+/// This many panics within `CRASH_LOOP_WINDOW` of run time count as a crash loop. The
+/// restart delays in between are added to the window, so a task that panics as soon as
+/// it starts is caught however long its delay is.
+pub const CRASH_LOOP_FAILURES: usize = 5;
+pub const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(60);
+/// Longest wait between restarts of a crash-looping task.
+pub const MAX_RESTART_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// When to restart a task after each panic.
+#[derive(Debug)]
+struct RestartBackoff {
+    restart_delay: Duration,
+    /// `CRASH_LOOP_WINDOW` plus the restart delays between `CRASH_LOOP_FAILURES` panics.
+    crash_loop_window: Duration,
+    /// Panics within the last `crash_loop_window`.
+    recent_failures: VecDeque<Instant>,
+    /// Delay used for the previous restart while crash-looping.
+    backoff: Option<Duration>,
+}
+
+impl RestartBackoff {
+    fn new(restart_delay: Duration) -> Self {
+        Self {
+            restart_delay,
+            crash_loop_window: CRASH_LOOP_WINDOW + restart_delay * (CRASH_LOOP_FAILURES as u32 - 1),
+            recent_failures: VecDeque::with_capacity(CRASH_LOOP_FAILURES),
+            backoff: None,
+        }
+    }
+
+    /// Record a panic at `now` and return how long to wait before restarting, along with
+    /// whether the task is crash-looping.
+    fn next_delay(&mut self, now: Instant) -> (Duration, bool) {
+        let window = self.crash_loop_window;
+        self.recent_failures
+            .retain(|failure| now.saturating_duration_since(*failure) < window);
+        self.recent_failures.push_back(now);
+        if self.recent_failures.len() < CRASH_LOOP_FAILURES {
+            self.backoff = None;
+            return (self.restart_delay, false);
+        }
+        let delay = self
+            .backoff
+            .map_or(self.restart_delay, |previous| previous * 2)
+            .max(self.restart_delay * 2)
+            .min(MAX_RESTART_DELAY);
+        self.backoff = Some(delay);
+        (delay, true)
+    }
+}
+
+/// A background task and how often it has been restarted.
+pub struct SupervisedTask {
+    name: String,
+    restarts: Arc<AtomicU32>,
+    handle: JoinHandle<()>,
+}
+
+impl SupervisedTask {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Times the task panicked and was started again.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Whether the task finished for good: it returned, or was aborted.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    tasks: Vec<SupervisedTask>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the future `factory` makes until it returns, starting a new one from `factory`
+    /// each time it panics.
+    pub fn spawn_supervised<F, Fut>(&mut self, name: &str, factory: F, restart_delay: Duration)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let restarts = Arc::new(AtomicU32::new(0));
+        let task_name = name.to_string();
+        let task_restarts = restarts.clone();
+        let handle = tokio::spawn(async move {
+            let mut backoff = RestartBackoff::new(restart_delay);
+            loop {
+                let error = match tokio::spawn(factory()).await {
+                    Ok(()) => {
+                        log::info!("Background task {} finished", task_name);
+                        return;
+                    }
+                    Err(e) if e.is_cancelled() => return,
+                    Err(e) => e,
+                };
+                let (delay, crash_looping) = backoff.next_delay(Instant::now());
+                let restarts = task_restarts.fetch_add(1, Ordering::Relaxed) + 1;
+                let panic = error.into_panic();
+                if crash_looping {
+                    log::error!(
+                        "CRITICAL: background task {} is crash-looping ({} panics within {}s, {} restarts in total), restarting in {}s: {}",
+                        task_name,
+                        CRASH_LOOP_FAILURES,
+                        backoff.crash_loop_window.as_secs(),
+                        restarts,
+                        delay.as_secs(),
+                        panic_message(panic.as_ref())
+                    );
+                } else {
+                    log::error!(
+                        "Background task {} panicked, restarting in {}s: {}",
+                        task_name,
+                        delay.as_secs(),
+                        panic_message(panic.as_ref())
+                    );
+                }
+                tokio::time::sleep(delay).await;
+            }
+        });
+        self.tasks.push(SupervisedTask {
+            name: name.to_string(),
+            restarts,
+            handle,
+        });
+    }
+
+    pub fn tasks(&self) -> &[SupervisedTask] {
+        &self.tasks
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restarts_a_panicking_task_until_it_returns() {
+        let runs = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new();
+        let counted = runs.clone();
+        supervisor.spawn_supervised(
+            "flaky",
+            move || {
+                let counted = counted.clone();
+                async move {
+                    if counted.fetch_add(1, Ordering::SeqCst) < 2 {
+                        panic!("boom");
+                    }
+                }
+            },
+            Duration::from_millis(1),
+        );
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !supervisor.tasks()[0].is_finished() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("the task finishes on its third run");
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(supervisor.tasks()[0].name(), "flaky");
+        assert_eq!(supervisor.tasks()[0].restarts(), 2);
+    }
+
+    #[test]
+    fn test_backoff_grows_once_crash_looping_and_is_capped() {
+        let base = Duration::from_secs(30);
+        let mut backoff = RestartBackoff::new(base);
+        let start = Instant::now();
+        let delays: Vec<(Duration, bool)> = (0..10)
+            .map(|i| backoff.next_delay(start + Duration::from_secs(i)))
+            .collect();
+
+        assert_eq!(delays[..4], [(base, false); 4]);
+        assert_eq!(
+            delays[4..]
+                .iter()
+                .map(|(d, _)| d.as_secs())
+                .collect::<Vec<_>>(),
+            [60, 120, 240, 480, 600, 600]
+        );
+        assert!(delays[4..].iter().all(|(_, crash_looping)| *crash_looping));
+
+        // Failures spread out again fall back to the plain delay.
+        let later = start + backoff.crash_loop_window * 2;
+        assert_eq!(backoff.next_delay(later), (base, false));
+    }
+
+    #[test]
+    fn test_task_panicking_on_start_is_a_crash_loop_with_production_delay() {
+        let mut backoff = RestartBackoff::new(TASK_RESTART_DELAY);
+        let mut now = Instant::now();
+        let mut crash_looping = false;
+        for _ in 0..CRASH_LOOP_FAILURES {
+            let (delay, looping) = backoff.next_delay(now);
+            crash_looping = looping;
+            now += delay;
+        }
+        assert!(crash_looping);
+    }
+}
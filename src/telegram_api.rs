@@ -9,11 +9,13 @@ use teloxide::sugar::request::RequestReplyExt;
 use teloxide::{
     prelude::*,
     types::{
-        ChatAction, ChatId, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaPhoto,
-        InputMediaVideo, MessageId, ParseMode, ReactionType, TelegramTransactionId, UserId,
+        ChatAction, ChatId, ChatMemberStatus, InlineKeyboardMarkup, InputFile, InputMedia,
+        InputMediaPhoto, InputMediaVideo, MessageId, ParseMode, ReactionType,
+        TelegramTransactionId, ThreadId, UserId,
     },
 };
 use tokio::sync::Mutex;
+use url::Url;
 
 use crate::downloader::MediaType;
 use crate::retry::{RetryPolicy, retry_async};
@@ -100,7 +102,7 @@ pub(crate) fn photo_dimensions_allowed(width: u32, height: u32) -> bool {
         && u64::from(width) * u64::from(height) <= MAX_PHOTO_PIXELS
 }
 
-fn image_limits() -> image::Limits {
+pub(crate) fn image_limits() -> image::Limits {
     let mut limits = image::Limits::default();
     limits.max_image_width = Some(MAX_PHOTO_WIDTH);
     limits.max_image_height = Some(MAX_PHOTO_HEIGHT);
@@ -108,30 +110,105 @@ fn image_limits() -> image::Limits {
     limits
 }
 
-#[derive(Debug, Clone)]
+/// Where the bytes of a file we send come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaSource {
+    /// A file on disk, uploaded with the request.
+    Path(PathBuf),
+    /// A file Telegram already has, e.g. one we sent before and cached the id of.
+    FileId(String),
+    /// Contents held in memory, uploaded under the given file name.
+    Bytes(Vec<u8>, String),
+}
+
+impl MediaSource {
+    fn input_file(&self) -> InputFile {
+        match self {
+            Self::Path(path) => InputFile::file(path),
+            Self::FileId(file_id) => InputFile::file_id(file_id.clone().into()),
+            Self::Bytes(bytes, file_name) => {
+                InputFile::memory(bytes.clone()).file_name(file_name.clone())
+            }
+        }
+    }
+
+    /// The file on disk, for sources that have one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path(path) => Some(path),
+            Self::FileId(_) | Self::Bytes(..) => None,
+        }
+    }
+}
+
+impl From<&Path> for MediaSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+/// For logs: never prints the bytes of a `Bytes` source.
+impl std::fmt::Display for MediaSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::FileId(_) => f.write_str("cached file"),
+            Self::Bytes(bytes, file_name) => write!(f, "{} ({} bytes)", file_name, bytes.len()),
+        }
+    }
+}
+
+/// A progress message we sent, to edit or delete later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusMessage {
+    /// Differs from the chat it was sent to when that group became a supergroup
+    /// meanwhile, as the old chat no longer takes edits.
+    pub chat_id: ChatId,
+    pub message_id: MessageId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SentMedia {
     pub file_id: String,
     pub media_type: MediaType,
+    /// The message carrying the media.
+    pub message_id: MessageId,
 }
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait TelegramApi: Send + Sync {
+    /// `duration` (seconds), `width` and `height` let clients size the player and seek bar
+    /// before the video has loaded. `reply_markup` is attached to the sent video.
+    #[allow(clippy::too_many_arguments)]
     async fn send_video(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        file_path: &Path,
+        media: MediaSource,
         caption: &str,
         thumbnail_filepath: Option<PathBuf>,
+        duration: Option<u32>,
+        width: Option<u32>,
+        height: Option<u32>,
+        reply_markup: Option<InlineKeyboardMarkup>,
     ) -> Result<(String, MessageId), teloxide::RequestError>;
     async fn send_photo(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        file_path: &Path,
+        media: MediaSource,
         caption: &str,
+        reply_markup: Option<InlineKeyboardMarkup>,
     ) -> Result<(String, MessageId), teloxide::RequestError>;
+    /// Send a file as a document, so Telegram delivers it without recompression.
+    async fn send_document(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+    ) -> Result<MessageId, teloxide::RequestError>;
     async fn edit_message_reply_markup(
         &self,
         chat_id: ChatId,
@@ -144,36 +221,42 @@ pub trait TelegramApi: Send + Sync {
         message_id: MessageId,
         message: &str,
     ) -> Result<(), teloxide::RequestError>;
-    async fn send_media_group(
+    /// Send a transient progress message and return where it ended up, so it can be
+    /// removed later.
+    async fn send_status_message(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        media: Vec<InputMedia>,
-    ) -> Result<Vec<SentMedia>, teloxide::RequestError>;
-    async fn send_chat_action(
+        text: &str,
+    ) -> Result<StatusMessage, teloxide::RequestError>;
+    /// Replace the text of a message we sent, e.g. a status message that became final.
+    async fn edit_message_text(
         &self,
         chat_id: ChatId,
-        action: ChatAction,
+        message_id: MessageId,
+        text: &str,
     ) -> Result<(), teloxide::RequestError>;
-    async fn set_message_reaction(
+    async fn delete_message(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        reaction: Option<ReactionType>,
     ) -> Result<(), teloxide::RequestError>;
-    async fn send_cached_video(
+    async fn send_media_group(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        file_id: &str,
-        caption: &str,
-    ) -> Result<MessageId, teloxide::RequestError>;
-    async fn send_cached_photo(
+        media: Vec<InputMedia>,
+    ) -> Result<Vec<SentMedia>, teloxide::RequestError>;
+    async fn send_chat_action(
+        &self,
+        chat_id: ChatId,
+        action: ChatAction,
+    ) -> Result<(), teloxide::RequestError>;
+    async fn set_message_reaction(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        file_id: &str,
-        caption: &str,
+        reaction: Option<ReactionType>,
     ) -> Result<(), teloxide::RequestError>;
     async fn send_cached_media_group(
         &self,
@@ -181,7 +264,7 @@ pub trait TelegramApi: Send + Sync {
         message_id: MessageId,
         files: &[CachedFile],
         caption: &str,
-    ) -> Result<(), teloxide::RequestError>;
+    ) -> Result<Vec<MessageId>, teloxide::RequestError>;
 
     async fn send_audio(
         &self,
@@ -190,6 +273,14 @@ pub trait TelegramApi: Send + Sync {
         file_path: &std::path::Path,
     ) -> Result<(), teloxide::RequestError>;
 
+    /// Send a round video note. The file must already be a square mp4 of at most a minute.
+    async fn send_video_note(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        file_path: &Path,
+    ) -> Result<(), teloxide::RequestError>;
+
     async fn send_invoice(
         &self,
         chat_id: ChatId,
@@ -199,6 +290,13 @@ pub trait TelegramApi: Send + Sync {
         price_amount: u32,
     ) -> Result<(), teloxide::RequestError>;
 
+    /// The user's current status in the chat, e.g. whether they administer it.
+    async fn get_chat_member(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> Result<ChatMemberStatus, teloxide::RequestError>;
+
     async fn answer_callback_query(
         &self,
         callback_query_id: &str,
@@ -228,12 +326,49 @@ pub trait TelegramApi: Send + Sync {
         text: &str,
     ) -> Result<(), teloxide::RequestError>;
 
+    /// Send a text message replying to a message the bot sent earlier. Unlike the other
+    /// replies, this fails when that message is gone rather than sending a plain message.
+    async fn reply_to_sent_message(
+        &self,
+        chat_id: ChatId,
+        sent_message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError>;
+
     /// Refund a Telegram Stars payment. user_id is the payer's Telegram user ID.
     async fn refund_star_payment(
         &self,
         user_id: i64,
         telegram_payment_charge_id: &str,
     ) -> Result<(), teloxide::RequestError>;
+
+    /// This API with messages to `chat_id` posted in the forum topic `thread_id`, so the
+    /// answers to a message sent in a topic stay in that topic. Other chats are unaffected.
+    fn in_thread(&self, chat_id: ChatId, thread_id: Option<ThreadId>) -> Arc<dyn TelegramApi>;
+}
+
+/// Whether `error` means the bot can't message the chat at all: the user blocked it, it
+/// was removed from the group, or the chat is gone. Retrying or replying won't help.
+pub fn is_chat_unreachable(error: &teloxide::RequestError) -> bool {
+    matches!(
+        error,
+        teloxide::RequestError::Api(
+            teloxide::ApiError::BotBlocked
+                | teloxide::ApiError::BotKicked
+                | teloxide::ApiError::BotKickedFromSupergroup
+                | teloxide::ApiError::BotKickedFromChannel
+                | teloxide::ApiError::ChatNotFound
+                | teloxide::ApiError::GroupDeactivated
+                | teloxide::ApiError::UserDeactivated
+                | teloxide::ApiError::CantInitiateConversation
+        )
+    )
+}
+
+/// The forum topic a message was sent in. Replies in non-forum groups also carry a
+/// thread id, but Telegram only accepts it for topics.
+pub fn topic_thread_id(message: &Message) -> Option<ThreadId> {
+    message.thread_id.filter(|_| message.is_topic_message)
 }
 
 #[derive(Clone)]
@@ -241,6 +376,32 @@ pub struct TeloxideApi {
     bot: Bot,
     limiter: Arc<TelegramRequestLimiter>,
     retry_policy: RetryPolicy,
+    /// Forum topic that messages to this chat are posted in, set by `in_thread`.
+    topic: Option<(ChatId, ThreadId)>,
+}
+
+/// Post `request` in the forum topic `api` is scoped to, when it goes to that topic's chat.
+macro_rules! in_topic {
+    ($api:expr, $chat_id:expr, $request:expr) => {{
+        let request = $request;
+        match $api.topic {
+            Some((topic_chat_id, thread_id)) if topic_chat_id == $chat_id => {
+                request.message_thread_id(thread_id)
+            }
+            _ => request,
+        }
+    }};
+}
+
+/// Make `request` a reply to `reply_to`, if set.
+macro_rules! replying {
+    ($request:expr, $reply_to:expr) => {{
+        let request = $request;
+        match $reply_to {
+            Some(reply_to) => request.reply_to(reply_to),
+            None => request,
+        }
+    }};
 }
 
 impl TeloxideApi {
@@ -253,9 +414,17 @@ impl TeloxideApi {
                 base_delay: Duration::from_millis(250),
                 max_delay: Duration::from_secs(30),
             },
+            topic: None,
         }
     }
 
+    /// An API whose requests go to `base_url`, e.g. a local Bot API server. The dispatcher
+    /// needs a bot with the same base URL, so callers that also run one should configure
+    /// the bot with `Bot::set_api_url` instead.
+    pub fn new_with_base_url(bot: Bot, base_url: Url) -> Self {
+        Self::new(bot.set_api_url(base_url))
+    }
+
     /// Helper to determine the appropriate chat action for a media group.
     /// If any video is present, it's UploadVideo. Otherwise, it's UploadPhoto.
     fn get_media_group_action(media: &[InputMedia]) -> ChatAction {
@@ -306,6 +475,50 @@ impl TeloxideApi {
         )
         .await
     }
+
+    /// `request` for a message replying to `message_id`. `op` builds the request for the
+    /// chat and the message it gets, if any. When the user deleted their message while we
+    /// were working on it, Telegram rejects the reply, so it is sent once more as a plain
+    /// message. The same happens when the group was upgraded to a supergroup meanwhile,
+    /// this time to the supergroup, where the original message has no id.
+    async fn reply_request<T, Fut, Op>(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        label: &'static str,
+        mut op: Op,
+    ) -> Result<T, teloxide::RequestError>
+    where
+        Op: FnMut(ChatId, Option<MessageId>) -> Fut,
+        Fut: std::future::Future<Output = Result<T, teloxide::RequestError>>,
+    {
+        let result = self
+            .request(Some(chat_id), label, || op(chat_id, Some(message_id)))
+            .await;
+        match result {
+            Err(teloxide::RequestError::MigrateToChatId(new_chat_id)) => {
+                log::warn!(
+                    "{} failed: chat {} is now supergroup {}, sending there",
+                    label,
+                    chat_id,
+                    new_chat_id
+                );
+                self.request(Some(new_chat_id), label, || op(new_chat_id, None))
+                    .await
+            }
+            Err(teloxide::RequestError::Api(teloxide::ApiError::MessageToReplyNotFound)) => {
+                log::warn!(
+                    "{} failed: message {} in chat {} is gone, sending without a reply",
+                    label,
+                    message_id,
+                    chat_id
+                );
+                self.request(Some(chat_id), label, || op(chat_id, None))
+                    .await
+            }
+            result => result,
+        }
+    }
 }
 
 struct TelegramRequestLimiter {
@@ -349,27 +562,50 @@ impl TelegramApi for TeloxideApi {
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        file_path: &Path,
+        media: MediaSource,
         caption: &str,
         thumbnail_filepath: Option<PathBuf>,
+        duration: Option<u32>,
+        width: Option<u32>,
+        height: Option<u32>,
+        reply_markup: Option<InlineKeyboardMarkup>,
     ) -> Result<(String, MessageId), teloxide::RequestError> {
-        log::info!("Sending video {:?} to chat {}", file_path, chat_id);
+        log::info!("Sending video {} to chat {}", media, chat_id);
         self.send_chat_action(chat_id, ChatAction::UploadVideo)
             .await?;
         let message = self
-            .request(Some(chat_id), "telegram.send_video", || {
-                let mut request = self
-                    .bot
-                    .send_video(chat_id, InputFile::file(file_path))
-                    .caption(caption.to_owned())
-                    .parse_mode(ParseMode::Html)
-                    .reply_to(message_id);
+            .reply_request(
+                chat_id,
+                message_id,
+                "telegram.send_video",
+                |chat_id, reply_to| {
+                    let mut request = self
+                        .bot
+                        .send_video(chat_id, media.input_file())
+                        .caption(caption.to_owned())
+                        .parse_mode(ParseMode::Html);
 
-                if let Some(p) = thumbnail_filepath.clone() {
-                    request = request.thumbnail(InputFile::file(p));
-                }
-                async move { request.await }
-            })
+                    if let Some(reply_to) = reply_to {
+                        request = request.reply_to(reply_to);
+                    }
+                    if let Some(p) = thumbnail_filepath.clone() {
+                        request = request.thumbnail(InputFile::file(p));
+                    }
+                    if let Some(duration) = duration {
+                        request = request.duration(duration);
+                    }
+                    if let Some(width) = width {
+                        request = request.width(width);
+                    }
+                    if let Some(height) = height {
+                        request = request.height(height);
+                    }
+                    if let Some(markup) = reply_markup.clone() {
+                        request = request.reply_markup(markup);
+                    }
+                    in_topic!(self, chat_id, request).send()
+                },
+            )
             .await?;
         let file_id = message
             .video()
@@ -387,21 +623,32 @@ impl TelegramApi for TeloxideApi {
         &self,
         chat_id: ChatId,
         message_id: MessageId,
-        file_path: &Path,
+        media: MediaSource,
         caption: &str,
+        reply_markup: Option<InlineKeyboardMarkup>,
     ) -> Result<(String, MessageId), teloxide::RequestError> {
-        log::info!("Sending photo {:?} to chat {}", file_path, chat_id);
+        log::info!("Sending photo {} to chat {}", media, chat_id);
         self.send_chat_action(chat_id, ChatAction::UploadPhoto)
             .await?;
         let message = self
-            .request(Some(chat_id), "telegram.send_photo", || async {
-                self.bot
-                    .send_photo(chat_id, InputFile::file(file_path))
-                    .caption(caption.to_owned())
-                    .parse_mode(ParseMode::Html)
-                    .reply_to(message_id)
-                    .await
-            })
+            .reply_request(
+                chat_id,
+                message_id,
+                "telegram.send_photo",
+                |chat_id, reply_to| {
+                    let mut request = replying!(
+                        self.bot
+                            .send_photo(chat_id, media.input_file())
+                            .caption(caption.to_owned())
+                            .parse_mode(ParseMode::Html),
+                        reply_to
+                    );
+                    if let Some(markup) = reply_markup.clone() {
+                        request = request.reply_markup(markup);
+                    }
+                    in_topic!(self, chat_id, request).send()
+                },
+            )
             .await?;
         let file_id = message
             .photo()
@@ -416,6 +663,40 @@ impl TelegramApi for TeloxideApi {
         Ok((file_id, message.id))
     }
 
+    async fn send_document(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        media: MediaSource,
+        caption: &str,
+    ) -> Result<MessageId, teloxide::RequestError> {
+        log::info!("Sending document {} to chat {}", media, chat_id);
+        self.send_chat_action(chat_id, ChatAction::UploadDocument)
+            .await?;
+        let message = self
+            .reply_request(
+                chat_id,
+                message_id,
+                "telegram.send_document",
+                |chat_id, reply_to| {
+                    in_topic!(
+                        self,
+                        chat_id,
+                        replying!(
+                            self.bot
+                                .send_document(chat_id, media.input_file())
+                                .caption(caption.to_owned())
+                                .parse_mode(ParseMode::Html),
+                            reply_to
+                        )
+                    )
+                    .send()
+                },
+            )
+            .await?;
+        Ok(message.id)
+    }
+
     async fn edit_message_reply_markup(
         &self,
         chat_id: ChatId,
@@ -443,17 +724,82 @@ impl TelegramApi for TeloxideApi {
         message: &str,
     ) -> Result<(), teloxide::RequestError> {
         log::info!("Sending text to chat {}", chat_id);
-        self.request(Some(chat_id), "telegram.send_message", || async {
+        self.reply_request(
+            chat_id,
+            message_id,
+            "telegram.send_message",
+            |chat_id, reply_to| {
+                in_topic!(
+                    self,
+                    chat_id,
+                    replying!(
+                        self.bot
+                            .send_message(chat_id, message.to_owned())
+                            .parse_mode(ParseMode::Html),
+                        reply_to
+                    )
+                )
+                .send()
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn send_status_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &str,
+    ) -> Result<StatusMessage, teloxide::RequestError> {
+        let message = self
+            .reply_request(
+                chat_id,
+                message_id,
+                "telegram.send_status_message",
+                |chat_id, reply_to| {
+                    in_topic!(
+                        self,
+                        chat_id,
+                        replying!(self.bot.send_message(chat_id, text.to_owned()), reply_to)
+                    )
+                    .send()
+                },
+            )
+            .await?;
+        Ok(StatusMessage {
+            chat_id: message.chat.id,
+            message_id: message.id,
+        })
+    }
+
+    async fn edit_message_text(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        self.request(Some(chat_id), "telegram.edit_message_text", || async {
             self.bot
-                .send_message(chat_id, message.to_owned())
-                .parse_mode(ParseMode::Html)
-                .reply_to(message_id)
+                .edit_message_text(chat_id, message_id, text.to_owned())
                 .await
         })
         .await?;
         Ok(())
     }
 
+    async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), teloxide::RequestError> {
+        self.request(Some(chat_id), "telegram.delete_message", || async {
+            self.bot.delete_message(chat_id, message_id).await
+        })
+        .await?;
+        Ok(())
+    }
+
     async fn send_media_group(
         &self,
         chat_id: ChatId,
@@ -472,12 +818,19 @@ impl TelegramApi for TeloxideApi {
         let action = Self::get_media_group_action(&media);
         self.send_chat_action(chat_id, action).await?;
         let messages = self
-            .request(Some(chat_id), "telegram.send_media_group", || async {
-                self.bot
-                    .send_media_group(chat_id, media.clone())
-                    .reply_to(message_id)
-                    .await
-            })
+            .reply_request(
+                chat_id,
+                message_id,
+                "telegram.send_media_group",
+                |chat_id, reply_to| {
+                    in_topic!(
+                        self,
+                        chat_id,
+                        replying!(self.bot.send_media_group(chat_id, media.clone()), reply_to)
+                    )
+                    .send()
+                },
+            )
             .await?;
 
         let sent: Vec<SentMedia> = messages
@@ -487,11 +840,13 @@ impl TelegramApi for TeloxideApi {
                     Some(SentMedia {
                         file_id: video.file.id.to_string(),
                         media_type: MediaType::Video,
+                        message_id: msg.id,
                     })
                 } else if let Some(photos) = msg.photo() {
                     photos.last().map(|p| SentMedia {
                         file_id: p.file.id.to_string(),
                         media_type: MediaType::Photo,
+                        message_id: msg.id,
                     })
                 } else {
                     None
@@ -507,10 +862,16 @@ impl TelegramApi for TeloxideApi {
         chat_id: ChatId,
         action: ChatAction,
     ) -> Result<(), teloxide::RequestError> {
-        self.request(Some(chat_id), "telegram.send_chat_action", || async {
-            self.bot.send_chat_action(chat_id, action).await
-        })
-        .await?;
+        let send = |chat_id| {
+            self.request(Some(chat_id), "telegram.send_chat_action", move || {
+                in_topic!(self, chat_id, self.bot.send_chat_action(chat_id, action)).send()
+            })
+        };
+        match send(chat_id).await {
+            // Keep going so the message that follows reaches the supergroup.
+            Err(teloxide::RequestError::MigrateToChatId(new_chat_id)) => send(new_chat_id).await?,
+            result => result?,
+        };
         Ok(())
     }
 
@@ -531,60 +892,15 @@ impl TelegramApi for TeloxideApi {
         Ok(())
     }
 
-    async fn send_cached_video(
-        &self,
-        chat_id: ChatId,
-        message_id: MessageId,
-        file_id: &str,
-        caption: &str,
-    ) -> Result<MessageId, teloxide::RequestError> {
-        log::info!("Sending cached video to chat {}", chat_id);
-        self.send_chat_action(chat_id, ChatAction::UploadVideo)
-            .await?;
-        let msg = self
-            .request(Some(chat_id), "telegram.send_cached_video", || async {
-                self.bot
-                    .send_video(chat_id, InputFile::file_id(file_id.to_owned().into()))
-                    .caption(caption.to_owned())
-                    .parse_mode(ParseMode::Html)
-                    .reply_to(message_id)
-                    .await
-            })
-            .await?;
-        Ok(msg.id)
-    }
-
-    async fn send_cached_photo(
-        &self,
-        chat_id: ChatId,
-        message_id: MessageId,
-        file_id: &str,
-        caption: &str,
-    ) -> Result<(), teloxide::RequestError> {
-        log::info!("Sending cached photo to chat {}", chat_id);
-        self.send_chat_action(chat_id, ChatAction::UploadPhoto)
-            .await?;
-        self.request(Some(chat_id), "telegram.send_cached_photo", || async {
-            self.bot
-                .send_photo(chat_id, InputFile::file_id(file_id.to_owned().into()))
-                .caption(caption.to_owned())
-                .parse_mode(ParseMode::Html)
-                .reply_to(message_id)
-                .await
-        })
-        .await?;
-        Ok(())
-    }
-
     async fn send_cached_media_group(
         &self,
         chat_id: ChatId,
         message_id: MessageId,
         files: &[CachedFile],
         caption: &str,
-    ) -> Result<(), teloxide::RequestError> {
+    ) -> Result<Vec<MessageId>, teloxide::RequestError> {
         if files.is_empty() {
-            return Ok(());
+            return Ok(vec![]);
         }
         log::info!(
             "Sending cached media group ({} items) to chat {}",
@@ -619,18 +935,22 @@ impl TelegramApi for TeloxideApi {
 
         let action = Self::get_media_group_action(&media);
         self.send_chat_action(chat_id, action).await?;
-        self.request(
-            Some(chat_id),
-            "telegram.send_cached_media_group",
-            || async {
-                self.bot
-                    .send_media_group(chat_id, media.clone())
-                    .reply_to(message_id)
-                    .await
-            },
-        )
-        .await?;
-        Ok(())
+        let messages = self
+            .reply_request(
+                chat_id,
+                message_id,
+                "telegram.send_cached_media_group",
+                |chat_id, reply_to| {
+                    in_topic!(
+                        self,
+                        chat_id,
+                        replying!(self.bot.send_media_group(chat_id, media.clone()), reply_to)
+                    )
+                    .send()
+                },
+            )
+            .await?;
+        Ok(messages.iter().map(|msg| msg.id).collect())
     }
 
     async fn send_audio(
@@ -642,12 +962,52 @@ impl TelegramApi for TeloxideApi {
         log::info!("Sending audio {:?} to chat {}", file_path, chat_id);
         self.send_chat_action(chat_id, ChatAction::UploadDocument)
             .await?;
-        self.request(Some(chat_id), "telegram.send_audio", || async {
-            self.bot
-                .send_audio(chat_id, InputFile::file(file_path))
-                .reply_to(message_id)
-                .await
-        })
+        self.reply_request(
+            chat_id,
+            message_id,
+            "telegram.send_audio",
+            |chat_id, reply_to| {
+                in_topic!(
+                    self,
+                    chat_id,
+                    replying!(
+                        self.bot.send_audio(chat_id, InputFile::file(file_path)),
+                        reply_to
+                    )
+                )
+                .send()
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn send_video_note(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        file_path: &Path,
+    ) -> Result<(), teloxide::RequestError> {
+        log::info!("Sending video note {:?} to chat {}", file_path, chat_id);
+        self.send_chat_action(chat_id, ChatAction::UploadVideoNote)
+            .await?;
+        self.reply_request(
+            chat_id,
+            message_id,
+            "telegram.send_video_note",
+            |chat_id, reply_to| {
+                in_topic!(
+                    self,
+                    chat_id,
+                    replying!(
+                        self.bot
+                            .send_video_note(chat_id, InputFile::file(file_path)),
+                        reply_to
+                    )
+                )
+                .send()
+            },
+        )
         .await?;
         Ok(())
     }
@@ -678,6 +1038,20 @@ impl TelegramApi for TeloxideApi {
         Ok(())
     }
 
+    async fn get_chat_member(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> Result<ChatMemberStatus, teloxide::RequestError> {
+        // Not a message to the chat, so only the global limit applies.
+        let member = self
+            .request(None, "telegram.get_chat_member", || async {
+                self.bot.get_chat_member(chat_id, user_id).await
+            })
+            .await?;
+        Ok(member.status())
+    }
+
     async fn answer_callback_query(
         &self,
         callback_query_id: &str,
@@ -725,16 +1099,23 @@ impl TelegramApi for TeloxideApi {
         text: &str,
         keyboard: InlineKeyboardMarkup,
     ) -> Result<(), teloxide::RequestError> {
-        self.request(
-            Some(chat_id),
+        self.reply_request(
+            chat_id,
+            message_id,
             "telegram.send_text_with_keyboard",
-            || async {
-                self.bot
-                    .send_message(chat_id, text.to_owned())
-                    .parse_mode(ParseMode::Html)
-                    .reply_to(message_id)
+            |chat_id, reply_to| {
+                in_topic!(
+                    self,
+                    chat_id,
+                    replying!(
+                        self.bot
+                            .send_message(chat_id, text.to_owned())
+                            .parse_mode(ParseMode::Html),
+                        reply_to
+                    )
                     .reply_markup(keyboard.clone())
-                    .await
+                )
+                .send()
             },
         )
         .await?;
@@ -747,11 +1128,41 @@ impl TelegramApi for TeloxideApi {
         text: &str,
     ) -> Result<(), teloxide::RequestError> {
         log::info!("Sending text (no reply) to chat {}", chat_id);
-        self.request(Some(chat_id), "telegram.send_text_no_reply", || async {
-            self.bot
-                .send_message(chat_id, text.to_owned())
-                .parse_mode(ParseMode::Html)
-                .await
+        self.request(Some(chat_id), "telegram.send_text_no_reply", || {
+            in_topic!(
+                self,
+                chat_id,
+                self.bot
+                    .send_message(chat_id, text.to_owned())
+                    .parse_mode(ParseMode::Html)
+            )
+            .send()
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn reply_to_sent_message(
+        &self,
+        chat_id: ChatId,
+        sent_message_id: MessageId,
+        text: &str,
+    ) -> Result<(), teloxide::RequestError> {
+        log::info!(
+            "Replying to message {} in chat {}",
+            sent_message_id,
+            chat_id
+        );
+        self.request(Some(chat_id), "telegram.reply_to_sent_message", || {
+            in_topic!(
+                self,
+                chat_id,
+                self.bot
+                    .send_message(chat_id, text.to_owned())
+                    .parse_mode(ParseMode::Html)
+                    .reply_to(sent_message_id)
+            )
+            .send()
         })
         .await?;
         Ok(())
@@ -774,4 +1185,269 @@ impl TelegramApi for TeloxideApi {
         .await?;
         Ok(())
     }
+
+    fn in_thread(&self, chat_id: ChatId, thread_id: Option<ThreadId>) -> Arc<dyn TelegramApi> {
+        Arc::new(Self {
+            topic: thread_id.map(|thread_id| (chat_id, thread_id)),
+            ..self.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        TEST_BOT_TOKEN, TEST_DELETED_MESSAGE_ID, TEST_MIGRATED_CHAT_ID, TEST_PHOTO_FILE_ID,
+        TEST_SUPERGROUP_CHAT_ID, TEST_VIDEO_FILE_ID, TestBotServer,
+    };
+
+    #[tokio::test]
+    async fn test_new_with_base_url_sends_to_given_server() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new_with_base_url(Bot::new(TEST_BOT_TOKEN), server.url());
+
+        api.send_text_message(ChatId(1), MessageId(7), "hello")
+            .await
+            .unwrap();
+
+        assert_eq!(server.calls(), ["sendmessage"]);
+    }
+
+    #[tokio::test]
+    async fn test_reply_to_deleted_message_is_sent_without_reply() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+
+        api.send_text_message(ChatId(1), MessageId(TEST_DELETED_MESSAGE_ID), "done")
+            .await
+            .unwrap();
+
+        assert_eq!(server.calls(), ["sendmessage", "sendmessage"]);
+        let params = server.params();
+        assert_eq!(
+            params[0]["reply_parameters"]["message_id"],
+            TEST_DELETED_MESSAGE_ID
+        );
+        assert_eq!(params[1]["reply_parameters"], serde_json::Value::Null);
+        assert_eq!(params[1]["text"], "done");
+    }
+
+    #[tokio::test]
+    async fn test_reply_to_deleted_sent_message_fails() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+
+        let result = api
+            .reply_to_sent_message(ChatId(1), MessageId(TEST_DELETED_MESSAGE_ID), "again")
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(teloxide::RequestError::Api(
+                teloxide::ApiError::MessageToReplyNotFound
+            ))
+        ));
+        assert_eq!(server.calls(), ["sendmessage"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_to_migrated_group_goes_to_supergroup() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+
+        api.send_chat_action(ChatId(TEST_MIGRATED_CHAT_ID), ChatAction::Typing)
+            .await
+            .unwrap();
+        api.send_text_message(ChatId(TEST_MIGRATED_CHAT_ID), MessageId(7), "done")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.calls(),
+            [
+                "sendchataction",
+                "sendchataction",
+                "sendmessage",
+                "sendmessage"
+            ]
+        );
+        let chat_ids: Vec<_> = server
+            .params()
+            .iter()
+            .map(|params| params["chat_id"].clone())
+            .collect();
+        assert_eq!(
+            chat_ids,
+            [
+                TEST_MIGRATED_CHAT_ID,
+                TEST_SUPERGROUP_CHAT_ID,
+                TEST_MIGRATED_CHAT_ID,
+                TEST_SUPERGROUP_CHAT_ID
+            ]
+        );
+        // The message being answered only exists in the old group.
+        assert_eq!(
+            server.params()[3]["reply_parameters"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_message_in_migrated_group_reports_supergroup() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+
+        let status = api
+            .send_status_message(ChatId(TEST_MIGRATED_CHAT_ID), MessageId(7), "Downloading…")
+            .await
+            .unwrap();
+
+        assert_eq!(status.chat_id, ChatId(TEST_SUPERGROUP_CHAT_ID));
+        assert_eq!(status.message_id, MessageId(102));
+    }
+
+    #[tokio::test]
+    async fn test_send_video_returns_file_id() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+        let video = tempfile::NamedTempFile::new().unwrap();
+
+        let (file_id, message_id) = api
+            .send_video(
+                ChatId(1),
+                MessageId(7),
+                video.path().into(),
+                "caption",
+                None,
+                Some(12),
+                Some(1280),
+                Some(720),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(file_id, TEST_VIDEO_FILE_ID);
+        assert_eq!(message_id, MessageId(100));
+        assert_eq!(server.calls(), ["sendchataction", "sendvideo"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_video_by_file_id() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+
+        let (file_id, _) = api
+            .send_video(
+                ChatId(1),
+                MessageId(7),
+                MediaSource::FileId(TEST_VIDEO_FILE_ID.to_string()),
+                "caption",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(file_id, TEST_VIDEO_FILE_ID);
+        assert_eq!(server.calls(), ["sendchataction", "sendvideo"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_video_note() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+        let video_note = tempfile::NamedTempFile::new().unwrap();
+
+        api.send_video_note(ChatId(1), MessageId(7), video_note.path())
+            .await
+            .unwrap();
+
+        assert_eq!(server.calls(), ["sendchataction", "sendvideonote"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_photo_returns_largest_size() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+        let photo = tempfile::NamedTempFile::new().unwrap();
+
+        let (file_id, _) = api
+            .send_photo(
+                ChatId(1),
+                MessageId(7),
+                photo.path().into(),
+                "caption",
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(file_id, TEST_PHOTO_FILE_ID);
+    }
+
+    #[tokio::test]
+    async fn test_send_media_group_maps_sent_media() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+        let media = vec![InputMedia::Photo(InputMediaPhoto::new(InputFile::file_id(
+            "existing".to_string().into(),
+        )))];
+
+        let sent = api
+            .send_media_group(ChatId(1), MessageId(7), media)
+            .await
+            .unwrap();
+
+        let sent: Vec<(&str, MediaType)> = sent
+            .iter()
+            .map(|s| (s.file_id.as_str(), s.media_type))
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                (TEST_VIDEO_FILE_ID, MediaType::Video),
+                (TEST_PHOTO_FILE_ID, MediaType::Photo)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_text_and_reaction_requests_succeed() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot());
+
+        api.send_text_message(ChatId(1), MessageId(7), "hello")
+            .await
+            .unwrap();
+        api.set_message_reaction(ChatId(2), MessageId(7), None)
+            .await
+            .unwrap();
+
+        assert_eq!(server.calls(), ["sendmessage", "setmessagereaction"]);
+    }
+
+    #[tokio::test]
+    async fn test_in_thread_posts_in_topic_of_its_chat_only() {
+        let server = TestBotServer::start().await;
+        let api = TeloxideApi::new(server.bot()).in_thread(ChatId(1), Some(ThreadId(MessageId(5))));
+
+        api.send_text_message(ChatId(1), MessageId(7), "in topic")
+            .await
+            .unwrap();
+        api.send_text_no_reply(ChatId(2), "elsewhere")
+            .await
+            .unwrap();
+
+        let thread_ids: Vec<_> = server
+            .params()
+            .iter()
+            .map(|params| params["message_thread_id"].clone())
+            .collect();
+        assert_eq!(thread_ids, [serde_json::json!(5), serde_json::Value::Null]);
+    }
 }
@@ -1,4 +1,14 @@
-use crate::downloader::MediaInfo;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use url::Url;
+
+use crate::downloader::{
+    DownloadError, DownloadedItem, DownloadedMedia, Downloader, MediaInfo, MediaType,
+};
+use crate::probe::{CorruptFile, ProbeError};
 
 pub fn create_test_info() -> MediaInfo {
     MediaInfo {
@@ -7,3 +17,340 @@ pub fn create_test_info() -> MediaInfo {
         ..Default::default()
     }
 }
+
+/// A `Downloader` that answers every URL with the same canned results, for tests that
+/// don't need per-call expectations. Counts the metadata lookups and downloads it was
+/// asked for.
+#[derive(Debug)]
+pub struct TestDownloader {
+    pub metadata: Result<MediaInfo, DownloadError>,
+    pub download_result: Result<DownloadedMedia, DownloadError>,
+    pub thumbnail_result: Result<PathBuf, DownloadError>,
+    metadata_lookups: AtomicUsize,
+    downloads: AtomicUsize,
+}
+
+impl TestDownloader {
+    /// Returns `metadata`, downloads a video to `/tmp/<id>.mp4` and has a thumbnail at
+    /// `/tmp/<id>.jpg`. None of these files exist.
+    pub fn success(metadata: MediaInfo) -> Self {
+        let item = DownloadedItem {
+            filepath: PathBuf::from(format!("/tmp/{}.mp4", metadata.id)),
+            media_type: MediaType::Video,
+            thumbnail_filepath: None,
+            title: None,
+            playlist_index: None,
+            info_json_filepath: None,
+        };
+        let thumbnail = PathBuf::from(format!("/tmp/{}.jpg", metadata.id));
+        Self {
+            metadata: Ok(metadata),
+            download_result: Ok(DownloadedMedia::Single(item)),
+            thumbnail_result: Ok(thumbnail),
+            metadata_lookups: AtomicUsize::new(0),
+            downloads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Fails every call with `error`.
+    pub fn failure(error: DownloadError) -> Self {
+        Self {
+            download_result: Err(replay_error(&error)),
+            thumbnail_result: Err(replay_error(&error)),
+            metadata: Err(error),
+            metadata_lookups: AtomicUsize::new(0),
+            downloads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns `metadata` but fails the download and the thumbnail with `error`.
+    pub fn download_failure(metadata: MediaInfo, error: DownloadError) -> Self {
+        Self {
+            metadata: Ok(metadata),
+            ..Self::failure(error)
+        }
+    }
+
+    /// Download `media` instead of the default video.
+    pub fn with_download(mut self, media: DownloadedMedia) -> Self {
+        self.download_result = Ok(media);
+        self
+    }
+
+    /// Download a single video at `filepath` with its thumbnail, e.g. real files in a
+    /// temporary directory.
+    pub fn with_video(self, filepath: PathBuf, thumbnail_filepath: Option<PathBuf>) -> Self {
+        self.with_download(DownloadedMedia::Single(DownloadedItem {
+            filepath,
+            media_type: MediaType::Video,
+            thumbnail_filepath,
+            title: None,
+            playlist_index: None,
+            info_json_filepath: None,
+        }))
+    }
+
+    pub fn with_thumbnail(mut self, thumbnail: PathBuf) -> Self {
+        self.thumbnail_result = Ok(thumbnail);
+        self
+    }
+
+    /// Number of `get_media_metadata` calls so far.
+    pub fn metadata_lookups(&self) -> usize {
+        self.metadata_lookups.load(Ordering::SeqCst)
+    }
+
+    /// Number of `download_media` calls so far.
+    pub fn downloads(&self) -> usize {
+        self.downloads.load(Ordering::SeqCst)
+    }
+}
+
+/// `DownloadError` can't be cloned, so stored errors are rebuilt for each call. Variants
+/// holding errors from other crates come back as `CommandFailed` with the same message.
+fn replay_error(error: &DownloadError) -> DownloadError {
+    match error {
+        DownloadError::CommandFailed(message) => DownloadError::CommandFailed(message.clone()),
+        DownloadError::ParsingFailed(message) => DownloadError::ParsingFailed(message.clone()),
+        DownloadError::Timeout(seconds) => DownloadError::Timeout(*seconds),
+        DownloadError::GeoBlocked(message) => DownloadError::GeoBlocked(message.clone()),
+        DownloadError::Unsupported(operation) => DownloadError::Unsupported(operation),
+        DownloadError::IoError { context, source } => DownloadError::IoError {
+            context,
+            source: std::io::Error::new(source.kind(), source.to_string()),
+        },
+        DownloadError::Corrupted { path, source } => DownloadError::Corrupted {
+            path: path.clone(),
+            source: match source {
+                CorruptFile::Unreadable(e) => {
+                    CorruptFile::Unreadable(std::io::Error::new(e.kind(), e.to_string()))
+                }
+                CorruptFile::Empty => CorruptFile::Empty,
+                CorruptFile::Unparseable(e) => {
+                    CorruptFile::Unparseable(ProbeError::Failed(e.to_string()))
+                }
+                CorruptFile::NoDuration => CorruptFile::NoDuration,
+            },
+        },
+        DownloadError::InvalidJson { .. } | DownloadError::ShortUrlExpansion(_) => {
+            DownloadError::CommandFailed(error.display_to_log())
+        }
+    }
+}
+
+fn replay<T: Clone>(result: &Result<T, DownloadError>) -> Result<T, DownloadError> {
+    match result {
+        Ok(value) => Ok(value.clone()),
+        Err(error) => Err(replay_error(error)),
+    }
+}
+
+#[async_trait]
+impl Downloader for TestDownloader {
+    async fn get_media_metadata(&self, _url: &Url) -> Result<MediaInfo, DownloadError> {
+        self.metadata_lookups.fetch_add(1, Ordering::SeqCst);
+        replay(&self.metadata)
+    }
+
+    async fn download_media(
+        &self,
+        _info: &mut MediaInfo,
+        _url: &Url,
+    ) -> Result<DownloadedMedia, DownloadError> {
+        self.downloads.fetch_add(1, Ordering::SeqCst);
+        replay(&self.download_result)
+    }
+
+    fn estimate_download_time(&self, _info: &MediaInfo) -> Option<Duration> {
+        None
+    }
+
+    async fn list_formats(&self, _url: &Url) -> Result<String, DownloadError> {
+        Ok(String::new())
+    }
+
+    async fn download_thumbnail(
+        &self,
+        _info: &MediaInfo,
+        _url: &Url,
+    ) -> Result<PathBuf, DownloadError> {
+        replay(&self.thumbnail_result)
+    }
+}
+
+/// A minimal stand-in for the Telegram Bot API, for end-to-end tests of `TeloxideApi`.
+///
+/// Answers the send endpoints with canned success responses and records the method
+/// names it was called with, lowercased (e.g. `sendvideo`), along with JSON parameters.
+/// Replies to `TEST_DELETED_MESSAGE_ID` fail as they do after the user deleted it, and
+/// messages to `TEST_MIGRATED_CHAT_ID` fail as for a group that became
+/// `TEST_SUPERGROUP_CHAT_ID`.
+pub struct TestBotServer {
+    url: url::Url,
+    calls: std::sync::Arc<std::sync::Mutex<Vec<(String, serde_json::Value)>>>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+pub const TEST_BOT_TOKEN: &str = "123456:TEST";
+pub const TEST_VIDEO_FILE_ID: &str = "test-video-file-id";
+pub const TEST_PHOTO_FILE_ID: &str = "test-photo-file-id";
+pub const TEST_DELETED_MESSAGE_ID: i32 = 404;
+pub const TEST_MIGRATED_CHAT_ID: i64 = -42;
+pub const TEST_SUPERGROUP_CHAT_ID: i64 = -1_000_000_000_042;
+
+impl TestBotServer {
+    pub async fn start() -> Self {
+        let calls = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let app = axum::Router::new().route(
+            "/{token}/{method}",
+            axum::routing::post(
+                move |axum::extract::Path((_token, method)): axum::extract::Path<(
+                    String,
+                    String,
+                )>,
+                      body: axum::body::Bytes| {
+                    let method = method.to_ascii_lowercase();
+                    // Multipart uploads are recorded without parameters.
+                    let params: serde_json::Value =
+                        serde_json::from_slice(&body).unwrap_or_default();
+                    let replies_to_deleted = params["reply_parameters"]["message_id"]
+                        == serde_json::json!(TEST_DELETED_MESSAGE_ID);
+                    let to_migrated_chat =
+                        params["chat_id"] == serde_json::json!(TEST_MIGRATED_CHAT_ID);
+                    let chat_id = params["chat_id"].as_i64();
+                    recorded.lock().unwrap().push((method.clone(), params));
+                    async move {
+                        if to_migrated_chat {
+                            return axum::Json(serde_json::json!({
+                                "ok": false,
+                                "error_code": 400,
+                                "description": "Bad Request: group chat was upgraded to a supergroup chat",
+                                "parameters": {"migrate_to_chat_id": TEST_SUPERGROUP_CHAT_ID}
+                            }));
+                        }
+                        if replies_to_deleted {
+                            return axum::Json(serde_json::json!({
+                                "ok": false,
+                                "error_code": 400,
+                                "description": "Bad Request: message to be replied not found"
+                            }));
+                        }
+                        let mut response = bot_api_response(&method);
+                        // Sent messages come back from the chat they were sent to.
+                        if let Some(chat_id) = chat_id
+                            && response["result"]["chat"].is_object()
+                        {
+                            response["result"]["chat"] = test_chat(chat_id);
+                        }
+                        axum::Json(response)
+                    }
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        Self { url, calls, server }
+    }
+
+    /// Base URL to use as the Bot API server.
+    pub fn url(&self) -> url::Url {
+        self.url.clone()
+    }
+
+    /// A bot whose requests go to this server.
+    pub fn bot(&self) -> teloxide::Bot {
+        teloxide::Bot::new(TEST_BOT_TOKEN).set_api_url(self.url.clone())
+    }
+
+    /// Methods called so far, in order.
+    pub fn calls(&self) -> Vec<String> {
+        let calls = self.calls.lock().unwrap();
+        calls.iter().map(|(method, _)| method.clone()).collect()
+    }
+
+    /// JSON parameters of each call so far, in order; `Null` for multipart uploads.
+    pub fn params(&self) -> Vec<serde_json::Value> {
+        let calls = self.calls.lock().unwrap();
+        calls.iter().map(|(_, params)| params.clone()).collect()
+    }
+}
+
+impl Drop for TestBotServer {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// A private chat for user ids, a supergroup otherwise.
+fn test_chat(chat_id: i64) -> serde_json::Value {
+    if chat_id > 0 {
+        serde_json::json!({"id": chat_id, "type": "private", "first_name": "Test"})
+    } else {
+        serde_json::json!({"id": chat_id, "type": "supergroup", "title": "Test"})
+    }
+}
+
+fn test_message(message_id: i32, media: serde_json::Value) -> serde_json::Value {
+    let mut message = serde_json::json!({
+        "message_id": message_id,
+        "date": 1_700_000_000,
+        "chat": test_chat(1),
+        "from": {"id": 42, "is_bot": true, "first_name": "CrabberBot", "username": "crabberbot"}
+    });
+    for (key, value) in media.as_object().unwrap() {
+        message[key] = value.clone();
+    }
+    message
+}
+
+fn test_video() -> serde_json::Value {
+    serde_json::json!({"video": {
+        "file_id": TEST_VIDEO_FILE_ID,
+        "file_unique_id": "video-unique",
+        "width": 1280,
+        "height": 720,
+        "duration": 12,
+        "mime_type": "video/mp4",
+        "file_size": 1_048_576
+    }})
+}
+
+fn test_photo() -> serde_json::Value {
+    serde_json::json!({"photo": [
+        {"file_id": "test-photo-thumb", "file_unique_id": "photo-small", "width": 90, "height": 90},
+        {"file_id": TEST_PHOTO_FILE_ID, "file_unique_id": "photo-large", "width": 1080, "height": 1080}
+    ]})
+}
+
+fn bot_api_response(method: &str) -> serde_json::Value {
+    let result = match method {
+        "sendvideo" => test_message(100, test_video()),
+        "sendphoto" => test_message(101, test_photo()),
+        "sendmessage" => test_message(102, serde_json::json!({"text": "ok"})),
+        "sendmediagroup" => serde_json::json!([
+            test_message(103, test_video()),
+            test_message(104, test_photo()),
+        ]),
+        "sendvideonote" => test_message(
+            105,
+            serde_json::json!({"video_note": {
+                "file_id": "test-video-note", "file_unique_id": "video-note", "length": 640, "duration": 12
+            }}),
+        ),
+        "sendchataction" | "setmessagereaction" => serde_json::json!(true),
+        _ => {
+            return serde_json::json!({
+                "ok": false,
+                "error_code": 404,
+                "description": format!("Not Found: method {method} is not simulated")
+            });
+        }
+    };
+    serde_json::json!({"ok": true, "result": result})
+}
@@ -0,0 +1,385 @@
+//! `/thumb`: sends the cover image of a video or post instead of the media itself.
+//!
+//! Only the metadata and the thumbnail are fetched, so the size and duration limits of
+//! regular downloads don't apply.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use teloxide::prelude::*;
+use teloxide::types::{ChatAction, Me, MessageId};
+use url::Url;
+
+use crate::concurrency::{BotChat, ConcurrencyLimiter};
+use crate::downloader::{CaptionOptions, Downloader, build_caption};
+use crate::handler::{PipelineConfig, parse_link, remove_files};
+use crate::quota::{DailyDownloadQuota, check_daily_quota};
+use crate::rate_limiter::{COMMAND_RATE_LIMITED_MESSAGE, RateLimiter};
+use crate::storage::Storage;
+use crate::telegram_api::{MediaSource, TelegramApi, topic_thread_id};
+use crate::url_cleanup::cleanup_url_with_rules;
+
+/// The link of a `/thumb` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbRequest {
+    pub url: Url,
+}
+
+impl ThumbRequest {
+    pub fn parse(text: &str) -> Option<Self> {
+        parse_link(text.trim()).map(|url| Self { url })
+    }
+}
+
+/// `/thumb <link>`: rate-limited like other commands, and counted against the daily
+/// quota like a download.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_thumb(
+    downloader: Arc<dyn Downloader>,
+    api: Arc<dyn TelegramApi>,
+    download_limiter: Arc<ConcurrencyLimiter<BotChat>>,
+    storage: Arc<dyn Storage>,
+    pipeline_config: Arc<PipelineConfig>,
+    rate_limiter: Arc<RateLimiter>,
+    daily_quota: DailyDownloadQuota,
+    me: Me,
+    message: Message,
+    request: ThumbRequest,
+) -> ResponseResult<()> {
+    let chat_id = message.chat.id;
+    let api = api.in_thread(chat_id, topic_thread_id(&message));
+    log::info!(
+        "request_context action=thumb update_message_id={} chat_id={} user_id={:?} url={}",
+        message.id,
+        chat_id,
+        message.from.as_ref().map(|user| user.id.0),
+        request.url
+    );
+    if !rate_limiter.check_command(chat_id.0) {
+        log::info!("Rate-limited command from chat_id: {}", chat_id);
+        return api
+            .send_text_message(chat_id, message.id, COMMAND_RATE_LIMITED_MESSAGE)
+            .await;
+    }
+
+    let Some(guard) = download_limiter.try_lock(BotChat {
+        bot_id: me.id,
+        chat_id,
+    }) else {
+        return api
+            .send_text_message(
+                chat_id,
+                message.id,
+                "I'm already working on a request for you. Please wait until it's finished!",
+            )
+            .await;
+    };
+    if let Some(host) = request.url.host_str() {
+        guard.progress().set_domain(host.trim_start_matches("www."));
+    }
+    if let Some(refusal) = check_daily_quota(storage.as_ref(), daily_quota, chat_id).await {
+        return api.send_text_message(chat_id, message.id, &refusal).await;
+    }
+
+    process_thumb_request(
+        &request.url,
+        chat_id,
+        message.id,
+        downloader.as_ref(),
+        api.as_ref(),
+        storage.as_ref(),
+        &pipeline_config,
+    )
+    .await
+}
+
+/// Reply with the thumbnail of the media at `url` as a photo, captioned like a download,
+/// and log the request. Problems are reported to the user; only Telegram errors are
+/// returned.
+pub async fn process_thumb_request(
+    url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    downloader: &dyn Downloader,
+    api: &dyn TelegramApi,
+    storage: &dyn Storage,
+    config: &PipelineConfig,
+) -> Result<(), teloxide::RequestError> {
+    let start = Instant::now();
+    let result = thumb(url, chat_id, message_id, downloader, api, &config.caption).await;
+    let status = match &result {
+        Ok(status) => *status,
+        Err(_) => "error",
+    };
+    storage
+        .log_request(
+            chat_id.0,
+            cleanup_url_with_rules(url, &config.url_cleanup_rules).as_str(),
+            status,
+            start.elapsed().as_millis() as i64,
+            None,
+        )
+        .await;
+    result.map(|_| ())
+}
+
+/// The work of `process_thumb_request`, returning the status to log.
+async fn thumb(
+    url: &Url,
+    chat_id: ChatId,
+    message_id: MessageId,
+    downloader: &dyn Downloader,
+    api: &dyn TelegramApi,
+    caption: &CaptionOptions,
+) -> Result<&'static str, teloxide::RequestError> {
+    api.send_chat_action(chat_id, ChatAction::Typing).await?;
+    let info = match downloader.get_media_metadata(url).await {
+        Ok(info) => info,
+        Err(e) => {
+            log::error!(
+                "Failed to get metadata for the thumbnail of {}: {}",
+                url,
+                e.display_to_log()
+            );
+            return api
+                .send_text_message(chat_id, message_id, e.display_to_user())
+                .await
+                .map(|()| "error");
+        }
+    };
+    // Playlists such as carousels often only have thumbnails on their entries.
+    let has_thumbnail = info.thumbnail.is_some()
+        || info
+            .entries
+            .iter()
+            .flatten()
+            .next()
+            .is_some_and(|entry| entry.thumbnail.is_some());
+    if !has_thumbnail {
+        log::info!("No thumbnail for {}", url);
+        return api
+            .send_text_message(
+                chat_id,
+                message_id,
+                "This link doesn't have a thumbnail or cover image.",
+            )
+            .await
+            .map(|()| "validation_error");
+    }
+
+    let path = match downloader.download_thumbnail(&info, url).await {
+        Ok(path) => path,
+        Err(e) => {
+            log::error!(
+                "Failed to download the thumbnail of {}: {}",
+                url,
+                e.display_to_log()
+            );
+            return api
+                .send_text_message(chat_id, message_id, e.display_to_user())
+                .await
+                .map(|()| "error");
+        }
+    };
+    let result = api
+        .send_photo(
+            chat_id,
+            message_id,
+            MediaSource::Path(path.clone()),
+            &build_caption(&info, url, caption),
+            None,
+        )
+        .await
+        .map(|_| "success");
+    remove_files(std::slice::from_ref(&path)).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::MockDownloader;
+    use crate::memory_storage::MemoryStorage;
+    use crate::storage::MockStorage;
+    use crate::telegram_api::MockTelegramApi;
+    use crate::test_utils::{TestDownloader, create_test_info};
+    use mockall::predicate::*;
+
+    fn expect_logged_status(status: &'static str) -> MockStorage {
+        let mut storage = MockStorage::new();
+        storage
+            .expect_log_request()
+            .withf(move |chat_id, url, logged, _, _| {
+                *chat_id == 1 && url.starts_with("https://example.com/") && logged == status
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| ());
+        storage
+    }
+
+    fn test_message(chat_id: i64) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 2,
+            "date": 1_700_000_000,
+            "chat": {"id": chat_id, "type": "private", "first_name": "Test"},
+            "text": "/thumb https://example.com/video"
+        }))
+        .unwrap()
+    }
+
+    fn test_me() -> Me {
+        serde_json::from_value(serde_json::json!({
+            "id": 42,
+            "is_bot": true,
+            "first_name": "Crab",
+            "username": "crab_bot",
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+            "can_connect_to_business": false,
+            "has_main_web_app": false
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sends_thumbnail_with_caption_and_removes_it() {
+        let url = Url::parse("https://example.com/video").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let thumbnail = dir.path().join("uuid.123.jpg");
+        std::fs::write(&thumbnail, b"jpeg").unwrap();
+
+        let mut info = create_test_info();
+        info.title = Some("Cover".to_string());
+        let downloader = TestDownloader::success(info.clone()).with_thumbnail(thumbnail.clone());
+        let expected_caption = build_caption(&info, &url, &CaptionOptions::default());
+        let sent = thumbnail.clone();
+        let mut api = MockTelegramApi::new();
+        api.expect_send_photo()
+            .withf(move |chat, message, media, caption, _| {
+                *chat == ChatId(1)
+                    && *message == MessageId(2)
+                    && *media == MediaSource::Path(sent.clone())
+                    && caption == expected_caption
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(("photo-id".to_string(), MessageId(3))));
+
+        api.expect_send_chat_action()
+            .with(eq(ChatId(1)), eq(ChatAction::Typing))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        process_thumb_request(
+            &url,
+            ChatId(1),
+            MessageId(2),
+            &downloader,
+            &api,
+            &expect_logged_status("success"),
+            &PipelineConfig::default(),
+        )
+        .await
+        .unwrap();
+        assert!(!thumbnail.exists());
+        assert_eq!(downloader.downloads(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_media_without_thumbnail_is_reported() {
+        let url = Url::parse("https://example.com/audio").unwrap();
+        let mut downloader = MockDownloader::new();
+        downloader.expect_get_media_metadata().returning(|_| {
+            let mut info = create_test_info();
+            info.thumbnail = None;
+            Ok(info)
+        });
+        downloader.expect_download_thumbnail().never();
+        let mut api = MockTelegramApi::new();
+        api.expect_send_text_message()
+            .with(
+                eq(ChatId(1)),
+                eq(MessageId(2)),
+                eq("This link doesn't have a thumbnail or cover image."),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        api.expect_send_photo().never();
+
+        api.expect_send_chat_action()
+            .with(eq(ChatId(1)), eq(ChatAction::Typing))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        process_thumb_request(
+            &url,
+            ChatId(1),
+            MessageId(2),
+            &downloader,
+            &api,
+            &expect_logged_status("validation_error"),
+            &PipelineConfig::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_thumb_command_is_rate_limited_and_counts_against_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let thumbnail = dir.path().join("uuid.123.jpg");
+        std::fs::write(&thumbnail, b"jpeg").unwrap();
+        let downloader: Arc<dyn Downloader> =
+            Arc::new(TestDownloader::success(create_test_info()).with_thumbnail(thumbnail));
+
+        let mut replies = MockTelegramApi::new();
+        replies
+            .expect_send_chat_action()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        replies
+            .expect_send_photo()
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(("photo-id".to_string(), MessageId(3))));
+        replies
+            .expect_send_text_message()
+            .withf(|_, _, text| text.starts_with("You've reached today's limit of 1 downloads."))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        replies
+            .expect_send_text_message()
+            .with(always(), always(), eq(COMMAND_RATE_LIMITED_MESSAGE))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let replies = Arc::new(replies);
+        let mut api = MockTelegramApi::new();
+        api.expect_in_thread()
+            .returning(move |_, _| replies.clone() as Arc<dyn TelegramApi>);
+        let api: Arc<dyn TelegramApi> = Arc::new(api);
+
+        let storage = Arc::new(MemoryStorage::new());
+        let download_limiter = Arc::new(ConcurrencyLimiter::new());
+        let pipeline_config = Arc::new(PipelineConfig::default());
+        let rate_limiter = Arc::new(RateLimiter::new(2));
+        let handle = || {
+            handle_thumb(
+                downloader.clone(),
+                api.clone(),
+                download_limiter.clone(),
+                storage.clone(),
+                pipeline_config.clone(),
+                rate_limiter.clone(),
+                DailyDownloadQuota(1),
+                test_me(),
+                test_message(1),
+                ThumbRequest::parse("https://example.com/video").unwrap(),
+            )
+        };
+
+        // Sent, then refused by the quota, then by the command rate limit.
+        for _ in 0..3 {
+            handle().await.unwrap();
+        }
+        assert_eq!(storage.get_daily_download_count(1).await, 1);
+    }
+}
@@ -0,0 +1,146 @@
+//! Tracks Telegram uploads in flight, to tell slow uploads apart from slow downloads.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use teloxide::types::{ChatId, MessageId};
+
+use crate::downloader::RollingAverage;
+
+/// Number of recent uploads averaged for `completed_upload_avg_ms`.
+const COMPLETED_UPLOAD_SAMPLES: usize = 50;
+
+/// Point-in-time view of the upload metrics, for reporting.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UploadSnapshot {
+    pub active: usize,
+    pub oldest_age: Option<Duration>,
+    pub completed_avg_ms: Option<f64>,
+}
+
+/// Start times of uploads in flight, keyed by the chat and the message being answered.
+#[derive(Debug)]
+pub struct PendingUploads {
+    uploads: DashMap<(ChatId, MessageId), Instant>,
+    completed_ms: Mutex<RollingAverage>,
+}
+
+impl Default for PendingUploads {
+    fn default() -> Self {
+        Self {
+            uploads: DashMap::new(),
+            completed_ms: Mutex::new(RollingAverage::new(COMPLETED_UPLOAD_SAMPLES)),
+        }
+    }
+}
+
+impl PendingUploads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that the upload answering `message_id` has started.
+    pub fn start(&self, chat_id: ChatId, message_id: MessageId) {
+        self.start_at(chat_id, message_id, Instant::now());
+    }
+
+    /// Record that the upload has finished and return how long it took.
+    pub fn finish(&self, chat_id: ChatId, message_id: MessageId) -> Option<Duration> {
+        self.finish_at(chat_id, message_id, Instant::now())
+    }
+
+    /// Forget an upload that will never finish, e.g. because its request timed out.
+    pub fn abandon(&self, chat_id: ChatId, message_id: MessageId) {
+        self.uploads.remove(&(chat_id, message_id));
+    }
+
+    fn start_at(&self, chat_id: ChatId, message_id: MessageId, now: Instant) {
+        self.uploads.insert((chat_id, message_id), now);
+    }
+
+    fn finish_at(&self, chat_id: ChatId, message_id: MessageId, now: Instant) -> Option<Duration> {
+        let (_, started) = self.uploads.remove(&(chat_id, message_id))?;
+        let elapsed = now.duration_since(started);
+        self.completed_ms
+            .lock()
+            .expect("upload average lock poisoned")
+            .push(elapsed.as_secs_f64() * 1000.0);
+        Some(elapsed)
+    }
+
+    pub fn active_upload_count(&self) -> usize {
+        self.uploads.len()
+    }
+
+    /// Age of the longest-running upload still in flight.
+    pub fn oldest_upload_age(&self) -> Option<Duration> {
+        self.oldest_upload_age_at(Instant::now())
+    }
+
+    fn oldest_upload_age_at(&self, now: Instant) -> Option<Duration> {
+        self.uploads
+            .iter()
+            .map(|entry| now.duration_since(*entry.value()))
+            .max()
+    }
+
+    pub fn snapshot(&self) -> UploadSnapshot {
+        UploadSnapshot {
+            active: self.active_upload_count(),
+            oldest_age: self.oldest_upload_age(),
+            completed_avg_ms: self.completed_upload_avg_ms(),
+        }
+    }
+
+    /// Mean duration of the most recent completed uploads, in milliseconds.
+    pub fn completed_upload_avg_ms(&self) -> Option<f64> {
+        self.completed_ms
+            .lock()
+            .expect("upload average lock poisoned")
+            .average()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_active_and_completed_uploads() {
+        let uploads = PendingUploads::new();
+        let start = Instant::now();
+        uploads.start_at(ChatId(1), MessageId(10), start);
+        uploads.start_at(ChatId(2), MessageId(10), start + Duration::from_secs(2));
+
+        assert_eq!(uploads.active_upload_count(), 2);
+        assert_eq!(
+            uploads.oldest_upload_age_at(start + Duration::from_secs(5)),
+            Some(Duration::from_secs(5))
+        );
+
+        let elapsed = uploads.finish_at(ChatId(1), MessageId(10), start + Duration::from_secs(3));
+        assert_eq!(elapsed, Some(Duration::from_secs(3)));
+        uploads.finish_at(ChatId(2), MessageId(10), start + Duration::from_secs(3));
+
+        assert_eq!(uploads.active_upload_count(), 0);
+        assert_eq!(uploads.oldest_upload_age(), None);
+        assert_eq!(uploads.completed_upload_avg_ms(), Some(2000.0));
+    }
+
+    #[test]
+    fn test_abandoned_upload_is_not_averaged() {
+        let uploads = PendingUploads::new();
+        uploads.start(ChatId(1), MessageId(1));
+        uploads.abandon(ChatId(1), MessageId(1));
+        assert_eq!(uploads.active_upload_count(), 0);
+        assert_eq!(uploads.completed_upload_avg_ms(), None);
+    }
+
+    #[test]
+    fn test_finish_without_start_is_ignored() {
+        let uploads = PendingUploads::new();
+        assert_eq!(uploads.finish(ChatId(1), MessageId(1)), None);
+        assert_eq!(uploads.completed_upload_avg_ms(), None);
+    }
+}
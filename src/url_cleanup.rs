@@ -0,0 +1,284 @@
+use regex::Regex;
+use serde::Deserialize;
+use url::Url;
+
+/// Per-host policy for which query parameters survive URL normalization.
+///
+/// The first rule whose `host_pattern` matches the (www-stripped) host wins. Parameters
+/// listed in `params_to_keep` are the only ones retained when that list is non-empty;
+/// `params_to_strip` is then removed from whatever remains. `strip_all` drops the whole query.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UrlCleanupRule {
+    #[serde(with = "serde_regex")]
+    pub host_pattern: Regex,
+    #[serde(default)]
+    pub params_to_keep: Vec<String>,
+    #[serde(default)]
+    pub params_to_strip: Vec<String>,
+    #[serde(default)]
+    pub strip_all: bool,
+}
+
+impl UrlCleanupRule {
+    fn retains(&self, key: &str) -> bool {
+        if self.strip_all {
+            return false;
+        }
+        let kept = self.params_to_keep.is_empty() || self.params_to_keep.iter().any(|p| p == key);
+        kept && !self.params_to_strip.iter().any(|p| p == key)
+    }
+}
+
+mod serde_regex {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Built-in rules used when `URL_CLEANUP_RULES_JSON` is not set.
+#[must_use]
+pub fn default_rules() -> Vec<UrlCleanupRule> {
+    vec![
+        UrlCleanupRule {
+            // `list` is kept so playlist links still reach yt-dlp as playlists.
+            host_pattern: Regex::new(r"(^|\.)youtube\.com$|^youtu\.be$").expect("valid regex"),
+            params_to_keep: vec!["v".to_string(), "t".to_string(), "list".to_string()],
+            params_to_strip: vec![],
+            strip_all: false,
+        },
+        UrlCleanupRule {
+            // `igsh` is the newer name of the same share-tracking parameter.
+            host_pattern: Regex::new(r"(^|\.)instagram\.com$").expect("valid regex"),
+            params_to_keep: vec![],
+            params_to_strip: vec!["igshid".to_string(), "igsh".to_string()],
+            strip_all: false,
+        },
+        UrlCleanupRule {
+            host_pattern: Regex::new(r"(^|\.)tiktok\.com$").expect("valid regex"),
+            params_to_keep: vec![],
+            params_to_strip: vec![],
+            strip_all: true,
+        },
+    ]
+}
+
+/// Parse a JSON array of rules, as supplied via `URL_CLEANUP_RULES_JSON`.
+pub fn parse_rules(json: &str) -> Result<Vec<UrlCleanupRule>, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+/// Host in the form used for policy and cache-key comparisons: punycode, lowercase,
+/// without the trailing root dot. `url` already applies IDNA when parsing http(s) URLs;
+/// this covers hosts that arrive as plain strings, e.g. from configuration.
+#[must_use]
+pub fn ascii_host(host: &str) -> String {
+    let host = host.trim_end_matches('.');
+    let ascii = url::quirks::domain_to_ascii(host);
+    if ascii.is_empty() {
+        host.to_lowercase()
+    } else {
+        ascii
+    }
+}
+
+/// Host in Unicode form for display, so `xn--e1afmkfd.xn--p1ai` is shown as `пример.рф`.
+#[must_use]
+pub fn display_host(host: &str) -> String {
+    let unicode = url::quirks::domain_to_unicode(host);
+    if unicode.is_empty() {
+        host.to_string()
+    } else {
+        unicode
+    }
+}
+
+/// Creates a normalized URL for use as a cache key:
+/// - strips the fragment
+/// - normalizes the host to lowercase punycode without a trailing dot
+/// - removes `www.` prefix
+/// - filters query params with the first rule matching the host, in either punycode or
+///   Unicode form (no match strips them all)
+/// - removes trailing slash from path
+#[must_use]
+pub fn cleanup_url_with_rules(original_url: &Url, rules: &[UrlCleanupRule]) -> Url {
+    let mut cleaned_url = original_url.clone();
+    cleaned_url.set_fragment(None);
+
+    if let Some(host) = cleaned_url.host_str().map(str::to_owned) {
+        let normalized = ascii_host(&host);
+        if normalized != host {
+            let _ = cleaned_url.set_host(Some(&normalized));
+        }
+    }
+
+    // Normalize www. prefix so e.g. www.instagram.com and instagram.com share a cache entry
+    if let Some(stripped) = cleaned_url
+        .host_str()
+        .and_then(|host| host.strip_prefix("www."))
+        .map(str::to_owned)
+    {
+        let _ = cleaned_url.set_host(Some(&stripped));
+    }
+
+    let host = cleaned_url.host_str().unwrap_or_default().to_owned();
+    let unicode_host = display_host(&host);
+    let rule = rules.iter().find(|rule| {
+        rule.host_pattern.is_match(&host) || rule.host_pattern.is_match(&unicode_host)
+    });
+
+    let kept: Vec<(String, String)> = match rule {
+        Some(rule) => original_url
+            .query_pairs()
+            .filter(|(key, _)| rule.retains(key))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect(),
+        None => Vec::new(),
+    };
+    if kept.is_empty() {
+        cleaned_url.set_query(None);
+    } else {
+        cleaned_url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+
+    // Remove trailing slash from path (e.g. /p/ABC123/ -> /p/ABC123)
+    let path = cleaned_url.path().to_owned();
+    if path.len() > 1 && path.ends_with('/') {
+        cleaned_url.set_path(path.trim_end_matches('/'));
+    }
+
+    cleaned_url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(url: &str) -> String {
+        cleanup_url_with_rules(&Url::parse(url).unwrap(), &default_rules()).to_string()
+    }
+
+    #[test]
+    fn test_youtube_keeps_video_id_timestamp_and_playlist() {
+        assert_eq!(
+            clean("https://www.youtube.com/watch?v=abc&t=42&list=PL1&si=xyz"),
+            "https://youtube.com/watch?v=abc&t=42&list=PL1"
+        );
+        assert_eq!(
+            clean("https://www.youtube.com/playlist?list=PL1&si=xyz"),
+            "https://youtube.com/playlist?list=PL1"
+        );
+    }
+
+    #[test]
+    fn test_youtu_be_drops_tracking() {
+        assert_eq!(
+            clean("https://youtu.be/abc?si=tracking"),
+            "https://youtu.be/abc"
+        );
+    }
+
+    #[test]
+    fn test_instagram_strips_share_id_only() {
+        assert_eq!(
+            clean("https://www.instagram.com/p/ABC/?igshid=123&img_index=2"),
+            "https://instagram.com/p/ABC?img_index=2"
+        );
+    }
+
+    #[test]
+    fn test_tiktok_strips_everything() {
+        assert_eq!(
+            clean("https://www.tiktok.com/@user/video/1?is_from_webapp=1&sender_device=pc#top"),
+            "https://tiktok.com/@user/video/1"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_host_defaults_to_full_strip() {
+        assert_eq!(
+            clean("https://example.com/video/?utm_source=x"),
+            "https://example.com/video"
+        );
+    }
+
+    #[test]
+    fn test_idn_hosts_share_a_cache_key() {
+        let expected = "https://xn--e1afmkfd.xn--p1ai/video";
+        assert_eq!(clean("https://ПРИМЕР.рф/video"), expected);
+        assert_eq!(clean("https://www.пример.рф./video/"), expected);
+        assert_eq!(clean("https://xn--e1afmkfd.xn--p1ai/video"), expected);
+    }
+
+    #[test]
+    fn test_mixed_script_lookalike_does_not_match_rule() {
+        // Cyrillic "а" in "instаgram" makes this a different domain.
+        let cleaned = clean("https://www.instаgram.com/p/ABC/?img_index=2");
+        assert!(cleaned.starts_with("https://xn--"), "{cleaned}");
+        assert!(!cleaned.contains("img_index"));
+    }
+
+    #[test]
+    fn test_rules_match_unicode_and_punycode_hosts() {
+        let rules = parse_rules(r#"[{"host_pattern": "^пример\\.рф$", "params_to_keep": ["id"]}]"#)
+            .unwrap();
+        for url in [
+            "https://пример.рф/watch?id=7&ref=x",
+            "https://xn--e1afmkfd.xn--p1ai/watch?id=7&ref=x",
+        ] {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(
+                cleanup_url_with_rules(&url, &rules).as_str(),
+                "https://xn--e1afmkfd.xn--p1ai/watch?id=7"
+            );
+        }
+    }
+
+    #[test]
+    fn test_host_forms() {
+        assert_eq!(ascii_host("ПРИМЕР.рф."), "xn--e1afmkfd.xn--p1ai");
+        assert_eq!(ascii_host("Example.COM"), "example.com");
+        assert_eq!(display_host("xn--e1afmkfd.xn--p1ai"), "пример.рф");
+        assert_eq!(display_host("example.com"), "example.com");
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = parse_rules(
+            r#"[
+                {"host_pattern": "^example\\.com$", "params_to_keep": ["id"]},
+                {"host_pattern": "example", "strip_all": true}
+            ]"#,
+        )
+        .unwrap();
+        let url = Url::parse("https://example.com/watch?id=7&ref=home").unwrap();
+        assert_eq!(
+            cleanup_url_with_rules(&url, &rules).as_str(),
+            "https://example.com/watch?id=7"
+        );
+    }
+
+    #[test]
+    fn test_keep_and_strip_lists_combine() {
+        let rules = parse_rules(
+            r#"[{"host_pattern": ".*", "params_to_keep": ["a", "b"], "params_to_strip": ["b"]}]"#,
+        )
+        .unwrap();
+        let url = Url::parse("https://example.com/?a=1&b=2&c=3").unwrap();
+        assert_eq!(
+            cleanup_url_with_rules(&url, &rules).as_str(),
+            "https://example.com/?a=1"
+        );
+    }
+
+    #[test]
+    fn test_parse_rules_rejects_invalid_regex() {
+        assert!(parse_rules(r#"[{"host_pattern": "("}]"#).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Expands shortened links before a request is handled, so yt-dlp and the cache see the
+//! URL the shortener points to.
+//!
+//! Anyone can make a short link point anywhere, so redirects are only followed to public
+//! http(s) hosts: the bot must not be usable to probe its own network.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use url::{Host, Url};
+
+use crate::downloader::DownloadError;
+
+/// Domains whose links only redirect to the media, e.g. `vm.tiktok.com/AbCdEfG`.
+/// `youtu.be` is left out: yt-dlp reads its links as they are, without a request.
+pub const SHORTENER_HOSTS: &[&str] = &["vm.tiktok.com", "t.co", "bit.ly"];
+
+/// Upper bound for following a short link's redirects.
+const EXPANSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Redirects followed before giving up, as reqwest's default policy does.
+const MAX_REDIRECTS: usize = 10;
+
+/// Whether `url` is on one of the `SHORTENER_HOSTS` or a subdomain of one, such as
+/// `www.bit.ly`.
+pub fn is_short_url(url: &Url) -> bool {
+    url.host_str().is_some_and(|host| {
+        SHORTENER_HOSTS.iter().any(|shortener| {
+            host.strip_suffix(shortener)
+                .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('.'))
+        })
+    })
+}
+
+/// Whether `ip` is reachable on the public internet, i.e. not loopback, private,
+/// link-local, shared, documentation or otherwise reserved.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let [first, second, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        || (first == 0x2001 && second == 0x0db8))
+}
+
+/// Whether a short link may redirect to `url`: http(s), and not to an address outside
+/// the public internet. Names are checked once resolved, by `PublicResolver`.
+fn is_public_http_url(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    match url.host() {
+        Some(Host::Ipv4(ip)) => is_public_ipv4(ip),
+        Some(Host::Ipv6(ip)) => is_public_ip(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        None => false,
+    }
+}
+
+/// Follow up to `MAX_REDIRECTS` redirects, each to a URL `is_allowed` accepts.
+fn redirect_policy(is_allowed: fn(&Url) -> bool) -> Policy {
+    Policy::custom(move |attempt: Attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !is_allowed(attempt.url()) {
+            let refused = format!("refusing to follow redirect to {}", attempt.url());
+            attempt.error(refused)
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Resolves names to their public addresses only, so a short link can't reach a private
+/// address through a name either.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Client for `expand_short_url`, which only follows redirects to public http(s) hosts.
+pub fn short_url_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .redirect(redirect_policy(is_public_http_url))
+        .dns_resolver(std::sync::Arc::new(PublicResolver))
+        .build()
+}
+
+/// The URL `url` finally redirects to, found with a HEAD request that follows redirects.
+/// `client` should come from `short_url_client`.
+pub async fn expand_short_url(url: &Url, client: &reqwest::Client) -> Result<Url, DownloadError> {
+    let response = client
+        .head(url.clone())
+        .timeout(EXPANSION_TIMEOUT)
+        .send()
+        .await
+        .map_err(DownloadError::ShortUrlExpansion)?;
+    Ok(response.url().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::Redirect;
+    use axum::routing::get;
+
+    #[test]
+    fn test_is_short_url() {
+        for url in [
+            "https://vm.tiktok.com/AbCdEfG/",
+            "https://t.co/xyz",
+            "https://bit.ly/abc",
+            "https://www.bit.ly/abc",
+        ] {
+            assert!(is_short_url(&Url::parse(url).unwrap()), "{url}");
+        }
+        for url in [
+            "https://www.tiktok.com/@a/video/1",
+            "https://www.youtube.com/watch?v=abc",
+            "https://youtu.be/abc",
+            "https://notbit.ly/abc",
+        ] {
+            assert!(!is_short_url(&Url::parse(url).unwrap()), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_redirects_only_go_to_public_http_urls() {
+        for url in [
+            "https://www.tiktok.com/@user/video/1",
+            "http://93.184.215.14/video",
+            "https://[2606:4700::1]/video",
+        ] {
+            assert!(is_public_http_url(&Url::parse(url).unwrap()), "{url}");
+        }
+        for url in [
+            "file:///etc/passwd",
+            "ftp://example.com/video",
+            "http://localhost:8080/",
+            "http://api.localhost/",
+            "http://127.0.0.1/",
+            "http://10.0.0.5/",
+            "http://172.16.0.1/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(!is_public_http_url(&Url::parse(url).unwrap()), "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expand_short_url_refuses_redirect_to_private_host() {
+        let app = axum::Router::new()
+            .route(
+                "/AbC",
+                get(|| async { Redirect::temporary("http://169.254.169.254/latest/meta-data/") }),
+            )
+            .route(
+                "/local",
+                get(|| async { Redirect::temporary("http://localhost/admin") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let client = short_url_client().unwrap();
+        for path in ["AbC", "local"] {
+            let url = Url::parse(&format!("{base}/{path}")).unwrap();
+            assert!(matches!(
+                expand_short_url(&url, &client).await,
+                Err(DownloadError::ShortUrlExpansion(_))
+            ));
+        }
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_expand_short_url_follows_redirects() {
+        let app = axum::Router::new()
+            .route("/AbC", get(|| async { Redirect::permanent("/hop") }))
+            .route(
+                "/hop",
+                get(|| async { Redirect::temporary("/@user/video/1?lang=en") }),
+            )
+            .route("/@user/video/1", get(|| async { "video" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        // The test server is local, which `short_url_client` would refuse.
+        let client = reqwest::Client::builder()
+            .redirect(redirect_policy(|url| url.scheme() == "http"))
+            .build()
+            .unwrap();
+        let expanded = expand_short_url(&Url::parse(&format!("{base}/AbC")).unwrap(), &client)
+            .await
+            .unwrap();
+
+        assert_eq!(expanded.as_str(), format!("{base}/@user/video/1?lang=en"));
+        server.abort();
+    }
+}
@@ -1,10 +1,19 @@
 use crate::downloader::MediaInfo;
 use thiserror::Error;
 
-const MAX_DURATION_SECONDS: f64 = 1800.0;
+pub const MAX_DURATION_SECONDS: f64 = 1800.0;
 const MAX_FILESIZE_BYTES: u64 = 500 * 1024 * 1024; // 500 MB
+/// A local Bot API server accepts uploads up to 2 GB, Telegram's limit for any file, so
+/// the warn margin never reaches past it.
+const LOCAL_BOT_API_MAX_FILESIZE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
 const MAX_VIDEO_PLAYLIST_ITEMS: usize = 5;
 const MAX_IMAGE_PLAYLIST_ITEMS: usize = 10;
+/// Most items any playlist may have.
+pub const MAX_PLAYLIST_ITEMS: usize = MAX_IMAGE_PLAYLIST_ITEMS;
+/// Default for `ValidationConfig::warn_margin_percent`.
+pub const DEFAULT_WARN_MARGIN_PERCENT: u32 = 20;
+/// Telegram plays video notes of up to a minute.
+pub const MAX_VIDEO_NOTE_SECONDS: u32 = 60;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ValidationError {
@@ -14,22 +23,138 @@ pub enum ValidationError {
     #[error("The media file is too large: {found_mb:.0} MB is over the {limit_mb:.0} MB limit.")]
     TooLarge { found_mb: u64, limit_mb: u64 },
 
-    #[error("The playlist is too long: {found} items is more than the maximum of {limit}.")]
+    #[error(
+        "The total playlist size is too large: {found_mb:.0} MB is over the {limit_mb:.0} MB limit."
+    )]
+    PlaylistTooLarge { found_mb: u64, limit_mb: u64 },
+
+    /// `found` may be short of the real length: metadata lists at most one item more than
+    /// `MAX_PLAYLIST_ITEMS`.
+    #[error("The playlist is too long: it has more than the maximum of {limit} items.")]
     TooManyItems { found: usize, limit: usize },
+
+    #[error("I can't download live streams. Please wait until the stream ends and try again.")]
+    LiveStream,
+
+    #[error("This video is only available as {codec}, which Telegram can't play inline.")]
+    UnsupportedCodec { codec: String },
+
+    #[error(
+        "Round videos can be at most {limit} seconds long, but this video is {found:.0} seconds."
+    )]
+    VideoNoteTooLong { found: f64, limit: u32 },
+
+    #[error("Round videos can only be made from a single video, not a playlist.")]
+    VideoNotePlaylist,
+
+    #[error(
+        "I only download YouTube Shorts or videos under {limit:.0} minutes, and this one is {found:.0} minutes. Share a clip of the part you want, or send the Short instead."
+    )]
+    YouTubeTooLong { found: f64, limit: f64 },
 }
 
-#[must_use]
-pub fn validate_media_metadata(info: &MediaInfo) -> Result<(), ValidationError> {
-    if let Some(entries) = &info.entries {
-        let is_video_playlist = entries
-            .first()
-            .and_then(|entry| entry.media_type.as_ref())
-            .is_some_and(|m_type| m_type == "video");
+/// Media slightly over a limit, within the warn margin. It is still downloaded.
+#[derive(Error, Debug, PartialEq)]
+pub enum ValidationWarning {
+    #[error(
+        "⚠️ Heads up: this is {found:.0} minutes long, slightly over the usual {limit:.0} minute limit. It may take a while."
+    )]
+    SlightlyTooLong { found: f64, limit: f64 },
 
-        let limit = if is_video_playlist {
-            MAX_VIDEO_PLAYLIST_ITEMS
+    #[error(
+        "⚠️ Heads up: this file is {found_mb:.0} MB, slightly over the usual {limit_mb:.0} MB limit. It may take a while."
+    )]
+    SlightlyTooLarge { found_mb: u64, limit_mb: u64 },
+}
+
+/// HEVC (H.265), as reported by yt-dlp, e.g. "hvc1.1.6.L93.B0". Telegram's mobile clients
+/// can't play it inline.
+fn is_hevc(codec: &str) -> bool {
+    let family = codec.split('.').next().unwrap_or_default();
+    ["hevc", "hvc1", "hev1", "h265"]
+        .iter()
+        .any(|hevc| family.eq_ignore_ascii_case(hevc))
+}
+
+/// Surround formats Telegram's players can't decode: DTS (with its `dtse` and `dtsh` MP4
+/// tags), Dolby TrueHD, Dolby Digital Plus (`ec-3`, or `eac3` in ffprobe) and Dolby Atmos.
+fn is_unsupported_audio(codec: &str) -> bool {
+    let family = codec.split('.').next().unwrap_or_default();
+    ["dts", "dtse", "dtsh", "truehd", "ec-3", "eac3", "atmos"]
+        .iter()
+        .any(|unsupported| family.eq_ignore_ascii_case(unsupported))
+}
+
+/// Limits that depend on the deployment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationConfig {
+    pub max_filesize_bytes: u64,
+    /// How far over the duration and size limits, in percent, media is still downloaded
+    /// with a warning instead of rejected.
+    pub warn_margin_percent: u32,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            max_filesize_bytes: MAX_FILESIZE_BYTES,
+            warn_margin_percent: DEFAULT_WARN_MARGIN_PERCENT,
+        }
+    }
+}
+
+impl ValidationConfig {
+    /// Limits for uploads through a local Bot API server when `local` is true.
+    pub fn for_bot_api(local: bool) -> Self {
+        if local {
+            Self {
+                max_filesize_bytes: LOCAL_BOT_API_MAX_FILESIZE_BYTES,
+                ..Self::default()
+            }
         } else {
+            Self::default()
+        }
+    }
+
+    fn within_margin(&self, limit: f64) -> f64 {
+        limit * (1.0 + f64::from(self.warn_margin_percent) / 100.0)
+    }
+}
+
+/// Check media against the limits before downloading. Media over a duration or size
+/// limit by no more than the warn margin passes with a warning.
+pub fn validate_media_metadata(
+    info: &MediaInfo,
+    config: &ValidationConfig,
+) -> Result<Option<ValidationWarning>, ValidationError> {
+    if info.is_live_stream() {
+        return Err(ValidationError::LiveStream);
+    }
+
+    // The download sorts formats by `FORMAT_SORT` (H.264 video, m4a audio), so these only
+    // turn up when a site offers nothing else or format selection falls back. Reject them
+    // up front rather than upload something that won't play.
+    if let Some(codec) = std::iter::once(info)
+        .chain(info.entries.iter().flatten())
+        .flat_map(|media| {
+            let video = media.video_codec().filter(|codec| is_hevc(codec));
+            let audio = media
+                .audio_codec()
+                .filter(|codec| is_unsupported_audio(codec));
+            video.into_iter().chain(audio)
+        })
+        .next()
+    {
+        return Err(ValidationError::UnsupportedCodec {
+            codec: codec.to_string(),
+        });
+    }
+
+    if let Some(entries) = &info.entries {
+        let limit = if info.is_image_gallery() {
             MAX_IMAGE_PLAYLIST_ITEMS
+        } else {
+            MAX_VIDEO_PLAYLIST_ITEMS
         };
 
         if entries.len() > limit {
@@ -38,24 +163,63 @@ pub fn validate_media_metadata(info: &MediaInfo) -> Result<(), ValidationError>
                 limit,
             });
         }
-    } else {
-        if let Some(duration) = info.duration {
-            if duration > MAX_DURATION_SECONDS {
-                return Err(ValidationError::TooLong {
-                    found: duration / 60.0,
-                    limit: MAX_DURATION_SECONDS / 60.0,
-                });
-            }
+
+        if let Some(total) = info.playlist_total_filesize()
+            && total > config.max_filesize_bytes
+        {
+            return Err(ValidationError::PlaylistTooLarge {
+                found_mb: total / 1024 / 1024,
+                limit_mb: config.max_filesize_bytes / 1024 / 1024,
+            });
         }
+        return Ok(None);
+    }
 
-        if let Some(filesize) = info.filesize {
-            if filesize > MAX_FILESIZE_BYTES {
-                return Err(ValidationError::TooLarge {
-                    found_mb: filesize / 1024 / 1024,
-                    limit_mb: MAX_FILESIZE_BYTES / 1024 / 1024,
-                });
-            }
+    let mut warning = None;
+    if let Some(duration) = info.duration
+        && duration > MAX_DURATION_SECONDS
+    {
+        let (found, limit) = (duration / 60.0, MAX_DURATION_SECONDS / 60.0);
+        if duration > config.within_margin(MAX_DURATION_SECONDS) {
+            return Err(ValidationError::TooLong { found, limit });
         }
+        warning = Some(ValidationWarning::SlightlyTooLong { found, limit });
+    }
+
+    if let Some(filesize) = info.filesize
+        && filesize > config.max_filesize_bytes
+    {
+        let (found_mb, limit_mb) = (
+            filesize / 1024 / 1024,
+            config.max_filesize_bytes / 1024 / 1024,
+        );
+        let hard_limit = config
+            .within_margin(config.max_filesize_bytes as f64)
+            .min(LOCAL_BOT_API_MAX_FILESIZE_BYTES as f64);
+        if filesize as f64 > hard_limit {
+            return Err(ValidationError::TooLarge { found_mb, limit_mb });
+        }
+        warning = warning.or(Some(ValidationWarning::SlightlyTooLarge {
+            found_mb,
+            limit_mb,
+        }));
+    }
+    Ok(warning)
+}
+
+/// Extra checks for `/roundify`, on top of `validate_media_metadata`. There is no warn
+/// margin: anything over a minute would be cut.
+pub fn validate_video_note(info: &MediaInfo) -> Result<(), ValidationError> {
+    if info.entries.is_some() {
+        return Err(ValidationError::VideoNotePlaylist);
+    }
+    if let Some(duration) = info.duration
+        && duration > f64::from(MAX_VIDEO_NOTE_SECONDS)
+    {
+        return Err(ValidationError::VideoNoteTooLong {
+            found: duration,
+            limit: MAX_VIDEO_NOTE_SECONDS,
+        });
     }
     Ok(())
 }
@@ -65,21 +229,80 @@ mod tests {
     use super::*;
     use crate::test_utils::create_test_info;
 
+    #[test]
+    fn test_hevc_is_rejected() {
+        for codec in ["hevc", "hvc1.1.6.L93.B0", "hev1.2.4.L120.90", "H265"] {
+            let mut info = create_test_info();
+            info.vcodec = Some(codec.to_string());
+            assert_eq!(
+                validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
+                ValidationError::UnsupportedCodec {
+                    codec: codec.to_string()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_surround_audio_is_rejected() {
+        for codec in [
+            "dts", "DTS", "dtse", "dtsh", "truehd", "ec-3", "EC-3", "eac3", "atmos",
+        ] {
+            let mut info = create_test_info();
+            info.acodec = Some(codec.to_string());
+            assert_eq!(
+                validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
+                ValidationError::UnsupportedCodec {
+                    codec: codec.to_string()
+                }
+            );
+        }
+        for codec in ["mp4a.40.2", "opus", "none"] {
+            let mut info = create_test_info();
+            info.acodec = Some(codec.to_string());
+            assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_hevc_playlist_entry_is_rejected() {
+        let mut info = create_test_info();
+        let mut entry = create_test_info();
+        entry.vcodec = Some("hvc1.1.6.L93.B0".to_string());
+        info.entries = Some(vec![create_test_info(), entry]);
+        assert!(matches!(
+            validate_media_metadata(&info, &ValidationConfig::default()),
+            Err(ValidationError::UnsupportedCodec { .. })
+        ));
+    }
+
+    #[test]
+    fn test_h264_and_audio_only_are_accepted() {
+        for codec in ["avc1.64001F", "none"] {
+            let mut info = create_test_info();
+            info.vcodec = Some(codec.to_string());
+            assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_ok());
+        }
+    }
+
     #[test]
     fn test_valid_single_item() {
         let mut info = create_test_info();
         info.duration = Some(MAX_DURATION_SECONDS / 2.0);
         info.filesize = Some(MAX_FILESIZE_BYTES - 1);
-        assert!(validate_media_metadata(&info).is_ok());
+        assert_eq!(
+            validate_media_metadata(&info, &ValidationConfig::default()),
+            Ok(None)
+        );
     }
 
     #[test]
     fn test_item_too_long() {
         let mut info = create_test_info();
-        let duration = MAX_DURATION_SECONDS + 1.0;
+        let duration = MAX_DURATION_SECONDS * 1.2 + 1.0;
         info.duration = Some(duration);
         assert_eq!(
-            validate_media_metadata(&info).unwrap_err(),
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
             ValidationError::TooLong {
                 found: duration / 60.0,
                 limit: MAX_DURATION_SECONDS / 60.0
@@ -87,13 +310,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_item_slightly_too_long_warns() {
+        let mut info = create_test_info();
+        info.duration = Some(MAX_DURATION_SECONDS * 1.05);
+        let warning = validate_media_metadata(&info, &ValidationConfig::default()).unwrap();
+        assert_eq!(
+            warning,
+            Some(ValidationWarning::SlightlyTooLong {
+                found: 31.5,
+                limit: 30.0
+            })
+        );
+        assert!(warning.unwrap().to_string().contains("slightly over"));
+
+        let strict = ValidationConfig {
+            warn_margin_percent: 0,
+            ..ValidationConfig::default()
+        };
+        assert!(matches!(
+            validate_media_metadata(&info, &strict),
+            Err(ValidationError::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn test_item_slightly_too_large_warns() {
+        let mut info = create_test_info();
+        info.filesize = Some(MAX_FILESIZE_BYTES + 1);
+        assert_eq!(
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap(),
+            Some(ValidationWarning::SlightlyTooLarge {
+                found_mb: 500,
+                limit_mb: 500,
+            })
+        );
+    }
+
     #[test]
     fn test_item_too_large() {
         let mut info = create_test_info();
-        let size = MAX_FILESIZE_BYTES + 1;
+        let size = MAX_FILESIZE_BYTES / 5 * 6 + 1;
         info.filesize = Some(size);
         assert_eq!(
-            validate_media_metadata(&info).unwrap_err(),
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
             ValidationError::TooLarge {
                 found_mb: size / 1024 / 1024,
                 limit_mb: MAX_FILESIZE_BYTES / 1024 / 1024,
@@ -101,13 +361,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_local_bot_api_allows_larger_files() {
+        let mut info = create_test_info();
+        info.filesize = Some(MAX_FILESIZE_BYTES + 1);
+        let local = ValidationConfig::for_bot_api(true);
+        assert_eq!(validate_media_metadata(&info, &local), Ok(None));
+
+        info.filesize = Some(LOCAL_BOT_API_MAX_FILESIZE_BYTES * 2);
+        assert_eq!(
+            validate_media_metadata(&info, &local).unwrap_err(),
+            ValidationError::TooLarge {
+                found_mb: 4096,
+                limit_mb: 2048,
+            }
+        );
+
+        // The warn margin doesn't stretch past what Telegram accepts at all.
+        info.filesize = Some(LOCAL_BOT_API_MAX_FILESIZE_BYTES + 1);
+        assert_eq!(
+            validate_media_metadata(&info, &local).unwrap_err(),
+            ValidationError::TooLarge {
+                found_mb: 2048,
+                limit_mb: 2048,
+            }
+        );
+        assert_eq!(
+            ValidationConfig::for_bot_api(false),
+            ValidationConfig::default()
+        );
+    }
+
     #[test]
     fn test_valid_video_playlist() {
         let mut info = create_test_info();
         let mut video_entry = create_test_info();
         video_entry.media_type = Some("video".to_string());
         info.entries = Some(vec![video_entry; MAX_VIDEO_PLAYLIST_ITEMS]);
-        assert!(validate_media_metadata(&info).is_ok());
+        assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_ok());
     }
 
     #[test]
@@ -118,7 +409,7 @@ mod tests {
         video_entry.media_type = Some("video".to_string());
         info.entries = Some(vec![video_entry; n_items]);
         assert_eq!(
-            validate_media_metadata(&info).unwrap_err(),
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
             ValidationError::TooManyItems {
                 found: n_items,
                 limit: MAX_VIDEO_PLAYLIST_ITEMS,
@@ -133,10 +424,10 @@ mod tests {
         assert!(n_items > MAX_VIDEO_PLAYLIST_ITEMS);
 
         let mut image_entry = create_test_info();
-        image_entry.media_type = Some("image".to_string());
+        image_entry.ext = Some("jpg".to_string());
         info.entries = Some(vec![image_entry; n_items]);
 
-        assert!(validate_media_metadata(&info).is_ok());
+        assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_ok());
     }
 
     #[test]
@@ -144,10 +435,10 @@ mod tests {
         let mut info = create_test_info();
         let n_items = MAX_IMAGE_PLAYLIST_ITEMS + 1;
         let mut image_entry = create_test_info();
-        image_entry.media_type = Some("image".to_string());
+        image_entry.ext = Some("webp".to_string());
         info.entries = Some(vec![image_entry; n_items]);
         assert_eq!(
-            validate_media_metadata(&info).unwrap_err(),
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
             ValidationError::TooManyItems {
                 found: n_items,
                 limit: MAX_IMAGE_PLAYLIST_ITEMS,
@@ -156,19 +447,125 @@ mod tests {
     }
 
     #[test]
-    fn test_playlist_with_no_type_uses_image_limit() {
+    fn test_playlist_total_size_is_limited() {
+        let mut info = create_test_info();
+        let mut entry = create_test_info();
+        entry.media_type = Some("video".to_string());
+        entry.filesize = Some(MAX_FILESIZE_BYTES / 4);
+        let unknown_size = MediaInfo {
+            filesize: None,
+            ..entry.clone()
+        };
+        info.entries = Some(vec![
+            entry.clone(),
+            entry.clone(),
+            entry.clone(),
+            unknown_size,
+        ]);
+        assert_eq!(
+            validate_media_metadata(&info, &ValidationConfig::default()),
+            Ok(None)
+        );
+
+        info.entries = Some(vec![entry; 5]);
+        let error = validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err();
+        assert_eq!(
+            error,
+            ValidationError::PlaylistTooLarge {
+                found_mb: 625,
+                limit_mb: 500,
+            }
+        );
+        assert!(error.to_string().contains("total playlist size"));
+    }
+
+    #[test]
+    fn test_mixed_gallery_uses_video_limit() {
+        let mut info = create_test_info();
+        let mut photo = create_test_info();
+        photo.ext = Some("JPG".to_string());
+        let mut video = create_test_info();
+        video.ext = Some("mp4".to_string());
+        let mut entries = vec![photo; MAX_VIDEO_PLAYLIST_ITEMS];
+        entries.push(video);
+        info.entries = Some(entries);
+
+        assert!(!info.is_image_gallery());
+        assert_eq!(
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
+            ValidationError::TooManyItems {
+                found: MAX_VIDEO_PLAYLIST_ITEMS + 1,
+                limit: MAX_VIDEO_PLAYLIST_ITEMS,
+            }
+        );
+    }
+
+    #[test]
+    fn test_playlist_with_unknown_extensions_uses_video_limit() {
         let mut info = create_test_info();
         let n_items = MAX_VIDEO_PLAYLIST_ITEMS + 1;
         let mut untyped_entry = create_test_info();
-        untyped_entry.media_type = None;
+        untyped_entry.ext = None;
         info.entries = Some(vec![untyped_entry; n_items]);
 
-        assert!(validate_media_metadata(&info).is_ok());
+        assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_err());
     }
 
     #[test]
     fn test_single_item_with_no_metadata_is_valid() {
         let info = create_test_info();
-        assert!(validate_media_metadata(&info).is_ok());
+        assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_live_stream_is_rejected() {
+        let mut info = create_test_info();
+        info.is_live = Some(true);
+        assert_eq!(
+            validate_media_metadata(&info, &ValidationConfig::default()).unwrap_err(),
+            ValidationError::LiveStream
+        );
+    }
+
+    #[test]
+    fn test_finished_live_stream_is_valid() {
+        let mut info = create_test_info();
+        info.is_live = Some(false);
+        info.was_live = Some(true);
+        assert!(validate_media_metadata(&info, &ValidationConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_video_note_duration_is_limited_to_a_minute() {
+        let mut info = create_test_info();
+        info.duration = Some(60.0);
+        assert_eq!(validate_video_note(&info), Ok(()));
+
+        info.duration = Some(61.4);
+        let error = validate_video_note(&info).unwrap_err();
+        assert_eq!(
+            error,
+            ValidationError::VideoNoteTooLong {
+                found: 61.4,
+                limit: 60
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Round videos can be at most 60 seconds long, but this video is 61 seconds."
+        );
+
+        info.duration = None;
+        assert_eq!(validate_video_note(&info), Ok(()));
+    }
+
+    #[test]
+    fn test_video_note_rejects_playlists() {
+        let mut info = create_test_info();
+        info.entries = Some(vec![create_test_info()]);
+        assert_eq!(
+            validate_video_note(&info),
+            Err(ValidationError::VideoNotePlaylist)
+        );
     }
 }
@@ -0,0 +1,124 @@
+//! Webhook routing for running several bots behind one axum listener.
+
+use url::Url;
+
+/// Public URL Telegram posts a bot's updates to: `<WEBHOOK_URL>/webhook/<bot_username>`.
+///
+/// Any path already present on the base URL is kept as a prefix, so the bots can sit
+/// behind a reverse proxy that mounts them under a sub-path.
+#[must_use]
+pub fn bot_webhook_url(base: &Url, bot_username: &str) -> Url {
+    let mut url = base.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().extend(["webhook", bot_username]);
+    }
+    url
+}
+
+/// Webhook URL of one of `bot_count` bots. A lone bot keeps `WEBHOOK_URL` as it is, so
+/// single-bot deployments don't have to move their webhook; several bots each get their
+/// own `bot_webhook_url`.
+#[must_use]
+pub fn webhook_url_for(base: &Url, bot_username: &str, bot_count: usize) -> Url {
+    if bot_count == 1 {
+        base.clone()
+    } else {
+        bot_webhook_url(base, bot_username)
+    }
+}
+
+/// Combine the per-bot webhook routers into the single app served on the shared port.
+pub fn merge_webhook_routers(routers: impl IntoIterator<Item = axum::Router>) -> axum::Router {
+    routers
+        .into_iter()
+        .fold(axum::Router::new(), |app, router| app.merge(router))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use teloxide::update_listeners::webhooks::{Options, axum_no_setup};
+    use tower::ServiceExt;
+
+    const SECRET: &str = "secret";
+
+    fn base() -> Url {
+        Url::parse("https://bots.example.com").unwrap()
+    }
+
+    /// The listener is returned so its update channel stays open for the test.
+    fn router_for(bot_username: &str) -> (impl Sized, axum::Router) {
+        let options = Options::new(
+            ([127, 0, 0, 1], 8080).into(),
+            bot_webhook_url(&base(), bot_username),
+        )
+        .secret_token(SECRET.to_string());
+        let (listener, _stop, router) = axum_no_setup(options);
+        (listener, router)
+    }
+
+    fn update_request(path: &str) -> Request<Body> {
+        Request::post(path)
+            .header("content-type", "application/json")
+            .header("x-telegram-bot-api-secret-token", SECRET)
+            .body(Body::from(r#"{"update_id": 1}"#))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bot_webhook_url_appends_username() {
+        assert_eq!(
+            bot_webhook_url(&base(), "crabberbot").as_str(),
+            "https://bots.example.com/webhook/crabberbot"
+        );
+    }
+
+    #[test]
+    fn test_bot_webhook_url_keeps_base_path_prefix() {
+        let with_slash = Url::parse("https://example.com/tg/").unwrap();
+        let without_slash = Url::parse("https://example.com/tg?x=1").unwrap();
+        assert_eq!(
+            bot_webhook_url(&with_slash, "friends_bot").as_str(),
+            "https://example.com/tg/webhook/friends_bot"
+        );
+        assert_eq!(
+            bot_webhook_url(&without_slash, "friends_bot").as_str(),
+            "https://example.com/tg/webhook/friends_bot"
+        );
+    }
+
+    #[test]
+    fn test_single_bot_keeps_webhook_url() {
+        let base = Url::parse("https://bots.example.com/hook").unwrap();
+        assert_eq!(
+            webhook_url_for(&base, "crabberbot", 1).as_str(),
+            "https://bots.example.com/hook"
+        );
+        assert_eq!(
+            webhook_url_for(&base, "crabberbot", 2).as_str(),
+            "https://bots.example.com/hook/webhook/crabberbot"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merged_router_serves_each_bot_path() {
+        let (_public, public_router) = router_for("public_bot");
+        let (_friends, friends_router) = router_for("friends_bot");
+        let app = merge_webhook_routers([public_router, friends_router]);
+
+        for path in ["/webhook/public_bot", "/webhook/friends_bot"] {
+            let response = app.clone().oneshot(update_request(path)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "path {path}");
+        }
+
+        let response = app
+            .oneshot(update_request("/webhook/unknown_bot"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}